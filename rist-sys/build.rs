@@ -1,32 +1,303 @@
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 fn main() {
     println!("cargo:rerun-if-changed=wrapper.h");
 
+    let include_paths = find_librist();
+
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    generate_bindings(&include_paths, &out_path.join("bindings.rs"));
+}
+
+/// Emit the link flags for librist and return its include paths.
+///
+/// With the `vendored` feature librist is built from source, see
+/// [`build_vendored`]. Otherwise `LIBRIST_LIB_DIR` (and optionally
+/// `LIBRIST_INCLUDE_DIR`) point at a locally built librist, or pkg-config
+/// is used. `LIBRIST_STATIC=1` links the static library in the last two
+/// cases.
+fn find_librist() -> Vec<PathBuf> {
+    println!("cargo:rerun-if-env-changed=LIBRIST_STATIC");
+    println!("cargo:rerun-if-env-changed=LIBRIST_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=LIBRIST_INCLUDE_DIR");
+
+    let statik = env::var("LIBRIST_STATIC").is_ok_and(|v| v != "0" && !v.is_empty());
+    let include_dir = env::var_os("LIBRIST_INCLUDE_DIR").map(PathBuf::from);
+
+    if env::var_os("CARGO_FEATURE_VENDORED").is_some() {
+        let prefix = build_vendored();
+        return link_local(&prefix.join("lib"), Some(prefix.join("include")), true);
+    }
+
+    if let Some(lib_dir) = env::var_os("LIBRIST_LIB_DIR") {
+        return link_local(&PathBuf::from(lib_dir), include_dir, statik);
+    }
+
     let library = pkg_config::Config::new()
         .atleast_version("0.2")
+        .statik(statik)
         .probe("librist")
-        .expect("librist not found. Install librist and ensure pkg-config can find it.");
+        .expect(
+            "librist not found. Install librist and ensure pkg-config can find it, \
+             or set LIBRIST_LIB_DIR (and LIBRIST_INCLUDE_DIR) to a local build.",
+        );
+
+    if let Some(backend) = crypto_backend() {
+        let private = pkg_config::Config::new()
+            .statik(true)
+            .cargo_metadata(false)
+            .probe("librist");
+        if let Ok(private) = private {
+            check_crypto_backend(backend, &private.libs);
+        }
+    }
+
+    include_dir
+        .into_iter()
+        .chain(library.include_paths)
+        .collect()
+}
+
+/// Link the librist in `lib_dir`, returning its include paths.
+///
+/// The librist.pc that `meson install` puts in `lib_dir/pkgconfig` lists
+/// the libraries librist was built against. It is used to check the crypto
+/// backend, and to link a static librist's dependencies, which can
+/// otherwise only be guessed from the `crypto-*` features.
+fn link_local(lib_dir: &Path, include_dir: Option<PathBuf>, statik: bool) -> Vec<PathBuf> {
+    let kind = if statik { "static" } else { "dylib" };
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib={kind}=rist");
+
+    let pc = lib_dir.join("pkgconfig").join("librist.pc");
+    println!("cargo:rerun-if-changed={}", pc.display());
+    let private = private_libs(&pc);
+    if let Some(backend) = crypto_backend() {
+        match &private {
+            Some((_, libs)) => check_crypto_backend(backend, libs),
+            None => println!(
+                "cargo:warning=could not verify that librist was built for {}: no {}",
+                backend.feature,
+                pc.display()
+            ),
+        }
+    }
+
+    if statik {
+        // a static librist doesn't carry its dependencies
+        let (search, libs) = private.unwrap_or_else(|| match crypto_backend() {
+            // librist spawns its own threads
+            Some(backend) => (
+                Vec::new(),
+                backend
+                    .libs
+                    .iter()
+                    .chain(&["pthread"])
+                    .map(|lib| lib.to_string())
+                    .collect(),
+            ),
+            None => panic!(
+                "LIBRIST_STATIC needs {} to find the libraries librist was built against, \
+                 or a crypto-* feature naming its crypto backend",
+                pc.display()
+            ),
+        });
+        for dir in search {
+            println!("cargo:rustc-link-search=native={}", dir.display());
+        }
+        for lib in libs {
+            println!("cargo:rustc-link-lib=dylib={lib}");
+        }
+    }
+
+    // a librist install puts headers next to lib/
+    let include_dir = include_dir.or_else(|| {
+        let guess = lib_dir.parent()?.join("include");
+        guess.is_dir().then_some(guess)
+    });
+    include_dir.into_iter().collect()
+}
+
+/// Build a static librist from source with meson and ninja, returning the
+/// prefix it was installed to.
+///
+/// The sources come from `LIBRIST_SRC_DIR`, or the `librist` directory next
+/// to this file. Without a `crypto-*` feature librist's bundled mbedtls is
+/// compiled in; with one, the system's mbedtls or nettle is used.
+fn build_vendored() -> PathBuf {
+    println!("cargo:rerun-if-env-changed=LIBRIST_SRC_DIR");
+    let src = env::var_os("LIBRIST_SRC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("librist"));
+    if !src.join("meson.build").is_file() {
+        panic!(
+            "the vendored feature needs the librist sources: set LIBRIST_SRC_DIR or put them in {}",
+            src.display()
+        );
+    }
+    println!("cargo:rerun-if-changed={}", src.display());
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let build = out.join("librist-build");
+    let prefix = out.join("librist");
+    let crypto = crypto_backend().map_or(BUNDLED_MBEDTLS, |backend| backend.meson_options);
 
+    let mut setup = Command::new("meson");
+    setup
+        .arg("setup")
+        .arg(&build)
+        .arg(&src)
+        .arg(format!("--prefix={}", prefix.display()))
+        .args([
+            "--libdir=lib",
+            "--buildtype=release",
+            "--default-library=static",
+            "-Dbuilt_tools=false",
+            "-Dtest=false",
+        ])
+        .args(crypto);
+    if build.join("build.ninja").is_file() {
+        setup.arg("--reconfigure");
+    }
+    run(&mut setup);
+    run(Command::new("meson").arg("install").arg("-C").arg(&build));
+    prefix
+}
+
+/// Run a build tool, failing the build if it fails.
+fn run(command: &mut Command) {
+    let status = command
+        .status()
+        .unwrap_or_else(|e| panic!("failed to run {command:?}: {e}; is it installed?"));
+    if !status.success() {
+        panic!("{command:?} failed with {status}");
+    }
+}
+
+/// A crypto library librist can be built against.
+struct CryptoBackend {
+    feature: &'static str,
+    /// Libraries a static librist needs, in link order.
+    libs: &'static [&'static str],
+    /// Options selecting the backend in librist's meson build.
+    meson_options: &'static [&'static str],
+}
+
+const MBEDTLS: CryptoBackend = CryptoBackend {
+    feature: "crypto-mbedtls",
+    libs: &["mbedcrypto"],
+    meson_options: &[
+        "-Duse_mbedtls=true",
+        "-Dbuiltin_mbedtls=false",
+        "-Duse_nettle=false",
+    ],
+};
+
+const NETTLE: CryptoBackend = CryptoBackend {
+    feature: "crypto-nettle",
+    libs: &["hogweed", "nettle", "gmp"],
+    meson_options: &["-Duse_mbedtls=false", "-Duse_nettle=true"],
+};
+
+/// Meson options for a vendored build without a `crypto-*` feature.
+const BUNDLED_MBEDTLS: &[&str] = &[
+    "-Duse_mbedtls=true",
+    "-Dbuiltin_mbedtls=true",
+    "-Duse_nettle=false",
+];
+
+/// The backend selected with the `crypto-*` features, if any.
+fn crypto_backend() -> Option<&'static CryptoBackend> {
+    let mbedtls = env::var_os("CARGO_FEATURE_CRYPTO_MBEDTLS").is_some();
+    let nettle = env::var_os("CARGO_FEATURE_CRYPTO_NETTLE").is_some();
+
+    match (mbedtls, nettle) {
+        (true, true) => {
+            panic!("the crypto-mbedtls and crypto-nettle features are mutually exclusive")
+        }
+        (true, false) => Some(&MBEDTLS),
+        (false, true) => Some(&NETTLE),
+        (false, false) => None,
+    }
+}
+
+/// Fail the build if librist, given its private libraries, uses another
+/// backend.
+///
+/// The backend shows up in the private libraries of librist.pc, except when
+/// librist was built with its bundled copy of mbedtls.
+fn check_crypto_backend(wanted: &CryptoBackend, libs: &[String]) {
+    let found = [&MBEDTLS, &NETTLE]
+        .into_iter()
+        .find(|backend| libs.iter().any(|lib| backend.libs.contains(&lib.as_str())));
+
+    match found {
+        Some(backend) if backend.feature != wanted.feature => panic!(
+            "the {} feature is enabled, but librist was built for {}",
+            wanted.feature, backend.feature
+        ),
+        Some(_) => {}
+        None => println!(
+            "cargo:warning=could not verify that librist was built for {}",
+            wanted.feature
+        ),
+    }
+}
+
+/// The link search paths and libraries in the `Libs.private` of the
+/// pkg-config file at `pc`, if there is one.
+fn private_libs(pc: &Path) -> Option<(Vec<PathBuf>, Vec<String>)> {
+    let contents = std::fs::read_to_string(pc).ok()?;
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let expand = |value: &str, vars: &[(String, String)]| {
+        vars.iter().fold(value.to_string(), |value, (name, var)| {
+            value.replace(&format!("${{{name}}}"), var)
+        })
+    };
+
+    let (mut search, mut libs) = (Vec::new(), Vec::new());
+    for line in contents.lines() {
+        if let Some(flags) = line.strip_prefix("Libs.private:") {
+            for flag in expand(flags, &vars).split_whitespace() {
+                if let Some(dir) = flag.strip_prefix("-L") {
+                    search.push(PathBuf::from(dir));
+                } else if let Some(lib) = flag.strip_prefix("-l") {
+                    libs.push(lib.to_string());
+                } else if flag == "-pthread" {
+                    libs.push("pthread".to_string());
+                }
+            }
+        } else if let Some((name, value)) = line.split_once('=') {
+            if !name.contains(':') && !name.trim().is_empty() {
+                let value = expand(value.trim(), &vars);
+                vars.push((name.trim().to_string(), value));
+            }
+        }
+    }
+    Some((search, libs))
+}
+
+fn generate_bindings(include_paths: &[PathBuf], out: &Path) {
     let mut builder = bindgen::Builder::default()
         .header("wrapper.h")
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
         .allowlist_function("rist_.*")
+        .allowlist_function("librist_.*")
         .allowlist_type("rist_.*")
         .allowlist_var("RIST_.*")
         .generate_comments(true)
         .derive_debug(true)
         .derive_default(true);
 
-    for path in &library.include_paths {
+    for path in include_paths {
         builder = builder.clang_arg(format!("-I{}", path.display()));
     }
 
     let bindings = builder.generate().expect("Unable to generate bindings");
 
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
     bindings
-        .write_to_file(out_path.join("bindings.rs"))
+        .write_to_file(out)
         .expect("Couldn't write bindings!");
 }
@@ -1,6 +1,8 @@
 //! Raw FFI bindings to librist.
 //!
-//! This crate provides low-level bindings generated by bindgen.
+//! This crate provides low-level bindings generated by bindgen from the
+//! librist headers at build time, so every build, docs included, needs
+//! librist's headers and libclang.
 //! For a safe API, use the `rist` crate instead.
 
 #![allow(non_upper_case_globals)]
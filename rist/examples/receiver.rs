@@ -29,7 +29,7 @@ async fn main() -> rist::Result<()> {
                 total_bytes += data.payload().len() as u64;
                 total_packets += 1;
 
-                if total_packets % 100 == 0 {
+                if total_packets.is_multiple_of(100) {
                     println!(
                         "Received {} packets, {} bytes, flow_id={}",
                         total_packets,
@@ -28,7 +28,7 @@ fn main() -> rist::Result<()> {
             Ok(n) => {
                 total_sent += n as u64;
 
-                if total_sent % (1316 * 100) == 0 {
+                if total_sent.is_multiple_of(1316 * 100) {
                     println!("Sent {} bytes", total_sent);
                 }
             }
@@ -0,0 +1,454 @@
+//! Threshold alarms on connection stats.
+//!
+//! An [`Alarms`] set watches the stats a receiver or sender reports and
+//! raises an [`AlarmEvent`] when a [`Threshold`] is crossed and again when it
+//! clears, so operators get alerts instead of raw counters. A debounce time
+//! keeps a link hovering around a threshold from flapping.
+//!
+//! Loss thresholds count losses from the change in cumulative counters, so
+//! they need the stats in the default [`StatsMode::Cumulative`](crate::StatsMode).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::alarms::{AlarmState, Alarms, Threshold};
+//! use rist::{Profile, Receiver};
+//! use std::time::Duration;
+//!
+//! # fn example() -> rist::Result<()> {
+//! let mut alarms = Alarms::new()
+//!     .threshold(Threshold::QualityBelow(98.0))
+//!     .threshold(Threshold::RttAbove(Duration::from_millis(200)))
+//!     .threshold(Threshold::LostPerMinuteAbove(100))
+//!     .threshold(Threshold::LossRateAbove {
+//!         percent: 0.5,
+//!         window: Duration::from_secs(10),
+//!     })
+//!     .debounce(Duration::from_secs(5));
+//!
+//! let receiver = Receiver::new(Profile::Main)?;
+//! receiver.on_stats(move |stats| {
+//!     for event in alarms.observe(stats) {
+//!         match event.state {
+//!             AlarmState::Tripped => eprintln!("ALARM {:?}: {}", event.threshold, event.value),
+//!             AlarmState::Cleared => eprintln!("cleared {:?}", event.threshold),
+//!         }
+//!     }
+//! });
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{ReceiverStats, SenderStats};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// A condition that trips an alarm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// Quality (0-100) drops below the value.
+    QualityBelow(f64),
+    /// Round-trip time rises above the value.
+    RttAbove(Duration),
+    /// More than this many packets were lost for good in the last minute.
+    /// Only receivers report losses.
+    LostPerMinuteAbove(u64),
+    /// The share of packets lost for good, `lost / (received + lost)`,
+    /// rises above `percent` over the last `window`. Unlike quality this
+    /// looks past the latest report, so a short burst of loss stays visible
+    /// for the whole window. Only receivers report losses.
+    LossRateAbove {
+        /// Highest acceptable loss rate in percent.
+        percent: f64,
+        /// How far back losses are counted.
+        window: Duration,
+    },
+}
+
+/// Whether an alarm started or stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmState {
+    /// The threshold has been crossed for the debounce time.
+    Tripped,
+    /// The threshold has been clear again for the debounce time.
+    Cleared,
+}
+
+/// An alarm tripping or clearing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlarmEvent {
+    /// The threshold concerned.
+    pub threshold: Threshold,
+    /// Whether it tripped or cleared.
+    pub state: AlarmState,
+    /// The flow (receivers) or peer (senders) the stats describe.
+    pub id: u32,
+    /// The value that was compared: quality in percent, RTT in ms, lost
+    /// packets in the last minute or loss rate in percent.
+    pub value: f64,
+}
+
+/// Stats [`Alarms`] can watch.
+pub trait AlarmStats {
+    /// Flow or peer ID, alarms are kept per ID.
+    fn alarm_id(&self) -> u32;
+    /// Quality percentage (0-100).
+    fn alarm_quality(&self) -> f64;
+    /// Round-trip time in ms.
+    fn alarm_rtt_ms(&self) -> u32;
+    /// Total packets lost for good, if reported.
+    fn alarm_lost(&self) -> Option<u64>;
+    /// Total packets received, if losses are reported.
+    fn alarm_received(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl AlarmStats for ReceiverStats {
+    fn alarm_id(&self) -> u32 {
+        self.flow_id
+    }
+
+    fn alarm_quality(&self) -> f64 {
+        self.quality
+    }
+
+    fn alarm_rtt_ms(&self) -> u32 {
+        self.rtt
+    }
+
+    fn alarm_lost(&self) -> Option<u64> {
+        Some(u64::from(self.lost))
+    }
+
+    fn alarm_received(&self) -> Option<u64> {
+        Some(self.received)
+    }
+}
+
+impl AlarmStats for SenderStats {
+    fn alarm_id(&self) -> u32 {
+        self.peer_id
+    }
+
+    fn alarm_quality(&self) -> f64 {
+        self.quality
+    }
+
+    fn alarm_rtt_ms(&self) -> u32 {
+        self.rtt
+    }
+
+    fn alarm_lost(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Debounced state of one threshold for one ID.
+#[derive(Debug, Default)]
+struct AlarmTracker {
+    tripped: bool,
+    // since when the condition has disagreed with `tripped`
+    pending_since: Option<Instant>,
+}
+
+impl AlarmTracker {
+    fn update(&mut self, crossed: bool, now: Instant, debounce: Duration) -> Option<AlarmState> {
+        if crossed == self.tripped {
+            self.pending_since = None;
+            return None;
+        }
+        let since = *self.pending_since.get_or_insert(now);
+        if now.saturating_duration_since(since) < debounce {
+            return None;
+        }
+        self.tripped = crossed;
+        self.pending_since = None;
+        Some(if crossed {
+            AlarmState::Tripped
+        } else {
+            AlarmState::Cleared
+        })
+    }
+}
+
+/// Packets lost and received in one report's interval.
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    lost: u64,
+    received: u64,
+}
+
+/// Losses reported for one ID during the longest window watched.
+#[derive(Debug, Default)]
+struct LossWindow {
+    last_total: Option<Counts>,
+    recent: VecDeque<(Instant, Counts)>,
+}
+
+impl LossWindow {
+    fn record(&mut self, total: Counts, now: Instant, keep: Duration) {
+        // a total that went down was reset, all of it is new
+        let increase = |total: u64, last: u64| if total >= last { total - last } else { total };
+        let new = match self.last_total {
+            Some(last) => Counts {
+                lost: increase(total.lost, last.lost),
+                received: increase(total.received, last.received),
+            },
+            None => Counts::default(),
+        };
+        self.last_total = Some(total);
+        self.recent.push_back((now, new));
+        while let Some(&(at, _)) = self.recent.front() {
+            if now.saturating_duration_since(at) < keep {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+
+    /// Packets lost and received in reports from the last `window`.
+    fn within(&self, window: Duration, now: Instant) -> Counts {
+        self.recent
+            .iter()
+            .rev()
+            .take_while(|(at, _)| now.saturating_duration_since(*at) < window)
+            .fold(Counts::default(), |sum, (_, counts)| Counts {
+                lost: sum.lost + counts.lost,
+                received: sum.received + counts.received,
+            })
+    }
+}
+
+/// A set of thresholds watched over a stream of stats reports.
+///
+/// Feed it every report with [`observe`](Self::observe), e.g. from
+/// `on_stats`. Each threshold is tracked separately per flow or peer.
+#[derive(Debug, Default)]
+pub struct Alarms {
+    thresholds: Vec<Threshold>,
+    debounce: Duration,
+    trackers: HashMap<(usize, u32), AlarmTracker>,
+    losses: HashMap<u32, LossWindow>,
+}
+
+impl Alarms {
+    /// Create an empty set that trips as soon as a threshold is crossed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watch `threshold` as well.
+    pub fn threshold(mut self, threshold: Threshold) -> Self {
+        self.thresholds.push(threshold);
+        self
+    }
+
+    /// Only trip (or clear) an alarm once its threshold has been crossed
+    /// (or clear) for `debounce`.
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// IDs and thresholds currently tripped.
+    pub fn active(&self) -> Vec<(u32, Threshold)> {
+        let mut active: Vec<(u32, usize)> = self
+            .trackers
+            .iter()
+            .filter(|(_, tracker)| tracker.tripped)
+            .map(|((index, id), _)| (*id, *index))
+            .collect();
+        active.sort_unstable();
+        active
+            .into_iter()
+            .map(|(id, index)| (id, self.thresholds[index]))
+            .collect()
+    }
+
+    /// Check a stats report, returning the alarms that tripped or cleared.
+    pub fn observe(&mut self, stats: &impl AlarmStats) -> Vec<AlarmEvent> {
+        self.observe_at(stats, Instant::now())
+    }
+
+    fn observe_at(&mut self, stats: &impl AlarmStats, now: Instant) -> Vec<AlarmEvent> {
+        let id = stats.alarm_id();
+        let keep = self
+            .thresholds
+            .iter()
+            .filter_map(|threshold| match threshold {
+                Threshold::LossRateAbove { window, .. } => Some(*window),
+                _ => None,
+            })
+            .fold(MINUTE, Duration::max);
+        let losses = stats.alarm_lost().map(|lost| {
+            let total = Counts {
+                lost,
+                received: stats.alarm_received().unwrap_or(0),
+            };
+            let losses = self.losses.entry(id).or_default();
+            losses.record(total, now, keep);
+            &*losses
+        });
+
+        let mut events = Vec::new();
+        for (index, threshold) in self.thresholds.iter().enumerate() {
+            let (crossed, value) = match *threshold {
+                Threshold::QualityBelow(min) => {
+                    let quality = stats.alarm_quality();
+                    (quality < min, quality)
+                }
+                Threshold::RttAbove(max) => {
+                    let rtt = stats.alarm_rtt_ms();
+                    (u128::from(rtt) > max.as_millis(), f64::from(rtt))
+                }
+                Threshold::LostPerMinuteAbove(max) => match losses {
+                    Some(losses) => {
+                        let lost = losses.within(MINUTE, now).lost;
+                        (lost > max, lost as f64)
+                    }
+                    None => continue,
+                },
+                Threshold::LossRateAbove { percent, window } => match losses {
+                    Some(losses) => {
+                        let counts = losses.within(window, now);
+                        let total = counts.lost + counts.received;
+                        let rate = if total == 0 {
+                            0.0
+                        } else {
+                            counts.lost as f64 * 100.0 / total as f64
+                        };
+                        (rate > percent, rate)
+                    }
+                    None => continue,
+                },
+            };
+            let tracker = self.trackers.entry((index, id)).or_default();
+            if let Some(state) = tracker.update(crossed, now, self.debounce) {
+                events.push(AlarmEvent {
+                    threshold: *threshold,
+                    state,
+                    id,
+                    value,
+                });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(quality: f64, rtt: u32, lost: u32) -> ReceiverStats {
+        ReceiverStats {
+            flow_id: 1,
+            quality,
+            rtt,
+            lost,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_trips_and_clears_with_debounce() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut alarms = Alarms::new()
+            .threshold(Threshold::QualityBelow(98.0))
+            .debounce(Duration::from_secs(2));
+
+        assert!(alarms.observe_at(&report(99.0, 10, 0), at(0)).is_empty());
+        // a single bad report doesn't trip
+        assert!(alarms.observe_at(&report(90.0, 10, 0), at(1)).is_empty());
+        assert!(alarms.observe_at(&report(99.0, 10, 0), at(2)).is_empty());
+
+        assert!(alarms.observe_at(&report(90.0, 10, 0), at(3)).is_empty());
+        let events = alarms.observe_at(&report(91.0, 10, 0), at(5));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].state, AlarmState::Tripped);
+        assert_eq!(events[0].value, 91.0);
+        assert_eq!(alarms.active(), vec![(1, Threshold::QualityBelow(98.0))]);
+
+        assert!(alarms.observe_at(&report(99.0, 10, 0), at(6)).is_empty());
+        let events = alarms.observe_at(&report(99.0, 10, 0), at(8));
+        assert_eq!(events[0].state, AlarmState::Cleared);
+        assert!(alarms.active().is_empty());
+    }
+
+    #[test]
+    fn test_rtt_and_loss_thresholds() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut alarms = Alarms::new()
+            .threshold(Threshold::RttAbove(Duration::from_millis(200)))
+            .threshold(Threshold::LostPerMinuteAbove(10));
+
+        let events = alarms.observe_at(&report(100.0, 250, 100), at(0));
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].threshold,
+            Threshold::RttAbove(Duration::from_millis(200))
+        );
+
+        // 100 lost before the first report don't count, 15 since then do
+        let events = alarms.observe_at(&report(100.0, 250, 115), at(10));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].threshold, Threshold::LostPerMinuteAbove(10));
+        assert_eq!(events[0].value, 15.0);
+
+        // a minute later the losses have aged out
+        let events = alarms.observe_at(&report(100.0, 100, 115), at(75));
+        assert_eq!(events.len(), 2);
+        assert!(events
+            .iter()
+            .all(|event| event.state == AlarmState::Cleared));
+    }
+
+    #[test]
+    fn test_loss_rate_over_window() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let threshold = Threshold::LossRateAbove {
+            percent: 1.0,
+            window: Duration::from_secs(10),
+        };
+        let mut alarms = Alarms::new().threshold(threshold);
+        let report = |received: u64, lost: u32| ReceiverStats {
+            flow_id: 1,
+            received,
+            lost,
+            quality: 100.0,
+            ..Default::default()
+        };
+
+        assert!(alarms.observe_at(&report(0, 0), at(0)).is_empty());
+        // 5 lost of 1000 is 0.5%
+        assert!(alarms.observe_at(&report(995, 5), at(1)).is_empty());
+        // another 10 lost of 500 makes it 1%, still not above
+        assert!(alarms.observe_at(&report(1485, 15), at(2)).is_empty());
+        let events = alarms.observe_at(&report(1975, 25), at(3));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].threshold, threshold);
+        assert_eq!(events[0].value, 25.0 * 100.0 / 2000.0);
+
+        // the burst stays in the window until it has aged out
+        assert!(alarms.observe_at(&report(2075, 25), at(5)).is_empty());
+        let events = alarms.observe_at(&report(3075, 25), at(14));
+        assert_eq!(events[0].state, AlarmState::Cleared);
+        assert_eq!(events[0].value, 0.0);
+    }
+
+    #[test]
+    fn test_senders_never_trip_loss() {
+        let mut alarms = Alarms::new().threshold(Threshold::LostPerMinuteAbove(0));
+        let stats = SenderStats {
+            retransmitted: 1000,
+            ..Default::default()
+        };
+        assert!(alarms.observe(&stats).is_empty());
+    }
+}
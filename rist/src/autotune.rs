@@ -0,0 +1,386 @@
+//! Recovery buffer auto-tuning for links with varying RTT.
+//!
+//! A fixed recovery buffer has to be sized for the worst RTT a link will
+//! ever see, which wastes latency most of the time on links such as LTE
+//! backup paths whose RTT swings between 20 ms and 300 ms. A
+//! [`RecoveryTuner`] watches the RTT and quality a receiver reports and
+//! proposes a recovery buffer and retry limit that fit the current
+//! conditions, within configured bounds.
+//!
+//! librist fixes a peer's settings when it is created, so applying new
+//! settings re-creates the peer (see [`Receiver::reconnect_peer`]), which
+//! resets its session; a listening peer drops the senders connected to it.
+//! So the tuner doesn't chase the loss its own changes cause, it starts
+//! from the settings the peer was created with, ignores reports for
+//! [`TuningBounds::hold`] after the tuner was created or the last change,
+//! and only proposes a change when it is significant. Fewer retries also
+//! need the quality to clear the threshold by a margin.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::autotune::{RecoveryTuner, TuningBounds};
+//! use rist::{Profile, Receiver, ReceiverOptions};
+//! use std::time::Duration;
+//!
+//! # fn example() -> rist::Result<()> {
+//! let options = ReceiverOptions::new();
+//! let mut receiver = Receiver::new(Profile::Main)?;
+//! let mut peer = receiver.add_peer_with_options("rist://@:5000", &options)?;
+//! receiver.start()?;
+//!
+//! let mut tuner = RecoveryTuner::new(TuningBounds::default(), &options);
+//! loop {
+//!     while let Some(block) = receiver.read(Duration::from_millis(100))? {
+//!         // ...
+//!     }
+//!     peer = tuner.tune(&mut receiver, peer, &options)?;
+//! }
+//! # }
+//! ```
+
+use crate::{PeerId, Receiver, ReceiverOptions, ReceiverStats, Result, StatsSnapshot};
+use std::time::{Duration, Instant};
+
+/// Weight of a new RTT sample in the smoothed RTT.
+const RTT_SMOOTHING: f64 = 0.25;
+
+/// Relative change in buffer length below which no update is proposed.
+const MIN_BUFFER_CHANGE: f64 = 0.2;
+
+/// Extra buffer on top of the retransmission rounds, to absorb jitter.
+const JITTER_MARGIN: f64 = 1.25;
+
+/// Percentage points the quality has to clear a threshold by before the
+/// retry limit is lowered.
+const QUALITY_HYSTERESIS: f64 = 0.5;
+
+/// Limits for the settings a [`RecoveryTuner`] proposes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningBounds {
+    /// Smallest recovery buffer.
+    pub min_buffer: Duration,
+    /// Largest recovery buffer.
+    pub max_buffer: Duration,
+    /// Retry limit on a clean link.
+    pub min_retries: u32,
+    /// Retry limit on a lossy link.
+    pub max_retries: u32,
+    /// Minimum time between two changes, and before the first. Reports
+    /// arriving in this time are ignored, as re-creating the peer disturbs
+    /// the link.
+    pub hold: Duration,
+}
+
+impl Default for TuningBounds {
+    fn default() -> Self {
+        Self {
+            min_buffer: Duration::from_millis(100),
+            max_buffer: Duration::from_secs(2),
+            min_retries: 3,
+            max_retries: 10,
+            hold: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Recovery settings proposed by a [`RecoveryTuner`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tuning {
+    /// Recovery buffer length.
+    pub buffer: Duration,
+    /// Retransmission requests per lost packet.
+    pub max_retries: u32,
+}
+
+impl Tuning {
+    /// The settings a peer created with `options` runs with, taking
+    /// librist's defaults for those `options` leaves unset.
+    pub fn from_options(options: &ReceiverOptions) -> Self {
+        let mut config = rist_sys::rist_peer_config::default();
+        // on failure the defaults stay zeroed, and only the options count
+        unsafe { rist_sys::rist_peer_config_defaults_set(&mut config) };
+        options.apply_to_peer_config(&mut config);
+        Self {
+            buffer: Duration::from_millis(u64::from(config.recovery_length_max)),
+            max_retries: config.max_retries,
+        }
+    }
+
+    /// Apply these settings on top of `options`.
+    pub fn apply_to(&self, options: &ReceiverOptions) -> ReceiverOptions {
+        options
+            .clone()
+            .recovery_length_min(self.buffer)
+            .recovery_length_max(self.buffer)
+            .max_retries(self.max_retries)
+    }
+}
+
+/// Proposes recovery settings from observed RTT and quality.
+#[derive(Debug, Clone)]
+pub struct RecoveryTuner {
+    bounds: TuningBounds,
+    rtt: Option<f64>,
+    current: Tuning,
+    last_change: Instant,
+    // when the last report fed by `tune` arrived
+    last_report: Option<Instant>,
+}
+
+impl RecoveryTuner {
+    /// Create a tuner proposing settings within `bounds` for a peer that
+    /// was just created with `options`.
+    pub fn new(bounds: TuningBounds, options: &ReceiverOptions) -> Self {
+        Self {
+            bounds,
+            rtt: None,
+            current: Tuning::from_options(options),
+            last_change: Instant::now(),
+            last_report: None,
+        }
+    }
+
+    /// The settings the peer runs with: those it was created with, or the
+    /// last ones proposed.
+    pub fn current(&self) -> Tuning {
+        self.current
+    }
+
+    /// Feed a stats report, returning new settings if they should be applied.
+    ///
+    /// Feed each report once, e.g. from a stats callback; the RTT is
+    /// smoothed over the reports fed.
+    pub fn observe(&mut self, stats: &ReceiverStats) -> Option<Tuning> {
+        self.observe_at(stats, Instant::now())
+    }
+
+    /// Feed the receiver's latest stats and apply any proposed change to
+    /// `peer`, using `options` for everything else.
+    ///
+    /// A report already fed by an earlier call is skipped, so this can be
+    /// called more often than the stats interval.
+    ///
+    /// Returns the peer's ID, which changes when the peer is re-created. If
+    /// re-creating it fails the peer keeps its settings, and the tuner
+    /// waits [`TuningBounds::hold`] before trying again.
+    pub fn tune(
+        &mut self,
+        receiver: &mut Receiver,
+        peer: PeerId,
+        options: &ReceiverOptions,
+    ) -> Result<PeerId> {
+        let previous = self.current;
+        let Some(tuning) = receiver
+            .stats_snapshot()
+            .and_then(|snapshot| self.observe_snapshot(&snapshot))
+        else {
+            return Ok(peer);
+        };
+        receiver
+            .reconnect_peer(peer, &tuning.apply_to(options))
+            .inspect_err(|_| self.current = previous)
+    }
+
+    /// Feed a report unless it was fed before.
+    fn observe_snapshot(&mut self, snapshot: &StatsSnapshot<ReceiverStats>) -> Option<Tuning> {
+        if self.last_report == Some(snapshot.captured) {
+            return None;
+        }
+        self.last_report = Some(snapshot.captured);
+        self.observe_at(&snapshot.stats, snapshot.captured)
+    }
+
+    fn observe_at(&mut self, stats: &ReceiverStats, now: Instant) -> Option<Tuning> {
+        // no RTT measured yet, or the link is still settling
+        if stats.rtt == 0 || now.saturating_duration_since(self.last_change) < self.bounds.hold {
+            return None;
+        }
+        let sample = f64::from(stats.rtt);
+        let rtt = match self.rtt {
+            Some(rtt) => rtt + RTT_SMOOTHING * (sample - rtt),
+            None => sample,
+        };
+        self.rtt = Some(rtt);
+
+        let target = self.target(rtt, stats.quality);
+        if !significant(self.current, target) {
+            return None;
+        }
+
+        self.current = target;
+        self.last_change = now;
+        Some(target)
+    }
+
+    /// Settings for a smoothed RTT in milliseconds and quality in percent.
+    fn target(&self, rtt: f64, quality: f64) -> Tuning {
+        let bounds = &self.bounds;
+        let tiers = [
+            (99.0, bounds.min_retries),
+            (95.0, (bounds.min_retries + bounds.max_retries) / 2),
+        ];
+        let max_retries = tiers
+            .into_iter()
+            .find(|&(threshold, retries)| {
+                let margin = if retries < self.current.max_retries {
+                    QUALITY_HYSTERESIS
+                } else {
+                    0.0
+                };
+                quality >= threshold + margin
+            })
+            .map_or(bounds.max_retries, |(_, retries)| retries);
+
+        // every retry costs a round trip, plus the original transmission
+        let buffer_ms = rtt * f64::from(max_retries + 1) * JITTER_MARGIN;
+        let buffer = Duration::from_secs_f64(buffer_ms / 1000.0)
+            .clamp(bounds.min_buffer, bounds.max_buffer.max(bounds.min_buffer));
+
+        Tuning {
+            buffer,
+            max_retries,
+        }
+    }
+}
+
+/// Whether moving from `current` to `target` is worth re-creating the peer.
+fn significant(current: Tuning, target: Tuning) -> bool {
+    let old = current.buffer.as_secs_f64();
+    let new = target.buffer.as_secs_f64();
+    current.max_retries != target.max_retries || (new - old).abs() > old * MIN_BUFFER_CHANGE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tuner for a peer created with a 1 s buffer and 6 retries.
+    fn configured_tuner() -> RecoveryTuner {
+        let options = ReceiverOptions::new()
+            .recovery_length_max(Duration::from_secs(1))
+            .max_retries(6);
+        RecoveryTuner::new(TuningBounds::default(), &options)
+    }
+
+    fn stats(rtt: u32, quality: f64) -> ReceiverStats {
+        ReceiverStats {
+            rtt,
+            quality,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_buffer_follows_rtt_within_bounds() {
+        let mut tuner = configured_tuner();
+        let now = Instant::now() + Duration::from_secs(10);
+
+        assert_eq!(tuner.observe_at(&stats(0, 100.0), now), None);
+
+        // 20 ms RTT, clean link: 3 retries -> 20 * 4 * 1.25 = 100 ms
+        let tuning = tuner.observe_at(&stats(20, 100.0), now).unwrap();
+        assert_eq!(tuning.buffer, Duration::from_millis(100));
+        assert_eq!(tuning.max_retries, 3);
+
+        // 300 ms RTT on a lossy link hits the upper bound
+        let mut tuner = configured_tuner();
+        let now = Instant::now() + Duration::from_secs(10);
+        let tuning = tuner.observe_at(&stats(300, 80.0), now).unwrap();
+        assert_eq!(tuning.buffer, Duration::from_secs(2));
+        assert_eq!(tuning.max_retries, 10);
+    }
+
+    #[test]
+    fn test_holds_off_between_changes() {
+        let mut tuner = configured_tuner();
+        let start = Instant::now();
+
+        // no change right after the peer was created
+        assert_eq!(tuner.observe_at(&stats(20, 100.0), start), None);
+        let start = start + Duration::from_secs(10);
+        tuner.observe_at(&stats(20, 100.0), start).unwrap();
+
+        // reports inside the hold time are ignored
+        assert_eq!(
+            tuner.observe_at(&stats(200, 100.0), start + Duration::from_secs(1)),
+            None
+        );
+        let later = start + Duration::from_secs(11);
+        let tuning = tuner.observe_at(&stats(200, 100.0), later).unwrap();
+        assert!(tuning.buffer > Duration::from_millis(100));
+        assert_eq!(tuner.current(), tuning);
+    }
+
+    #[test]
+    fn test_retries_step_down_with_margin() {
+        let mut tuner = configured_tuner();
+        let start = Instant::now() + Duration::from_secs(10);
+        assert_eq!(
+            tuner
+                .observe_at(&stats(100, 80.0), start)
+                .unwrap()
+                .max_retries,
+            10
+        );
+
+        // just over the 99% threshold isn't enough to drop to 3 retries
+        let later = start + Duration::from_secs(10);
+        assert_eq!(
+            tuner
+                .observe_at(&stats(100, 99.2), later)
+                .unwrap()
+                .max_retries,
+            6
+        );
+        let later = later + Duration::from_secs(10);
+        assert_eq!(
+            tuner
+                .observe_at(&stats(100, 99.6), later)
+                .unwrap()
+                .max_retries,
+            3
+        );
+    }
+
+    #[test]
+    fn test_feeds_each_report_once() {
+        let mut tuner = configured_tuner();
+        let captured = Instant::now() + Duration::from_secs(10);
+        let snapshot = |rtt| StatsSnapshot {
+            stats: stats(rtt, 100.0),
+            window: Duration::from_secs(1),
+            captured,
+            captured_at: std::time::SystemTime::now(),
+            since_previous: None,
+        };
+
+        assert!(tuner.observe_snapshot(&snapshot(20)).is_some());
+        // the same report again, whatever it says, is ignored
+        assert_eq!(tuner.observe_snapshot(&snapshot(300)), None);
+        assert_eq!(tuner.rtt, Some(20.0));
+    }
+
+    #[test]
+    fn test_ignores_small_changes() {
+        let mut tuner = configured_tuner();
+        let start = Instant::now() + Duration::from_secs(10);
+        tuner.observe_at(&stats(100, 100.0), start).unwrap();
+
+        let later = start + Duration::from_secs(20);
+        assert_eq!(tuner.observe_at(&stats(110, 100.0), later), None);
+    }
+
+    #[test]
+    fn test_starts_from_configured_settings() {
+        // 100 ms RTT on a clean link: 3 retries -> 100 * 4 * 1.25 = 500 ms
+        let options = ReceiverOptions::new()
+            .recovery_length_max(Duration::from_millis(500))
+            .max_retries(3);
+        let mut tuner = RecoveryTuner::new(TuningBounds::default(), &options);
+        assert_eq!(tuner.current(), Tuning::from_options(&options));
+
+        let later = Instant::now() + Duration::from_secs(10);
+        assert_eq!(tuner.observe_at(&stats(100, 100.0), later), None);
+    }
+}
@@ -0,0 +1,450 @@
+//! Measure RIST throughput, latency and loss.
+//!
+//! Runs a sender/receiver pair over loopback, or one half of a pair over a
+//! real link, and prints a JSON report on stdout. In loopback mode the
+//! traffic can be dropped, reordered and delayed on the way, to measure
+//! recovery under those conditions.
+//!
+//! Run with: cargo run --release --features cli --bin rist-bench -- --ramp 10000000:10000000:100000000
+
+use clap::{Parser, ValueEnum};
+use rist::testing::{Impairment, ImpairmentProxy, ImpairmentStats};
+use rist::tokio::{AsyncReceiver, AsyncSender};
+use rist::{LogLevel, Profile, ReceiverOptions, SenderOptions};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+/// Marker at the start of every benchmark payload.
+const MAGIC: &[u8; 4] = b"RBCH";
+/// Magic, sequence number and send timestamp.
+const HEADER_SIZE: usize = 4 + 8 + 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    /// Sender and receiver in this process over localhost.
+    Loopback,
+    /// Only send to `--url`.
+    Send,
+    /// Only receive on `--url`.
+    Recv,
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "rist-bench",
+    version,
+    about = "RIST throughput and latency benchmark"
+)]
+struct Args {
+    /// Which half of the link to run.
+    #[arg(long, value_enum, default_value = "loopback")]
+    mode: Mode,
+
+    /// RIST URL for send/recv modes. Loopback picks a free local port.
+    #[arg(long)]
+    url: Option<String>,
+
+    /// RIST profile (simple, main, advanced).
+    #[arg(short, long, default_value = "main")]
+    profile: Profile,
+
+    /// Fixed target bitrate in bits per second.
+    #[arg(long, default_value_t = 10_000_000)]
+    bitrate: u64,
+
+    /// Ramp the bitrate as `start:step:max` and report the highest step
+    /// that stays under `--loss-threshold`.
+    #[arg(long)]
+    ramp: Option<String>,
+
+    /// Seconds per bitrate step.
+    #[arg(long, default_value_t = 5)]
+    duration: u64,
+
+    /// Payload size in bytes.
+    #[arg(long, default_value_t = 1316)]
+    packet_size: usize,
+
+    /// Recovery buffer size in milliseconds.
+    #[arg(long, default_value_t = 1000)]
+    buffer: u64,
+
+    /// Loss percentage above which a ramp step is considered unsustainable.
+    #[arg(long, default_value_t = 0.1)]
+    loss_threshold: f64,
+
+    /// Percentage of packets to drop on the way to the receiver (loopback
+    /// only).
+    #[arg(long, default_value_t = 0.0)]
+    drop_percent: f64,
+
+    /// Percentage of packets to hold back so later ones overtake them
+    /// (loopback only).
+    #[arg(long, default_value_t = 0.0)]
+    reorder_percent: f64,
+
+    /// How long reordered packets are held back, in milliseconds.
+    #[arg(long, default_value_t = 10)]
+    reorder_delay: u64,
+
+    /// Maximum random delay added to each packet, in milliseconds
+    /// (loopback only).
+    #[arg(long, default_value_t = 0)]
+    jitter: u64,
+
+    /// Also impair the return path, which carries retransmission requests.
+    #[arg(long)]
+    impair_return_path: bool,
+
+    /// Seed for the impairment decisions, so runs can be repeated.
+    #[arg(long, default_value_t = 0x5eed)]
+    seed: u64,
+
+    /// librist log level.
+    #[arg(long, default_value = "warn")]
+    log_level: LogLevel,
+}
+
+/// Result of one bitrate step.
+#[derive(Debug, Default)]
+struct StepReport {
+    target_bps: u64,
+    achieved_bps: u64,
+    sent: u64,
+    received: u64,
+    duplicates: u64,
+    /// Sequence numbers that never reached the application.
+    lost: u64,
+    /// Packets librist gave up recovering, from the receiver's stats.
+    rist_lost: u64,
+    recovered: u64,
+    latency_us: Vec<u64>,
+    impaired: Option<ImpairmentStats>,
+}
+
+impl StepReport {
+    fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        self.sent.saturating_sub(self.received) as f64 * 100.0 / self.sent as f64
+    }
+
+    fn percentile(&self, p: f64) -> u64 {
+        if self.latency_us.is_empty() {
+            return 0;
+        }
+        let idx = ((self.latency_us.len() - 1) as f64 * p).round() as usize;
+        self.latency_us[idx]
+    }
+
+    fn to_json(&self) -> String {
+        let impaired = match self.impaired {
+            Some(stats) => format!(
+                ",\"impaired\":{{\"forwarded\":{},\"dropped\":{},\"reordered\":{},\"returned\":{}}}",
+                stats.forwarded, stats.dropped, stats.reordered, stats.returned
+            ),
+            None => String::new(),
+        };
+        format!(
+            "{{\"target_bps\":{},\"achieved_bps\":{},\"sent\":{},\"received\":{},\
+             \"duplicates\":{},\"lost\":{},\"loss_percent\":{:.4},\"rist_lost\":{},\"rist_recovered\":{},\
+             \"latency_us\":{{\"p50\":{},\"p90\":{},\"p99\":{},\"max\":{}}}{impaired}}}",
+            self.target_bps,
+            self.achieved_bps,
+            self.sent,
+            self.received,
+            self.duplicates,
+            self.lost,
+            self.loss_percent(),
+            self.rist_lost,
+            self.recovered,
+            self.percentile(0.50),
+            self.percentile(0.90),
+            self.percentile(0.99),
+            self.latency_us.last().copied().unwrap_or(0),
+        )
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rist-bench: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    rist::set_logging(args.log_level)?;
+    if args.packet_size < HEADER_SIZE {
+        return Err(format!("--packet-size must be at least {HEADER_SIZE}").into());
+    }
+    if let Some(impairment) = impairment(&args) {
+        if args.mode != Mode::Loopback {
+            return Err("impairments only apply in loopback mode".into());
+        }
+        if args.profile == Profile::Simple {
+            return Err("impairments need a single-port profile (main or advanced)".into());
+        }
+        for (name, percent) in [
+            ("--drop-percent", impairment.drop_percent),
+            ("--reorder-percent", impairment.reorder_percent),
+        ] {
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!("{name} must be between 0 and 100").into());
+            }
+        }
+    }
+
+    let steps = match &args.ramp {
+        Some(ramp) => parse_ramp(ramp)?,
+        None => vec![args.bitrate],
+    };
+
+    let mut reports = Vec::new();
+    for bitrate in steps {
+        let report = match args.mode {
+            Mode::Loopback => run_loopback(&args, bitrate).await?,
+            Mode::Send => run_send(&args, bitrate).await?,
+            Mode::Recv => run_recv(&args).await?,
+        };
+        eprintln!(
+            "rist-bench: {} bps -> {} bps, loss {:.3}%, p99 {}us",
+            report.target_bps,
+            report.achieved_bps,
+            report.loss_percent(),
+            report.percentile(0.99)
+        );
+        let unsustainable = report.loss_percent() > args.loss_threshold;
+        reports.push(report);
+        if args.ramp.is_some() && unsustainable {
+            break;
+        }
+    }
+
+    let max_sustainable = reports
+        .iter()
+        .filter(|r| r.loss_percent() <= args.loss_threshold)
+        .map(|r| r.target_bps)
+        .max()
+        .unwrap_or(0);
+    let steps: Vec<String> = reports.iter().map(StepReport::to_json).collect();
+    let impairment = match impairment(&args) {
+        Some(impairment) => format!(
+            ",\"impairment\":{{\"drop_percent\":{},\"reorder_percent\":{},\"reorder_delay_ms\":{},\
+             \"jitter_ms\":{},\"impair_return_path\":{},\"seed\":{}}}",
+            impairment.drop_percent,
+            impairment.reorder_percent,
+            args.reorder_delay,
+            args.jitter,
+            impairment.impair_return_path,
+            impairment.seed
+        ),
+        None => String::new(),
+    };
+    println!(
+        "{{\"mode\":\"{:?}\",\"packet_size\":{},\"buffer_ms\":{},\"max_sustainable_bps\":{}{impairment},\"steps\":[{}]}}",
+        args.mode,
+        args.packet_size,
+        args.buffer,
+        max_sustainable,
+        steps.join(",")
+    );
+    Ok(())
+}
+
+fn parse_ramp(ramp: &str) -> Result<Vec<u64>, Box<dyn std::error::Error>> {
+    let parts: Vec<u64> = ramp.split(':').map(str::parse).collect::<Result<_, _>>()?;
+    let [start, step, max] = parts[..] else {
+        return Err("--ramp must be start:step:max".into());
+    };
+    if step == 0 || start > max {
+        return Err("--ramp needs a non-zero step and start <= max".into());
+    }
+    Ok((start..=max).step_by(step as usize).collect())
+}
+
+/// The impairment asked for on the command line, `None` for a clean link.
+fn impairment(args: &Args) -> Option<Impairment> {
+    let impairment = Impairment::new()
+        .drop_percent(args.drop_percent)
+        .reorder_percent(args.reorder_percent)
+        .reorder_delay(Duration::from_millis(args.reorder_delay))
+        .jitter(Duration::from_millis(args.jitter))
+        .impair_return_path(args.impair_return_path)
+        .seed(args.seed);
+    let clean = impairment.drop_percent == 0.0
+        && impairment.reorder_percent == 0.0
+        && impairment.jitter.is_zero();
+    (!clean).then_some(impairment)
+}
+
+fn receiver_options(args: &Args) -> ReceiverOptions {
+    let buffer = Duration::from_millis(args.buffer);
+    ReceiverOptions::new()
+        .recovery_length_min(buffer)
+        .recovery_length_max(buffer)
+}
+
+fn sender_options(args: &Args) -> SenderOptions {
+    let buffer = Duration::from_millis(args.buffer);
+    SenderOptions::new()
+        .recovery_length_min(buffer)
+        .recovery_length_max(buffer)
+}
+
+fn require_url(args: &Args) -> Result<&str, Box<dyn std::error::Error>> {
+    args.url
+        .as_deref()
+        .ok_or_else(|| "--url is required for send and recv modes".into())
+}
+
+async fn run_loopback(args: &Args, bitrate: u64) -> Result<StepReport, Box<dyn std::error::Error>> {
+    let port = std::net::UdpSocket::bind("127.0.0.1:0")?
+        .local_addr()?
+        .port();
+    let receiver = AsyncReceiver::bind_with_options(
+        args.profile,
+        &format!("rist://@:{port}"),
+        receiver_options(args),
+    )?;
+    // the sender goes through the proxy, if the link is impaired
+    let proxy = impairment(args)
+        .map(|impairment| ImpairmentProxy::start(port, impairment))
+        .transpose()?;
+    let send_port = proxy.as_ref().map_or(port, ImpairmentProxy::port);
+    let sender = AsyncSender::connect_with_options(
+        args.profile,
+        &format!("rist://127.0.0.1:{send_port}"),
+        sender_options(args),
+    )
+    .await?;
+
+    let done = Arc::new(AtomicBool::new(false));
+    let drain = Duration::from_millis(args.buffer) + Duration::from_millis(500);
+    let recv_task = tokio::spawn(receive(receiver, done.clone(), drain));
+
+    let sent = send(sender, bitrate, args.packet_size, args.duration).await?;
+    done.store(true, Ordering::Relaxed);
+
+    let mut report = recv_task.await??;
+    report.target_bps = bitrate;
+    report.sent = sent;
+    report.impaired = proxy.map(|proxy| proxy.stats());
+    report.achieved_bps = report.received * args.packet_size as u64 * 8 / args.duration.max(1);
+    Ok(report)
+}
+
+async fn run_send(args: &Args, bitrate: u64) -> Result<StepReport, Box<dyn std::error::Error>> {
+    let sender =
+        AsyncSender::connect_with_options(args.profile, require_url(args)?, sender_options(args))
+            .await?;
+    let sent = send(sender, bitrate, args.packet_size, args.duration).await?;
+    Ok(StepReport {
+        target_bps: bitrate,
+        achieved_bps: sent * args.packet_size as u64 * 8 / args.duration.max(1),
+        sent,
+        ..Default::default()
+    })
+}
+
+async fn run_recv(args: &Args) -> Result<StepReport, Box<dyn std::error::Error>> {
+    let receiver =
+        AsyncReceiver::bind_with_options(args.profile, require_url(args)?, receiver_options(args))?;
+    let done = Arc::new(AtomicBool::new(false));
+    let started = Instant::now();
+    let mut report = receive(receiver, done, Duration::from_secs(args.duration)).await?;
+    let elapsed = started.elapsed().as_secs().max(1);
+    report.achieved_bps = report.received * args.packet_size as u64 * 8 / elapsed;
+    // the sender's count isn't known here; infer it from the highest sequence seen
+    report.sent = report.received + report.lost;
+    Ok(report)
+}
+
+/// Send paced packets for `duration_secs`, returning how many were sent.
+async fn send(
+    mut sender: AsyncSender,
+    bitrate: u64,
+    packet_size: usize,
+    duration_secs: u64,
+) -> rist::Result<u64> {
+    let interval = Duration::from_secs_f64(packet_size as f64 * 8.0 / bitrate.max(1) as f64);
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(duration_secs);
+    let mut payload = vec![0u8; packet_size];
+    payload[..4].copy_from_slice(MAGIC);
+
+    let mut seq = 0u64;
+    while Instant::now() < deadline {
+        payload[4..12].copy_from_slice(&seq.to_be_bytes());
+        payload[12..20].copy_from_slice(&now_micros().to_be_bytes());
+        sender
+            .write_all(&payload)
+            .await
+            .map_err(|_| rist::Error::Send)?;
+        seq += 1;
+
+        let due = started + interval.mul_f64(seq as f64);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+    Ok(seq)
+}
+
+/// Receive until `done` is set and no packet arrived for `idle`.
+async fn receive(
+    receiver: AsyncReceiver,
+    done: Arc<AtomicBool>,
+    idle: Duration,
+) -> rist::Result<StepReport> {
+    let mut report = StepReport::default();
+    let mut seen = std::collections::HashSet::new();
+    let mut max_seq = None;
+
+    loop {
+        let Some(block) = receiver.recv_timeout(idle).await? else {
+            if done.load(Ordering::Relaxed) || max_seq.is_some() {
+                break;
+            }
+            continue;
+        };
+        let payload = block.payload();
+        if payload.len() < HEADER_SIZE || &payload[..4] != MAGIC {
+            continue;
+        }
+
+        let seq = u64::from_be_bytes(payload[4..12].try_into().unwrap());
+        let sent_at = u64::from_be_bytes(payload[12..20].try_into().unwrap());
+        if !seen.insert(seq) {
+            report.duplicates += 1;
+            continue;
+        }
+        report.received += 1;
+        report.latency_us.push(now_micros().saturating_sub(sent_at));
+        max_seq = max_seq.max(Some(seq));
+    }
+
+    if let Some(stats) = receiver.raw_stats() {
+        report.recovered = stats.recovered as u64;
+        report.rist_lost = stats.lost as u64;
+    }
+    report.lost = max_seq.map_or(0, |max| (max + 1).saturating_sub(report.received));
+    report.latency_us.sort_unstable();
+    Ok(report)
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,180 @@
+//! Listen for a RIST stream and write it to UDP, multicast or a file.
+//!
+//! Run with: cargo run --features cli --bin rist-recv -- -i rist://@:5000 -o udp://239.0.0.1:1234
+
+use clap::Parser;
+use rist::tokio::AsyncReceiver;
+use rist::{LogLevel, Profile, ReceiverOptions, RistUrl};
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(name = "rist-recv", version, about = "Receive a RIST stream")]
+struct Args {
+    /// Input RIST URL to listen on (e.g. `rist://@:5000`).
+    #[arg(short, long)]
+    input: String,
+
+    /// Output: `udp://host:port` (unicast or multicast), a file path, or `-` for stdout.
+    #[arg(short, long)]
+    output: String,
+
+    /// RIST profile (simple, main, advanced).
+    #[arg(short, long, default_value = "main")]
+    profile: Profile,
+
+    /// Recovery buffer size in milliseconds.
+    #[arg(short, long)]
+    buffer: Option<u64>,
+
+    /// Socket receive buffer size in bytes, for high bitrates.
+    #[arg(long)]
+    rcvbuf: Option<usize>,
+
+    /// Pre-shared encryption secret.
+    #[arg(short, long)]
+    secret: Option<String>,
+
+    /// AES key size used with `--secret` (128 or 256).
+    #[arg(long, default_value_t = 128)]
+    aes_type: u32,
+
+    /// Multicast TTL for UDP output.
+    #[arg(long, default_value_t = 1)]
+    ttl: u32,
+
+    /// Seconds between stats lines. 0 disables stats.
+    #[arg(long, default_value_t = 5)]
+    stats_interval: u64,
+
+    /// librist log level.
+    #[arg(long, default_value = "warn")]
+    log_level: LogLevel,
+}
+
+/// Where received payloads are written.
+enum Output {
+    Udp(UdpSocket),
+    Writer(Box<dyn Write + Send>),
+}
+
+impl Output {
+    fn open(target: &str, ttl: u32) -> io::Result<Self> {
+        if let Some(addr) = target.strip_prefix("udp://") {
+            let addr: SocketAddr = addr
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let bind: SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0u16; 8], 0).into()
+            };
+            let socket = UdpSocket::bind(bind)?;
+            if addr.ip().is_multicast() && addr.is_ipv4() {
+                socket.set_multicast_ttl_v4(ttl)?;
+            }
+            socket.connect(addr)?;
+            return Ok(Output::Udp(socket));
+        }
+
+        if target == "-" {
+            return Ok(Output::Writer(Box::new(io::stdout())));
+        }
+        Ok(Output::Writer(Box::new(File::create(target)?)))
+    }
+
+    fn write(&mut self, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Output::Udp(socket) => socket.send(payload).map(|_| ()),
+            Output::Writer(writer) => writer.write_all(payload),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rist-recv: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    rist::set_logging(args.log_level)?;
+
+    let mut options = ReceiverOptions::new();
+    if let Some(ms) = args.buffer {
+        options = options
+            .recovery_length_min(Duration::from_millis(ms))
+            .recovery_length_max(Duration::from_millis(ms));
+    }
+    if let Some(bytes) = args.rcvbuf {
+        options = options.recv_buffer_size(bytes);
+    }
+
+    let url = input_url(&args.input, &args)?;
+    let receiver = AsyncReceiver::bind_with_options(args.profile, &url, options)?;
+    let mut output = Output::open(&args.output, args.ttl)?;
+    eprintln!("rist-recv: listening on {} -> {}", args.input, args.output);
+    if let Some(buffers) = receiver.peers().iter().find_map(|peer| peer.socket_buffers) {
+        eprintln!("rist-recv: socket receive buffer {} bytes", buffers.recv);
+    }
+
+    let stats_period = Duration::from_secs(args.stats_interval.max(1));
+    let mut stats_timer = tokio::time::interval(stats_period);
+    stats_timer.tick().await;
+
+    let mut packets = 0u64;
+    let mut bytes = 0u64;
+
+    loop {
+        tokio::select! {
+            block = receiver.recv() => {
+                let Some(block) = block? else { return Ok(()) };
+                output.write(block.payload())?;
+                packets += 1;
+                bytes += block.payload().len() as u64;
+            }
+            _ = stats_timer.tick(), if args.stats_interval > 0 => {
+                print_stats(&receiver, packets, bytes);
+            }
+        }
+    }
+}
+
+fn print_stats(receiver: &AsyncReceiver, packets: u64, bytes: u64) {
+    match receiver.raw_stats() {
+        Some(stats) => eprintln!(
+            "rist-recv: packets={packets} bytes={bytes} flow_id={} peers={} bitrate={}bps \
+             quality={:.1}% rtt={}ms read_jitter={:.2}ms lost={} recovered={} reordered={} \
+             max_spacing={:.2}ms",
+            stats.flow_id,
+            stats.peer_count,
+            stats.bandwidth,
+            stats.quality,
+            stats.rtt,
+            stats.read_jitter.as_secs_f64() * 1000.0,
+            stats.lost,
+            stats.recovered,
+            stats.reordered,
+            stats.max_inter_packet_spacing.as_secs_f64() * 1000.0,
+        ),
+        None => eprintln!("rist-recv: packets={packets} bytes={bytes} (no stats yet)"),
+    }
+}
+
+/// Append the command line security settings as URL parameters.
+fn input_url(url: &str, args: &Args) -> rist::Result<String> {
+    let Some(secret) = &args.secret else {
+        return Ok(url.to_string());
+    };
+    let url = RistUrl::append_param(url, "secret", secret)?;
+    RistUrl::append_param(&url, "aes-type", args.aes_type)
+}
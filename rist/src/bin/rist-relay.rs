@@ -0,0 +1,116 @@
+//! Accept a RIST stream and republish it to one or more RIST destinations.
+//!
+//! Run with: cargo run --features cli --bin rist-relay -- -i rist://@:5000 -o rist://10.0.0.2:5000
+
+use clap::Parser;
+use rist::relay::Relay;
+use rist::tokio::{AsyncReceiver, AsyncSender};
+use rist::{LogLevel, Profile, ReceiverOptions, SenderOptions};
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "rist-relay",
+    version,
+    about = "Relay a RIST stream to other RIST destinations"
+)]
+struct Args {
+    /// Input RIST URL to listen on (e.g. `rist://@:5000`).
+    #[arg(short, long)]
+    input: String,
+
+    /// Output RIST URL. Repeat to republish to several destinations.
+    #[arg(short, long = "output", required = true)]
+    outputs: Vec<String>,
+
+    /// RIST profile (simple, main, advanced).
+    #[arg(short, long, default_value = "main")]
+    profile: Profile,
+
+    /// Recovery buffer size in milliseconds, applied to both legs.
+    #[arg(short, long)]
+    buffer: Option<u64>,
+
+    /// Seconds between stats lines. 0 disables stats.
+    #[arg(long, default_value_t = 5)]
+    stats_interval: u64,
+
+    /// librist log level.
+    #[arg(long, default_value = "warn")]
+    log_level: LogLevel,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rist-relay: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> rist::Result<()> {
+    rist::set_logging(args.log_level)?;
+
+    let mut receiver_options = ReceiverOptions::new();
+    let mut sender_options = SenderOptions::new();
+    if let Some(ms) = args.buffer {
+        let buffer = Duration::from_millis(ms);
+        receiver_options = receiver_options
+            .recovery_length_min(buffer)
+            .recovery_length_max(buffer);
+        sender_options = sender_options
+            .recovery_length_min(buffer)
+            .recovery_length_max(buffer);
+    }
+
+    let input = AsyncReceiver::bind_with_options(args.profile, &args.input, receiver_options)?;
+    let mut relay = Relay::new(input);
+    for url in &args.outputs {
+        let output =
+            AsyncSender::connect_with_options(args.profile, url, sender_options.clone()).await?;
+        relay.add_output(output);
+        eprintln!("rist-relay: {} -> {url}", args.input);
+    }
+
+    if args.stats_interval == 0 {
+        return relay.run().await;
+    }
+
+    let mut stats_timer = tokio::time::interval(Duration::from_secs(args.stats_interval));
+    stats_timer.tick().await;
+    loop {
+        tokio::select! {
+            result = relay.forward_next() => {
+                result?;
+            }
+            _ = stats_timer.tick() => print_stats(&relay),
+        }
+    }
+}
+
+fn print_stats(relay: &Relay) {
+    let input = relay
+        .input()
+        .raw_stats()
+        .map(|s| format!("quality={:.1}% rtt={}ms lost={}", s.quality, s.rtt, s.lost))
+        .unwrap_or_else(|| "no stats yet".to_string());
+    eprintln!(
+        "rist-relay: packets={} bytes={} input: {input}",
+        relay.forwarded_packets(),
+        relay.forwarded_bytes()
+    );
+
+    for (i, output) in relay.outputs().iter().enumerate() {
+        if let Some(s) = output.raw_stats() {
+            eprintln!(
+                "rist-relay:   output[{i}] quality={:.1}% rtt={}ms retransmitted={}",
+                s.quality, s.rtt, s.retransmitted
+            );
+        }
+    }
+}
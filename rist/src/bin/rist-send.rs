@@ -0,0 +1,201 @@
+//! Read a UDP source or file and send it over RIST.
+//!
+//! Run with: cargo run --features cli --bin rist-send -- -i udp://@:1234 -o rist://127.0.0.1:5000
+
+use clap::Parser;
+use rist::{LogLevel, Profile, RistUrl, Sender, SenderOptions};
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// Size of a RIST payload carrying seven MPEG-TS packets.
+const TS_PAYLOAD_SIZE: usize = 7 * 188;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "rist-send",
+    version,
+    about = "Send a UDP source or file over RIST"
+)]
+struct Args {
+    /// Input: `udp://[@]host:port`, a file path, or `-` for stdin.
+    #[arg(short, long)]
+    input: String,
+
+    /// Output RIST URL. Repeat for bonding or redundant paths.
+    #[arg(short, long = "output", required = true)]
+    outputs: Vec<String>,
+
+    /// RIST profile (simple, main, advanced).
+    #[arg(short, long, default_value = "main")]
+    profile: Profile,
+
+    /// Recovery buffer size in milliseconds.
+    #[arg(short, long)]
+    buffer: Option<u64>,
+
+    /// Maximum recovery bandwidth in kbps.
+    #[arg(long)]
+    bandwidth: Option<u32>,
+
+    /// Pre-shared encryption secret.
+    #[arg(short, long)]
+    secret: Option<String>,
+
+    /// AES key size used with `--secret` (128 or 256).
+    #[arg(long, default_value_t = 128)]
+    aes_type: u32,
+
+    /// Canonical name reported to the receiver.
+    #[arg(long)]
+    cname: Option<String>,
+
+    /// Load-balancing weight for each output. 0 duplicates to every output.
+    #[arg(long)]
+    weight: Option<u32>,
+
+    /// Pace file input at this bitrate (bits per second).
+    #[arg(long)]
+    bitrate: Option<u64>,
+
+    /// librist log level.
+    #[arg(long, default_value = "warn")]
+    log_level: LogLevel,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rist-send: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    rist::set_logging(args.log_level)?;
+
+    let mut options = SenderOptions::new();
+    if let Some(ms) = args.buffer {
+        options = options
+            .recovery_length_min(Duration::from_millis(ms))
+            .recovery_length_max(Duration::from_millis(ms));
+    }
+    if let Some(bandwidth) = args.bandwidth {
+        options = options.recovery_maxbitrate(bandwidth);
+    }
+    let is_udp = args.input.starts_with("udp://");
+    if let Some(bitrate) = args.bitrate.filter(|b| *b > 0 && !is_udp) {
+        options = options.pace_bitrate(bitrate);
+    }
+
+    let mut sender = Sender::new(args.profile)?;
+    for output in &args.outputs {
+        let url = output_url(output, &args)?;
+        sender.add_peer_with_options(&url, &options)?;
+        eprintln!("rist-send: sending to {output}");
+    }
+    sender.start()?;
+
+    let mut total = 0u64;
+    let mut on_packet = |packet: &[u8]| -> rist::Result<()> {
+        total += sender.send(packet)? as u64;
+        Ok(())
+    };
+
+    if let Some(addr) = args.input.strip_prefix("udp://") {
+        send_udp(addr, &mut on_packet)?;
+    } else if args.input == "-" {
+        send_stream(io::stdin().lock(), &mut on_packet)?;
+    } else {
+        send_stream(File::open(&args.input)?, &mut on_packet)?;
+    }
+
+    // keep the peers up for their recovery buffer, so the tail of the
+    // input can still be retransmitted
+    sender.stop()?;
+    eprintln!("rist-send: sent {total} bytes");
+    Ok(())
+}
+
+/// Append the command line security and identity settings as URL parameters.
+fn output_url(url: &str, args: &Args) -> rist::Result<String> {
+    let mut url = url.to_string();
+    if let Some(secret) = &args.secret {
+        url = RistUrl::append_param(&url, "secret", secret)?;
+        url = RistUrl::append_param(&url, "aes-type", args.aes_type)?;
+    }
+    if let Some(cname) = &args.cname {
+        url = RistUrl::append_param(&url, "cname", cname)?;
+    }
+    if let Some(weight) = args.weight {
+        url = RistUrl::append_param(&url, "weight", weight)?;
+    }
+    Ok(url)
+}
+
+/// Forward every datagram received on `addr` (`@` binds to all interfaces).
+fn send_udp(
+    addr: &str,
+    on_packet: &mut impl FnMut(&[u8]) -> rist::Result<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listen = addr.strip_prefix('@').unwrap_or(addr);
+    let listen = if listen.starts_with(':') {
+        format!("0.0.0.0{listen}")
+    } else {
+        listen.to_string()
+    };
+    let addr: SocketAddr = listen.parse()?;
+
+    let socket = match addr {
+        SocketAddr::V4(v4) if v4.ip().is_multicast() => {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, v4.port()))?;
+            socket.join_multicast_v4(v4.ip(), &Ipv4Addr::UNSPECIFIED)?;
+            socket
+        }
+        _ => UdpSocket::bind(addr)?,
+    };
+    eprintln!("rist-send: reading udp from {addr}");
+
+    let mut buf = [0u8; rist_sys::RIST_MAX_PACKET_SIZE as usize];
+    loop {
+        let n = socket.recv(&mut buf)?;
+        on_packet(&buf[..n])?;
+    }
+}
+
+/// Send a byte stream in TS-aligned chunks.
+///
+/// With `--bitrate` the sender itself paces the writes.
+fn send_stream(
+    mut input: impl Read,
+    on_packet: &mut impl FnMut(&[u8]) -> rist::Result<()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut buf = [0u8; TS_PAYLOAD_SIZE];
+
+    loop {
+        let n = read_full(&mut input, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        on_packet(&buf[..n])?;
+    }
+}
+
+/// Fill `buf` as far as possible, returning a short count only at EOF.
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
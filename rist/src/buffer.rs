@@ -0,0 +1,180 @@
+//! Where the binding gets the buffers it copies payloads into.
+//!
+//! Received blocks are handed out as librist left them, but a few places
+//! need buffers of their own: `AsyncSender` copies each payload for the
+//! librist write that runs off the async runtime, `AsyncReceiver`'s
+//! `AsyncRead` keeps whatever part of a block the caller's buffer had no
+//! room for, and the recorder buffers its file writes. A [`BufferSource`]
+//! decides where those buffers come from and where they go afterwards, e.g.
+//! a [`BufferPool`] filled up front so nothing is allocated while
+//! streaming.
+//!
+//! Buffers are `Vec<u8>`, so their memory comes from the global allocator;
+//! a source controls when buffers are allocated and how they are reused.
+//!
+//! Install a source with `set_buffer_source()` on `AsyncSender` and
+//! `AsyncReceiver`, or with `Recorder::buffer_source()`.
+//!
+//! # Example
+//!
+//! ```
+//! use rist::buffer::BufferSource;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! /// Allocates like the default source, counting the copies.
+//! #[derive(Default)]
+//! struct Counted(AtomicUsize);
+//!
+//! impl BufferSource for Counted {
+//!     fn acquire(&self, capacity: usize) -> Vec<u8> {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!         Vec::with_capacity(capacity)
+//!     }
+//! }
+//!
+//! let source = Counted::default();
+//! let buffer = source.acquire(1316);
+//! source.release(buffer);
+//! assert_eq!(source.0.load(Ordering::Relaxed), 1);
+//! ```
+
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Hands out the buffers the binding copies payloads into, and takes them
+/// back once they are no longer needed.
+pub trait BufferSource: Send + Sync {
+    /// An empty buffer with room for at least `capacity` bytes.
+    fn acquire(&self, capacity: usize) -> Vec<u8>;
+
+    /// Take back a buffer the binding is done with. The default drops it.
+    fn release(&self, buffer: Vec<u8>) {
+        drop(buffer);
+    }
+}
+
+/// Allocates a new buffer for every copy, the default source.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapBuffers;
+
+impl BufferSource for HeapBuffers {
+    fn acquire(&self, capacity: usize) -> Vec<u8> {
+        Vec::with_capacity(capacity)
+    }
+}
+
+/// Keeps released buffers for reuse instead of freeing them.
+#[derive(Debug)]
+pub struct BufferPool {
+    free: Mutex<Vec<Vec<u8>>>,
+    buffer_size: usize,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    /// Create a pool allocating buffers of at least `buffer_size` bytes
+    /// and keeping up to `max_buffers` free ones.
+    pub fn new(buffer_size: usize, max_buffers: usize) -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+            buffer_size,
+            max_buffers,
+        }
+    }
+
+    /// Allocate `count` buffers up front, up to the pool's limit.
+    pub fn prefill(self, count: usize) -> Self {
+        {
+            let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+            while free.len() < count.min(self.max_buffers) {
+                free.push(Vec::with_capacity(self.buffer_size));
+            }
+        }
+        self
+    }
+
+    /// Free buffers waiting to be reused.
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+impl BufferSource for BufferPool {
+    fn acquire(&self, capacity: usize) -> Vec<u8> {
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        match free
+            .iter()
+            .rposition(|buffer| buffer.capacity() >= capacity)
+        {
+            Some(pos) => free.swap_remove(pos),
+            None => Vec::with_capacity(capacity.max(self.buffer_size)),
+        }
+    }
+
+    fn release(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        if free.len() < self.max_buffers {
+            free.push(buffer);
+        }
+    }
+}
+
+/// The buffer source of a context, replaceable at runtime.
+#[cfg(feature = "tokio")]
+pub(crate) struct BufferSlot(Mutex<Arc<dyn BufferSource>>);
+
+#[cfg(feature = "tokio")]
+impl Default for BufferSlot {
+    fn default() -> Self {
+        Self(Mutex::new(Arc::new(HeapBuffers)))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl BufferSlot {
+    pub(crate) fn set(&self, source: Arc<dyn BufferSource>) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = source;
+    }
+
+    pub(crate) fn get(&self) -> Arc<dyn BufferSource> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_reuses_buffers() {
+        let pool = BufferPool::new(1316, 2).prefill(4);
+        assert_eq!(pool.available(), 2);
+
+        let mut first = pool.acquire(100);
+        assert!(first.capacity() >= 1316);
+        first.extend_from_slice(b"payload");
+        let ptr = first.as_ptr();
+        pool.release(first);
+
+        // a released buffer comes back empty
+        let again = pool.acquire(1316);
+        assert!(again.is_empty());
+        assert!(pool.acquire(1316).capacity() >= 1316);
+        assert_eq!(pool.available(), 0);
+        pool.release(again);
+        let reused = pool.acquire(10);
+        assert_eq!(reused.as_ptr(), ptr);
+
+        // too small buffers aren't handed out for larger copies
+        pool.release(Vec::with_capacity(8));
+        assert!(pool.acquire(4096).capacity() >= 4096);
+        assert_eq!(pool.available(), 1);
+
+        for _ in 0..4 {
+            pool.release(Vec::new());
+        }
+        assert_eq!(pool.available(), 2);
+    }
+}
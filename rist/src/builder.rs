@@ -0,0 +1,250 @@
+//! One-step construction of started receivers and senders.
+
+use crate::logging::LoggingSettings;
+use crate::stats::{ReceiverStats, SenderStats, StatsCallback, StatsMode, DEFAULT_STATS_INTERVAL};
+use crate::{Error, LogLevel, Profile, Receiver, ReceiverOptions, Result, Sender, SenderOptions};
+use std::time::Duration;
+
+/// Builder for a started [`Receiver`].
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::{Profile, Receiver};
+/// use std::time::Duration;
+///
+/// let receiver = Receiver::builder()
+///     .profile(Profile::Main)
+///     .peer("rist://@:5000")
+///     .stats_interval(Duration::from_secs(5))
+///     .on_stats(|stats| println!("quality: {:.1}%", stats.quality))
+///     .build()?;
+/// # Ok::<(), rist::Error>(())
+/// ```
+pub struct ReceiverBuilder {
+    profile: Profile,
+    peers: Vec<(String, ReceiverOptions)>,
+    log_level: Option<LogLevel>,
+    stats_interval: Duration,
+    stats_mode: StatsMode,
+    on_stats: Option<StatsCallback<ReceiverStats>>,
+}
+
+impl Default for ReceiverBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReceiverBuilder {
+    /// Create a builder using the Main profile and no peers.
+    pub fn new() -> Self {
+        Self {
+            profile: Profile::default(),
+            peers: Vec::new(),
+            log_level: None,
+            stats_interval: DEFAULT_STATS_INTERVAL,
+            stats_mode: StatsMode::default(),
+            on_stats: None,
+        }
+    }
+
+    /// Set the RIST profile.
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Add a peer with default options.
+    pub fn peer(self, url: impl Into<String>) -> Self {
+        self.peer_with_options(url, ReceiverOptions::default())
+    }
+
+    /// Add a peer with custom options.
+    pub fn peer_with_options(mut self, url: impl Into<String>, options: ReceiverOptions) -> Self {
+        self.peers.push((url.into(), options));
+        self
+    }
+
+    /// Add a peer with default options for every URL, e.g. a unicast and a
+    /// multicast listener, or two ports, all feeding the one receiver.
+    pub fn peers<I>(self, urls: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        urls.into_iter()
+            .fold(self, |builder, url| builder.peer(url))
+    }
+
+    /// Log librist messages for this context at `level`, independent of the
+    /// global level set by [`set_logging`](crate::set_logging).
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    /// Set how often librist reports stats.
+    pub fn stats_interval(mut self, interval: Duration) -> Self {
+        self.stats_interval = interval;
+        self
+    }
+
+    /// Report counters as running totals or per-report increases.
+    pub fn stats_mode(mut self, mode: StatsMode) -> Self {
+        self.stats_mode = mode;
+        self
+    }
+
+    /// Call `callback` with every new stats snapshot.
+    ///
+    /// The callback runs on a librist thread and must not block.
+    pub fn on_stats(mut self, callback: impl FnMut(&ReceiverStats) + Send + 'static) -> Self {
+        self.on_stats = Some(Box::new(callback));
+        self
+    }
+
+    /// Create the receiver, add every peer and start it.
+    pub fn build(self) -> Result<Receiver> {
+        if self.peers.is_empty() {
+            return Err(Error::Configuration(
+                "receiver needs at least one peer".to_string(),
+            ));
+        }
+
+        let logging = self.log_level.map(LoggingSettings::new).transpose()?;
+        let mut receiver = Receiver::create(self.profile, logging)?;
+        receiver.set_stats_interval(self.stats_interval)?;
+        receiver.set_stats_mode(self.stats_mode);
+        if let Some(callback) = self.on_stats {
+            receiver.on_stats(callback);
+        }
+
+        for (url, options) in &self.peers {
+            receiver.add_peer_with_options(url, options)?;
+        }
+        receiver.start()?;
+
+        Ok(receiver)
+    }
+}
+
+/// Builder for a started [`Sender`].
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::{Profile, Sender};
+///
+/// let sender = Sender::builder()
+///     .profile(Profile::Main)
+///     .peer("rist://192.168.1.1:5000")
+///     .on_stats(|stats| println!("rtt: {}ms", stats.rtt))
+///     .build()?;
+/// sender.send(b"hello")?;
+/// # Ok::<(), rist::Error>(())
+/// ```
+pub struct SenderBuilder {
+    profile: Profile,
+    flow_id: u32,
+    peers: Vec<(String, SenderOptions)>,
+    log_level: Option<LogLevel>,
+    stats_interval: Duration,
+    stats_mode: StatsMode,
+    on_stats: Option<StatsCallback<SenderStats>>,
+}
+
+impl Default for SenderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SenderBuilder {
+    /// Create a builder using the Main profile and no peers.
+    pub fn new() -> Self {
+        Self {
+            profile: Profile::default(),
+            flow_id: 0,
+            peers: Vec::new(),
+            log_level: None,
+            stats_interval: DEFAULT_STATS_INTERVAL,
+            stats_mode: StatsMode::default(),
+            on_stats: None,
+        }
+    }
+
+    /// Set the RIST profile.
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Send with the flow ID `flow_id`, see [`Sender::with_flow_id`].
+    pub fn flow_id(mut self, flow_id: u32) -> Self {
+        self.flow_id = flow_id;
+        self
+    }
+
+    /// Add a peer with default options.
+    pub fn peer(self, url: impl Into<String>) -> Self {
+        self.peer_with_options(url, SenderOptions::default())
+    }
+
+    /// Add a peer with custom options.
+    pub fn peer_with_options(mut self, url: impl Into<String>, options: SenderOptions) -> Self {
+        self.peers.push((url.into(), options));
+        self
+    }
+
+    /// Log librist messages for this context at `level`, independent of the
+    /// global level set by [`set_logging`](crate::set_logging).
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    /// Set how often librist reports stats.
+    pub fn stats_interval(mut self, interval: Duration) -> Self {
+        self.stats_interval = interval;
+        self
+    }
+
+    /// Report counters as running totals or per-report increases.
+    pub fn stats_mode(mut self, mode: StatsMode) -> Self {
+        self.stats_mode = mode;
+        self
+    }
+
+    /// Call `callback` with every new stats snapshot.
+    ///
+    /// The callback runs on a librist thread and must not block.
+    pub fn on_stats(mut self, callback: impl FnMut(&SenderStats) + Send + 'static) -> Self {
+        self.on_stats = Some(Box::new(callback));
+        self
+    }
+
+    /// Create the sender, add every peer and start it.
+    pub fn build(self) -> Result<Sender> {
+        if self.peers.is_empty() {
+            return Err(Error::Configuration(
+                "sender needs at least one peer".to_string(),
+            ));
+        }
+
+        let logging = self.log_level.map(LoggingSettings::new).transpose()?;
+        let mut sender = Sender::create(self.profile, self.flow_id, logging)?;
+        sender.set_stats_interval(self.stats_interval)?;
+        sender.set_stats_mode(self.stats_mode);
+        if let Some(callback) = self.on_stats {
+            sender.on_stats(callback);
+        }
+
+        for (url, options) in &self.peers {
+            sender.add_peer_with_options(url, options)?;
+        }
+        sender.start()?;
+
+        Ok(sender)
+    }
+}
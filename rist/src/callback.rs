@@ -0,0 +1,131 @@
+//! Panics in callbacks run by librist.
+//!
+//! Connection, stats, flow and OOB callbacks run on librist's threads. A
+//! panic there must not unwind into C, so every entry point from librist
+//! catches it, records it here and returns to librist as if the callback
+//! had finished. The panic message is still printed by the panic hook, and
+//! with the `tracing` feature it is logged as an error event too.
+//!
+//! A user callback that panicked is removed from its context and isn't
+//! called again, since the state it left behind can't be trusted. Setting
+//! a new callback replaces it as usual.
+//!
+//! The record of caught panics is shared by every context in the process:
+//! [`take_callback_panic`] returns the latest one, whichever context's
+//! callback it was.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// A panic caught in a callback run by librist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackPanic {
+    /// The librist callback that was running, e.g. `"stats"`.
+    pub callback: &'static str,
+    /// The panic message, if it was a string.
+    pub message: Option<String>,
+}
+
+impl std::fmt::Display for CallbackPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{} callback panicked: {message}", self.callback),
+            None => write!(f, "{} callback panicked", self.callback),
+        }
+    }
+}
+
+impl std::error::Error for CallbackPanic {}
+
+static PANICS: AtomicU64 = AtomicU64::new(0);
+static LAST_PANIC: Mutex<Option<CallbackPanic>> = Mutex::new(None);
+
+/// Panics caught in callbacks run by librist since the process started,
+/// across all contexts.
+pub fn callback_panics() -> u64 {
+    PANICS.load(Ordering::Relaxed)
+}
+
+/// Take the latest panic caught in a callback run by librist, if any
+/// happened since the last call.
+///
+/// This is process-wide: the panic may come from any context, and a call
+/// for one context takes it from everyone else too.
+pub fn take_callback_panic() -> Option<CallbackPanic> {
+    LAST_PANIC.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// Run `f` for the librist callback `callback`, returning `fallback` if it
+/// panics.
+pub(crate) fn guard<R>(callback: &'static str, fallback: R, f: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(ret) => ret,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned());
+            let caught = CallbackPanic { callback, message };
+            #[cfg(feature = "tracing")]
+            tracing::error!(callback, "{caught}");
+            PANICS.fetch_add(1, Ordering::Relaxed);
+            *LAST_PANIC.lock().unwrap_or_else(|e| e.into_inner()) = Some(caught);
+            fallback
+        }
+    }
+}
+
+/// Call the user callback in `slot`, if one is set.
+///
+/// A callback that panics is removed, so it is never called again, and the
+/// panic carries on once `slot` is unlocked, to be caught by [`guard`].
+pub(crate) fn call_user<C>(slot: &Mutex<Option<C>>, call: impl FnOnce(&mut C)) {
+    let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(callback) = guard.as_mut() else {
+        return;
+    };
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| call(callback))) {
+        *guard = None;
+        drop(guard);
+        panic::resume_unwind(payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_catches_panics() {
+        assert_eq!(guard("test", 0, || 1), 1);
+
+        let before = callback_panics();
+        assert_eq!(guard("test", -1, || panic!("boom {}", 7)), -1);
+        assert!(callback_panics() > before);
+        let caught = take_callback_panic().unwrap();
+        assert_eq!(caught.callback, "test");
+        assert_eq!(caught.to_string(), "test callback panicked: boom 7");
+    }
+
+    #[test]
+    fn test_panicking_callback_is_removed() {
+        let slot: Mutex<Option<crate::stats::StatsCallback<u32>>> =
+            Mutex::new(Some(Box::new(|n| assert!(*n < 2))));
+        guard("test", (), || call_user(&slot, |callback| callback(&1)));
+        assert!(slot.lock().unwrap().is_some());
+
+        guard("test", (), || call_user(&slot, |callback| callback(&2)));
+        assert!(!slot.is_poisoned());
+        assert!(slot.lock().unwrap().is_none());
+
+        // a new callback is taken
+        let calls = std::sync::Arc::new(AtomicU64::new(0));
+        let counter = calls.clone();
+        *slot.lock().unwrap() = Some(Box::new(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+        guard("test", (), || call_user(&slot, |callback| callback(&3)));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
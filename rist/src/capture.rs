@@ -0,0 +1,259 @@
+//! Capturing sent and received payloads for debugging.
+//!
+//! A [`CaptureTap`] writes every payload that goes through a sender or
+//! receiver to a file as newline-delimited JSON, one record per packet with
+//! its time, direction, metadata and hex-encoded payload. Attach it with
+//! `set_capture` and switch it on and off at runtime, so a field issue can be
+//! captured without tcpdump access on the box.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::capture::CaptureTap;
+//! use rist::{Profile, Receiver};
+//!
+//! # fn example() -> rist::Result<()> {
+//! let tap = CaptureTap::create("/tmp/rist-capture.ndjson")?;
+//! let receiver = Receiver::new(Profile::Main)?;
+//! receiver.set_capture(Some(tap.clone()));
+//!
+//! // later, e.g. from an admin endpoint
+//! tap.disable();
+//! tap.flush()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BlockMetadata, Result};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Which way a captured payload went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Written by a sender.
+    Sent,
+    /// Delivered by a receiver.
+    Received,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Sent => "sent",
+            Direction::Received => "received",
+        }
+    }
+}
+
+struct TapInner {
+    enabled: AtomicBool,
+    records: AtomicU64,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+/// Writes captured payloads as NDJSON.
+///
+/// Each line is an object like
+///
+/// ```text
+/// {"time":1700000000.123456789,"dir":"received","flow_id":1,"seq":42,"ts_ntp":0,"virt_src_port":0,"virt_dst_port":1968,"len":3,"payload":"474000"}
+/// ```
+///
+/// `time` is when the packet was captured, in seconds since the Unix epoch;
+/// `seq` is `null` when unset. Cloning is cheap and clones share the file, so
+/// one tap can capture several senders and receivers. Taps start enabled.
+/// Write errors are ignored, capturing never fails a send or read.
+#[derive(Clone)]
+pub struct CaptureTap {
+    inner: Arc<TapInner>,
+}
+
+impl CaptureTap {
+    /// Capture into `writer`.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            inner: Arc::new(TapInner {
+                enabled: AtomicBool::new(true),
+                records: AtomicU64::new(0),
+                out: Mutex::new(Box::new(writer)),
+            }),
+        }
+    }
+
+    /// Capture into a new file at `path`, replacing any existing one.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Start capturing again.
+    pub fn enable(&self) {
+        self.inner.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Stop capturing until [`enable`](Self::enable) is called.
+    pub fn disable(&self) {
+        self.inner.enabled.store(false, Ordering::Relaxed);
+    }
+
+    /// Whether payloads are being captured.
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Number of records written so far.
+    pub fn records(&self) -> u64 {
+        self.inner.records.load(Ordering::Relaxed)
+    }
+
+    /// Write out anything buffered.
+    pub fn flush(&self) -> Result<()> {
+        let mut out = self.inner.out.lock().unwrap_or_else(|e| e.into_inner());
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Write a record for `payload` if capturing.
+    pub(crate) fn record(&self, direction: Direction, payload: &[u8], metadata: &BlockMetadata) {
+        if !self.is_enabled() {
+            return;
+        }
+        let line = format_record(SystemTime::now(), direction, payload, metadata);
+        let Ok(mut out) = self.inner.out.lock() else {
+            return;
+        };
+        if out.write_all(line.as_bytes()).is_ok() {
+            self.inner.records.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl std::fmt::Debug for CaptureTap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CaptureTap")
+            .field("enabled", &self.is_enabled())
+            .field("records", &self.records())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The tap attached to a sender or receiver, if any.
+#[derive(Default)]
+pub(crate) struct CaptureSlot(Mutex<Option<CaptureTap>>);
+
+impl CaptureSlot {
+    pub(crate) fn set(&self, tap: Option<CaptureTap>) {
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = tap;
+        }
+    }
+
+    pub(crate) fn record(&self, direction: Direction, payload: &[u8], metadata: &BlockMetadata) {
+        if let Ok(slot) = self.0.lock() {
+            if let Some(tap) = slot.as_ref() {
+                tap.record(direction, payload, metadata);
+            }
+        }
+    }
+}
+
+/// One NDJSON line describing a captured payload.
+fn format_record(
+    time: SystemTime,
+    direction: Direction,
+    payload: &[u8],
+    metadata: &BlockMetadata,
+) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seq = metadata
+        .seq
+        .map_or_else(|| "null".to_string(), |seq| seq.to_string());
+
+    let mut line = String::with_capacity(160 + payload.len() * 2);
+    let _ = write!(
+        line,
+        "{{\"time\":{}.{:09},\"dir\":\"{}\",\"flow_id\":{},\"seq\":{},\"ts_ntp\":{},\
+         \"virt_src_port\":{},\"virt_dst_port\":{},\"len\":{},\"payload\":\"",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos(),
+        direction.as_str(),
+        metadata.flow_id,
+        seq,
+        metadata.ts_ntp,
+        metadata.virt_src_port,
+        metadata.virt_dst_port,
+        payload.len(),
+    );
+    for byte in payload {
+        let _ = write!(line, "{byte:02x}");
+    }
+    line.push_str("\"}\n");
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_format_record() {
+        let metadata = BlockMetadata {
+            seq: Some(42),
+            flow_id: 7,
+            virt_dst_port: 1968,
+            ..Default::default()
+        };
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        assert_eq!(
+            format_record(time, Direction::Received, &[0x47, 0x40, 0x00], &metadata),
+            "{\"time\":1700000000.000000005,\"dir\":\"received\",\"flow_id\":7,\"seq\":42,\
+             \"ts_ntp\":0,\"virt_src_port\":0,\"virt_dst_port\":1968,\"len\":3,\
+             \"payload\":\"474000\"}\n"
+        );
+        assert!(
+            format_record(time, Direction::Sent, &[], &BlockMetadata::default())
+                .contains("\"dir\":\"sent\",\"flow_id\":0,\"seq\":null")
+        );
+    }
+
+    #[test]
+    fn test_toggle_at_runtime() {
+        let buf = Shared::default();
+        let tap = CaptureTap::new(buf.clone());
+        let slot = CaptureSlot::default();
+        slot.record(Direction::Sent, b"before", &BlockMetadata::default());
+        slot.set(Some(tap.clone()));
+
+        slot.record(Direction::Sent, b"one", &BlockMetadata::default());
+        tap.disable();
+        slot.record(Direction::Sent, b"skipped", &BlockMetadata::default());
+        tap.enable();
+        slot.record(Direction::Received, b"two", &BlockMetadata::default());
+
+        assert_eq!(tap.records(), 2);
+        let captured = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = captured.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("\"payload\":\"6f6e65\"}"));
+        assert!(lines[1].contains("\"dir\":\"received\""));
+    }
+}
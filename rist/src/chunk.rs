@@ -0,0 +1,381 @@
+//! Sending buffers larger than one packet.
+//!
+//! A RIST payload holds at most one packet's worth of data. [`Chunker`]
+//! cuts a buffer of any length, e.g. a file or a serialized blob, into
+//! payloads that each start with a small header naming the chunker, the
+//! message and the chunk's place in it, and [`Reassembler`] puts the message back together
+//! from those payloads on the far side, whatever order they arrive in.
+//!
+//! RIST recovers lost packets within its buffer, so over a healthy link
+//! every message arrives whole. A chunk lost for good leaves its message
+//! incomplete; the reassembler drops the oldest incomplete messages once
+//! too many are waiting, see [`Reassembler::max_incomplete`].
+//!
+//! Each chunker picks a random session ID, so messages from a restarted
+//! sender, or from two senders on one flow, don't mix even though their
+//! message IDs overlap.
+//!
+//! # Example
+//!
+//! ```
+//! use rist::chunk::{Chunker, Reassembler};
+//!
+//! let blob = vec![7u8; 10_000];
+//! let mut chunker = Chunker::new();
+//! let mut reassembler = Reassembler::new();
+//!
+//! let mut received = None;
+//! for payload in chunker.chunk(&blob) {
+//!     // sender.send(&payload) ... receiver.read() on the far side
+//!     received = reassembler.push(&payload)?;
+//! }
+//! assert_eq!(received.as_deref(), Some(&blob[..]));
+//! # Ok::<(), rist::Error>(())
+//! ```
+
+use crate::ts::PAYLOAD_SIZE;
+use crate::{Error, Result};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+
+/// First bytes of every chunk.
+const MAGIC: &[u8; 2] = b"RC";
+
+/// Size of the header in front of every chunk: magic, session ID, message
+/// ID, chunk index and chunk count.
+pub const HEADER_SIZE: usize = 18;
+
+/// Cuts buffers into RIST payloads for [`Reassembler`].
+#[derive(Debug, Clone)]
+pub struct Chunker {
+    payload_size: usize,
+    session: u32,
+    next_id: u32,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunker {
+    /// Cut buffers into payloads of [`PAYLOAD_SIZE`] bytes.
+    pub fn new() -> Self {
+        Self::with_payload_size(PAYLOAD_SIZE)
+    }
+
+    /// Cut buffers into payloads of up to `payload_size` bytes, header
+    /// included, limited to what librist can send in one packet.
+    pub fn with_payload_size(payload_size: usize) -> Self {
+        Self {
+            payload_size: payload_size
+                .clamp(HEADER_SIZE + 1, rist_sys::RIST_MAX_PACKET_SIZE as usize),
+            session: RandomState::new().build_hasher().finish() as u32,
+            next_id: 0,
+        }
+    }
+
+    /// Cut `data` into payloads, to be sent in order. An empty buffer
+    /// still makes one payload, so it arrives as an empty message.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` needs more than `u32::MAX` chunks.
+    pub fn chunk<'a>(&mut self, data: &'a [u8]) -> Chunks<'a> {
+        let body = self.payload_size - HEADER_SIZE;
+        let count = data.len().div_ceil(body).max(1);
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        Chunks {
+            data,
+            body,
+            session: self.session,
+            id,
+            index: 0,
+            count: u32::try_from(count).expect("buffer too large to chunk"),
+        }
+    }
+}
+
+/// The payloads of one buffer, from [`Chunker::chunk`].
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    data: &'a [u8],
+    body: usize,
+    session: u32,
+    id: u32,
+    index: u32,
+    count: u32,
+}
+
+impl Iterator for Chunks<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.index == self.count {
+            return None;
+        }
+        let start = self.index as usize * self.body;
+        let body = &self.data[start..(start + self.body).min(self.data.len())];
+
+        let mut payload = Vec::with_capacity(HEADER_SIZE + body.len());
+        payload.extend_from_slice(MAGIC);
+        payload.extend_from_slice(&self.session.to_be_bytes());
+        payload.extend_from_slice(&self.id.to_be_bytes());
+        payload.extend_from_slice(&self.index.to_be_bytes());
+        payload.extend_from_slice(&self.count.to_be_bytes());
+        payload.extend_from_slice(body);
+        self.index += 1;
+        Some(payload)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = (self.count - self.index) as usize;
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for Chunks<'_> {}
+
+/// A message still missing chunks.
+struct Partial {
+    count: u32,
+    chunks: HashMap<u32, Vec<u8>>,
+    size: usize,
+}
+
+/// Puts buffers cut by [`Chunker`] back together.
+pub struct Reassembler {
+    // by session and message ID
+    partial: HashMap<(u32, u32), Partial>,
+    // incomplete messages, oldest first
+    order: VecDeque<(u32, u32)>,
+    max_incomplete: usize,
+    max_message_size: usize,
+    dropped: u64,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self {
+            partial: HashMap::new(),
+            order: VecDeque::new(),
+            max_incomplete: 16,
+            max_message_size: 64 << 20,
+            dropped: 0,
+        }
+    }
+}
+
+impl Reassembler {
+    /// Create a reassembler keeping up to 16 incomplete messages of up to
+    /// 64 MiB each.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep up to `messages` incomplete messages, dropping the oldest when
+    /// another one starts.
+    pub fn max_incomplete(mut self, messages: usize) -> Self {
+        self.max_incomplete = messages.max(1);
+        self
+    }
+
+    /// Reject messages larger than `bytes`.
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Messages dropped incomplete so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Messages waiting for more chunks.
+    pub fn incomplete(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Take in one received payload, returning the message it completes.
+    ///
+    /// Fails with [`Error::InvalidChunk`] for a payload that wasn't made by
+    /// a [`Chunker`] or a message over the size limit, which is dropped.
+    pub fn push(&mut self, payload: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (session, id, index, count, body) = decode(payload)?;
+        let max_size = self.max_message_size;
+        if count == 1 {
+            if body.len() > max_size {
+                self.dropped += 1;
+                return Err(Error::InvalidChunk(format!(
+                    "message {id} is larger than {max_size} bytes"
+                )));
+            }
+            return Ok(Some(body.to_vec()));
+        }
+
+        let key = (session, id);
+        if !self.partial.contains_key(&key) {
+            while self.partial.len() >= self.max_incomplete {
+                self.drop_oldest();
+            }
+            self.partial.insert(
+                key,
+                Partial {
+                    count,
+                    chunks: HashMap::new(),
+                    size: 0,
+                },
+            );
+            self.order.push_back(key);
+        }
+
+        let partial = self.partial.get_mut(&key).expect("inserted above");
+        if partial.count != count {
+            return Err(Error::InvalidChunk(format!(
+                "message {id} has {count} chunks, expected {}",
+                partial.count
+            )));
+        }
+        if !partial.chunks.contains_key(&index) {
+            partial.size += body.len();
+            partial.chunks.insert(index, body.to_vec());
+        }
+        if partial.size > max_size {
+            self.remove(key);
+            self.dropped += 1;
+            return Err(Error::InvalidChunk(format!(
+                "message {id} is larger than {max_size} bytes"
+            )));
+        }
+        if partial.chunks.len() < count as usize {
+            return Ok(None);
+        }
+
+        let mut partial = self.remove(key).expect("still waiting");
+        let mut message = Vec::with_capacity(partial.size);
+        for index in 0..count {
+            message.extend_from_slice(&partial.chunks.remove(&index).unwrap_or_default());
+        }
+        Ok(Some(message))
+    }
+
+    /// Forget every incomplete message, counting them as dropped.
+    pub fn discard_incomplete(&mut self) {
+        self.dropped += self.partial.len() as u64;
+        self.partial.clear();
+        self.order.clear();
+    }
+
+    fn remove(&mut self, key: (u32, u32)) -> Option<Partial> {
+        self.order.retain(|waiting| *waiting != key);
+        self.partial.remove(&key)
+    }
+
+    fn drop_oldest(&mut self) {
+        if let Some(key) = self.order.pop_front() {
+            self.partial.remove(&key);
+            self.dropped += 1;
+        }
+    }
+}
+
+impl std::fmt::Debug for Reassembler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reassembler")
+            .field("incomplete", &self.partial.len())
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+/// Split a chunk into session ID, message ID, index, count and body.
+fn decode(payload: &[u8]) -> Result<(u32, u32, u32, u32, &[u8])> {
+    if payload.len() < HEADER_SIZE || &payload[..2] != MAGIC {
+        return Err(Error::InvalidChunk("missing chunk header".to_string()));
+    }
+    let field = |at: usize| u32::from_be_bytes(payload[at..at + 4].try_into().unwrap());
+    let (session, id, index, count) = (field(2), field(6), field(10), field(14));
+    if index >= count {
+        return Err(Error::InvalidChunk(format!(
+            "chunk {index} of a {count}-chunk message"
+        )));
+    }
+    Ok((session, id, index, count, &payload[HEADER_SIZE..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_out_of_order() {
+        let data: Vec<u8> = (0..5000u32).map(|n| n as u8).collect();
+        let mut chunker = Chunker::with_payload_size(HEADER_SIZE + 1000);
+        let mut chunks: Vec<_> = chunker.chunk(&data).collect();
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|c| c.len() <= HEADER_SIZE + 1000));
+
+        chunks.swap(0, 3);
+        let mut reassembler = Reassembler::new();
+        let last = chunks.pop().unwrap();
+        for chunk in &chunks {
+            assert_eq!(reassembler.push(chunk).unwrap(), None);
+        }
+        // duplicates are ignored
+        assert_eq!(reassembler.push(&chunks[0]).unwrap(), None);
+        assert_eq!(reassembler.push(&last).unwrap(), Some(data));
+        assert_eq!(reassembler.incomplete(), 0);
+
+        let empty: Vec<_> = chunker.chunk(&[]).collect();
+        assert_eq!(empty.len(), 1);
+        assert_eq!(reassembler.push(&empty[0]).unwrap(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_drops_incomplete_messages() {
+        let mut chunker = Chunker::with_payload_size(HEADER_SIZE + 10);
+        let mut reassembler = Reassembler::new().max_incomplete(2);
+        for _ in 0..3 {
+            let first = chunker.chunk(&[1; 30]).next().unwrap();
+            assert_eq!(reassembler.push(&first).unwrap(), None);
+        }
+        assert_eq!(reassembler.incomplete(), 2);
+        assert_eq!(reassembler.dropped(), 1);
+
+        let mut reassembler = Reassembler::new().max_message_size(15);
+        let chunks: Vec<_> = chunker.chunk(&[1; 30]).collect();
+        reassembler.push(&chunks[0]).unwrap();
+        assert!(matches!(
+            reassembler.push(&chunks[1]),
+            Err(Error::InvalidChunk(_))
+        ));
+        assert!(reassembler.push(b"not a chunk").is_err());
+
+        let mut reassembler = Reassembler::new().max_message_size(5);
+        let single: Vec<_> = chunker.chunk(&[1; 8]).collect();
+        assert_eq!(single.len(), 1);
+        assert!(matches!(
+            reassembler.push(&single[0]),
+            Err(Error::InvalidChunk(_))
+        ));
+        assert_eq!(reassembler.dropped(), 1);
+    }
+
+    #[test]
+    fn test_keeps_sessions_apart() {
+        // a restarted sender starts again from message ID 0
+        let mut before = Chunker::with_payload_size(HEADER_SIZE + 10);
+        let mut after = Chunker::with_payload_size(HEADER_SIZE + 10);
+        let old: Vec<_> = before.chunk(&[1; 20]).collect();
+        let new: Vec<_> = after.chunk(&[2; 20]).collect();
+
+        let mut reassembler = Reassembler::new();
+        assert_eq!(reassembler.push(&old[0]).unwrap(), None);
+        assert_eq!(reassembler.push(&new[0]).unwrap(), None);
+        assert_eq!(reassembler.push(&new[1]).unwrap(), Some(vec![2; 20]));
+        assert_eq!(reassembler.push(&old[1]).unwrap(), Some(vec![1; 20]));
+    }
+}
@@ -0,0 +1,633 @@
+//! Senders and receivers described by a TOML or YAML file.
+//!
+//! A file names the profile, peers, options, logging and stats settings of
+//! one context; [`Config::from_path`] loads it and
+//! [`build_sender`](Config::build_sender) or
+//! [`build_receiver`](Config::build_receiver) turns it into a started
+//! context:
+//!
+//! ```toml
+//! profile = "main"
+//! preset = "satellite"
+//! peers = ["rist://@:5000", "rist://@:5002"]
+//!
+//! [options]
+//! recovery_length_max_ms = 4000
+//! max_retries = 8
+//!
+//! [logging]
+//! level = "info"
+//!
+//! [stats]
+//! interval_ms = 5000
+//! mode = "interval"
+//! ```
+//!
+//! Every key is optional except `peers`. Options start from the preset's,
+//! if any, and the keys under `[options]` override them. The same keys are
+//! used in YAML.
+//!
+//! # Environment overrides
+//!
+//! [`with_env`](Config::with_env) layers these variables on top, so secrets
+//! stay out of config files and containers can be tuned without a rebuild:
+//!
+//! | Variable | Overrides |
+//! |---|---|
+//! | `RIST_PROFILE` | `profile` |
+//! | `RIST_PRESET` | `preset` |
+//! | `RIST_PEER_URL` | `peers`, comma-separated |
+//! | `RIST_FLOW_ID` | `flow_id` |
+//! | `RIST_SECRET` | `secret` |
+//! | `RIST_AES_KEY_SIZE` | `aes_key_size` |
+//! | `RIST_BUFFER_MS` | `recovery_length_min_ms` and `recovery_length_max_ms` |
+//! | `RIST_RTT_MIN_MS` | `recovery_rtt_min_ms` |
+//! | `RIST_RTT_MAX_MS` | `recovery_rtt_max_ms` |
+//! | `RIST_MAX_RETRIES` | `max_retries` |
+//! | `RIST_LOG_LEVEL` | `logging.level` |
+//! | `RIST_STATS_INTERVAL_MS` | `stats.interval_ms` |
+//!
+//! Empty variables are ignored.
+
+use crate::url::has_param;
+use crate::{
+    Error, LogLevel, Preset, Profile, Receiver, ReceiverBuilder, ReceiverOptions, Result, RistUrl,
+    Sender, SenderBuilder, SenderOptions, StatsMode,
+};
+use serde::{Deserialize, Deserializer};
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A sender or receiver loaded from a file.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::Config;
+///
+/// let config = Config::from_path("/etc/rist/contribution.toml")?;
+/// let receiver = config.receiver_builder()?
+///     .on_stats(|stats| println!("quality: {:.1}%", stats.quality))
+///     .build()?;
+/// # Ok::<(), rist::Error>(())
+/// ```
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// RIST profile, the preset's or Main if not set.
+    #[serde(default, deserialize_with = "parsed")]
+    pub profile: Option<Profile>,
+    /// Preset the options start from.
+    #[serde(default, deserialize_with = "parsed")]
+    pub preset: Option<Preset>,
+    /// Flow ID a sender sends with, see [`Sender::with_flow_id`].
+    #[serde(default)]
+    pub flow_id: Option<u32>,
+    /// Peer URLs, all sharing the same options.
+    pub peers: Vec<String>,
+    /// Encryption passphrase added to every peer URL, see
+    /// [`RistUrl::append_param`] for the characters it may not contain.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// AES key size in bits to encrypt with, the preset's or 128 if not set.
+    #[serde(default)]
+    pub aes_key_size: Option<u32>,
+    /// Peer options.
+    #[serde(default)]
+    pub options: OptionsConfig,
+    /// Logging settings.
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Stats settings.
+    #[serde(default)]
+    pub stats: StatsConfig,
+}
+
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("profile", &self.profile)
+            .field("preset", &self.preset)
+            .field("flow_id", &self.flow_id)
+            .field("peers", &self.peers)
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .field("aes_key_size", &self.aes_key_size)
+            .field("options", &self.options)
+            .field("logging", &self.logging)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+/// The `[options]` section of a [`Config`].
+///
+/// Each key sets the [`SenderOptions`] or [`ReceiverOptions`] field of the
+/// same name, with durations in milliseconds. Keys that only apply to a
+/// receiver are ignored by a sender.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OptionsConfig {
+    /// Derive the recovery settings from this latency budget, see
+    /// [`ReceiverOptions::with_target_latency`]. Needs `expected_bitrate`.
+    pub target_latency_ms: Option<u64>,
+    /// Expected stream bitrate in bits per second, for `target_latency_ms`.
+    pub expected_bitrate: Option<u64>,
+    /// Minimum recovery buffer length.
+    pub recovery_length_min_ms: Option<u64>,
+    /// Maximum recovery buffer length.
+    pub recovery_length_max_ms: Option<u64>,
+    /// Lowest RTT recovery assumes.
+    pub recovery_rtt_min_ms: Option<u64>,
+    /// Highest RTT recovery assumes.
+    pub recovery_rtt_max_ms: Option<u64>,
+    /// Reorder buffer size in packets.
+    pub recovery_reorder_buffer: Option<u32>,
+    /// Maximum recovery bitrate in kbps.
+    pub recovery_maxbitrate: Option<u32>,
+    /// Maximum bitrate for the return channel in kbps.
+    pub recovery_maxbitrate_return: Option<u32>,
+    /// Most retransmission requests per lost packet.
+    pub max_retries: Option<u32>,
+    /// Load balancing weight among peers, 0 to duplicate to all.
+    pub weight: Option<u32>,
+    /// Time without data after which the peer is considered gone.
+    pub session_timeout_ms: Option<u64>,
+    /// Interval between keepalives.
+    pub keepalive_interval_ms: Option<u64>,
+    /// DSCP code point to mark outgoing packets with.
+    pub dscp: Option<u8>,
+    /// Unicast TTL / hop limit of outgoing packets.
+    pub ttl: Option<u8>,
+    /// Socket receive buffer size in bytes.
+    pub recv_buffer_size: Option<usize>,
+    /// Socket send buffer size in bytes.
+    pub send_buffer_size: Option<usize>,
+    /// Receiver output FIFO size in packets, 0 to disable.
+    pub fifo_size: Option<u32>,
+}
+
+/// The `[logging]` section of a [`Config`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// Log librist messages for this context at this level, e.g. `"info"`.
+    #[serde(default, deserialize_with = "parsed")]
+    pub level: Option<LogLevel>,
+}
+
+/// The `[stats]` section of a [`Config`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsConfig {
+    /// How often librist reports stats.
+    pub interval_ms: Option<u64>,
+    /// `"cumulative"` or `"interval"` counters, see [`StatsMode`].
+    #[serde(default, deserialize_with = "parsed")]
+    pub mode: Option<StatsMode>,
+}
+
+/// Deserialize a string with the type's [`FromStr`], so files use the same
+/// names as the command line tools.
+fn parsed<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Parse the environment variable `name`, if it is set and not empty.
+fn env_var<T>(var: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    var(name)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| Error::Configuration(format!("{name}: {e}")))
+        })
+        .transpose()
+}
+
+/// Apply an [`OptionsConfig`] to a [`SenderOptions`] or [`ReceiverOptions`].
+macro_rules! apply_options {
+    ($options:expr, $config:expr) => {{
+        let config = $config;
+        let mut options = $options;
+        if let Some(latency) = config.target_latency_ms {
+            let bitrate = config.expected_bitrate.ok_or_else(|| {
+                Error::Configuration("target_latency_ms needs expected_bitrate".to_string())
+            })?;
+            options = options.with_target_latency(Duration::from_millis(latency), bitrate);
+        }
+        if let Some(ms) = config.recovery_length_min_ms {
+            options = options.recovery_length_min(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.recovery_length_max_ms {
+            options = options.recovery_length_max(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.recovery_rtt_min_ms {
+            options = options.recovery_rtt_min(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.recovery_rtt_max_ms {
+            options = options.recovery_rtt_max(Duration::from_millis(ms));
+        }
+        if let Some(packets) = config.recovery_reorder_buffer {
+            options = options.recovery_reorder_buffer(packets);
+        }
+        if let Some(bitrate) = config.recovery_maxbitrate {
+            options = options.recovery_maxbitrate(bitrate);
+        }
+        if let Some(bitrate) = config.recovery_maxbitrate_return {
+            options = options.recovery_maxbitrate_return(bitrate);
+        }
+        if let Some(retries) = config.max_retries {
+            options = options.max_retries(retries);
+        }
+        if let Some(weight) = config.weight {
+            options = options.weight(weight);
+        }
+        if let Some(ms) = config.session_timeout_ms {
+            options = options.session_timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.keepalive_interval_ms {
+            options = options.keepalive_interval(Duration::from_millis(ms));
+        }
+        if let Some(dscp) = config.dscp {
+            options = options.dscp(dscp);
+        }
+        if let Some(ttl) = config.ttl {
+            options = options.ttl(ttl);
+        }
+        if let Some(bytes) = config.recv_buffer_size {
+            options = options.recv_buffer_size(bytes);
+        }
+        if let Some(bytes) = config.send_buffer_size {
+            options = options.send_buffer_size(bytes);
+        }
+        options.validate()?;
+        options
+    }};
+}
+
+impl Config {
+    /// Load a config file, TOML or YAML by its extension (`.toml`, `.yaml`
+    /// or `.yml`).
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let config = match extension.to_ascii_lowercase().as_str() {
+            "toml" => Self::from_toml(&text),
+            "yaml" | "yml" => Self::from_yaml(&text),
+            _ => {
+                return Err(Error::Configuration(format!(
+                    "{}: expected a .toml, .yaml or .yml file",
+                    path.display()
+                )))
+            }
+        };
+        config.map_err(|e| Error::Configuration(format!("{}: {e}", path.display())))
+    }
+
+    /// Parse a config from TOML.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| Error::Configuration(e.message().to_string()))
+    }
+
+    /// Parse a config from YAML.
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        serde_yaml::from_str(text).map_err(|e| Error::Configuration(e.to_string()))
+    }
+
+    /// A config from the environment variables alone.
+    pub fn from_env() -> Result<Self> {
+        Self::default().with_env()
+    }
+
+    /// Override settings with the `RIST_*` environment variables, see the
+    /// [module docs](self).
+    pub fn with_env(self) -> Result<Self> {
+        self.with_vars(|name| std::env::var(name).ok())
+    }
+
+    fn with_vars(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        if let Some(profile) = env_var(&var, "RIST_PROFILE")? {
+            self.profile = Some(profile);
+        }
+        if let Some(preset) = env_var(&var, "RIST_PRESET")? {
+            self.preset = Some(preset);
+        }
+        if let Some(urls) = env_var::<String>(&var, "RIST_PEER_URL")? {
+            self.peers = urls
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(flow_id) = env_var(&var, "RIST_FLOW_ID")? {
+            self.flow_id = Some(flow_id);
+        }
+        if let Some(secret) = env_var(&var, "RIST_SECRET")? {
+            self.secret = Some(secret);
+        }
+        if let Some(key_size) = env_var(&var, "RIST_AES_KEY_SIZE")? {
+            self.aes_key_size = Some(key_size);
+        }
+        if let Some(ms) = env_var(&var, "RIST_BUFFER_MS")? {
+            self.options.recovery_length_min_ms = Some(ms);
+            self.options.recovery_length_max_ms = Some(ms);
+        }
+        if let Some(ms) = env_var(&var, "RIST_RTT_MIN_MS")? {
+            self.options.recovery_rtt_min_ms = Some(ms);
+        }
+        if let Some(ms) = env_var(&var, "RIST_RTT_MAX_MS")? {
+            self.options.recovery_rtt_max_ms = Some(ms);
+        }
+        if let Some(retries) = env_var(&var, "RIST_MAX_RETRIES")? {
+            self.options.max_retries = Some(retries);
+        }
+        if let Some(level) = env_var(&var, "RIST_LOG_LEVEL")? {
+            self.logging.level = Some(level);
+        }
+        if let Some(ms) = env_var(&var, "RIST_STATS_INTERVAL_MS")? {
+            self.stats.interval_ms = Some(ms);
+        }
+        Ok(self)
+    }
+
+    /// The peer URLs, with the secret and key size added if a secret is set.
+    ///
+    /// A URL that already has a `secret` is left as it is, and one that
+    /// already has an `aes-type` keeps it.
+    pub fn peer_urls(&self) -> Result<Vec<String>> {
+        let Some(secret) = &self.secret else {
+            return Ok(self.peers.clone());
+        };
+        let key_size = self
+            .aes_key_size
+            .or(self.preset.and_then(Preset::aes_key_size))
+            .unwrap_or(128);
+        self.peers
+            .iter()
+            .map(|url| {
+                if has_param(url, "secret") {
+                    return Ok(url.clone());
+                }
+                let url = RistUrl::append_param(url, "secret", secret)?;
+                if has_param(&url, "aes-type") {
+                    return Ok(url);
+                }
+                RistUrl::append_param(&url, "aes-type", key_size)
+            })
+            .collect()
+    }
+
+    /// The profile to create the context with.
+    pub fn profile(&self) -> Profile {
+        self.profile
+            .or(self.preset.map(Preset::profile))
+            .unwrap_or_default()
+    }
+
+    /// Sender options for every peer.
+    pub fn sender_options(&self) -> Result<SenderOptions> {
+        let options = self.preset.map(Preset::sender_options).unwrap_or_default();
+        Ok(apply_options!(options, &self.options))
+    }
+
+    /// Receiver options for every peer.
+    pub fn receiver_options(&self) -> Result<ReceiverOptions> {
+        let options = self
+            .preset
+            .map(Preset::receiver_options)
+            .unwrap_or_default();
+        let mut options = apply_options!(options, &self.options);
+        if let Some(size) = self.options.fifo_size {
+            options = options.fifo_size(size);
+        }
+        Ok(options)
+    }
+
+    /// A sender builder with everything in the config, to add callbacks to
+    /// before building.
+    pub fn sender_builder(&self) -> Result<SenderBuilder> {
+        let options = self.sender_options()?;
+        let mut builder = Sender::builder().profile(self.profile());
+        if let Some(flow_id) = self.flow_id {
+            builder = builder.flow_id(flow_id);
+        }
+        if let Some(level) = self.logging.level {
+            builder = builder.log_level(level);
+        }
+        if let Some(ms) = self.stats.interval_ms {
+            builder = builder.stats_interval(Duration::from_millis(ms));
+        }
+        if let Some(mode) = self.stats.mode {
+            builder = builder.stats_mode(mode);
+        }
+        Ok(self.peer_urls()?.into_iter().fold(builder, |builder, url| {
+            builder.peer_with_options(url, options.clone())
+        }))
+    }
+
+    /// A receiver builder with everything in the config, to add callbacks
+    /// to before building.
+    pub fn receiver_builder(&self) -> Result<ReceiverBuilder> {
+        let options = self.receiver_options()?;
+        let mut builder = Receiver::builder().profile(self.profile());
+        if let Some(level) = self.logging.level {
+            builder = builder.log_level(level);
+        }
+        if let Some(ms) = self.stats.interval_ms {
+            builder = builder.stats_interval(Duration::from_millis(ms));
+        }
+        if let Some(mode) = self.stats.mode {
+            builder = builder.stats_mode(mode);
+        }
+        Ok(self.peer_urls()?.into_iter().fold(builder, |builder, url| {
+            builder.peer_with_options(url, options.clone())
+        }))
+    }
+
+    /// Create the sender, add every peer and start it.
+    pub fn build_sender(&self) -> Result<Sender> {
+        self.sender_builder()?.build()
+    }
+
+    /// Create the receiver, add every peer and start it.
+    pub fn build_receiver(&self) -> Result<Receiver> {
+        self.receiver_builder()?.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+        preset = "satellite"
+        flow_id = 7
+        peers = ["rist://@:5000", "rist://@:5002"]
+
+        [options]
+        recovery_length_max_ms = 4000
+        fifo_size = 1024
+
+        [logging]
+        level = "info"
+
+        [stats]
+        interval_ms = 5000
+        mode = "interval"
+    "#;
+
+    #[test]
+    fn test_toml_and_yaml_agree() {
+        let yaml = "
+preset: satellite
+flow_id: 7
+peers:
+  - rist://@:5000
+  - rist://@:5002
+options:
+  recovery_length_max_ms: 4000
+  fifo_size: 1024
+logging:
+  level: info
+stats:
+  interval_ms: 5000
+  mode: interval
+";
+        let config = Config::from_toml(TOML).unwrap();
+        assert_eq!(config, Config::from_yaml(yaml).unwrap());
+        assert_eq!(config.profile(), Profile::Main);
+        assert_eq!(config.logging.level, Some(LogLevel::Info));
+        assert_eq!(config.peers.len(), 2);
+    }
+
+    #[test]
+    fn test_options_override_preset() {
+        let config = Config::from_toml(TOML).unwrap();
+        let options = config.receiver_options().unwrap();
+        assert_eq!(options.recovery_length_max, Some(Duration::from_secs(4)));
+        assert_eq!(
+            options.recovery_rtt_max,
+            Preset::Satellite.receiver_options().recovery_rtt_max
+        );
+        assert_eq!(options.fifo_size, Some(1024));
+    }
+
+    #[test]
+    fn test_rejects_bad_configs() {
+        // unknown keys are typos, not silently ignored
+        assert!(Config::from_toml("peers = []\nbuffer_ms = 100").is_err());
+        assert!(Config::from_toml("peers = []\nprofile = \"extended\"").is_err());
+        assert!(Config::from_toml("profile = \"main\"").is_err());
+
+        let config = Config::from_toml("peers = []\n[options]\ntarget_latency_ms = 500").unwrap();
+        assert!(matches!(
+            config.sender_options(),
+            Err(Error::Configuration(_))
+        ));
+        let config = Config::from_toml(
+            "peers = []\n[options]\nrecovery_length_min_ms = 500\nrecovery_length_max_ms = 100",
+        )
+        .unwrap();
+        assert!(matches!(
+            config.receiver_options(),
+            Err(Error::InvalidOption { .. })
+        ));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = |name: &str| {
+            let value = match name {
+                "RIST_PEER_URL" => "rist://10.0.0.2:5000, rist://10.0.0.3:5000?cname=b",
+                "RIST_SECRET" => "s3cret",
+                "RIST_BUFFER_MS" => "1500",
+                "RIST_LOG_LEVEL" => "debug",
+                "RIST_FLOW_ID" => "",
+                _ => return None,
+            };
+            Some(value.to_string())
+        };
+        let config = Config::from_toml(TOML).unwrap().with_vars(vars).unwrap();
+        assert_eq!(config.logging.level, Some(LogLevel::Debug));
+        assert!(!format!("{config:?}").contains("s3cret"));
+        // empty variables leave the file's value alone
+        assert_eq!(config.flow_id, Some(7));
+        assert_eq!(
+            config.peer_urls().unwrap(),
+            [
+                "rist://10.0.0.2:5000?secret=s3cret&aes-type=256",
+                "rist://10.0.0.3:5000?cname=b&secret=s3cret&aes-type=256",
+            ]
+        );
+        let options = config.sender_options().unwrap();
+        assert_eq!(
+            options.recovery_length_min,
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            options.recovery_length_max,
+            Some(Duration::from_millis(1500))
+        );
+
+        let bad = |name: &str| (name == "RIST_BUFFER_MS").then(|| "1.5s".to_string());
+        assert!(matches!(
+            Config::default().with_vars(bad),
+            Err(Error::Configuration(reason)) if reason.starts_with("RIST_BUFFER_MS")
+        ));
+        let vars = |name: &str| {
+            let value = match name {
+                "RIST_PEER_URL" => {
+                    "rist://10.0.0.2:5000?secret=own&aes-type=128, rist://10.0.0.3:5000?aes-type=128"
+                }
+                "RIST_SECRET" => "s3cret",
+                _ => return None,
+            };
+            Some(value.to_string())
+        };
+        let config = Config::from_toml(TOML).unwrap().with_vars(vars).unwrap();
+        assert_eq!(
+            config.peer_urls().unwrap(),
+            [
+                "rist://10.0.0.2:5000?secret=own&aes-type=128",
+                "rist://10.0.0.3:5000?aes-type=128&secret=s3cret",
+            ]
+        );
+
+        let secret = |name: &str| (name == "RIST_SECRET").then(|| "a&b".to_string());
+        let config = Config::from_toml(TOML).unwrap().with_vars(secret).unwrap();
+        assert!(config.peer_urls().is_err());
+    }
+
+    #[test]
+    fn test_from_path() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("rist-config-{}.toml", std::process::id()));
+        std::fs::write(&path, TOML).unwrap();
+        let config = Config::from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.unwrap().flow_id, Some(7));
+
+        let path = dir.join(format!("rist-config-{}.ini", std::process::id()));
+        assert!(matches!(Config::from_path(&path), Err(Error::Io(_))));
+        std::fs::write(&path, TOML).unwrap();
+        let config = Config::from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(config, Err(Error::Configuration(_))));
+    }
+}
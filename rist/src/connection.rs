@@ -0,0 +1,199 @@
+//! Peer connection tracking via the librist connection status callback.
+
+use crate::peer::{Peer, PeerInfo};
+use crate::{PeerId, Profile};
+use std::collections::HashMap;
+use std::os::raw::c_void;
+use std::sync::{Arc, Mutex};
+
+/// Connection state of a peer, as reported by librist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// The peer was created but no handshake has completed yet.
+    Connecting,
+    /// The peer is exchanging data and keepalives.
+    Connected,
+    /// The peer stopped responding.
+    TimedOut,
+}
+
+impl ConnectionState {
+    fn from_raw(status: rist_sys::rist_connection_status) -> Self {
+        match status {
+            rist_sys::rist_connection_status_RIST_CONNECTION_ESTABLISHED
+            | rist_sys::rist_connection_status_RIST_CLIENT_CONNECTED => Self::Connected,
+            _ => Self::TimedOut,
+        }
+    }
+}
+
+/// Callback invoked with every connection status change.
+pub(crate) type ConnectionCallback = Box<dyn FnMut(PeerId, ConnectionState) + Send>;
+
+/// Latest connection state of every peer on a context.
+///
+/// librist holds a raw pointer to this while the connection callback is
+/// registered, so it must outlive the context.
+pub(crate) struct ConnectionSlot {
+    states: Mutex<HashMap<PeerId, ConnectionState>>,
+    callback: Mutex<Option<ConnectionCallback>>,
+}
+
+impl ConnectionSlot {
+    pub(crate) fn new() -> Self {
+        Self {
+            states: Mutex::new(HashMap::new()),
+            callback: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn set_callback(&self, callback: Option<ConnectionCallback>) {
+        *self.callback.lock().unwrap_or_else(|e| e.into_inner()) = callback;
+    }
+
+    /// Returns `true` if any peer on the context is connected.
+    pub(crate) fn is_connected(&self) -> bool {
+        self.states
+            .lock()
+            .map(|states| states.values().any(|s| *s == ConnectionState::Connected))
+            .unwrap_or(false)
+    }
+
+    /// Peers currently connected, own and remote.
+    pub(crate) fn connected_peers(&self) -> Vec<PeerId> {
+        self.states
+            .lock()
+            .map(|states| {
+                states
+                    .iter()
+                    .filter(|(_, state)| **state == ConnectionState::Connected)
+                    .map(|(peer, _)| *peer)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Latest state of `peer`, or `None` if it isn't known on this context.
+    pub(crate) fn state(&self, peer: PeerId) -> Option<ConnectionState> {
+        self.states
+            .lock()
+            .ok()
+            .and_then(|states| states.get(&peer).copied())
+    }
+
+    /// Describe `own` peers plus any remote peers that connected to them,
+    /// on a context created with `profile`.
+    pub(crate) fn peers(&self, own: &[Peer], profile: Profile) -> Vec<PeerInfo> {
+        let Ok(states) = self.states.lock() else {
+            return Vec::new();
+        };
+
+        let mut infos: Vec<PeerInfo> = own
+            .iter()
+            .map(|peer| PeerInfo {
+                id: peer.id(),
+                url: Some(peer.url.clone()),
+                cname: peer.cname.clone(),
+                state: states
+                    .get(&peer.id())
+                    .copied()
+                    .unwrap_or(ConnectionState::Connecting),
+                socket_buffers: peer.socket_buffers(),
+                profile: peer.profile,
+                remote_cname: None,
+                stats: None,
+            })
+            .collect();
+
+        let mut remote: Vec<PeerInfo> = states
+            .iter()
+            .filter(|(id, _)| !own.iter().any(|peer| peer.id() == **id))
+            .map(|(id, state)| PeerInfo {
+                id: *id,
+                url: None,
+                cname: None,
+                state: *state,
+                socket_buffers: None,
+                profile,
+                remote_cname: None,
+                stats: None,
+            })
+            .collect();
+        remote.sort_by_key(|info| info.id);
+        infos.append(&mut remote);
+        infos
+    }
+
+    /// Start tracking a newly created peer.
+    pub(crate) fn track(&self, peer: PeerId) {
+        if let Ok(mut states) = self.states.lock() {
+            states.entry(peer).or_insert(ConnectionState::Connecting);
+        }
+    }
+
+    /// Stop tracking a destroyed peer.
+    pub(crate) fn forget(&self, peer: PeerId) {
+        if let Ok(mut states) = self.states.lock() {
+            states.remove(&peer);
+        }
+    }
+
+    /// Forget every peer, after they have been destroyed.
+    pub(crate) fn clear(&self) {
+        if let Ok(mut states) = self.states.lock() {
+            states.clear();
+        }
+    }
+
+    fn update(&self, peer: PeerId, state: ConnectionState, remove: bool) {
+        if let Ok(mut states) = self.states.lock() {
+            if remove {
+                states.remove(&peer);
+            } else {
+                states.insert(peer, state);
+            }
+        }
+        crate::callback::call_user(&self.callback, |callback| callback(peer, state));
+    }
+}
+
+/// Connection status callback for librist, `arg` is a `*const ConnectionSlot`.
+unsafe extern "C" fn connection_callback(
+    arg: *mut c_void,
+    peer: *mut rist_sys::rist_peer,
+    status: rist_sys::rist_connection_status,
+) {
+    if arg.is_null() || peer.is_null() {
+        return;
+    }
+
+    let slot = &*(arg as *const ConnectionSlot);
+    // librist destroys remote peers of a listener once they time out
+    let remove = status == rist_sys::rist_connection_status_RIST_CLIENT_TIMED_OUT;
+    crate::callback::guard("connection", (), || {
+        slot.update(
+            PeerId::from_raw(peer),
+            ConnectionState::from_raw(status),
+            remove,
+        )
+    });
+}
+
+/// Register `slot` to receive connection status changes from `ctx`.
+pub(crate) fn register_connection(
+    ctx: *mut rist_sys::rist_ctx,
+    slot: &Arc<ConnectionSlot>,
+) -> crate::Result<()> {
+    let slot_ptr = Arc::as_ptr(slot) as *mut c_void;
+
+    let ret = unsafe {
+        rist_sys::rist_connection_status_callback_set(ctx, Some(connection_callback), slot_ptr)
+    };
+    if ret != 0 {
+        return Err(crate::Error::Configuration(
+            "failed to set connection status callback".to_string(),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,563 @@
+//! Application-level continuity checks on received sequence numbers.
+//!
+//! librist's flow stats describe what happened on the wire, including
+//! packets it recovered. A [`LossDetector`] looks at what actually reached
+//! the application: it follows the `seq` of every [`DataBlock`] handed to it
+//! and reports gaps, late (reordered) arrivals and duplicates, per flow.
+//! A [`Reorderer`] goes further and puts blocks back in sequence order,
+//! marking the gaps it gives up on.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::continuity::{LossDetector, SeqEvent};
+//! use rist::{Profile, Receiver};
+//! use std::time::Duration;
+//!
+//! # fn example() -> rist::Result<()> {
+//! let mut receiver = Receiver::new(Profile::Main)?;
+//! receiver.add_peer("rist://@:5000")?;
+//! receiver.start()?;
+//!
+//! let mut detector = LossDetector::new();
+//! while let Some(block) = receiver.read(Duration::from_millis(100))? {
+//!     if let SeqEvent::Gap { first, missing } = detector.observe(&block) {
+//!         eprintln!("lost {missing} packets starting at {first}");
+//!     }
+//! }
+//! println!("{:?}", detector.stats());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::DataBlock;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How far back, in sequence numbers, missing packets are remembered.
+pub const DEFAULT_WINDOW: u64 = 1024;
+
+/// What one observed sequence number meant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqEvent {
+    /// The first packet of the flow, or the one after the newest so far.
+    InOrder,
+    /// Packets were skipped: `missing` sequence numbers starting at `first`
+    /// have not arrived (yet).
+    Gap {
+        /// First skipped sequence number.
+        first: u64,
+        /// Number of skipped sequence numbers.
+        missing: u64,
+    },
+    /// A packet reported missing by an earlier gap arrived late.
+    Reordered,
+    /// A packet that was already delivered arrived again.
+    Duplicate,
+    /// The sequence jumped back further than the window, e.g. because the
+    /// sender restarted or the counter wrapped; tracking starts over.
+    Discontinuity {
+        /// The sequence number that was expected next.
+        expected: u64,
+        /// The sequence number that arrived.
+        received: u64,
+    },
+}
+
+/// Counters kept by a [`LossDetector`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContinuityStats {
+    /// Packets observed, duplicates included.
+    pub received: u64,
+    /// Sequence numbers skipped and not delivered late.
+    pub lost: u64,
+    /// Packets that arrived after a later one.
+    pub reordered: u64,
+    /// Packets delivered more than once.
+    pub duplicates: u64,
+    /// Times the sequence restarted.
+    pub discontinuities: u64,
+}
+
+impl ContinuityStats {
+    fn add(&mut self, other: &Self) {
+        self.received += other.received;
+        self.lost += other.lost;
+        self.reordered += other.reordered;
+        self.duplicates += other.duplicates;
+        self.discontinuities += other.discontinuities;
+    }
+}
+
+#[derive(Debug, Default)]
+struct FlowState {
+    // newest sequence number seen, None before the first packet
+    highest: Option<u64>,
+    // skipped sequence numbers within the window
+    missing: BTreeSet<u64>,
+    stats: ContinuityStats,
+}
+
+impl FlowState {
+    fn observe(&mut self, seq: u64, window: u64) -> SeqEvent {
+        self.stats.received += 1;
+        let Some(highest) = self.highest else {
+            self.highest = Some(seq);
+            return SeqEvent::InOrder;
+        };
+
+        if seq > highest {
+            self.highest = Some(seq);
+            // forget what fell out of the window; it stays counted as lost
+            let floor = seq.saturating_sub(window);
+            self.missing = self.missing.split_off(&floor);
+
+            let expected = highest + 1;
+            if seq == expected {
+                return SeqEvent::InOrder;
+            }
+            let missing = seq - expected;
+            self.stats.lost += missing;
+            self.missing.extend(expected.max(floor)..seq);
+            return SeqEvent::Gap {
+                first: expected,
+                missing,
+            };
+        }
+
+        if highest - seq > window {
+            self.stats.discontinuities += 1;
+            self.highest = Some(seq);
+            self.missing.clear();
+            return SeqEvent::Discontinuity {
+                expected: highest + 1,
+                received: seq,
+            };
+        }
+        if self.missing.remove(&seq) {
+            self.stats.lost -= 1;
+            self.stats.reordered += 1;
+            SeqEvent::Reordered
+        } else {
+            self.stats.duplicates += 1;
+            SeqEvent::Duplicate
+        }
+    }
+}
+
+/// Tracks the sequence numbers of received blocks and reports gaps,
+/// reordering and duplicates, independently of librist's own stats.
+///
+/// Each flow is tracked separately. Missing packets are remembered for
+/// [`DEFAULT_WINDOW`] sequence numbers (see [`window`](Self::window)); a
+/// packet older than that is taken as the sequence jumping back, a
+/// [`SeqEvent::Discontinuity`].
+#[derive(Debug)]
+pub struct LossDetector {
+    window: u64,
+    flows: HashMap<u32, FlowState>,
+}
+
+impl Default for LossDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LossDetector {
+    /// Create a detector with the default window.
+    pub fn new() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            flows: HashMap::new(),
+        }
+    }
+
+    /// Remember missing packets for `window` sequence numbers.
+    pub fn window(mut self, window: u64) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Check the sequence number of a received block.
+    pub fn observe(&mut self, block: &DataBlock) -> SeqEvent {
+        self.observe_seq(block.flow_id(), block.seq())
+    }
+
+    /// Check a sequence number received on `flow_id`.
+    pub fn observe_seq(&mut self, flow_id: u32, seq: u64) -> SeqEvent {
+        let window = self.window;
+        self.flows.entry(flow_id).or_default().observe(seq, window)
+    }
+
+    /// Counters summed over every flow.
+    pub fn stats(&self) -> ContinuityStats {
+        let mut total = ContinuityStats::default();
+        for flow in self.flows.values() {
+            total.add(&flow.stats);
+        }
+        total
+    }
+
+    /// Counters for one flow, if it has been seen.
+    pub fn flow_stats(&self, flow_id: u32) -> Option<ContinuityStats> {
+        self.flows.get(&flow_id).map(|flow| flow.stats)
+    }
+
+    /// Forget every flow and its counters.
+    pub fn reset(&mut self) {
+        self.flows.clear();
+    }
+}
+
+/// Sequence numbers skipped in the output of a [`Reorderer`].
+///
+/// Also the error an `AsyncReceiver` in ordered mode returns from a read
+/// for a gap, see `AsyncReceiver::set_ordered_reads`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeqGap {
+    /// First skipped sequence number.
+    pub first: u64,
+    /// Number of skipped sequence numbers.
+    pub missing: u64,
+}
+
+impl std::fmt::Display for SeqGap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} packets missing from sequence number {}",
+            self.missing, self.first
+        )
+    }
+}
+
+impl std::error::Error for SeqGap {}
+
+/// Output of a [`Reorderer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ordered<T> {
+    /// The next item in sequence.
+    Item(T),
+    /// Sequence numbers that never arrived; the next item follows them.
+    Gap(SeqGap),
+}
+
+/// Puts items back in sequence order.
+///
+/// Items are held while an earlier sequence number is missing, until
+/// `window` items are waiting or, with a [`max_hold`](Self::max_hold), the
+/// first of them has waited that long; then the missing ones are given up
+/// on and reported as a [`SeqGap`]. [`flush`](Self::flush) gives up on
+/// everything missing at once, e.g. at the end of a stream.
+///
+/// Items older than what was already handed out are dropped, except for
+/// one more than `window` behind, which is taken as the sequence starting
+/// over: everything held is handed out first, then ordering restarts from
+/// it.
+///
+/// Sequence numbers of one flow are expected; blocks from several flows
+/// can't be ordered against each other.
+#[derive(Debug)]
+pub struct Reorderer<T = DataBlock> {
+    window: usize,
+    max_hold: Option<Duration>,
+    next: Option<u64>,
+    // with the time each item arrived
+    held: BTreeMap<u64, (Instant, T)>,
+    ready: VecDeque<Ordered<T>>,
+    dropped: u64,
+}
+
+impl<T> Reorderer<T> {
+    /// Hold up to `window` items while waiting for a missing one.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            max_hold: None,
+            next: None,
+            held: BTreeMap::new(),
+            ready: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Also give up on a missing item once the first item behind it has
+    /// been held for `max_hold`, however few are held.
+    pub fn max_hold(mut self, max_hold: Duration) -> Self {
+        self.max_hold = Some(max_hold);
+        self
+    }
+
+    /// Take in the item with sequence number `seq`.
+    pub fn push(&mut self, seq: u64, item: T) {
+        self.push_at(seq, item, Instant::now());
+    }
+
+    /// The next item or gap, `None` while waiting for a missing item.
+    pub fn pop(&mut self) -> Option<Ordered<T>> {
+        self.pop_at(Instant::now())
+    }
+
+    /// When [`pop`](Self::pop) gives up on the missing item it is waiting
+    /// for, `None` without a [`max_hold`](Self::max_hold) or if it isn't
+    /// waiting.
+    pub fn deadline(&self) -> Option<Instant> {
+        let max_hold = self.max_hold?;
+        if !self.ready.is_empty() || self.held.contains_key(&self.next?) {
+            return None;
+        }
+        let arrived = self.held.values().map(|(arrived, _)| *arrived).min()?;
+        Some(arrived + max_hold)
+    }
+
+    /// Give up on every missing item, so [`pop`](Self::pop) hands out
+    /// everything held, with the gaps between.
+    pub fn flush(&mut self) {
+        self.release_all();
+    }
+
+    fn push_at(&mut self, seq: u64, item: T, now: Instant) {
+        let Some(next) = self.next else {
+            self.next = Some(seq);
+            self.held.insert(seq, (now, item));
+            return;
+        };
+        if seq < next {
+            if next - seq <= self.window as u64 {
+                // late or duplicate
+                self.dropped += 1;
+                return;
+            }
+            self.release_all();
+            self.next = Some(seq);
+        }
+        if self.held.insert(seq, (now, item)).is_some() {
+            self.dropped += 1;
+        }
+    }
+
+    fn pop_at(&mut self, now: Instant) -> Option<Ordered<T>> {
+        if let Some(ready) = self.ready.pop_front() {
+            return Some(ready);
+        }
+        let next = self.next?;
+        if let Some((_, item)) = self.held.remove(&next) {
+            self.next = Some(next + 1);
+            return Some(Ordered::Item(item));
+        }
+        let expired = self.deadline().is_some_and(|deadline| deadline <= now);
+        if self.held.len() < self.window && !expired {
+            return None;
+        }
+        let (&first_held, _) = self.held.first_key_value()?;
+        self.next = Some(first_held);
+        Some(Ordered::Gap(SeqGap {
+            first: next,
+            missing: first_held - next,
+        }))
+    }
+
+    /// Items waiting for a missing one.
+    pub fn held(&self) -> usize {
+        self.held.len() + self.ready.len()
+    }
+
+    /// Late and duplicate items dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Queue everything held for output, with the gaps between.
+    fn release_all(&mut self) {
+        let mut next = self.next;
+        for (seq, (_, item)) in std::mem::take(&mut self.held) {
+            if let Some(expected) = next.filter(|expected| *expected < seq) {
+                self.ready.push_back(Ordered::Gap(SeqGap {
+                    first: expected,
+                    missing: seq - expected,
+                }));
+            }
+            self.ready.push_back(Ordered::Item(item));
+            next = Some(seq + 1);
+        }
+        if next.is_some() {
+            self.next = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap_then_late_arrival() {
+        let mut detector = LossDetector::new();
+        assert_eq!(detector.observe_seq(1, 10), SeqEvent::InOrder);
+        assert_eq!(detector.observe_seq(1, 11), SeqEvent::InOrder);
+        assert_eq!(
+            detector.observe_seq(1, 15),
+            SeqEvent::Gap {
+                first: 12,
+                missing: 3
+            }
+        );
+        assert_eq!(detector.observe_seq(1, 13), SeqEvent::Reordered);
+        assert_eq!(detector.observe_seq(1, 13), SeqEvent::Duplicate);
+        assert_eq!(detector.observe_seq(1, 15), SeqEvent::Duplicate);
+
+        let stats = detector.stats();
+        assert_eq!(stats.received, 6);
+        assert_eq!(stats.lost, 2);
+        assert_eq!(stats.reordered, 1);
+        assert_eq!(stats.duplicates, 2);
+    }
+
+    #[test]
+    fn test_flows_are_independent() {
+        let mut detector = LossDetector::new();
+        detector.observe_seq(1, 100);
+        detector.observe_seq(2, 5);
+        assert_eq!(detector.observe_seq(1, 101), SeqEvent::InOrder);
+        assert_eq!(detector.observe_seq(2, 6), SeqEvent::InOrder);
+        detector.observe_seq(2, 8);
+
+        assert_eq!(detector.flow_stats(1).unwrap().lost, 0);
+        assert_eq!(detector.flow_stats(2).unwrap().lost, 1);
+        assert_eq!(detector.flow_stats(3), None);
+        assert_eq!(detector.stats().received, 5);
+    }
+
+    #[test]
+    fn test_window_limits_memory() {
+        let mut detector = LossDetector::new().window(8);
+        detector.observe_seq(0, 0);
+        detector.observe_seq(0, 1000);
+        // all 999 count as lost, but only the last 8 can still be filled
+        assert_eq!(detector.stats().lost, 999);
+        assert_eq!(detector.observe_seq(0, 995), SeqEvent::Reordered);
+        assert_eq!(detector.observe_seq(0, 995), SeqEvent::Duplicate);
+        assert_eq!(detector.stats().lost, 998);
+        assert_eq!(detector.observe_seq(0, 992), SeqEvent::Reordered);
+        assert!(matches!(
+            detector.observe_seq(0, 991),
+            SeqEvent::Discontinuity { .. }
+        ));
+    }
+
+    #[test]
+    fn test_jump_back_restarts() {
+        let mut detector = LossDetector::new().window(16);
+        detector.observe_seq(0, 5000);
+        assert_eq!(
+            detector.observe_seq(0, 3),
+            SeqEvent::Discontinuity {
+                expected: 5001,
+                received: 3
+            }
+        );
+        assert_eq!(detector.observe_seq(0, 4), SeqEvent::InOrder);
+        assert_eq!(detector.stats().discontinuities, 1);
+        assert_eq!(detector.stats().lost, 0);
+    }
+
+    #[test]
+    fn test_reorders_within_window() {
+        let mut reorderer = Reorderer::new(3);
+        reorderer.push(10, 'a');
+        reorderer.push(12, 'c');
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('a')));
+        assert_eq!(reorderer.pop(), None);
+        reorderer.push(11, 'b');
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('b')));
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('c')));
+
+        // 13 never arrives
+        for (seq, item) in [(14, 'e'), (15, 'f'), (16, 'g')] {
+            reorderer.push(seq, item);
+        }
+        assert_eq!(
+            reorderer.pop(),
+            Some(Ordered::Gap(SeqGap {
+                first: 13,
+                missing: 1
+            }))
+        );
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('e')));
+
+        // too late now
+        reorderer.push(13, 'd');
+        assert_eq!(reorderer.dropped(), 1);
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('f')));
+    }
+
+    #[test]
+    fn test_reorderer_restarts() {
+        let mut reorderer = Reorderer::new(2);
+        reorderer.push(100, 1);
+        assert_eq!(reorderer.pop(), Some(Ordered::Item(1)));
+        reorderer.push(102, 3);
+        // the sender started over
+        reorderer.push(0, 4);
+        assert_eq!(
+            reorderer.pop(),
+            Some(Ordered::Gap(SeqGap {
+                first: 101,
+                missing: 1
+            }))
+        );
+        assert_eq!(reorderer.pop(), Some(Ordered::Item(3)));
+        assert_eq!(reorderer.pop(), Some(Ordered::Item(4)));
+        assert_eq!(reorderer.held(), 0);
+    }
+
+    #[test]
+    fn test_reorderer_max_hold() {
+        let start = Instant::now();
+        let mut reorderer = Reorderer::new(100).max_hold(Duration::from_millis(50));
+        reorderer.push_at(1, 'a', start);
+        assert_eq!(reorderer.pop_at(start), Some(Ordered::Item('a')));
+        assert_eq!(reorderer.deadline(), None);
+
+        // 2 never arrives
+        reorderer.push_at(3, 'c', start + Duration::from_millis(10));
+        reorderer.push_at(4, 'd', start + Duration::from_millis(20));
+        let deadline = start + Duration::from_millis(60);
+        assert_eq!(reorderer.deadline(), Some(deadline));
+        assert_eq!(reorderer.pop_at(deadline - Duration::from_millis(1)), None);
+        assert_eq!(
+            reorderer.pop_at(deadline),
+            Some(Ordered::Gap(SeqGap {
+                first: 2,
+                missing: 1
+            }))
+        );
+        assert_eq!(reorderer.deadline(), None);
+        assert_eq!(reorderer.pop_at(deadline), Some(Ordered::Item('c')));
+        assert_eq!(reorderer.pop_at(deadline), Some(Ordered::Item('d')));
+        assert_eq!(reorderer.pop_at(deadline), None);
+    }
+
+    #[test]
+    fn test_reorderer_flush() {
+        let mut reorderer = Reorderer::new(100);
+        reorderer.push(1, 'a');
+        reorderer.push(3, 'c');
+        reorderer.push(6, 'f');
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('a')));
+        assert_eq!(reorderer.pop(), None);
+
+        reorderer.flush();
+        let gap = |first, missing| Some(Ordered::Gap(SeqGap { first, missing }));
+        assert_eq!(reorderer.pop(), gap(2, 1));
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('c')));
+        assert_eq!(reorderer.pop(), gap(4, 2));
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('f')));
+        assert_eq!(reorderer.pop(), None);
+
+        // ordering carries on after what was flushed
+        reorderer.push(7, 'g');
+        assert_eq!(reorderer.pop(), Some(Ordered::Item('g')));
+    }
+}
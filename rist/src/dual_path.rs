@@ -0,0 +1,316 @@
+//! Hitless protection by receiving one flow over two network paths.
+//!
+//! In the style of SMPTE 2022-7, the sender sends the same flow to two
+//! receivers on separate paths. A [`DualPathReceiver`] reads both, passes
+//! on whichever copy of each packet arrives first and drops the other, so
+//! loss on one path is covered by the other without a switchover.
+//!
+//! Each path is its own RIST session with its own retransmissions. The
+//! sender should feed both from one context (e.g. one
+//! [`Sender`](crate::Sender) with two peers) so both copies carry the same
+//! sequence numbers.
+//!
+//! If one path fails, [`DualPathReceiver::recv`] keeps reading the other
+//! and only returns the error once both have failed or stopped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::dual_path::DualPathReceiver;
+//! use rist::Profile;
+//!
+//! # async fn example() -> rist::Result<()> {
+//! let receiver = DualPathReceiver::bind(Profile::Main, "rist://@:5000", "rist://@:6000")?;
+//! while let Some(block) = receiver.recv().await? {
+//!     // one copy of every packet, from whichever path delivered it first
+//! }
+//! println!("packets saved by path B: {}", receiver.stats().saved_by_b);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tokio::AsyncReceiver;
+use crate::{DataBlock, Profile, ReceiverStats, Result};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Sequence numbers remembered for deduplication.
+const WINDOW: usize = 8192;
+
+/// One of the two paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Path {
+    /// The first path.
+    A,
+    /// The second path.
+    B,
+}
+
+/// Counters kept by a [`DualPathReceiver`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DualPathStats {
+    /// Packets passed on that arrived first over path A.
+    pub delivered_a: u64,
+    /// Packets passed on that arrived first over path B.
+    pub delivered_b: u64,
+    /// Second copies dropped.
+    pub duplicates: u64,
+    /// Packets that only ever arrived over path A.
+    pub saved_by_a: u64,
+    /// Packets that only ever arrived over path B.
+    pub saved_by_b: u64,
+}
+
+/// Connection state and latest stats of one path.
+#[derive(Debug, Clone)]
+pub struct PathHealth {
+    /// Whether the path's peer is connected.
+    pub connected: bool,
+    /// The path's latest receiver stats, if any were reported yet.
+    pub stats: Option<ReceiverStats>,
+    /// The error that made [`DualPathReceiver::recv`] stop reading the
+    /// path, if any.
+    pub failed: Option<String>,
+}
+
+/// Tracks which path delivered each recent packet.
+#[derive(Debug, Default)]
+struct SeqMerger {
+    // (flow ID, seq) -> path that delivered it, and whether the other copy arrived
+    seen: HashMap<(u32, u64), (Path, bool)>,
+    order: VecDeque<(u32, u64)>,
+    // highest seq seen per flow ID
+    highest: HashMap<u32, u64>,
+    stats: DualPathStats,
+}
+
+impl SeqMerger {
+    /// Returns `true` if this is the first copy of the packet.
+    fn accept(&mut self, path: Path, flow_id: u32, seq: u64) -> bool {
+        let highest = self.highest.entry(flow_id).or_insert(seq);
+        if seq.saturating_add(WINDOW as u64) < *highest {
+            // the sender restarted or the seq wrapped: the remembered seqs
+            // would make new packets look like duplicates
+            *highest = seq;
+            self.reset();
+        } else {
+            *highest = (*highest).max(seq);
+        }
+
+        let key = (flow_id, seq);
+        if let Some((_, both)) = self.seen.get_mut(&key) {
+            *both = true;
+            self.stats.duplicates += 1;
+            return false;
+        }
+
+        if self.order.len() == WINDOW {
+            self.forget_oldest();
+        }
+        self.seen.insert(key, (path, false));
+        self.order.push_back(key);
+        match path {
+            Path::A => self.stats.delivered_a += 1,
+            Path::B => self.stats.delivered_b += 1,
+        }
+        true
+    }
+
+    /// Drop the oldest packet from the window. If its second copy never
+    /// came, the path that delivered it saved it.
+    fn forget_oldest(&mut self) {
+        let Some(key) = self.order.pop_front() else {
+            return;
+        };
+        match self.seen.remove(&key) {
+            Some((Path::A, false)) => self.stats.saved_by_a += 1,
+            Some((Path::B, false)) => self.stats.saved_by_b += 1,
+            _ => {}
+        }
+    }
+
+    /// Forget every packet in the window.
+    fn reset(&mut self) {
+        while !self.order.is_empty() {
+            self.forget_oldest();
+        }
+    }
+}
+
+/// Merges one flow received over two paths into a single output.
+///
+/// Packets are deduplicated by flow ID and sequence number. A packet only
+/// counts as saved by a path once it leaves the deduplication window of
+/// the last 8192 packets without the other copy having arrived. A
+/// sequence number far behind the newest one resets the window, as the
+/// sender restarted or the sequence numbers wrapped.
+pub struct DualPathReceiver {
+    a: AsyncReceiver,
+    b: AsyncReceiver,
+    merger: Mutex<SeqMerger>,
+    ended: Mutex<Ended>,
+}
+
+/// Paths `recv` stopped reading.
+#[derive(Debug, Default)]
+struct Ended {
+    a: bool,
+    b: bool,
+    // the last error that ended a path
+    errors: HashMap<Path, String>,
+}
+
+impl Ended {
+    fn get_mut(&mut self, path: Path) -> &mut bool {
+        match path {
+            Path::A => &mut self.a,
+            Path::B => &mut self.b,
+        }
+    }
+}
+
+impl DualPathReceiver {
+    /// Listen on `url_a` and `url_b`.
+    pub fn bind(profile: Profile, url_a: &str, url_b: &str) -> Result<Self> {
+        Ok(Self::new(
+            AsyncReceiver::bind(profile, url_a)?,
+            AsyncReceiver::bind(profile, url_b)?,
+        ))
+    }
+
+    /// Merge two already configured receivers.
+    pub fn new(a: AsyncReceiver, b: AsyncReceiver) -> Self {
+        Self {
+            a,
+            b,
+            merger: Mutex::new(SeqMerger::default()),
+            ended: Mutex::new(Ended::default()),
+        }
+    }
+
+    /// Receive the next packet from whichever path delivers it first.
+    ///
+    /// A path that fails or is stopped is no longer read, and the other
+    /// one carries on alone; see [`path_health`](Self::path_health) for
+    /// the error. Once both have ended, returns the error that ended the
+    /// last one, or `Ok(None)` if it was stopped and drained.
+    pub async fn recv(&self) -> Result<Option<DataBlock>> {
+        loop {
+            let (a_ended, b_ended) = {
+                let ended = self.ended.lock().unwrap_or_else(|e| e.into_inner());
+                (ended.a, ended.b)
+            };
+            let (path, block) = ::tokio::select! {
+                block = self.a.recv(), if !a_ended => (Path::A, block),
+                block = self.b.recv(), if !b_ended => (Path::B, block),
+                else => return Ok(None),
+            };
+            let block = match block {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    *self
+                        .ended
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .get_mut(path) = true;
+                    continue;
+                }
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(?path, "dual path receive failed: {e}");
+                    let mut ended = self.ended.lock().unwrap_or_else(|e| e.into_inner());
+                    *ended.get_mut(path) = true;
+                    ended.errors.insert(path, e.to_string());
+                    if ended.a && ended.b {
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+
+            let mut merger = self.merger.lock().unwrap_or_else(|e| e.into_inner());
+            if merger.accept(path, block.flow_id(), block.seq()) {
+                return Ok(Some(block));
+            }
+        }
+    }
+
+    /// The receiver for `path`.
+    pub fn path(&self, path: Path) -> &AsyncReceiver {
+        match path {
+            Path::A => &self.a,
+            Path::B => &self.b,
+        }
+    }
+
+    /// Connection state and latest stats of `path`.
+    pub fn path_health(&self, path: Path) -> PathHealth {
+        let receiver = self.path(path);
+        PathHealth {
+            connected: receiver.is_connected(),
+            stats: receiver.raw_stats(),
+            failed: self
+                .ended
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .errors
+                .get(&path)
+                .cloned(),
+        }
+    }
+
+    /// Delivery and deduplication counters so far.
+    pub fn stats(&self) -> DualPathStats {
+        self.merger.lock().unwrap_or_else(|e| e.into_inner()).stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivers_first_copy_only() {
+        let mut merger = SeqMerger::default();
+        assert!(merger.accept(Path::A, 1, 10));
+        assert!(!merger.accept(Path::B, 1, 10));
+        assert!(merger.accept(Path::B, 1, 11));
+        assert!(!merger.accept(Path::A, 1, 11));
+        // same seq on another flow is a different packet
+        assert!(merger.accept(Path::A, 2, 10));
+
+        let stats = merger.stats;
+        assert_eq!(stats.delivered_a, 2);
+        assert_eq!(stats.delivered_b, 1);
+        assert_eq!(stats.duplicates, 2);
+    }
+
+    #[test]
+    fn test_counts_saves_when_leaving_window() {
+        let mut merger = SeqMerger::default();
+        // path A lost seq 0 and 1
+        merger.accept(Path::B, 1, 0);
+        merger.accept(Path::B, 1, 1);
+        for seq in 2..WINDOW as u64 + 2 {
+            merger.accept(Path::A, 1, seq);
+            merger.accept(Path::B, 1, seq);
+        }
+
+        assert_eq!(merger.stats.saved_by_b, 2);
+        assert_eq!(merger.stats.saved_by_a, 0);
+    }
+
+    #[test]
+    fn test_resets_window_on_seq_jump_back() {
+        let mut merger = SeqMerger::default();
+        for seq in 100_000..100_010 {
+            assert!(merger.accept(Path::A, 1, seq));
+        }
+        // the sender restarted: old seqs are new packets
+        assert!(merger.accept(Path::A, 1, 0));
+        assert!(!merger.accept(Path::B, 1, 0));
+        assert!(merger.accept(Path::B, 1, 1));
+        assert_eq!(merger.order.len(), 2);
+        assert_eq!(merger.stats.saved_by_a, 10);
+    }
+}
@@ -8,21 +8,60 @@ pub enum Error {
     #[error("failed to create peer: {0}")]
     PeerCreation(String),
 
+    #[error("no such peer")]
+    UnknownPeer,
+
+    #[error("no such flow: {0}")]
+    UnknownFlow(u32),
+
     #[error("failed to parse URL: {0}")]
     UrlParse(String),
 
     #[error("invalid configuration: {0}")]
     Configuration(String),
 
+    #[error("invalid option {field}: {reason}")]
+    InvalidOption { field: &'static str, reason: String },
+
+    #[error("{feature} is not supported by the {profile:?} profile")]
+    UnsupportedByProfile {
+        profile: crate::Profile,
+        feature: &'static str,
+    },
+
     #[error("failed to start context")]
     Start,
 
     #[error("failed to send data")]
     Send,
 
+    #[error("packet dropped by the output bitrate cap")]
+    BitrateCapExceeded,
+
+    #[error("sender queue full, packet rejected")]
+    QueueFull,
+
+    #[error("packet could not be queued before the send deadline")]
+    SendTimeout,
+
+    #[error("invalid OOB message: {0}")]
+    InvalidMessage(String),
+
+    #[error("invalid chunk: {0}")]
+    InvalidChunk(String),
+
+    #[error("remote command rejected: {0}")]
+    CommandRejected(String),
+
+    #[error("no ack for the remote command in time")]
+    CommandTimeout,
+
     #[error("failed to read data")]
     Read,
 
+    #[error("gave up restarting after {attempts} attempts: {error}")]
+    RestartsExhausted { attempts: u32, error: String },
+
     #[error("invalid string: contains null byte")]
     NulError(#[from] std::ffi::NulError),
 
@@ -32,6 +71,12 @@ pub enum Error {
     #[error("context not started")]
     NotStarted,
 
+    #[error("context stopped")]
+    Stopped,
+
+    #[error("timed out waiting for the peer to connect")]
+    ConnectTimeout,
+
     #[error("timeout value too large")]
     TimeoutOverflow,
 
@@ -43,4 +88,7 @@ pub enum Error {
 
     #[error("eventfd error: {0}")]
     EventFd(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
@@ -0,0 +1,312 @@
+//! Redundant contribution with automatic switchover.
+//!
+//! A [`SenderGroup`] keeps a connection up to a primary destination and one
+//! or more backups, but only sends media to one of them at a time. When the
+//! active destination loses its connection or its quality drops, the group
+//! switches to the highest-priority healthy backup, and returns to the
+//! primary once it has been healthy for a while.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::failover::SenderGroup;
+//! use rist::Profile;
+//!
+//! # fn example() -> rist::Result<()> {
+//! let group = SenderGroup::new(
+//!     Profile::Main,
+//!     &["rist://primary.example.com:5000", "rist://backup.example.com:5000"],
+//! )?;
+//! group.send(&[0x47; 1316])?;
+//! println!("sending to destination {}", group.active());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, Profile, Result, Sender, SenderOptions};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// When a [`SenderGroup`] switches between destinations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailoverPolicy {
+    /// Lowest link quality, in percent, still considered healthy.
+    pub min_quality: f64,
+    /// How long the active destination must stay unhealthy before switching
+    /// away from it.
+    pub switch_after: Duration,
+    /// How long a higher-priority destination must stay healthy before
+    /// switching back to it.
+    pub restore_after: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            min_quality: 90.0,
+            switch_after: Duration::from_secs(1),
+            restore_after: Duration::from_secs(10),
+        }
+    }
+}
+
+impl FailoverPolicy {
+    /// Create the default policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the lowest link quality, in percent, still considered healthy.
+    pub fn min_quality(mut self, quality: f64) -> Self {
+        self.min_quality = quality;
+        self
+    }
+
+    /// Set how long the active destination must be unhealthy before switching.
+    pub fn switch_after(mut self, duration: Duration) -> Self {
+        self.switch_after = duration;
+        self
+    }
+
+    /// Set how long a higher-priority destination must be healthy before
+    /// switching back.
+    pub fn restore_after(mut self, duration: Duration) -> Self {
+        self.restore_after = duration;
+        self
+    }
+}
+
+/// Health of one destination and when it last changed.
+#[derive(Debug, Clone, Copy)]
+struct Health {
+    healthy: bool,
+    since: Instant,
+}
+
+impl Health {
+    fn update(&mut self, healthy: bool, now: Instant) {
+        if healthy != self.healthy {
+            self.healthy = healthy;
+            self.since = now;
+        }
+    }
+
+    fn held_for(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.since)
+    }
+}
+
+#[derive(Debug)]
+struct GroupState {
+    active: usize,
+    health: Vec<Health>,
+    switches: u64,
+}
+
+/// Sends to a primary destination, failing over to backups in priority order.
+///
+/// Every destination is its own [`Sender`] and stays connected, so a switch
+/// only changes where media goes. Health is checked on every send.
+pub struct SenderGroup {
+    senders: Vec<Sender>,
+    policy: FailoverPolicy,
+    state: Mutex<GroupState>,
+}
+
+impl SenderGroup {
+    /// Start sending to `urls`, highest priority first, with default options
+    /// and policy.
+    pub fn new(profile: Profile, urls: &[&str]) -> Result<Self> {
+        Self::with_options(
+            profile,
+            urls,
+            &SenderOptions::default(),
+            FailoverPolicy::default(),
+        )
+    }
+
+    /// Start sending to `urls`, highest priority first, configuring every
+    /// destination with `options`.
+    pub fn with_options(
+        profile: Profile,
+        urls: &[&str],
+        options: &SenderOptions,
+        policy: FailoverPolicy,
+    ) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(Error::Configuration(
+                "sender group needs at least one destination".to_string(),
+            ));
+        }
+
+        let senders = urls
+            .iter()
+            .map(|url| {
+                let mut sender = Sender::new(profile)?;
+                sender.add_peer_with_options(url, options)?;
+                sender.start()?;
+                Ok(sender)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let now = Instant::now();
+        let health = vec![
+            Health {
+                healthy: false,
+                since: now,
+            };
+            senders.len()
+        ];
+
+        Ok(Self {
+            senders,
+            policy,
+            state: Mutex::new(GroupState {
+                active: 0,
+                health,
+                switches: 0,
+            }),
+        })
+    }
+
+    /// Send data to the active destination, switching first if needed.
+    pub fn send(&self, data: &[u8]) -> Result<usize> {
+        let active = self.check();
+        self.senders[active].send(data)
+    }
+
+    /// Re-check destination health and switch if the policy says so.
+    ///
+    /// [`send`](Self::send) does this itself; call it to keep the active
+    /// destination up to date while not sending. Returns the active index.
+    pub fn check(&self) -> usize {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        for (sender, health) in self.senders.iter().zip(state.health.iter_mut()) {
+            health.update(self.is_healthy(sender), now);
+        }
+
+        let next = choose(state.active, &state.health, &self.policy, now);
+        if next != state.active {
+            state.active = next;
+            state.switches += 1;
+        }
+        state.active
+    }
+
+    /// Index of the destination currently receiving media, in the order
+    /// the URLs were given.
+    pub fn active(&self) -> usize {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).active
+    }
+
+    /// Number of switches so far.
+    pub fn switches(&self) -> u64 {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .switches
+    }
+
+    /// The sender for every destination, highest priority first.
+    pub fn senders(&self) -> &[Sender] {
+        &self.senders
+    }
+
+    /// The policy deciding when to switch.
+    pub fn policy(&self) -> &FailoverPolicy {
+        &self.policy
+    }
+
+    fn is_healthy(&self, sender: &Sender) -> bool {
+        // no report yet means nothing has been measured against the link
+        let quality_ok = match sender.raw_stats() {
+            Some(stats) => stats.quality >= self.policy.min_quality,
+            None => true,
+        };
+        sender.is_connected() && quality_ok
+    }
+}
+
+/// Pick the destination to send to, given the current one and everyone's health.
+fn choose(active: usize, health: &[Health], policy: &FailoverPolicy, now: Instant) -> usize {
+    // go back to a higher-priority destination once it has proven stable
+    let restore = health[..active]
+        .iter()
+        .position(|h| h.healthy && h.held_for(now) >= policy.restore_after);
+    if let Some(index) = restore {
+        return index;
+    }
+
+    let current = health[active];
+    if current.healthy || current.held_for(now) < policy.switch_after {
+        return active;
+    }
+
+    health.iter().position(|h| h.healthy).unwrap_or(active)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(states: &[(bool, u64)], now: Instant) -> Vec<Health> {
+        states
+            .iter()
+            .map(|&(healthy, secs)| Health {
+                healthy,
+                since: now - Duration::from_secs(secs),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stays_while_healthy_or_within_switch_delay() {
+        let now = Instant::now();
+        let policy = FailoverPolicy::default();
+
+        assert_eq!(
+            choose(0, &health(&[(true, 5), (true, 5)], now), &policy, now),
+            0
+        );
+        // unhealthy, but not for long enough yet
+        assert_eq!(
+            choose(0, &health(&[(false, 0), (true, 5)], now), &policy, now),
+            0
+        );
+    }
+
+    #[test]
+    fn test_fails_over_to_highest_priority_healthy_backup() {
+        let now = Instant::now();
+        let policy = FailoverPolicy::default();
+
+        let states = health(&[(false, 2), (false, 2), (true, 0)], now);
+        assert_eq!(choose(0, &states, &policy, now), 2);
+
+        // nothing healthy to switch to
+        let states = health(&[(false, 2), (false, 2)], now);
+        assert_eq!(choose(0, &states, &policy, now), 0);
+    }
+
+    #[test]
+    fn test_restores_primary_only_after_hold_off() {
+        let now = Instant::now();
+        let policy = FailoverPolicy::default().restore_after(Duration::from_secs(10));
+
+        let states = health(&[(true, 3), (true, 30)], now);
+        assert_eq!(choose(1, &states, &policy, now), 1);
+
+        let states = health(&[(true, 12), (true, 30)], now);
+        assert_eq!(choose(1, &states, &policy, now), 0);
+    }
+
+    #[test]
+    fn test_rejects_empty_group() {
+        assert!(matches!(
+            SenderGroup::new(Profile::Main, &[]),
+            Err(Error::Configuration(_))
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+//! Sharing one RIST ingest between several consumers.
+//!
+//! A [`Fanout`] reads from one [`AsyncReceiver`] and broadcasts every
+//! payload to any number of subscribers, so a recorder, a monitor and a
+//! transcoder can all consume the same stream without each opening its own
+//! RIST session.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::fanout::Fanout;
+//! use rist::tokio::AsyncReceiver;
+//! use rist::Profile;
+//!
+//! # async fn example() -> rist::Result<()> {
+//! let input = AsyncReceiver::bind(Profile::Main, "rist://@:5000")?;
+//! let fanout = Fanout::new(input, 1024);
+//!
+//! let mut recorder = fanout.subscribe();
+//! tokio::spawn(async move {
+//!     while let Ok(packet) = recorder.recv().await {
+//!         // write packet.payload() to disk
+//!     }
+//! });
+//!
+//! fanout.run().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tokio::AsyncReceiver;
+use crate::{BlockMetadata, DataBlock, Result};
+use ::tokio::sync::broadcast;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A received payload shared between subscribers.
+///
+/// Cloning is cheap: every subscriber shares the one received block.
+#[derive(Debug, Clone)]
+pub struct Packet {
+    block: DataBlock,
+}
+
+impl Packet {
+    /// The payload data.
+    pub fn payload(&self) -> &[u8] {
+        self.block.payload()
+    }
+
+    /// The packet's timestamp, sequence number, flow ID and virtual ports,
+    /// ready to pass to `send_with_metadata`.
+    pub fn metadata(&self) -> BlockMetadata {
+        self.block.metadata()
+    }
+
+    /// The received block itself.
+    pub fn block(&self) -> &DataBlock {
+        &self.block
+    }
+}
+
+/// Broadcasts packets from one receiver to many subscribers.
+///
+/// Each subscriber has its own queue of up to `capacity` packets. A
+/// subscriber that falls further behind misses the oldest packets and gets
+/// [`RecvError::Lagged`](broadcast::error::RecvError::Lagged) instead of
+/// holding up the ingest or the other subscribers. Packets arriving while
+/// nobody is subscribed are dropped.
+pub struct Fanout {
+    input: AsyncReceiver,
+    tx: broadcast::Sender<Packet>,
+    packets: AtomicU64,
+}
+
+impl Fanout {
+    /// Create a fan-out reading from `input`, buffering up to `capacity`
+    /// packets per subscriber.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(input: AsyncReceiver, capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self {
+            input,
+            tx,
+            packets: AtomicU64::new(0),
+        }
+    }
+
+    /// Subscribe to packets received from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Packet> {
+        self.tx.subscribe()
+    }
+
+    /// Number of live subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// The receiver packets are read from.
+    pub fn input(&self) -> &AsyncReceiver {
+        &self.input
+    }
+
+    /// Number of packets broadcast so far.
+    pub fn broadcast_packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    /// Broadcast packets until the input is stopped and drained, or fails.
+    pub async fn run(&self) -> Result<()> {
+        while self.forward_next().await?.is_some() {}
+        Ok(())
+    }
+
+    /// Wait for the next packet and broadcast it to every subscriber.
+    ///
+    /// Returns the broadcast packet, or `None` once the input is stopped
+    /// and drained.
+    pub async fn forward_next(&self) -> Result<Option<Packet>> {
+        let Some(block) = self.input.recv().await? else {
+            return Ok(None);
+        };
+
+        let packet = Packet { block };
+        // no subscribers is fine, the packet is simply dropped
+        let _ = self.tx.send(packet.clone());
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Profile;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_run_ends_when_input_stops() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let fanout = Fanout::new(AsyncReceiver::bind(Profile::Main, &url).unwrap(), 16);
+
+        let run = fanout.run();
+        ::tokio::pin!(run);
+        assert!(::tokio::time::timeout(Duration::from_millis(50), &mut run)
+            .await
+            .is_err());
+
+        fanout.input().stop().unwrap();
+        ::tokio::time::timeout(Duration::from_secs(1), run)
+            .await
+            .expect("run kept going after the input stopped")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fanout_reaches_every_subscriber() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender, receiver, ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        let fanout = Fanout::new(receiver, 16);
+        let mut first = fanout.subscribe();
+        let mut second = fanout.subscribe();
+        assert_eq!(fanout.subscriber_count(), 2);
+
+        sender.send(&[0x47u8; 1316]).await.unwrap();
+        let forwarded = ::tokio::time::timeout(Duration::from_secs(2), fanout.forward_next())
+            .await
+            .expect("fanout timed out")
+            .unwrap()
+            .expect("fanout forwarded nothing");
+        assert_eq!(fanout.broadcast_packets(), 1);
+
+        for subscriber in [&mut first, &mut second] {
+            let packet = subscriber.try_recv().unwrap();
+            assert_eq!(packet.payload(), forwarded.payload());
+            assert_eq!(packet.metadata(), forwarded.metadata());
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! Tracking of the flows a receiver delivers, so callers learn when one ends.
+
+use crate::health::Activity;
+use crate::stats::JitterEstimator;
+#[cfg(feature = "ts")]
+use crate::ts::{TsMonitor, TsStats};
+use crate::PeerId;
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "ts")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A flow seen by a receiver.
+///
+/// librist doesn't say when a packet arrived, so the times are taken as
+/// the flow's blocks are read from the receiver. They include how long
+/// librist held each block in its recovery buffer and how promptly the
+/// application reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowInfo {
+    /// The flow ID set by the sender.
+    pub flow_id: u32,
+    /// When a block of this flow was last read.
+    pub last_read: SystemTime,
+    /// Jitter of the times the flow's blocks were read, see
+    /// [`ReceiverStats::read_jitter`](crate::ReceiverStats::read_jitter).
+    pub read_jitter: Duration,
+}
+
+/// A flow stopping or starting to deliver data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// No block of the flow was read for the stall timeout.
+    Stalled {
+        /// The flow that stalled.
+        flow_id: u32,
+        /// When a block of the flow was last read.
+        last_read: SystemTime,
+    },
+    /// A block of a stalled flow was read again.
+    Resumed {
+        /// The flow that resumed.
+        flow_id: u32,
+        /// How long the flow delivered nothing.
+        stalled_for: Duration,
+    },
+}
+
+/// Callback invoked when a flow expires.
+pub(crate) type FlowCallback = Box<dyn FnMut(&FlowInfo) + Send>;
+
+/// Callback invoked when a flow stalls or resumes.
+pub(crate) type StreamCallback = Box<dyn FnMut(&StreamEvent) + Send>;
+
+struct StallWatch {
+    timeout: Duration,
+    callback: StreamCallback,
+}
+
+struct FlowEntry {
+    last_read: SystemTime,
+    last_read_at: Instant,
+    // reported as stalled and not resumed since
+    stalled: bool,
+    // remote peers that delivered data for this flow
+    peers: HashSet<PeerId>,
+    jitter: JitterEstimator,
+    #[cfg(feature = "ts")]
+    ts: Option<TsMonitor>,
+}
+
+impl FlowEntry {
+    fn info(&self, flow_id: u32) -> FlowInfo {
+        FlowInfo {
+            flow_id,
+            last_read: self.last_read,
+            read_jitter: self.jitter.jitter(),
+        }
+    }
+}
+
+/// Flows delivered by a receiver, keyed by flow ID.
+///
+/// librist tears a flow down once every sender feeding it has timed out, so
+/// a flow is expired when the last of its peers is reported as timed out.
+pub(crate) struct FlowSlot {
+    flows: Mutex<HashMap<u32, FlowEntry>>,
+    callback: Mutex<Option<FlowCallback>>,
+    stall: Mutex<Option<StallWatch>>,
+    // flows closed with `close`, and the peers that were sending them
+    closed: Mutex<HashMap<u32, HashSet<PeerId>>>,
+    // outlives the flows, which expire with their senders
+    activity: Activity,
+    #[cfg(feature = "ts")]
+    monitor_ts: AtomicBool,
+}
+
+impl FlowSlot {
+    pub(crate) fn new() -> Self {
+        Self {
+            flows: Mutex::new(HashMap::new()),
+            callback: Mutex::new(None),
+            stall: Mutex::new(None),
+            closed: Mutex::new(HashMap::new()),
+            activity: Activity::default(),
+            #[cfg(feature = "ts")]
+            monitor_ts: AtomicBool::new(false),
+        }
+    }
+
+    /// Time since any flow last delivered a block.
+    pub(crate) fn last_packet_age(&self) -> Option<Duration> {
+        self.activity.age()
+    }
+
+    /// Report flows that deliver nothing for `timeout` to `callback`, and
+    /// when they resume.
+    pub(crate) fn watch_stalls(&self, timeout: Duration, callback: StreamCallback) {
+        *self.stall.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some(StallWatch { timeout, callback });
+    }
+
+    /// Report the flows that have stalled since the last check.
+    pub(crate) fn check_stalls(&self) {
+        let Some(timeout) = self
+            .stall
+            .lock()
+            .ok()
+            .and_then(|guard| Some(guard.as_ref()?.timeout))
+        else {
+            return;
+        };
+        let now = Instant::now();
+        let mut stalled = Vec::new();
+        if let Ok(mut flows) = self.flows.lock() {
+            for (flow_id, entry) in flows.iter_mut() {
+                if entry.stalled || now.saturating_duration_since(entry.last_read_at) < timeout {
+                    continue;
+                }
+                entry.stalled = true;
+                stalled.push(StreamEvent::Stalled {
+                    flow_id: *flow_id,
+                    last_read: entry.last_read,
+                });
+            }
+        }
+        stalled.sort_by_key(|event| match event {
+            StreamEvent::Stalled { flow_id, .. } | StreamEvent::Resumed { flow_id, .. } => *flow_id,
+        });
+        for event in &stalled {
+            self.emit(event);
+        }
+    }
+
+    fn emit(&self, event: &StreamEvent) {
+        crate::callback::call_user(&self.stall, |watch| (watch.callback)(event));
+    }
+
+    pub(crate) fn set_callback(&self, callback: Option<FlowCallback>) {
+        *self.callback.lock().unwrap_or_else(|e| e.into_inner()) = callback;
+    }
+
+    /// Record that a block of `flow_id` stamped `ts_ntp` from `peer` was
+    /// just read.
+    pub(crate) fn seen(&self, flow_id: u32, peer: Option<PeerId>, ts_ntp: u64) {
+        self.activity.touch();
+        let now = Instant::now();
+        let mut resumed = None;
+        if let Ok(mut flows) = self.flows.lock() {
+            let entry = flows.entry(flow_id).or_insert_with(|| FlowEntry {
+                last_read: SystemTime::now(),
+                last_read_at: now,
+                stalled: false,
+                peers: HashSet::new(),
+                jitter: JitterEstimator::new(),
+                #[cfg(feature = "ts")]
+                ts: None,
+            });
+            if entry.stalled {
+                entry.stalled = false;
+                resumed = Some(StreamEvent::Resumed {
+                    flow_id,
+                    stalled_for: now.saturating_duration_since(entry.last_read_at),
+                });
+            }
+            entry.last_read = SystemTime::now();
+            entry.last_read_at = now;
+            entry.jitter.observe(now, ts_ntp);
+            if let Some(peer) = peer {
+                entry.peers.insert(peer);
+            }
+        }
+        if let Some(event) = resumed {
+            self.emit(&event);
+        }
+    }
+
+    /// Every flow that has delivered data and not expired, ordered by flow ID.
+    pub(crate) fn flows(&self) -> Vec<FlowInfo> {
+        let Ok(flows) = self.flows.lock() else {
+            return Vec::new();
+        };
+
+        let mut infos: Vec<FlowInfo> = flows
+            .iter()
+            .map(|(flow_id, entry)| entry.info(*flow_id))
+            .collect();
+        infos.sort_by_key(|info| info.flow_id);
+        infos
+    }
+
+    /// Read jitter of `flow_id`, zero for a flow not seen yet.
+    pub(crate) fn jitter(&self, flow_id: u32) -> Duration {
+        self.flows
+            .lock()
+            .ok()
+            .and_then(|flows| Some(flows.get(&flow_id)?.jitter.jitter()))
+            .unwrap_or_default()
+    }
+
+    /// Start or stop checking the payloads passed to
+    /// [`inspect_ts`](Self::inspect_ts). Stopping drops the counters.
+    #[cfg(feature = "ts")]
+    pub(crate) fn set_ts_monitor(&self, enabled: bool) {
+        self.monitor_ts.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            if let Ok(mut flows) = self.flows.lock() {
+                for entry in flows.values_mut() {
+                    entry.ts = None;
+                }
+            }
+        }
+    }
+
+    /// Check a payload of `flow_id` just read for TS errors, if enabled.
+    /// Call it after [`seen`](Self::seen).
+    #[cfg(feature = "ts")]
+    pub(crate) fn inspect_ts(&self, flow_id: u32, payload: &[u8]) {
+        if !self.monitor_ts.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Ok(mut flows) = self.flows.lock() {
+            if let Some(entry) = flows.get_mut(&flow_id) {
+                entry.ts.get_or_insert_with(TsMonitor::new).inspect(payload);
+            }
+        }
+    }
+
+    /// TS counters of `flow_id`, `None` while monitoring is off or before
+    /// the flow delivered anything.
+    #[cfg(feature = "ts")]
+    pub(crate) fn ts_stats(&self, flow_id: u32) -> Option<TsStats> {
+        let flows = self.flows.lock().ok()?;
+        Some(flows.get(&flow_id)?.ts.as_ref()?.stats().clone())
+    }
+
+    /// The one flow `peer` delivered data for, `None` if it delivered none
+    /// or several.
+    pub(crate) fn flow_of(&self, peer: PeerId) -> Option<u32> {
+        let flows = self.flows.lock().ok()?;
+        let mut ids = flows
+            .iter()
+            .filter(|(_, entry)| entry.peers.contains(&peer))
+            .map(|(flow_id, _)| *flow_id);
+        match (ids.next(), ids.next()) {
+            (Some(flow_id), None) => Some(flow_id),
+            _ => None,
+        }
+    }
+
+    /// Forget `flow_id` and report it as expired, then hold back its blocks
+    /// until a sender other than the ones that were sending it delivers it.
+    /// `false` for a flow that hasn't delivered data.
+    pub(crate) fn close(&self, flow_id: u32) -> bool {
+        let Some(entry) = self
+            .flows
+            .lock()
+            .ok()
+            .and_then(|mut flows| flows.remove(&flow_id))
+        else {
+            return false;
+        };
+        let info = entry.info(flow_id);
+        self.closed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(flow_id, entry.peers);
+        crate::callback::call_user(&self.callback, |callback| callback(&info));
+        true
+    }
+
+    /// Whether a block of `flow_id` from `peer` may be delivered, reopening
+    /// a closed flow when a new sender delivers it.
+    pub(crate) fn admits(&self, flow_id: u32, peer: Option<PeerId>) -> bool {
+        let mut closed = self.closed.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(senders) = closed.get(&flow_id) else {
+            return true;
+        };
+        match peer {
+            Some(peer) if !senders.contains(&peer) => {
+                closed.remove(&flow_id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Drop `peer` from every flow and expire the flows it was the last peer of.
+    pub(crate) fn peer_timed_out(&self, peer: PeerId) {
+        // a closed flow whose senders are all gone reopens for the next one
+        if let Ok(mut closed) = self.closed.lock() {
+            closed.retain(|_, senders| !(senders.remove(&peer) && senders.is_empty()));
+        }
+
+        let mut expired = Vec::new();
+        if let Ok(mut flows) = self.flows.lock() {
+            flows.retain(|flow_id, entry| {
+                if !entry.peers.remove(&peer) || !entry.peers.is_empty() {
+                    return true;
+                }
+                expired.push(entry.info(*flow_id));
+                false
+            });
+        }
+
+        if expired.is_empty() {
+            return;
+        }
+        expired.sort_by_key(|info| info.flow_id);
+        crate::callback::call_user(&self.callback, |callback| {
+            for info in &expired {
+                callback(info);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn peer(n: usize) -> PeerId {
+        PeerId::from_raw(n as *mut rist_sys::rist_peer)
+    }
+
+    #[test]
+    fn test_flow_expires_after_last_peer() {
+        let slot = FlowSlot::new();
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&expired);
+        slot.set_callback(Some(Box::new(move |info| {
+            sink.lock().unwrap().push(info.flow_id);
+        })));
+
+        slot.seen(7, Some(peer(0x10)), 0);
+        slot.seen(7, Some(peer(0x20)), 0);
+        slot.seen(9, Some(peer(0x20)), 0);
+        assert_eq!(slot.flows().len(), 2);
+
+        slot.peer_timed_out(peer(0x20));
+        assert_eq!(*expired.lock().unwrap(), vec![9]);
+        assert_eq!(slot.flows()[0].flow_id, 7);
+
+        slot.peer_timed_out(peer(0x10));
+        assert_eq!(*expired.lock().unwrap(), vec![9, 7]);
+        assert!(slot.flows().is_empty());
+    }
+
+    #[test]
+    fn test_stall_and_resume() {
+        let slot = FlowSlot::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        slot.watch_stalls(
+            Duration::from_millis(20),
+            Box::new(move |event| sink.lock().unwrap().push(*event)),
+        );
+
+        slot.seen(1, None, 0);
+        slot.check_stalls();
+        assert!(events.lock().unwrap().is_empty());
+
+        std::thread::sleep(Duration::from_millis(30));
+        slot.check_stalls();
+        // reported once, however often it is checked
+        slot.check_stalls();
+        slot.seen(1, None, 0);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], StreamEvent::Stalled { flow_id: 1, .. }));
+        match events[1] {
+            StreamEvent::Resumed {
+                flow_id,
+                stalled_for,
+            } => {
+                assert_eq!(flow_id, 1);
+                assert!(stalled_for >= Duration::from_millis(30));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_flow_of_peer() {
+        let slot = FlowSlot::new();
+        slot.seen(2, Some(peer(0x10)), 0);
+        slot.seen(4, Some(peer(0x20)), 0);
+        slot.seen(6, Some(peer(0x20)), 0);
+        assert_eq!(slot.flow_of(peer(0x10)), Some(2));
+        // ambiguous
+        assert_eq!(slot.flow_of(peer(0x20)), None);
+        assert_eq!(slot.flow_of(peer(0x30)), None);
+    }
+
+    #[cfg(feature = "ts")]
+    #[test]
+    fn test_ts_monitor_per_flow() {
+        let slot = FlowSlot::new();
+        let mut packet = [0xffu8; crate::ts::PACKET_SIZE];
+        packet[0] = crate::ts::SYNC_BYTE;
+
+        slot.seen(1, None, 0);
+        slot.inspect_ts(1, &packet);
+        assert_eq!(slot.ts_stats(1), None);
+
+        slot.set_ts_monitor(true);
+        slot.inspect_ts(1, &packet);
+        slot.inspect_ts(1, &packet[..100]);
+        slot.seen(2, None, 0);
+        slot.inspect_ts(2, &packet);
+        assert_eq!(slot.ts_stats(1).unwrap().packets, 1);
+        assert_eq!(slot.ts_stats(1).unwrap().sync_errors, 1);
+        assert_eq!(slot.ts_stats(2).unwrap().sync_errors, 0);
+
+        slot.set_ts_monitor(false);
+        assert_eq!(slot.ts_stats(1), None);
+    }
+
+    #[test]
+    fn test_close_flow() {
+        let slot = FlowSlot::new();
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&expired);
+        slot.set_callback(Some(Box::new(move |info| {
+            sink.lock().unwrap().push(info.flow_id);
+        })));
+
+        assert!(!slot.close(7));
+        slot.seen(7, Some(peer(0x10)), 0);
+        slot.seen(9, Some(peer(0x20)), 0);
+        assert!(slot.close(7));
+        assert_eq!(*expired.lock().unwrap(), vec![7]);
+        assert_eq!(slot.flows().len(), 1);
+
+        // held back while the same sender keeps sending
+        assert!(!slot.admits(7, Some(peer(0x10))));
+        assert!(!slot.admits(7, None));
+        assert!(slot.admits(9, Some(peer(0x20))));
+        // a new sender reopens it
+        assert!(slot.admits(7, Some(peer(0x30))));
+        assert!(slot.admits(7, Some(peer(0x10))));
+
+        // and so does the old sender once it has timed out
+        slot.seen(7, Some(peer(0x10)), 0);
+        assert!(slot.close(7));
+        slot.peer_timed_out(peer(0x10));
+        assert!(slot.admits(7, Some(peer(0x10))));
+    }
+
+    #[test]
+    fn test_flow_without_peer_never_expires() {
+        let slot = FlowSlot::new();
+        slot.seen(1, None, 0);
+        slot.peer_timed_out(peer(0x10));
+        assert_eq!(slot.flows().len(), 1);
+    }
+}
@@ -0,0 +1,165 @@
+//! Health reports for liveness and readiness probes.
+
+use crate::{ConnectionState, PeerInfo, ReceiverStats};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A snapshot of how a sender or receiver is doing, from `health()`.
+///
+/// Gathers what a liveness or readiness endpoint needs in one place.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::Receiver;
+/// use std::time::Duration;
+///
+/// # fn example(receiver: &Receiver) {
+/// let health = receiver.health();
+/// let status = if health.is_ready(Duration::from_secs(2)) { 200 } else { 503 };
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health {
+    /// The context is started and not stopped.
+    pub running: bool,
+    /// Peers on the context, own and remote.
+    pub peers: usize,
+    /// Peers currently connected.
+    pub connected_peers: usize,
+    /// Time since a packet was last delivered (receivers) or sent
+    /// (senders), `None` if there hasn't been one yet.
+    pub last_packet_age: Option<Duration>,
+    /// Quality percentage (0-100) from the latest stats report.
+    pub quality: Option<f64>,
+    /// Share of packets lost for good in the latest stats report, from 0.0
+    /// to 1.0. Only receivers report losses.
+    pub loss_ratio: Option<f64>,
+}
+
+impl Health {
+    pub(crate) fn new(
+        running: bool,
+        peers: &[PeerInfo],
+        last_packet_age: Option<Duration>,
+    ) -> Self {
+        Self {
+            running,
+            peers: peers.len(),
+            connected_peers: peers
+                .iter()
+                .filter(|peer| peer.state == ConnectionState::Connected)
+                .count(),
+            last_packet_age,
+            quality: None,
+            loss_ratio: None,
+        }
+    }
+
+    pub(crate) fn with_receiver_stats(mut self, stats: Option<ReceiverStats>) -> Self {
+        if let Some(stats) = stats {
+            let lost = u64::from(stats.lost);
+            self.quality = Some(stats.quality);
+            self.loss_ratio = Some(match stats.received + lost {
+                0 => 0.0,
+                total => lost as f64 / total as f64,
+            });
+        }
+        self
+    }
+
+    pub(crate) fn with_quality(mut self, quality: Option<f64>) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Whether the process should be left running: the context is started
+    /// and not stopped.
+    pub fn is_live(&self) -> bool {
+        self.running
+    }
+
+    /// Whether traffic is flowing: running, with a connected peer and a
+    /// packet within `max_packet_age`.
+    pub fn is_ready(&self, max_packet_age: Duration) -> bool {
+        self.running
+            && self.connected_peers > 0
+            && self
+                .last_packet_age
+                .is_some_and(|age| age <= max_packet_age)
+    }
+}
+
+/// When a packet last went through.
+#[derive(Debug, Default)]
+pub(crate) struct Activity {
+    last: Mutex<Option<Instant>>,
+}
+
+impl Activity {
+    pub(crate) fn touch(&self) {
+        if let Ok(mut last) = self.last.lock() {
+            *last = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn age(&self) -> Option<Duration> {
+        let last = (*self.last.lock().ok()?)?;
+        Some(last.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PeerId;
+
+    fn peer(n: usize, state: ConnectionState) -> PeerInfo {
+        PeerInfo {
+            id: PeerId::from_raw(n as *mut rist_sys::rist_peer),
+            url: None,
+            cname: None,
+            state,
+            socket_buffers: None,
+            profile: crate::Profile::Main,
+            remote_cname: None,
+            stats: None,
+        }
+    }
+
+    #[test]
+    fn test_readiness() {
+        let peers = [
+            peer(1, ConnectionState::Connecting),
+            peer(2, ConnectionState::Connected),
+        ];
+        let health = Health::new(true, &peers, Some(Duration::from_millis(500)));
+        assert_eq!(health.peers, 2);
+        assert_eq!(health.connected_peers, 1);
+        assert!(health.is_live());
+        assert!(health.is_ready(Duration::from_secs(1)));
+        assert!(!health.is_ready(Duration::from_millis(100)));
+
+        assert!(!Health::new(true, &peers, None).is_ready(Duration::MAX));
+        assert!(!Health::new(true, &peers[..1], Some(Duration::ZERO)).is_ready(Duration::MAX));
+        assert!(!Health::new(false, &peers, Some(Duration::ZERO)).is_live());
+    }
+
+    #[test]
+    fn test_loss_ratio_from_stats() {
+        let stats = ReceiverStats {
+            received: 990,
+            lost: 10,
+            quality: 99.0,
+            ..Default::default()
+        };
+        let health = Health::new(true, &[], None).with_receiver_stats(Some(stats));
+        assert_eq!(health.quality, Some(99.0));
+        assert_eq!(health.loss_ratio, Some(0.01));
+
+        let activity = Activity::default();
+        assert_eq!(activity.age(), None);
+        activity.touch();
+        assert!(activity.age().unwrap() < Duration::from_secs(1));
+    }
+}
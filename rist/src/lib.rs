@@ -21,24 +21,86 @@
 //! # Ok::<(), rist::Error>(())
 //! ```
 
+// Async tests hold TEST_MUTEX across awaits to keep from binding ports at
+// the same time; each runs on a runtime of its own, so that can't deadlock
+#![cfg_attr(test, allow(clippy::await_holding_lock))]
+
+pub mod alarms;
+pub mod autotune;
+pub mod buffer;
+mod builder;
+mod callback;
+pub mod capture;
+pub mod chunk;
+mod connection;
+pub mod continuity;
 mod error;
+pub mod failover;
+mod flow;
+mod health;
 mod logging;
+mod metadata;
+#[cfg(unix)]
+pub mod notify;
+pub mod oob;
 mod options;
+mod pacing;
+mod peer;
+mod peer_config;
+mod preset;
 mod profile;
 mod receiver;
 mod sender;
+mod socket;
 pub mod stats;
+pub mod supervisor;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod ts;
+mod url;
+mod version;
 
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "tokio")]
+pub mod dual_path;
+#[cfg(feature = "tokio")]
+pub mod fanout;
+#[cfg(feature = "tokio")]
+pub mod recorder;
+#[cfg(feature = "tokio")]
+pub mod relay;
+#[cfg(all(feature = "tokio", feature = "ts"))]
+pub mod replay;
 #[cfg(feature = "tokio")]
 pub mod tokio;
 
+pub use builder::{ReceiverBuilder, SenderBuilder};
+pub use callback::{callback_panics, take_callback_panic, CallbackPanic};
+#[cfg(feature = "config")]
+pub use config::Config;
+pub use connection::ConnectionState;
 pub use error::Error;
+pub use flow::{FlowInfo, StreamEvent};
+pub use health::Health;
 pub use logging::{set_logging, LogLevel};
-pub use options::{ReceiverOptions, RecoveryMode, SenderOptions};
+pub use metadata::{ntp_timestamp, ntp_to_system_time, BlockFlags, BlockMetadata};
+pub use options::{OverflowPolicy, ReceiverOptions, RecoveryMode, SenderOptions};
+pub use peer::{PeerId, PeerInfo, PeerStats};
+pub use peer_config::{parse_url, BufferBloatMode, CongestionControl, PeerConfig, TimingMode};
+pub use preset::Preset;
 pub use profile::Profile;
 pub use receiver::{DataBlock, Receiver};
 pub use sender::Sender;
-pub use stats::{ReceiverStats, SenderStats};
+pub use socket::SocketBuffers;
+pub use stats::{ReceiverStats, SenderStats, StatsDelta, StatsMode, StatsSnapshot};
+pub use url::RistUrl;
+pub use version::{
+    check_crypto_backend, crypto_backend, supports, version, CryptoBackend, Feature, Version,
+};
+
+/// The raw librist bindings, for use with the `as_raw()` accessors.
+pub use rist_sys as sys;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -47,10 +109,7 @@ pub(crate) static TEST_MUTEX: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
 #[cfg(test)]
 pub(crate) fn next_test_port() -> u16 {
-    std::net::UdpSocket::bind("127.0.0.1:0")
-        .and_then(|socket| socket.local_addr())
-        .map(|addr| addr.port())
-        .expect("failed to allocate test UDP port")
+    testing::free_port().expect("failed to allocate test UDP port")
 }
 
 #[cfg(test)]
@@ -181,6 +240,211 @@ mod tests {
         assert!(received_count > 0, "expected to receive some packets");
     }
 
+    #[test]
+    fn test_send_discontinuity() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let testing::Loopback {
+            sender, receiver, ..
+        } = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        // one packet with an adaptation field, one without
+        let mut payload = vec![0u8; ts::PACKET_SIZE * 2];
+        payload[0] = ts::SYNC_BYTE;
+        payload[3] = 0x30;
+        payload[4] = 7;
+        payload[ts::PACKET_SIZE] = ts::SYNC_BYTE;
+        payload[ts::PACKET_SIZE + 3] = 0x10;
+
+        let mut metadata = BlockMetadata::new();
+        metadata.flags.discontinuity = true;
+        sender.send_with_metadata(&payload, &metadata).unwrap();
+        // not TS, sent as is
+        sender.send_with_metadata(&[1, 2, 3], &metadata).unwrap();
+
+        let block = receiver.read(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(block.payload()[5], 0x80);
+        assert_eq!(
+            block.payload()[ts::PACKET_SIZE..],
+            payload[ts::PACKET_SIZE..]
+        );
+        let block = receiver.read(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(block.payload(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_sender_flow_id() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        assert!(matches!(
+            Sender::with_flow_id(Profile::Main, 7),
+            Err(Error::Configuration(_))
+        ));
+
+        let port = crate::next_test_port();
+        let receiver = Receiver::builder()
+            .peer(format!("rist://@127.0.0.1:{port}"))
+            .build()
+            .unwrap();
+        let sender = Sender::builder()
+            .flow_id(4242)
+            .peer(format!("rist://127.0.0.1:{port}"))
+            .build()
+            .unwrap();
+        assert_eq!(sender.flow_id().unwrap(), 4242);
+
+        sender.send(&[0x47u8; 1316]).unwrap();
+        let block = receiver.read(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(block.flow_id(), 4242);
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let simple = Sender::new(Profile::Simple).unwrap();
+        assert!(matches!(
+            simple.start_heartbeat(Duration::from_millis(50)),
+            Err(Error::UnsupportedByProfile { .. })
+        ));
+
+        let testing::Loopback {
+            sender, receiver, ..
+        } = testing::loopback(Profile::Main, &Default::default()).unwrap();
+        let far_end = receiver.start_heartbeat(Duration::from_millis(50)).unwrap();
+        let heartbeat = sender.start_heartbeat(Duration::from_millis(50)).unwrap();
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(3);
+        while heartbeat.rtt().is_none() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(heartbeat.rtt().unwrap() < Duration::from_secs(1));
+        assert!(heartbeat.is_alive(Duration::from_secs(1)));
+        assert!(far_end.is_alive(Duration::from_secs(1)));
+        assert_eq!(heartbeat.peers().len(), 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_oob_messages() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let testing::Loopback {
+            sender, receiver, ..
+        } = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        // taken in before the receiver uses OOB
+        assert_eq!(sender.send_message(&"cam 1").unwrap(), 1);
+        std::thread::sleep(Duration::from_millis(100));
+        let message = receiver
+            .recv_message::<String>(Duration::from_secs(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.body, "cam 1");
+
+        // answer on the peer the message came in on
+        receiver.send_message_to(message.peer, &[1, 2, 3]).unwrap();
+        let reply = sender
+            .recv_message::<Vec<u8>>(Duration::from_secs(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.body, [1, 2, 3]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_remote_control() {
+        use crate::oob::Command;
+
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let testing::Loopback {
+            sender, receiver, ..
+        } = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        // the encoder only starts waiting once the first command was sent
+        let encoder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            for _ in 0..2 {
+                let request = sender
+                    .recv_command(Duration::from_secs(2))
+                    .unwrap()
+                    .unwrap();
+                let result = match request.command {
+                    Command::SetBitrate { bitrate } if bitrate > 20_000_000 => {
+                        Err("bitrate too high".to_string())
+                    }
+                    _ => Ok(()),
+                };
+                sender.ack_command(&request, result).unwrap();
+            }
+        });
+
+        let timeout = Duration::from_secs(2);
+        receiver
+            .send_command(Command::SetBitrate { bitrate: 6_000_000 }, timeout)
+            .unwrap();
+        assert!(matches!(
+            receiver.send_command(
+                Command::SetBitrate {
+                    bitrate: 50_000_000
+                },
+                timeout
+            ),
+            Err(Error::CommandRejected(_))
+        ));
+        encoder.join().unwrap();
+    }
+
+    #[test]
+    fn test_data_block_as_bytes() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let testing::Loopback {
+            sender, receiver, ..
+        } = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        sender.send(&[0x47u8; 1316]).unwrap();
+        if let Ok(Some(block)) = receiver.read(Duration::from_millis(500)) {
+            let bytes: &[u8] = block.as_ref();
+            assert_eq!(bytes, block.payload());
+            assert_eq!(block.len(), 1316);
+            assert_eq!(&block[..4], &[0x47; 4]);
+        }
+    }
+
+    #[test]
+    fn test_queue_len_after_burst() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let testing::Loopback {
+            sender, receiver, ..
+        } = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        for _ in 0..10 {
+            sender.send(&[0x47u8; 188]).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(200));
+        if let Ok(Some(block)) = receiver.read(Duration::from_millis(500)) {
+            // the rest of the burst is still queued
+            assert_eq!(receiver.queue_len(), block.queue_len());
+            assert!(block.queue_len() < 10);
+        }
+        while let Ok(Some(_)) = receiver.read(Duration::from_millis(50)) {}
+        assert_eq!(receiver.queue_len(), 0);
+    }
+
+    #[test]
+    fn test_data_block_clone_shares_payload() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let testing::Loopback {
+            sender, receiver, ..
+        } = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        sender.send(&[0x47u8; 1316]).unwrap();
+        if let Ok(Some(block)) = receiver.read(Duration::from_millis(500)) {
+            let copy = block.clone();
+            drop(block);
+            assert_eq!(copy.payload(), &[0x47u8; 1316][..]);
+
+            let again = copy.clone();
+            assert_eq!(again.payload().as_ptr(), copy.payload().as_ptr());
+        }
+    }
+
     #[test]
     fn test_sender_empty_url() {
         let _guard = crate::TEST_MUTEX.lock().unwrap();
@@ -215,6 +479,367 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_builder_requires_peer() {
+        assert!(Receiver::builder().build().is_err());
+        assert!(Sender::builder().build().is_err());
+    }
+
+    #[test]
+    fn test_builder_roundtrip() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let port = crate::next_test_port();
+        let receiver = Receiver::builder()
+            .profile(Profile::Main)
+            .peer(format!("rist://@:{port}"))
+            .stats_interval(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let sender = Sender::builder()
+            .profile(Profile::Main)
+            .peer(format!("rist://127.0.0.1:{port}"))
+            .log_level(LogLevel::Error)
+            .build()
+            .unwrap();
+
+        sender.send(&[0x47u8; 1316]).unwrap();
+        if let Ok(Some(data)) = receiver.read(Duration::from_millis(500)) {
+            assert_eq!(data.payload().len(), 1316);
+        }
+    }
+
+    #[test]
+    fn test_close_flow() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let pair = testing::loopback(Profile::Main, &Default::default()).unwrap();
+        assert!(matches!(
+            pair.receiver.close_flow(7),
+            Err(Error::UnknownFlow(7))
+        ));
+
+        pair.sender.send(&[0x47u8; 1316]).unwrap();
+        let block = pair
+            .receiver
+            .read(Duration::from_secs(1))
+            .unwrap()
+            .expect("nothing received before closing");
+        pair.receiver.close_flow(block.flow_id()).unwrap();
+        assert!(pair.receiver.flows().is_empty());
+
+        for _ in 0..10 {
+            pair.sender.send(&[0x47u8; 1316]).unwrap();
+        }
+        assert!(pair
+            .receiver
+            .read(Duration::from_millis(300))
+            .unwrap()
+            .is_none());
+        assert!(pair.receiver.flows().is_empty());
+    }
+
+    #[test]
+    fn test_stop_rejects_send() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let mut pair = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        pair.sender.stop().unwrap();
+        pair.receiver.stop().unwrap();
+
+        assert!(pair.sender.is_stopped());
+        assert!(pair.receiver.is_stopped());
+        assert!(matches!(pair.sender.send(b"late"), Err(Error::Stopped)));
+        assert!(matches!(
+            pair.sender.add_peer("rist://127.0.0.1:1968"),
+            Err(Error::Stopped)
+        ));
+    }
+
+    #[test]
+    fn test_bitrate_cap_drops() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let pair = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        pair.sender.set_bitrate_cap(Some(100_000));
+        assert_eq!(pair.sender.bitrate_cap(), Some(100_000));
+        let results: Vec<_> = (0..20).map(|_| pair.sender.send(&[0x47u8; 1316])).collect();
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(Error::BitrateCapExceeded))));
+        assert!(pair.sender.bitrate_cap_drops() > 0);
+
+        pair.sender.set_bitrate_cap(None);
+        pair.sender.send(&[0x47u8; 1316]).unwrap();
+    }
+
+    #[test]
+    fn test_bitrate_cap_drops_without_pacing() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let options = testing::LoopbackOptions {
+            sender: SenderOptions::new().pace_bitrate(200_000),
+            ..Default::default()
+        };
+        let pair = testing::loopback(Profile::Main, &options).unwrap();
+
+        // one packet fits under the cap, the rest are dropped
+        pair.sender.set_bitrate_cap(Some(8_000));
+        let start = std::time::Instant::now();
+        let results: Vec<_> = (0..20).map(|_| pair.sender.send(&[0x47u8; 1316])).collect();
+        assert!(results[1..]
+            .iter()
+            .all(|r| matches!(r, Err(Error::BitrateCapExceeded))));
+        // 19 paced packets would take about a second
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_pause_discards_data() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let mut pair = testing::loopback(Profile::Main, &Default::default()).unwrap();
+
+        pair.receiver.pause();
+        assert!(pair.receiver.is_paused());
+        pair.sender.send(&[0x47u8; 1316]).unwrap();
+        assert!(pair
+            .receiver
+            .read(Duration::from_millis(200))
+            .unwrap()
+            .is_none());
+
+        pair.receiver.resume();
+        assert!(!pair.receiver.is_paused());
+        pair.sender.send(&[0x47u8; 188]).unwrap();
+        if let Ok(Some(data)) = pair.receiver.read(Duration::from_millis(500)) {
+            assert_eq!(data.payload().len(), 188);
+        }
+    }
+
+    #[test]
+    fn test_set_nacks_enabled() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let mut pair = testing::loopback(Profile::Main, &Default::default()).unwrap();
+        let receives = |pair: &testing::Loopback| {
+            (0..20).any(|_| {
+                pair.sender.send(&[0x47u8; 1316]).unwrap();
+                matches!(pair.receiver.read(Duration::from_millis(100)), Ok(Some(_)))
+            })
+        };
+
+        let ids = pair.receiver.set_nacks_enabled(false).unwrap();
+        assert_eq!(ids.len(), 1);
+        assert_eq!(pair.receiver.peers()[0].id, ids[0]);
+        // the listening peer was re-created on its port and still receives
+        assert!(receives(&pair), "nothing received with NACKs off");
+        // already off, so the peer stays connected
+        assert_eq!(pair.receiver.set_nacks_enabled(false).unwrap(), ids);
+
+        let restored = pair.receiver.set_nacks_enabled(true).unwrap();
+        assert_eq!(pair.receiver.peers()[0].id, restored[0]);
+        assert!(receives(&pair), "nothing received with NACKs back on");
+    }
+
+    #[test]
+    fn test_peer_state() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://127.0.0.1:{}", crate::next_test_port());
+        let mut sender = Sender::new(Profile::Main).unwrap();
+        let peer = sender.add_peer(&url).unwrap();
+
+        assert_eq!(sender.peer_state(peer), Some(ConnectionState::Connecting));
+        assert!(!sender.is_connected());
+
+        sender.start().unwrap();
+        sender.stop().unwrap();
+        assert_eq!(sender.peer_state(peer), None);
+    }
+
+    #[test]
+    fn test_list_peers() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://127.0.0.1:{}?cname=camera1", crate::next_test_port());
+        let mut sender = Sender::new(Profile::Main).unwrap();
+        let id = sender.add_peer(&url).unwrap();
+
+        let peers = sender.peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id, id);
+        assert_eq!(peers[0].url.as_deref(), Some(url.as_str()));
+        assert_eq!(peers[0].cname.as_deref(), Some("camera1"));
+        assert_eq!(peers[0].state, ConnectionState::Connecting);
+    }
+
+    #[test]
+    fn test_reconnect_peer() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://127.0.0.1:{}", crate::next_test_port());
+        let mut sender = Sender::new(Profile::Main).unwrap();
+        let old = sender.add_peer(&url).unwrap();
+        sender.start().unwrap();
+
+        let options = SenderOptions::new().recovery_length_max(Duration::from_secs(2));
+        let new = sender.reconnect_peer(old, &options).unwrap();
+
+        let peers = sender.peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id, new);
+        assert_eq!(peers[0].url.as_deref(), Some(url.as_str()));
+        assert!(matches!(
+            sender.reconnect_peer(old, &options),
+            Err(Error::UnknownPeer)
+        ));
+    }
+
+    #[test]
+    fn test_reconnect_listening_peer() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let mut pair = testing::loopback(Profile::Main, &Default::default()).unwrap();
+        let old = pair.receiver.peers()[0].id;
+
+        let options = ReceiverOptions::new().recovery_length_max(Duration::from_secs(2));
+        let new = pair.receiver.reconnect_peer(old, &options).unwrap();
+        assert_eq!(pair.receiver.peers().len(), 1);
+        assert_eq!(pair.receiver.peers()[0].id, new);
+
+        // the replacement could bind the port, so data still arrives
+        let received = (0..20).any(|_| {
+            pair.sender.send(&[0x47u8; 1316]).unwrap();
+            matches!(pair.receiver.read(Duration::from_millis(100)), Ok(Some(_)))
+        });
+        assert!(received, "nothing received after reconnecting");
+    }
+
+    #[test]
+    fn test_set_max_bandwidth() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://127.0.0.1:{}", crate::next_test_port());
+        let mut sender = Sender::new(Profile::Main).unwrap();
+        let old = sender.add_peer(&url).unwrap();
+        sender.start().unwrap();
+
+        let ids = sender.set_max_bandwidth(5_000_000).unwrap();
+        assert_eq!(ids.len(), 1);
+        assert_ne!(ids[0], old);
+        assert_eq!(sender.peers()[0].id, ids[0]);
+
+        // already at the limit, so the peer stays connected
+        assert_eq!(sender.set_max_bandwidth(5_000_000).unwrap(), ids);
+        assert!(matches!(
+            sender.set_max_bandwidth(999),
+            Err(Error::InvalidOption { .. })
+        ));
+        assert_eq!(sender.peers()[0].id, ids[0]);
+    }
+
+    #[test]
+    fn test_set_max_bandwidth_on_listening_peer() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let port = crate::next_test_port();
+        let mut sender = Sender::new(Profile::Main).unwrap();
+        sender
+            .add_peer(&format!("rist://@127.0.0.1:{port}"))
+            .unwrap();
+        sender.start().unwrap();
+        let mut receiver = Receiver::new(Profile::Main).unwrap();
+        receiver
+            .add_peer(&format!("rist://127.0.0.1:{port}"))
+            .unwrap();
+        receiver.start().unwrap();
+
+        let ids = sender.set_max_bandwidth(5_000_000).unwrap();
+        assert_eq!(sender.peers()[0].id, ids[0]);
+
+        // the replacement could bind the port, so the receiver gets data
+        let received = (0..30).any(|_| {
+            sender.send(&[0x47u8; 1316]).unwrap();
+            matches!(receiver.read(Duration::from_millis(100)), Ok(Some(_)))
+        });
+        assert!(received, "nothing received after changing the bandwidth");
+    }
+
+    #[test]
+    fn test_send_to_peer() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let mut pair = testing::loopback(Profile::Main, &Default::default()).unwrap();
+        let target = pair.sender.peers()[0].id;
+        pair.sender.send_to(target, &[0x47u8; 188]).unwrap();
+        let data = pair.receiver.read(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(data.payload().len(), 188);
+
+        // with a second peer the block would reach both, so nothing is sent
+        let mut second = Receiver::new(Profile::Main).unwrap();
+        let port = crate::next_test_port();
+        second
+            .add_peer(&format!("rist://@127.0.0.1:{port}"))
+            .unwrap();
+        second.start().unwrap();
+        let other = pair
+            .sender
+            .add_peer(&format!("rist://127.0.0.1:{port}"))
+            .unwrap();
+        assert!(matches!(
+            pair.sender.send_to(target, &[0x47u8; 1316]),
+            Err(Error::Configuration(_))
+        ));
+        assert!(matches!(
+            pair.sender.send_to(other, &[0x47u8; 1316]),
+            Err(Error::Configuration(_))
+        ));
+        assert!(second.read(Duration::from_millis(500)).unwrap().is_none());
+        assert!(pair
+            .receiver
+            .read(Duration::from_millis(100))
+            .unwrap()
+            .is_none());
+
+        // the old id is stale once the peer has been replaced
+        pair.sender
+            .reconnect_peer(other, &Default::default())
+            .unwrap();
+        assert!(matches!(
+            pair.sender.send_to(other, b"gone"),
+            Err(Error::UnknownPeer)
+        ));
+    }
+
+    #[test]
+    fn test_accept_flow_ids() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let options = testing::LoopbackOptions {
+            receiver: ReceiverOptions::new().accept_flow_ids(&[42]),
+            ..Default::default()
+        };
+        let pair = testing::loopback(Profile::Main, &options).unwrap();
+
+        for _ in 0..10 {
+            pair.sender.send_with_flow_id(&[0x47u8; 1316], 7).unwrap();
+            pair.sender.send_with_flow_id(&[0x47u8; 1316], 42).unwrap();
+        }
+
+        while let Ok(Some(data)) = pair.receiver.read(Duration::from_millis(200)) {
+            assert_eq!(data.flow_id(), 42);
+        }
+    }
+
+    #[test]
+    fn test_raw_context_roundtrip() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        assert!(unsafe { Sender::from_raw(std::ptr::null_mut(), Profile::Main) }.is_err());
+        assert!(unsafe { Receiver::from_raw(std::ptr::null_mut(), Profile::Main) }.is_err());
+
+        let mut ctx = std::ptr::null_mut();
+        let ret = unsafe {
+            sys::rist_sender_create(&mut ctx, Profile::Main.to_raw(), 0, std::ptr::null_mut())
+        };
+        assert_eq!(ret, 0);
+
+        let mut sender = unsafe { Sender::from_raw(ctx, Profile::Main) }.unwrap();
+        assert_eq!(sender.as_raw(), ctx);
+        let peer = sender
+            .add_peer(&format!("rist://127.0.0.1:{}", crate::next_test_port()))
+            .unwrap();
+        assert!(!peer.as_raw().is_null());
+        sender.start().unwrap();
+    }
+
     #[test]
     fn test_profiles() {
         let _guard = crate::TEST_MUTEX.lock().unwrap();
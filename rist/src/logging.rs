@@ -14,7 +14,6 @@ pub enum LogLevel {
 }
 
 impl LogLevel {
-    #[allow(dead_code)]
     pub(crate) fn to_raw(self) -> rist_sys::rist_log_level {
         match self {
             LogLevel::Disable => rist_sys::rist_log_level_RIST_LOG_DISABLE,
@@ -28,6 +27,25 @@ impl LogLevel {
     }
 }
 
+impl std::str::FromStr for LogLevel {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "disable" | "off" | "none" => Ok(LogLevel::Disable),
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "notice" => Ok(LogLevel::Notice),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "simulate" => Ok(LogLevel::Simulate),
+            _ => Err(crate::Error::Configuration(format!(
+                "unknown log level: {s}"
+            ))),
+        }
+    }
+}
+
 /// Set the global logging level for librist.
 pub fn set_logging(level: LogLevel) -> crate::Result<()> {
     unsafe {
@@ -59,3 +77,46 @@ pub fn set_logging(level: LogLevel) -> crate::Result<()> {
 
     Ok(())
 }
+
+/// Logging settings owned by a single context.
+///
+/// librist keeps a pointer to these for the lifetime of the context, so they
+/// are freed only after the context is destroyed.
+pub(crate) struct LoggingSettings(*mut rist_sys::rist_logging_settings);
+
+impl LoggingSettings {
+    /// Create settings logging to stderr at `level`.
+    pub(crate) fn new(level: LogLevel) -> crate::Result<Self> {
+        let mut settings: *mut rist_sys::rist_logging_settings = ptr::null_mut();
+        let ret = unsafe {
+            rist_sys::rist_logging_set(
+                &mut settings,
+                level.to_raw(),
+                None,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        if ret != 0 || settings.is_null() {
+            return Err(crate::Error::LoggingSetup);
+        }
+
+        Ok(Self(settings))
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut rist_sys::rist_logging_settings {
+        self.0
+    }
+}
+
+impl Drop for LoggingSettings {
+    fn drop(&mut self) {
+        unsafe {
+            rist_sys::rist_logging_settings_free2(&mut self.0);
+        }
+    }
+}
+
+// SAFETY: the settings are only read by librist after creation
+unsafe impl Send for LoggingSettings {}
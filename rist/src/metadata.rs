@@ -0,0 +1,177 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds from the NTP epoch (1900-01-01) to the Unix epoch.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Per-packet metadata carried alongside a RIST payload.
+///
+/// Read it from a received block with [`DataBlock::metadata`](crate::DataBlock::metadata)
+/// and hand it back to `send_with_metadata` to forward a packet without
+/// resetting its timing or identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockMetadata {
+    /// NTP timestamp of the packet. 0 lets librist stamp it on send.
+    pub ts_ntp: u64,
+    /// Sequence number to send with. `None` lets librist assign one.
+    pub seq: Option<u64>,
+    /// Flow ID. 0 uses the sender's own flow ID.
+    pub flow_id: u32,
+    /// Virtual source port.
+    pub virt_src_port: u16,
+    /// Virtual destination port.
+    pub virt_dst_port: u16,
+    /// Data flags to send with the packet.
+    pub flags: BlockFlags,
+}
+
+/// RIST data flags of a block.
+///
+/// librist receivers set these on the blocks they read, and
+/// [`DataBlock::flags`](crate::DataBlock::flags) reports them. RIST has no
+/// field for them on the wire, so a sender signals them in the payload
+/// instead: with `discontinuity` set, the discontinuity indicator of every
+/// MPEG-TS packet in the payload that has an adaptation field is set, so a
+/// relay that spliced content can tell the decoders downstream.
+///
+/// This is best-effort. Packets without an adaptation field, which is most
+/// packets of a typical video stream, can't be marked without changing
+/// their size and go out unchanged, as do payloads that aren't whole TS
+/// packets. The send succeeds either way, so a relay that must signal every
+/// splice has to make sure the first packet after it carries an adaptation
+/// field, e.g. the one with the PCR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockFlags {
+    /// The block doesn't follow on from the previous one, e.g. because
+    /// packets were lost or the content was spliced.
+    pub discontinuity: bool,
+    /// The block is the first of its flow. Receivers set this themselves,
+    /// so it is ignored on send.
+    pub buffer_start: bool,
+    /// librist's output FIFO overflowed before this block, so blocks were
+    /// dropped in between. Receivers set this themselves, so it is ignored
+    /// on send.
+    pub overflow: bool,
+}
+
+impl BlockFlags {
+    pub(crate) fn from_raw(flags: u32) -> Self {
+        Self {
+            discontinuity: flags
+                & rist_sys::rist_data_block_receiver_flags_RIST_DATA_FLAGS_DISCONTINUITY
+                != 0,
+            buffer_start: flags
+                & rist_sys::rist_data_block_receiver_flags_RIST_DATA_FLAGS_FLOW_BUFFER_START
+                != 0,
+            overflow: flags & rist_sys::rist_data_block_receiver_flags_RIST_DATA_FLAGS_OVERFLOW
+                != 0,
+        }
+    }
+}
+
+impl BlockMetadata {
+    /// Create empty metadata, leaving every field to librist.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create metadata stamped with the current wall-clock time.
+    pub fn now() -> Self {
+        Self {
+            ts_ntp: ntp_timestamp(SystemTime::now()),
+            ..Self::default()
+        }
+    }
+
+    /// Build a raw data block pointing at `data`.
+    ///
+    /// The returned block borrows `data`; it must not outlive it. The data
+    /// flags aren't passed on: librist's sender flags are a different set.
+    pub(crate) fn to_raw_block(self, data: &[u8]) -> rist_sys::rist_data_block {
+        let mut flags = 0;
+        if self.seq.is_some() {
+            flags |= rist_sys::rist_data_block_sender_flags_RIST_DATA_FLAGS_USE_SEQ;
+        }
+
+        rist_sys::rist_data_block {
+            payload: data.as_ptr() as *const _,
+            payload_len: data.len(),
+            ts_ntp: self.ts_ntp,
+            flow_id: self.flow_id,
+            flags,
+            seq: self.seq.unwrap_or(0),
+            virt_src_port: self.virt_src_port,
+            virt_dst_port: self.virt_dst_port,
+            // librist's sender ignores it and writes to every peer
+            peer: std::ptr::null_mut(),
+            ref_: std::ptr::null_mut(),
+        }
+    }
+}
+
+/// Encode `time` as a 64-bit NTP timestamp, as carried in `ts_ntp`: whole
+/// seconds since 1900 in the upper 32 bits, the fraction of a second in the
+/// lower 32.
+///
+/// Times before 1970 encode as the Unix epoch.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let ts = rist::ntp_timestamp(UNIX_EPOCH + Duration::from_millis(1500));
+/// assert_eq!(ts >> 32, 2_208_988_801);
+/// assert_eq!(ts & 0xffff_ffff, 1 << 31);
+/// ```
+pub fn ntp_timestamp(time: SystemTime) -> u64 {
+    let since_unix = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_unix.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (u64::from(since_unix.subsec_nanos()) << 32) / 1_000_000_000;
+    (secs << 32) | fraction
+}
+
+/// Decode a 64-bit NTP timestamp into wall-clock time.
+///
+/// Returns `None` for 0, which means "not set", and for times before 1970.
+pub fn ntp_to_system_time(ts_ntp: u64) -> Option<SystemTime> {
+    let secs = (ts_ntp >> 32).checked_sub(NTP_UNIX_OFFSET)?;
+    let nanos = ((ts_ntp & 0xffff_ffff) * 1_000_000_000) >> 32;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ntp_roundtrip() {
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let decoded = ntp_to_system_time(ntp_timestamp(time)).unwrap();
+        let error = decoded
+            .duration_since(time)
+            .unwrap_or_else(|e| e.duration());
+        // both conversions truncate
+        assert!(error <= Duration::from_nanos(1));
+
+        assert_eq!(ntp_to_system_time(0), None);
+        assert_eq!(ntp_timestamp(UNIX_EPOCH) >> 32, NTP_UNIX_OFFSET);
+    }
+
+    #[test]
+    fn test_now_is_current() {
+        let before = ntp_timestamp(SystemTime::now());
+        let stamped = BlockMetadata::now();
+        assert!(stamped.ts_ntp >= before);
+        assert_eq!(stamped.seq, None);
+    }
+
+    #[test]
+    fn test_flags_from_raw() {
+        assert_eq!(BlockFlags::from_raw(0), BlockFlags::default());
+        let flags = BlockFlags::from_raw(
+            rist_sys::rist_data_block_receiver_flags_RIST_DATA_FLAGS_DISCONTINUITY
+                | rist_sys::rist_data_block_receiver_flags_RIST_DATA_FLAGS_OVERFLOW,
+        );
+        assert!(flags.discontinuity);
+        assert!(!flags.buffer_start);
+        assert!(flags.overflow);
+    }
+}
@@ -0,0 +1,269 @@
+//! Data readiness for custom event loops.
+//!
+//! librist can signal a file descriptor whenever a receiver has data to
+//! read. [`Receiver::data_notify`](crate::Receiver::data_notify) hands out
+//! that descriptor as a [`DataNotify`], so a receiver can be driven from an
+//! existing epoll, mio or io_uring loop without tokio.
+//!
+//! With the `mio` feature a [`DataNotify`] is a `mio::event::Source`, and as
+//! it implements [`AsFd`] it also works with calloop's `Generic` source.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::{Profile, Receiver};
+//! use std::os::unix::io::AsRawFd;
+//! use std::time::Duration;
+//!
+//! # fn example() -> rist::Result<()> {
+//! let mut receiver = Receiver::new(Profile::Main)?;
+//! receiver.add_peer("rist://@:5000")?;
+//! let notify = receiver.data_notify()?;
+//! receiver.start()?;
+//!
+//! let fd = notify.as_raw_fd();
+//! // register `fd` for reading with the event loop, then on readiness:
+//! notify.clear()?;
+//! while let Some(block) = receiver.read(Duration::ZERO)? {
+//!     // process block
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Result;
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+
+/// A pipe librist writes to when data is available; the read end is
+/// non-blocking.
+#[derive(Debug)]
+pub(crate) struct NotifyPipe {
+    read: OwnedFd,
+    write: OwnedFd,
+}
+
+impl NotifyPipe {
+    pub(crate) fn new() -> io::Result<Self> {
+        let mut fds = [0i32; 2];
+        let ret = unsafe { libc::pipe(fds.as_mut_ptr()) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: pipe() just returned both descriptors and nothing else owns them
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        let flags = unsafe { libc::fcntl(read.as_raw_fd(), libc::F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ret = unsafe { libc::fcntl(read.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { read, write })
+    }
+
+    /// Make the read end readable, as if librist had signalled data.
+    ///
+    /// Does nothing if the pipe is full, as it is readable then anyway.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn signal(fd: RawFd) {
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLOUT,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pollfd, 1, 0) } == 1 {
+            unsafe { libc::write(fd, [1u8].as_ptr() as *const _, 1) };
+        }
+    }
+
+    /// The write end, to pass to librist.
+    pub(crate) fn write_fd(&self) -> RawFd {
+        self.write.as_raw_fd()
+    }
+
+    /// Drain pending notifications.
+    pub(crate) fn consume(&self) -> io::Result<()> {
+        let mut buf = [0u8; 64];
+        loop {
+            let ret =
+                unsafe { libc::read(self.read.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) };
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(());
+                }
+                return Err(err);
+            }
+            if ret == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl AsRawFd for NotifyPipe {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+}
+
+/// A descriptor that becomes readable when a receiver has data.
+///
+/// Wait for it with any readiness API, then call [`clear`](Self::clear)
+/// and read from the receiver until it has nothing left; a notification can
+/// stand for several blocks. The descriptor is non-blocking and stays owned
+/// by the receiver's notification pipe, which lives as long as any clone of
+/// this handle.
+#[derive(Debug, Clone)]
+pub struct DataNotify {
+    pipe: Arc<NotifyPipe>,
+}
+
+impl DataNotify {
+    pub(crate) fn new(pipe: NotifyPipe) -> Self {
+        Self {
+            pipe: Arc::new(pipe),
+        }
+    }
+
+    pub(crate) fn write_fd(&self) -> RawFd {
+        self.pipe.write_fd()
+    }
+
+    /// Consume the pending notifications, so the descriptor is no longer
+    /// readable until librist signals again.
+    pub fn clear(&self) -> Result<()> {
+        self.pipe.consume()?;
+        Ok(())
+    }
+}
+
+impl AsRawFd for DataNotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.pipe.as_raw_fd()
+    }
+}
+
+impl AsFd for DataNotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.pipe.read.as_fd()
+    }
+}
+
+/// Register the descriptor for readable events.
+///
+/// ```no_run
+/// use mio::{Events, Interest, Poll, Token};
+/// use rist::{Profile, Receiver};
+/// use std::time::Duration;
+///
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// const RIST: Token = Token(0);
+///
+/// let mut receiver = Receiver::new(Profile::Main)?;
+/// receiver.add_peer("rist://@:5000")?;
+/// let mut notify = receiver.data_notify()?;
+/// receiver.start()?;
+///
+/// let mut poll = Poll::new()?;
+/// poll.registry()
+///     .register(&mut notify, RIST, Interest::READABLE)?;
+///
+/// let mut events = Events::with_capacity(16);
+/// loop {
+///     poll.poll(&mut events, None)?;
+///     for event in &events {
+///         if event.token() == RIST {
+///             notify.clear()?;
+///             while let Some(block) = receiver.read(Duration::ZERO)? {
+///                 // process block
+///             }
+///         }
+///     }
+/// }
+/// # }
+/// ```
+#[cfg(feature = "mio")]
+impl mio::event::Source for DataNotify {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clear_drains_pipe() {
+        let notify = DataNotify::new(NotifyPipe::new().unwrap());
+        for _ in 0..3 {
+            let ret = unsafe { libc::write(notify.write_fd(), [1u8].as_ptr() as *const _, 1) };
+            assert_eq!(ret, 1);
+        }
+
+        let mut poll = libc::pollfd {
+            fd: notify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(unsafe { libc::poll(&mut poll, 1, 0) }, 1);
+
+        notify.clear().unwrap();
+        poll.revents = 0;
+        assert_eq!(unsafe { libc::poll(&mut poll, 1, 0) }, 0);
+        // already empty
+        notify.clear().unwrap();
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn test_mio_source() {
+        use mio::{Events, Interest, Poll, Token};
+        use std::time::Duration;
+
+        let mut notify = DataNotify::new(NotifyPipe::new().unwrap());
+        let mut poll = Poll::new().unwrap();
+        poll.registry()
+            .register(&mut notify, Token(7), Interest::READABLE)
+            .unwrap();
+
+        let mut events = Events::with_capacity(4);
+        poll.poll(&mut events, Some(Duration::ZERO)).unwrap();
+        assert!(events.is_empty());
+
+        let ret = unsafe { libc::write(notify.write_fd(), [1u8].as_ptr() as *const _, 1) };
+        assert_eq!(ret, 1);
+        poll.poll(&mut events, Some(Duration::from_secs(1)))
+            .unwrap();
+        let event = events.iter().next().unwrap();
+        assert_eq!(event.token(), Token(7));
+        assert!(event.is_readable());
+
+        notify.clear().unwrap();
+        poll.registry().deregister(&mut notify).unwrap();
+    }
+}
@@ -0,0 +1,762 @@
+//! Out-of-band data: application heartbeats, typed messages and remote
+//! control.
+//!
+//! The Main and Advanced profiles can carry out-of-band (OOB) packets next
+//! to the media, on the same RIST session. This module uses them for an
+//! application-level [`Heartbeat`]: both ends ping each other over OOB and
+//! answer each other's pings, which gives a round-trip time and a "far end
+//! alive" status that don't depend on librist's RTCP. RTCP only covers one
+//! hop, while OOB pings cross relays that forward OOB data, so the heartbeat
+//! tells whether the application at the other end is still there.
+//!
+//! A context answers the far end's pings from the moment it is created, so
+//! one end can run a heartbeat on its own; both ends start one to each know
+//! whether the other is alive.
+//!
+//! To qualify a link before going live, `rtt_probe()` sends a few pings of
+//! its own and waits for the answers, which works as soon as the peers are
+//! connected, before any media flows.
+//!
+//! With the `serde` feature, sender and receiver can also exchange typed
+//! [`Message`]s, such as the service name or encoder settings of a stream,
+//! with `send_message()` and `recv_message()`. Messages are JSON with a
+//! length prefix and must fit one OOB packet. Messages that arrive before
+//! `recv_message()` is called wait in a bounded queue, which drops the
+//! oldest once 64 are waiting.
+//!
+//! Also with `serde`, a small request/response protocol lets an operator at
+//! the receiving end control the contribution encoder through the same
+//! session: `send_command()` sends a [`Command`] and waits for the far end
+//! to take it in with `recv_command()` and answer with `ack_command()`.
+//! Like messages, commands wait in a bounded queue until taken in.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::{Profile, Sender};
+//! use std::time::Duration;
+//!
+//! # fn example() -> rist::Result<()> {
+//! let mut sender = Sender::new(Profile::Main)?;
+//! sender.add_peer("rist://192.168.1.1:5000")?;
+//! sender.start()?;
+//!
+//! let heartbeat = sender.start_heartbeat(Duration::from_secs(1))?;
+//! // ...
+//! if !heartbeat.is_alive(Duration::from_secs(5)) {
+//!     eprintln!("far end is gone");
+//! }
+//! if let Some(rtt) = heartbeat.rtt() {
+//!     println!("application RTT {rtt:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::connection::ConnectionSlot;
+use crate::{Error, PeerId, Profile, Result};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+mod control;
+#[cfg(feature = "serde")]
+mod message;
+mod probe;
+
+#[cfg(feature = "serde")]
+pub use control::{Command, CommandRequest};
+#[cfg(feature = "serde")]
+pub use message::Message;
+pub use probe::RttProbe;
+
+/// Prefix of every heartbeat packet, so other OOB traffic is left alone.
+const MAGIC: &[u8; 4] = b"RSHB";
+
+/// Length of an encoded [`Beat`].
+const BEAT_LEN: usize = MAGIC.len() + 1 + 4 + 8;
+
+const PING: u8 = 0;
+const PONG: u8 = 1;
+
+/// A heartbeat ping, or the pong echoing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Beat {
+    pong: bool,
+    seq: u32,
+    // when the ping was sent, in nanoseconds since the pinging side's epoch;
+    // only that side reads it, so the clocks don't need to agree
+    sent: u64,
+}
+
+impl Beat {
+    fn encode(&self) -> [u8; BEAT_LEN] {
+        let mut buf = [0u8; BEAT_LEN];
+        buf[..4].copy_from_slice(MAGIC);
+        buf[4] = if self.pong { PONG } else { PING };
+        buf[5..9].copy_from_slice(&self.seq.to_be_bytes());
+        buf[9..].copy_from_slice(&self.sent.to_be_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() != BEAT_LEN || &buf[..4] != MAGIC {
+            return None;
+        }
+        let pong = match buf[4] {
+            PING => false,
+            PONG => true,
+            _ => return None,
+        };
+        Some(Self {
+            pong,
+            seq: u32::from_be_bytes(buf[5..9].try_into().ok()?),
+            sent: u64::from_be_bytes(buf[9..].try_into().ok()?),
+        })
+    }
+}
+
+/// Heartbeat status of one peer, from [`Heartbeat::peers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerHeartbeat {
+    /// The peer the far end answered on.
+    pub peer: PeerId,
+    /// Round-trip time of the latest answered ping, `None` until one has
+    /// been answered.
+    pub rtt: Option<Duration>,
+    /// Time since anything was last heard from the far end.
+    pub last_seen: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PeerBeats {
+    rtt: Option<Duration>,
+    last_seen: Instant,
+}
+
+#[derive(Debug)]
+struct HeartbeatState {
+    epoch: Instant,
+    seq: AtomicU32,
+    peers: Mutex<HashMap<PeerId, PeerBeats>>,
+    latest_rtt: Mutex<Option<Duration>>,
+}
+
+impl HeartbeatState {
+    fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            seq: AtomicU32::new(0),
+            peers: Mutex::new(HashMap::new()),
+            latest_rtt: Mutex::new(None),
+        }
+    }
+
+    fn ping(&self, now: Instant) -> Beat {
+        Beat {
+            pong: false,
+            seq: self.seq.fetch_add(1, Ordering::Relaxed),
+            sent: now.saturating_duration_since(self.epoch).as_nanos() as u64,
+        }
+    }
+
+    /// Note a beat from `peer`, returning the pong to send back for a ping.
+    fn on_beat(&self, peer: PeerId, beat: Beat, now: Instant) -> Option<Beat> {
+        let rtt = beat.pong.then(|| {
+            let sent = self.epoch + Duration::from_nanos(beat.sent);
+            now.saturating_duration_since(sent)
+        });
+
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = peers.entry(peer).or_insert(PeerBeats {
+            rtt: None,
+            last_seen: now,
+        });
+        entry.last_seen = now;
+        if rtt.is_some() {
+            entry.rtt = rtt;
+            *self.latest_rtt.lock().unwrap_or_else(|e| e.into_inner()) = rtt;
+        }
+
+        (!beat.pong).then_some(Beat { pong: true, ..beat })
+    }
+}
+
+/// Handle on a context's heartbeat, from `start_heartbeat()`.
+///
+/// Clones share the same status. The heartbeat keeps running until the
+/// context is dropped, whether or not a handle is kept.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    state: Arc<HeartbeatState>,
+}
+
+impl Heartbeat {
+    /// Round-trip time of the latest answered ping from any peer.
+    pub fn rtt(&self) -> Option<Duration> {
+        *self
+            .state
+            .latest_rtt
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Time since anything was last heard from the far end, `None` if it
+    /// never answered.
+    pub fn last_seen(&self) -> Option<Duration> {
+        let peers = self.state.peers.lock().unwrap_or_else(|e| e.into_inner());
+        peers.values().map(|beats| beats.last_seen.elapsed()).min()
+    }
+
+    /// Returns `true` if the far end was heard from within `timeout`.
+    pub fn is_alive(&self, timeout: Duration) -> bool {
+        self.last_seen().is_some_and(|age| age <= timeout)
+    }
+
+    /// Status of every peer the far end was heard on.
+    pub fn peers(&self) -> Vec<PeerHeartbeat> {
+        let peers = self.state.peers.lock().unwrap_or_else(|e| e.into_inner());
+        let mut status: Vec<PeerHeartbeat> = peers
+            .iter()
+            .map(|(peer, beats)| PeerHeartbeat {
+                peer: *peer,
+                rtt: beats.rtt,
+                last_seen: beats.last_seen.elapsed(),
+            })
+            .collect();
+        status.sort_by_key(|status| status.peer);
+        status
+    }
+}
+
+/// The thread sending pings, stopped through `stop`.
+struct PingTask {
+    interval: Arc<Mutex<Duration>>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    handle: JoinHandle<()>,
+}
+
+/// OOB state of a context.
+///
+/// librist holds a raw pointer to this while the OOB callback is
+/// registered, so it must outlive the context.
+pub(crate) struct OobSlot {
+    // the context, as an address so the slot can be shared across threads
+    ctx: usize,
+    profile: Profile,
+    registered: Mutex<bool>,
+    heartbeat: Mutex<Option<Arc<HeartbeatState>>>,
+    pings: Mutex<Option<PingTask>>,
+    probes: probe::ProbeSlot,
+    #[cfg(feature = "serde")]
+    messages: message::MessageQueue<Vec<u8>>,
+    #[cfg(feature = "serde")]
+    control: control::ControlSlot,
+}
+
+impl OobSlot {
+    pub(crate) fn new(ctx: *mut rist_sys::rist_ctx, profile: Profile) -> Self {
+        Self {
+            ctx: ctx as usize,
+            profile,
+            registered: Mutex::new(false),
+            heartbeat: Mutex::new(None),
+            pings: Mutex::new(None),
+            probes: probe::ProbeSlot::default(),
+            #[cfg(feature = "serde")]
+            messages: message::MessageQueue::default(),
+            #[cfg(feature = "serde")]
+            control: control::ControlSlot::default(),
+        }
+    }
+
+    fn ctx(&self) -> *mut rist_sys::rist_ctx {
+        self.ctx as *mut rist_sys::rist_ctx
+    }
+
+    /// Register the OOB callback when the context is created, so the far
+    /// end's messages and pings are taken in before this end uses OOB.
+    ///
+    /// The Simple profile has no OOB, so it is left alone there.
+    pub(crate) fn register_early(self: &Arc<Self>) -> Result<()> {
+        if self.profile == Profile::Simple {
+            return Ok(());
+        }
+        self.register()
+    }
+
+    /// Register the OOB callback unless it already is.
+    fn register(self: &Arc<Self>) -> Result<()> {
+        let mut registered = self.registered.lock().unwrap_or_else(|e| e.into_inner());
+        if *registered {
+            return Ok(());
+        }
+        if self.profile == Profile::Simple {
+            return Err(Error::UnsupportedByProfile {
+                profile: self.profile,
+                feature: "out-of-band data",
+            });
+        }
+
+        let slot_ptr = Arc::as_ptr(self) as *mut c_void;
+        let ret =
+            unsafe { rist_sys::rist_oob_callback_set(self.ctx(), Some(oob_callback), slot_ptr) };
+        if ret != 0 {
+            return Err(Error::Configuration(
+                "failed to set OOB callback".to_string(),
+            ));
+        }
+        *registered = true;
+        Ok(())
+    }
+
+    /// Queue `payload` as an OOB packet to `peer`.
+    fn write(&self, peer: PeerId, payload: &[u8]) -> Result<usize> {
+        let block = rist_sys::rist_oob_block {
+            peer: peer.as_raw(),
+            payload: payload.as_ptr() as *const c_void,
+            payload_len: payload.len(),
+            ts_ntp: 0,
+        };
+        let ret = unsafe { rist_sys::rist_oob_write(self.ctx(), &block) };
+        if ret < 0 {
+            return Err(Error::Send);
+        }
+        Ok(ret as usize)
+    }
+
+    /// Ping every connected peer every `interval`, and answer the far end's
+    /// pings.
+    ///
+    /// Starting it again only changes the interval.
+    pub(crate) fn start_heartbeat(
+        self: &Arc<Self>,
+        connection: &Arc<ConnectionSlot>,
+        interval: Duration,
+    ) -> Result<Heartbeat> {
+        if interval.is_zero() {
+            return Err(Error::InvalidOption {
+                field: "heartbeat interval",
+                reason: "must not be zero".to_string(),
+            });
+        }
+        self.register()?;
+
+        let state = {
+            let mut heartbeat = self.heartbeat.lock().unwrap_or_else(|e| e.into_inner());
+            heartbeat
+                .get_or_insert_with(|| Arc::new(HeartbeatState::new()))
+                .clone()
+        };
+
+        let mut pings = self.pings.lock().unwrap_or_else(|e| e.into_inner());
+        match pings.as_ref() {
+            Some(task) => *task.interval.lock().unwrap_or_else(|e| e.into_inner()) = interval,
+            None => *pings = Some(self.spawn_pings(connection.clone(), state.clone(), interval)),
+        }
+
+        Ok(Heartbeat { state })
+    }
+
+    fn spawn_pings(
+        self: &Arc<Self>,
+        connection: Arc<ConnectionSlot>,
+        state: Arc<HeartbeatState>,
+        interval: Duration,
+    ) -> PingTask {
+        let interval = Arc::new(Mutex::new(interval));
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let slot = self.clone();
+        let task_interval = interval.clone();
+        let task_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            let (stopped, wake) = &*task_stop;
+            let mut guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+            while !*guard {
+                let ping = state.ping(Instant::now()).encode();
+                for peer in connection.connected_peers() {
+                    // a peer going away is the far end's problem to report
+                    let _ = slot.write(peer, &ping);
+                }
+
+                let wait = *task_interval.lock().unwrap_or_else(|e| e.into_inner());
+                guard = wake
+                    .wait_timeout(guard, wait)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
+            }
+        });
+
+        PingTask {
+            interval,
+            stop,
+            handle,
+        }
+    }
+
+    /// The connected peers to probe with `count` pings.
+    fn probe_peers(
+        self: &Arc<Self>,
+        connection: &ConnectionSlot,
+        count: u32,
+    ) -> Result<Vec<PeerId>> {
+        if count == 0 {
+            return Err(Error::InvalidOption {
+                field: "probe count",
+                reason: "must not be zero".to_string(),
+            });
+        }
+        self.register()?;
+        let peers = connection.connected_peers();
+        if peers.is_empty() {
+            return Err(Error::Send);
+        }
+        Ok(peers)
+    }
+
+    /// Send one probe ping to `peers`, returning its sequence number.
+    fn send_probe(&self, peers: &[PeerId]) -> u32 {
+        let seq = self.probes.ping(Instant::now());
+        let ping = Beat {
+            pong: false,
+            seq,
+            sent: 0,
+        }
+        .encode();
+        for peer in peers {
+            // a peer going away shows up as unanswered pings
+            let _ = self.write(*peer, &ping);
+        }
+        seq
+    }
+
+    /// Ping every connected peer `count` times and wait up to `timeout`
+    /// after the last ping for the answers.
+    pub(crate) fn rtt_probe(
+        self: &Arc<Self>,
+        connection: &ConnectionSlot,
+        count: u32,
+        timeout: Duration,
+    ) -> Result<RttProbe> {
+        let peers = self.probe_peers(connection, count)?;
+        let mut seqs = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            if i > 0 {
+                thread::sleep(probe::PROBE_INTERVAL);
+            }
+            seqs.push(self.send_probe(&peers));
+        }
+
+        self.probes
+            .wait(&seqs, peers.len(), Instant::now().checked_add(timeout));
+        Ok(RttProbe {
+            replies: self.probes.finish(&seqs),
+            peers,
+            sent: count,
+        })
+    }
+
+    /// Ping every connected peer `count` times and wait up to `timeout`
+    /// after the last ping for the answers.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn rtt_probe_async(
+        self: &Arc<Self>,
+        connection: &ConnectionSlot,
+        count: u32,
+        timeout: Duration,
+    ) -> Result<RttProbe> {
+        let peers = self.probe_peers(connection, count)?;
+        let mut seqs = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            if i > 0 {
+                ::tokio::time::sleep(probe::PROBE_INTERVAL).await;
+            }
+            seqs.push(self.send_probe(&peers));
+        }
+
+        self.probes
+            .wait_async(&seqs, peers.len(), Instant::now().checked_add(timeout))
+            .await;
+        Ok(RttProbe {
+            replies: self.probes.finish(&seqs),
+            peers,
+            sent: count,
+        })
+    }
+
+    /// Send `message` to every connected peer, returning how many it went to.
+    #[cfg(feature = "serde")]
+    pub(crate) fn send_message<T: Serialize>(
+        self: &Arc<Self>,
+        connection: &ConnectionSlot,
+        message: &T,
+    ) -> Result<usize> {
+        self.register()?;
+        let packet = message::encode(message::MAGIC, message)?;
+        let peers = connection.connected_peers();
+        for peer in &peers {
+            self.write(*peer, &packet)?;
+        }
+        Ok(peers.len())
+    }
+
+    /// Send `message` to `peer` only.
+    #[cfg(feature = "serde")]
+    pub(crate) fn send_message_to<T: Serialize>(
+        self: &Arc<Self>,
+        peer: PeerId,
+        message: &T,
+    ) -> Result<()> {
+        self.register()?;
+        self.write(peer, &message::encode(message::MAGIC, message)?)?;
+        Ok(())
+    }
+
+    /// The next message, waiting up to `timeout` for one.
+    #[cfg(feature = "serde")]
+    pub(crate) fn recv_message<T: DeserializeOwned>(
+        self: &Arc<Self>,
+        timeout: Duration,
+    ) -> Result<Option<Message<T>>> {
+        self.register()?;
+        self.messages
+            .pop(timeout)
+            .map(|(peer, body)| {
+                Ok(Message {
+                    peer,
+                    body: message::decode(&body)?,
+                })
+            })
+            .transpose()
+    }
+
+    /// The next message, waiting as long as it takes.
+    #[cfg(all(feature = "serde", feature = "tokio"))]
+    pub(crate) async fn recv_message_async<T: DeserializeOwned>(
+        self: &Arc<Self>,
+    ) -> Result<Message<T>> {
+        self.register()?;
+        let (peer, body) = self.messages.pop_async().await;
+        Ok(Message {
+            peer,
+            body: message::decode(&body)?,
+        })
+    }
+
+    /// Send `command` to every connected peer and wait up to `timeout` for
+    /// the first ack.
+    #[cfg(feature = "serde")]
+    pub(crate) fn send_command(
+        self: &Arc<Self>,
+        connection: &ConnectionSlot,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<()> {
+        let id = self.send_request(connection, command)?;
+        self.control.wait_ack(id, timeout)
+    }
+
+    /// Send `command` to every connected peer and wait up to `timeout` for
+    /// the first ack.
+    #[cfg(all(feature = "serde", feature = "tokio"))]
+    pub(crate) async fn send_command_async(
+        self: &Arc<Self>,
+        connection: &ConnectionSlot,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<()> {
+        let id = self.send_request(connection, command)?;
+        let result = ::tokio::time::timeout(timeout, self.control.wait_ack_async(id)).await;
+        self.control.forget(id);
+        result.map_err(|_| Error::CommandTimeout)?
+    }
+
+    #[cfg(feature = "serde")]
+    fn send_request(
+        self: &Arc<Self>,
+        connection: &ConnectionSlot,
+        command: Command,
+    ) -> Result<u32> {
+        self.register()?;
+        let peers = connection.connected_peers();
+        if peers.is_empty() {
+            return Err(Error::Send);
+        }
+
+        let (id, packet) = self.control.request(command)?;
+        for peer in peers {
+            if let Err(err) = self.write(peer, &packet) {
+                self.control.forget(id);
+                return Err(err);
+            }
+        }
+        Ok(id)
+    }
+
+    /// The next command from the far end, waiting up to `timeout` for one.
+    #[cfg(feature = "serde")]
+    pub(crate) fn recv_command(
+        self: &Arc<Self>,
+        timeout: Duration,
+    ) -> Result<Option<CommandRequest>> {
+        self.register()?;
+        Ok(self.control.next_request(timeout))
+    }
+
+    /// The next command from the far end, waiting as long as it takes.
+    #[cfg(all(feature = "serde", feature = "tokio"))]
+    pub(crate) async fn recv_command_async(self: &Arc<Self>) -> Result<CommandRequest> {
+        self.register()?;
+        Ok(self.control.next_request_async().await)
+    }
+
+    /// Answer `request`, with the reason on failure.
+    #[cfg(feature = "serde")]
+    pub(crate) fn ack_command(
+        self: &Arc<Self>,
+        request: &CommandRequest,
+        result: std::result::Result<(), String>,
+    ) -> Result<()> {
+        self.register()?;
+        self.write(request.peer, &control::ControlSlot::ack(request, result)?)?;
+        Ok(())
+    }
+
+    /// Stop the ping thread, before the context is destroyed.
+    pub(crate) fn shutdown(&self) {
+        let task = self.pings.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(task) = task {
+            let (stopped, wake) = &*task.stop;
+            *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+            wake.notify_all();
+            let _ = task.handle.join();
+        }
+    }
+
+    fn dispatch(&self, peer: PeerId, payload: &[u8]) {
+        #[cfg(feature = "serde")]
+        if let Some(body) = message::body(message::MAGIC, payload) {
+            self.messages.push(peer, body.to_vec());
+            return;
+        }
+        #[cfg(feature = "serde")]
+        if let Some(body) = message::body(control::MAGIC, payload) {
+            self.control.dispatch(peer, body);
+            return;
+        }
+
+        let Some(beat) = Beat::decode(payload) else {
+            return;
+        };
+        if beat.pong && self.probes.on_pong(peer, beat.seq, Instant::now()) {
+            return;
+        }
+        let state = self
+            .heartbeat
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        // the far end pings before this end has a heartbeat of its own
+        let state = state.unwrap_or_else(|| Arc::new(HeartbeatState::new()));
+
+        if let Some(pong) = state.on_beat(peer, beat, Instant::now()) {
+            let _ = self.write(peer, &pong.encode());
+        }
+    }
+}
+
+/// OOB callback for librist, `arg` is a `*const OobSlot`.
+unsafe extern "C" fn oob_callback(
+    arg: *mut c_void,
+    oob_block: *const rist_sys::rist_oob_block,
+) -> c_int {
+    if arg.is_null() || oob_block.is_null() {
+        return 0;
+    }
+
+    let slot = &*(arg as *const OobSlot);
+    let block = &*oob_block;
+    if block.peer.is_null() || block.payload.is_null() {
+        return 0;
+    }
+    let payload = std::slice::from_raw_parts(block.payload as *const u8, block.payload_len);
+    crate::callback::guard("oob", (), || {
+        slot.dispatch(PeerId::from_raw(block.peer), payload)
+    });
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beat_round_trip() {
+        let beat = Beat {
+            pong: true,
+            seq: 7,
+            sent: 123_456_789,
+        };
+        assert_eq!(Beat::decode(&beat.encode()), Some(beat));
+
+        assert_eq!(Beat::decode(b"RSHB"), None);
+        let mut other = beat.encode();
+        other[0] = b'X';
+        assert_eq!(Beat::decode(&other), None);
+        other = beat.encode();
+        other[4] = 9;
+        assert_eq!(Beat::decode(&other), None);
+    }
+
+    #[test]
+    fn test_pings_are_answered_and_timed() {
+        let pinger = HeartbeatState::new();
+        let answerer = HeartbeatState::new();
+        let peer = PeerId::from_raw(0x10 as *mut _);
+        let start = Instant::now();
+
+        let ping = pinger.ping(start);
+        let pong = answerer
+            .on_beat(peer, ping, start + Duration::from_millis(5))
+            .unwrap();
+        assert!(pong.pong);
+        assert_eq!(pong.seq, ping.seq);
+        assert!(answerer.peers.lock().unwrap()[&peer].rtt.is_none());
+
+        let later = start + Duration::from_millis(40);
+        assert_eq!(pinger.on_beat(peer, pong, later), None);
+        let rtt = pinger.latest_rtt.lock().unwrap().unwrap();
+        let expected = later.duration_since(pinger.epoch) - Duration::from_nanos(ping.sent);
+        assert_eq!(rtt, expected);
+        assert_eq!(pinger.peers.lock().unwrap()[&peer].last_seen, later);
+        assert_eq!(pinger.ping(later).seq, ping.seq + 1);
+    }
+
+    #[test]
+    fn test_alive_only_once_heard() {
+        let state = Arc::new(HeartbeatState::new());
+        let heartbeat = Heartbeat {
+            state: state.clone(),
+        };
+        assert!(!heartbeat.is_alive(Duration::from_secs(60)));
+        assert_eq!(heartbeat.rtt(), None);
+
+        let peer = PeerId::from_raw(0x20 as *mut _);
+        let ping = Beat {
+            pong: false,
+            seq: 0,
+            sent: 0,
+        };
+        state.on_beat(peer, ping, Instant::now());
+        assert!(heartbeat.is_alive(Duration::from_secs(60)));
+        assert_eq!(heartbeat.peers().len(), 1);
+        assert_eq!(heartbeat.peers()[0].rtt, None);
+    }
+}
@@ -0,0 +1,250 @@
+//! Remote control commands over OOB, with acknowledgements.
+
+use super::message::{self, MessageQueue};
+use crate::{Error, PeerId, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Prefix of every control packet, ahead of the body length.
+pub(crate) const MAGIC: &[u8; 4] = b"RSCT";
+
+/// A command for the far end, typically a contribution encoder, from
+/// `send_command()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Command {
+    /// Start sending.
+    Start,
+    /// Stop sending, keeping the session up.
+    Stop,
+    /// Change the encoding bitrate.
+    SetBitrate {
+        /// The new bitrate in bits per second.
+        bitrate: u64,
+    },
+}
+
+/// A command received from the far end, from `recv_command()`.
+///
+/// Answer it with `ack_command()`, or the sender gives up after its
+/// timeout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandRequest {
+    /// The peer the command arrived on, which the ack goes back to.
+    pub peer: PeerId,
+    /// The command.
+    pub command: Command,
+    id: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Packet {
+    Request { id: u32, command: Command },
+    Ack { id: u32, error: Option<String> },
+}
+
+/// Commands and acks of a context.
+#[derive(Default)]
+pub(crate) struct ControlSlot {
+    next_id: AtomicU32,
+    requests: MessageQueue<CommandRequest>,
+    // commands waiting for an ack, with the ack once it arrives
+    acks: Mutex<HashMap<u32, Option<Option<String>>>>,
+    acked: Condvar,
+    #[cfg(feature = "tokio")]
+    notify: ::tokio::sync::Notify,
+}
+
+impl ControlSlot {
+    /// Encode `command` as a new request, returning its ID and packet.
+    ///
+    /// The request waits for an ack until [`forget`](Self::forget) is called.
+    pub(crate) fn request(&self, command: Command) -> Result<(u32, Vec<u8>)> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let packet = message::encode(MAGIC, &Packet::Request { id, command })?;
+        self.acks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, None);
+        Ok((id, packet))
+    }
+
+    /// Encode the ack for `request`.
+    pub(crate) fn ack(
+        request: &CommandRequest,
+        result: std::result::Result<(), String>,
+    ) -> Result<Vec<u8>> {
+        let ack = Packet::Ack {
+            id: request.id,
+            error: result.err(),
+        };
+        message::encode(MAGIC, &ack)
+    }
+
+    /// Stop waiting for an ack to request `id`.
+    pub(crate) fn forget(&self, id: u32) {
+        self.acks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&id);
+    }
+
+    /// Take the ack to request `id` if it has arrived.
+    fn take_ack(acks: &mut HashMap<u32, Option<Option<String>>>, id: u32) -> Option<Result<()>> {
+        let error = acks.get_mut(&id)?.take()?;
+        acks.remove(&id);
+        Some(match error {
+            None => Ok(()),
+            Some(reason) => Err(Error::CommandRejected(reason)),
+        })
+    }
+
+    /// Wait up to `timeout` for the ack to request `id`.
+    pub(crate) fn wait_ack(&self, id: u32, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut acks = self.acks.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(result) = Self::take_ack(&mut acks, id) {
+                return result;
+            }
+            let wait = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if wait.is_zero() {
+                acks.remove(&id);
+                return Err(Error::CommandTimeout);
+            }
+            acks = self
+                .acked
+                .wait_timeout(acks, wait)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Wait for the ack to request `id`, as long as it takes.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn wait_ack_async(&self, id: u32) -> Result<()> {
+        loop {
+            // created before checking, so an ack in between still wakes it
+            let acked = self.notify.notified();
+            if let Some(result) =
+                Self::take_ack(&mut self.acks.lock().unwrap_or_else(|e| e.into_inner()), id)
+            {
+                return result;
+            }
+            acked.await;
+        }
+    }
+
+    /// The next command from the far end, waiting up to `timeout` for one.
+    pub(crate) fn next_request(&self, timeout: Duration) -> Option<CommandRequest> {
+        self.requests.pop(timeout).map(|(_, request)| request)
+    }
+
+    /// The next command from the far end, waiting as long as it takes.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn next_request_async(&self) -> CommandRequest {
+        self.requests.pop_async().await.1
+    }
+
+    /// Handle a control packet from `peer`.
+    pub(crate) fn dispatch(&self, peer: PeerId, body: &[u8]) {
+        // a far end speaking a different version is ignored rather than
+        // failing whoever reads next
+        let Ok(packet) = message::decode::<Packet>(body) else {
+            return;
+        };
+        match packet {
+            Packet::Request { id, command } => {
+                self.requests
+                    .push(peer, CommandRequest { peer, command, id });
+            }
+            Packet::Ack { id, error } => {
+                let mut acks = self.acks.lock().unwrap_or_else(|e| e.into_inner());
+                // acks nobody waits for any more are dropped
+                if let Some(ack @ None) = acks.get_mut(&id) {
+                    *ack = Some(error);
+                    self.acked.notify_all();
+                    #[cfg(feature = "tokio")]
+                    self.notify.notify_waiters();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> PeerId {
+        PeerId::from_raw(0x10 as *mut _)
+    }
+
+    /// Deliver `packet` to `slot` as if it came from the far end.
+    fn deliver(slot: &ControlSlot, packet: &[u8]) {
+        slot.dispatch(peer(), message::body(MAGIC, packet).unwrap());
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        let operator = ControlSlot::default();
+        let encoder = ControlSlot::default();
+
+        let command = Command::SetBitrate { bitrate: 6_000_000 };
+        let (id, packet) = operator.request(command).unwrap();
+        deliver(&encoder, &packet);
+        let request = encoder.next_request(Duration::ZERO).unwrap();
+        assert_eq!(request.command, command);
+        assert_eq!(request.peer, peer());
+
+        deliver(&operator, &ControlSlot::ack(&request, Ok(())).unwrap());
+        operator.wait_ack(id, Duration::ZERO).unwrap();
+        assert!(operator.acks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rejected_and_unanswered_commands() {
+        let operator = ControlSlot::default();
+        let encoder = ControlSlot::default();
+
+        let (id, packet) = operator.request(Command::Start).unwrap();
+        deliver(&encoder, &packet);
+        let request = encoder.next_request(Duration::ZERO).unwrap();
+        let ack = ControlSlot::ack(&request, Err("no input".to_string())).unwrap();
+        deliver(&operator, &ack);
+        assert!(matches!(
+            operator.wait_ack(id, Duration::ZERO),
+            Err(Error::CommandRejected(reason)) if reason == "no input"
+        ));
+
+        let (id, _) = operator.request(Command::Stop).unwrap();
+        assert!(matches!(
+            operator.wait_ack(id, Duration::from_millis(10)),
+            Err(Error::CommandTimeout)
+        ));
+        // a late ack is dropped
+        deliver(&operator, &ack);
+        assert!(operator.acks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_command_encoding() {
+        let packet = Packet::Request {
+            id: 3,
+            command: Command::SetBitrate { bitrate: 1000 },
+        };
+        let json = serde_json::to_string(&packet).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"request","id":3,"command":{"command":"set_bitrate","bitrate":1000}}"#
+        );
+    }
+}
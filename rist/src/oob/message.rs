@@ -0,0 +1,193 @@
+//! Typed messages over OOB, encoded as length-prefixed JSON.
+//!
+//! Control packets share the encoding under their own prefix, so they never
+//! reach `recv_message()`.
+
+use crate::{Error, PeerId, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Prefix of every message packet, ahead of the body length.
+pub(crate) const MAGIC: &[u8; 4] = b"RSMS";
+
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Largest encoded body, so a message fits one OOB packet.
+const MAX_BODY_LEN: usize = rist_sys::RIST_MAX_PACKET_SIZE as usize - HEADER_LEN;
+
+/// Messages kept until read; the oldest are dropped beyond this.
+const QUEUE_LEN: usize = 64;
+
+/// A message received over OOB, from `recv_message()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message<T> {
+    /// The peer the message arrived on.
+    pub peer: PeerId,
+    /// The decoded message.
+    pub body: T,
+}
+
+/// Encode `message` as one OOB packet starting with `magic`.
+pub(crate) fn encode<T: Serialize>(magic: &[u8; 4], message: &T) -> Result<Vec<u8>> {
+    let body = serde_json::to_vec(message).map_err(|e| Error::InvalidMessage(e.to_string()))?;
+    if body.len() > MAX_BODY_LEN {
+        return Err(Error::InvalidMessage(format!(
+            "{} bytes encoded, at most {MAX_BODY_LEN} fit an OOB packet",
+            body.len()
+        )));
+    }
+
+    let mut packet = Vec::with_capacity(HEADER_LEN + body.len());
+    packet.extend_from_slice(magic);
+    packet.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    packet.extend_from_slice(&body);
+    Ok(packet)
+}
+
+/// The body of a packet starting with `magic`, `None` for any other OOB
+/// packet.
+pub(crate) fn body<'a>(magic: &[u8; 4], packet: &'a [u8]) -> Option<&'a [u8]> {
+    let rest = packet.strip_prefix(magic)?;
+    if rest.len() < 4 {
+        return None;
+    }
+    let (len, body) = rest.split_at(4);
+    let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+    (len == body.len()).then_some(body)
+}
+
+pub(crate) fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T> {
+    serde_json::from_slice(body).map_err(|e| Error::InvalidMessage(e.to_string()))
+}
+
+/// Received packets waiting to be read, with the peer each arrived on.
+pub(crate) struct MessageQueue<T> {
+    queue: Mutex<VecDeque<(PeerId, T)>>,
+    ready: Condvar,
+    #[cfg(feature = "tokio")]
+    notify: ::tokio::sync::Notify,
+}
+
+impl<T> Default for MessageQueue<T> {
+    fn default() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            ready: Condvar::new(),
+            #[cfg(feature = "tokio")]
+            notify: ::tokio::sync::Notify::new(),
+        }
+    }
+}
+
+impl<T> MessageQueue<T> {
+    pub(crate) fn push(&self, peer: PeerId, item: T) {
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if queue.len() == QUEUE_LEN {
+            queue.pop_front();
+        }
+        queue.push_back((peer, item));
+        self.ready.notify_one();
+        #[cfg(feature = "tokio")]
+        self.notify.notify_one();
+    }
+
+    /// Take the next item, waiting up to `timeout` for one.
+    pub(crate) fn pop(&self, timeout: Duration) -> Option<(PeerId, T)> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(next) = queue.pop_front() {
+                return Some(next);
+            }
+            let wait = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if wait.is_zero() {
+                return None;
+            }
+            queue = self
+                .ready
+                .wait_timeout(queue, wait)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Take the next item, waiting as long as it takes.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn pop_async(&self) -> (PeerId, T) {
+        loop {
+            let next = self
+                .queue
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .pop_front();
+            if let Some(next) = next {
+                return next;
+            }
+            // a push in between leaves a permit, so this can't miss it
+            self.notify.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct ServiceInfo {
+        name: String,
+        bitrate: u64,
+    }
+
+    fn peer() -> PeerId {
+        PeerId::from_raw(0x10 as *mut _)
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let info = ServiceInfo {
+            name: "cam 1".to_string(),
+            bitrate: 8_000_000,
+        };
+        let packet = encode(MAGIC, &info).unwrap();
+        assert!(packet.starts_with(b"RSMS"));
+        let decoded: ServiceInfo = decode(body(MAGIC, &packet).unwrap()).unwrap();
+        assert_eq!(decoded, info);
+        assert_eq!(body(b"RSCT", &packet), None);
+    }
+
+    #[test]
+    fn test_rejects_other_packets() {
+        let packet = encode(MAGIC, &42u32).unwrap();
+        assert_eq!(body(MAGIC, &packet[..packet.len() - 1]), None);
+        assert_eq!(body(MAGIC, b"RSHB\0\0\0\0"), None);
+        assert!(matches!(
+            encode(MAGIC, &"x".repeat(MAX_BODY_LEN)),
+            Err(Error::InvalidMessage(_))
+        ));
+        assert!(matches!(
+            decode::<ServiceInfo>(b"not json"),
+            Err(Error::InvalidMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_drops_oldest_when_full() {
+        let queue = MessageQueue::default();
+        for n in 0..QUEUE_LEN as u32 + 2 {
+            queue.push(peer(), n);
+        }
+        assert_eq!(queue.pop(Duration::ZERO), Some((peer(), 2)));
+        assert_eq!(
+            std::iter::from_fn(|| queue.pop(Duration::ZERO)).count(),
+            QUEUE_LEN - 1
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! One-off RTT measurements over OOB, answered like heartbeat pings.
+
+use crate::PeerId;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Gap between the pings of one probe.
+pub(crate) const PROBE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Probe pings take the upper half of the sequence numbers, so their pongs
+/// aren't mistaken for the heartbeat's.
+const PROBE_SEQ: u32 = 1 << 31;
+
+/// Outcome of `rtt_probe()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RttProbe {
+    /// The peers that were pinged, those connected when the probe started.
+    pub peers: Vec<PeerId>,
+    /// Pings sent to each peer.
+    pub sent: u32,
+    /// Round-trip time of every answered ping, with the peer that answered,
+    /// grouped by ping.
+    pub replies: Vec<(PeerId, Duration)>,
+}
+
+impl RttProbe {
+    /// Shortest round trip, `None` if nothing was answered.
+    pub fn min(&self) -> Option<Duration> {
+        self.replies.iter().map(|(_, rtt)| *rtt).min()
+    }
+
+    /// Longest round trip, `None` if nothing was answered.
+    pub fn max(&self) -> Option<Duration> {
+        self.replies.iter().map(|(_, rtt)| *rtt).max()
+    }
+
+    /// Average round trip, `None` if nothing was answered.
+    pub fn mean(&self) -> Option<Duration> {
+        let total: Duration = self.replies.iter().map(|(_, rtt)| *rtt).sum();
+        Some(total / u32::try_from(self.replies.len()).ok().filter(|n| *n > 0)?)
+    }
+
+    /// Share of pings that weren't answered in time, from 0.0 to 1.0.
+    pub fn loss_ratio(&self) -> f64 {
+        let expected = self.sent as usize * self.peers.len();
+        if expected == 0 {
+            return 0.0;
+        }
+        (1.0 - self.replies.len() as f64 / expected as f64).max(0.0)
+    }
+}
+
+struct Ping {
+    sent: Instant,
+    replies: Vec<(PeerId, Duration)>,
+}
+
+/// Probe pings waiting for their pongs.
+#[derive(Default)]
+pub(crate) struct ProbeSlot {
+    next_seq: AtomicU32,
+    pings: Mutex<HashMap<u32, Ping>>,
+    answered: Condvar,
+    #[cfg(feature = "tokio")]
+    notify: ::tokio::sync::Notify,
+}
+
+impl ProbeSlot {
+    /// Start timing a new ping sent at `now`, returning its sequence number.
+    pub(crate) fn ping(&self, now: Instant) -> u32 {
+        let seq = PROBE_SEQ | self.next_seq.fetch_add(1, Ordering::Relaxed);
+        self.pings.lock().unwrap_or_else(|e| e.into_inner()).insert(
+            seq,
+            Ping {
+                sent: now,
+                replies: Vec::new(),
+            },
+        );
+        seq
+    }
+
+    /// Note the pong to `seq` from `peer`, returning `false` if it answers
+    /// a heartbeat ping rather than a probe.
+    pub(crate) fn on_pong(&self, peer: PeerId, seq: u32, now: Instant) -> bool {
+        if seq & PROBE_SEQ == 0 {
+            return false;
+        }
+        let mut pings = self.pings.lock().unwrap_or_else(|e| e.into_inner());
+        // pongs arriving after their probe gave up are dropped
+        if let Some(ping) = pings.get_mut(&seq) {
+            ping.replies
+                .push((peer, now.saturating_duration_since(ping.sent)));
+            self.answered.notify_all();
+            #[cfg(feature = "tokio")]
+            self.notify.notify_waiters();
+        }
+        true
+    }
+
+    fn all_answered(pings: &HashMap<u32, Ping>, seqs: &[u32], peers: usize) -> bool {
+        seqs.iter().all(|seq| {
+            pings
+                .get(seq)
+                .is_some_and(|ping| ping.replies.len() >= peers)
+        })
+    }
+
+    /// Wait until `peers` peers answered every ping in `seqs`, or until
+    /// `deadline`.
+    pub(crate) fn wait(&self, seqs: &[u32], peers: usize, deadline: Option<Instant>) {
+        let mut pings = self.pings.lock().unwrap_or_else(|e| e.into_inner());
+        while !Self::all_answered(&pings, seqs, peers) {
+            let wait = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if wait.is_zero() {
+                return;
+            }
+            pings = self
+                .answered
+                .wait_timeout(pings, wait)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Wait until `peers` peers answered every ping in `seqs`, or until
+    /// `deadline`.
+    #[cfg(feature = "tokio")]
+    pub(crate) async fn wait_async(&self, seqs: &[u32], peers: usize, deadline: Option<Instant>) {
+        let answered = async {
+            loop {
+                // created before checking, so a pong in between still wakes it
+                let pong = self.notify.notified();
+                let done = {
+                    let pings = self.pings.lock().unwrap_or_else(|e| e.into_inner());
+                    Self::all_answered(&pings, seqs, peers)
+                };
+                if done {
+                    return;
+                }
+                pong.await;
+            }
+        };
+        match deadline {
+            Some(deadline) => {
+                let deadline = ::tokio::time::Instant::from_std(deadline);
+                let _ = ::tokio::time::timeout_at(deadline, answered).await;
+            }
+            None => answered.await,
+        }
+    }
+
+    /// Stop timing `seqs`, returning their replies.
+    pub(crate) fn finish(&self, seqs: &[u32]) -> Vec<(PeerId, Duration)> {
+        let mut pings = self.pings.lock().unwrap_or_else(|e| e.into_inner());
+        seqs.iter()
+            .filter_map(|seq| pings.remove(seq))
+            .flat_map(|ping| ping.replies)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(n: usize) -> PeerId {
+        PeerId::from_raw(n as *mut rist_sys::rist_peer)
+    }
+
+    #[test]
+    fn test_probe_pongs_are_timed() {
+        let slot = ProbeSlot::default();
+        let start = Instant::now();
+        let first = slot.ping(start);
+        let second = slot.ping(start + Duration::from_millis(20));
+        assert_ne!(first & PROBE_SEQ, 0);
+
+        // heartbeat pongs are left alone
+        assert!(!slot.on_pong(peer(1), 7, start));
+        assert!(slot.on_pong(peer(1), first, start + Duration::from_millis(30)));
+        slot.wait(&[first, second], 1, Some(start));
+
+        assert!(slot.on_pong(peer(1), second, start + Duration::from_millis(60)));
+        // returns straight away once everything is answered
+        slot.wait(&[first, second], 1, None);
+
+        let probe = RttProbe {
+            peers: vec![peer(1)],
+            sent: 2,
+            replies: slot.finish(&[first, second]),
+        };
+        assert_eq!(probe.min(), Some(Duration::from_millis(30)));
+        assert_eq!(probe.max(), Some(Duration::from_millis(40)));
+        assert_eq!(probe.mean(), Some(Duration::from_millis(35)));
+        assert_eq!(probe.loss_ratio(), 0.0);
+
+        // late pongs are dropped
+        assert!(slot.on_pong(peer(1), first, start + Duration::from_secs(1)));
+        assert!(slot.finish(&[first]).is_empty());
+    }
+
+    #[test]
+    fn test_unanswered_probe() {
+        let probe = RttProbe {
+            peers: vec![peer(1), peer(2)],
+            sent: 4,
+            replies: vec![(peer(1), Duration::from_millis(10))],
+        };
+        assert_eq!(probe.loss_ratio(), 0.875);
+
+        let probe = RttProbe {
+            replies: Vec::new(),
+            ..probe
+        };
+        assert_eq!(probe.mean(), None);
+        assert_eq!(probe.loss_ratio(), 1.0);
+    }
+}
@@ -1,7 +1,145 @@
 //! Configuration options for RIST senders and receivers.
 
+use crate::pacing::Pacer;
+use crate::peer::PeerConfigurer;
+use crate::socket::SocketOptions;
+use crate::ts::PAYLOAD_SIZE;
+use crate::{BufferBloatMode, CongestionControl, TimingMode};
+use std::os::raw::c_int;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Bounds for a derived reorder buffer, in packets. The lower one is
+/// librist's default.
+const MIN_REORDER_BUFFER: u32 = 25;
+const MAX_REORDER_BUFFER: u32 = 4096;
+
+/// Largest recovery bitrate librist accepts; it scales the value by 1000
+/// internally.
+const MAX_RECOVERY_BITRATE: u32 = u32::MAX / 1000;
+
+/// Check the peer settings shared by [`ReceiverOptions`] and
+/// [`SenderOptions`] before librist sees them.
+macro_rules! validate_peer_settings {
+    ($options:expr) => {{
+        let options = $options;
+        for (field, bitrate) in [
+            ("recovery_maxbitrate", options.recovery_maxbitrate),
+            ("recovery_maxbitrate_return", options.recovery_maxbitrate_return),
+        ] {
+            if matches!(bitrate, Some(bitrate) if bitrate > MAX_RECOVERY_BITRATE) {
+                return Err(invalid(
+                    field,
+                    format!("must be at most {MAX_RECOVERY_BITRATE} kbps"),
+                ));
+            }
+        }
+        if options.recovery_maxbitrate == Some(0) {
+            return Err(invalid(
+                "recovery_maxbitrate",
+                "must be greater than 0".to_string(),
+            ));
+        }
+
+        for (field, duration) in [
+            ("recovery_length_min", options.recovery_length_min),
+            ("recovery_length_max", options.recovery_length_max),
+            ("recovery_rtt_min", options.recovery_rtt_min),
+            ("recovery_rtt_max", options.recovery_rtt_max),
+        ] {
+            check_millis(field, duration, u32::MAX as u128)?;
+        }
+        for (field, duration) in [
+            ("session_timeout", options.session_timeout),
+            ("keepalive_interval", options.keepalive_interval),
+        ] {
+            check_millis(field, duration, c_int::MAX as u128)?;
+        }
+
+        check_order(
+            ("recovery_length_min", options.recovery_length_min),
+            ("recovery_length_max", options.recovery_length_max),
+        )?;
+        check_order(
+            ("recovery_rtt_min", options.recovery_rtt_min),
+            ("recovery_rtt_max", options.recovery_rtt_max),
+        )?;
+        check_order(
+            ("min_retries", options.min_retries),
+            ("max_retries", options.max_retries),
+        )?;
+        check_order(
+            ("buffer_bloat_limit", options.buffer_bloat_limit),
+            ("buffer_bloat_hard_limit", options.buffer_bloat_hard_limit),
+        )?;
+        Ok(())
+    }};
+}
+
+/// Copy the peer settings shared by [`ReceiverOptions`] and
+/// [`SenderOptions`] into a librist peer config, leaving unset ones alone.
+macro_rules! apply_peer_settings {
+    ($options:expr, $config:expr) => {{
+        let options = $options;
+        let config: &mut rist_sys::rist_peer_config = $config;
+        if let Some(mode) = options.recovery_mode {
+            config.recovery_mode = mode.to_raw();
+        }
+        if let Some(bitrate) = options.recovery_maxbitrate {
+            config.recovery_maxbitrate = bitrate;
+        }
+        if let Some(bitrate) = options.recovery_maxbitrate_return {
+            config.recovery_maxbitrate_return = bitrate;
+        }
+        if let Some(duration) = options.recovery_length_min {
+            config.recovery_length_min = duration.as_millis() as u32;
+        }
+        if let Some(duration) = options.recovery_length_max {
+            config.recovery_length_max = duration.as_millis() as u32;
+        }
+        if let Some(buffer) = options.recovery_reorder_buffer {
+            config.recovery_reorder_buffer = buffer;
+        }
+        if let Some(duration) = options.recovery_rtt_min {
+            config.recovery_rtt_min = duration.as_millis() as u32;
+        }
+        if let Some(duration) = options.recovery_rtt_max {
+            config.recovery_rtt_max = duration.as_millis() as u32;
+        }
+        if let Some(retries) = options.min_retries {
+            config.min_retries = retries;
+        }
+        if let Some(retries) = options.max_retries {
+            config.max_retries = retries;
+            config.min_retries = config.min_retries.min(retries);
+        }
+        if let Some(weight) = options.weight {
+            config.weight = weight;
+        }
+        if let Some(mode) = options.buffer_bloat_mode {
+            config.buffer_bloat_mode = mode.to_raw();
+        }
+        if let Some(limit) = options.buffer_bloat_limit {
+            config.buffer_bloat_limit = limit;
+        }
+        if let Some(limit) = options.buffer_bloat_hard_limit {
+            config.buffer_bloat_hard_limit = limit;
+        }
+        if let Some(mode) = options.congestion_control {
+            config.congestion_control_mode = mode.to_raw();
+        }
+        if let Some(mode) = options.timing_mode {
+            config.timing_mode = mode.to_raw();
+        }
+        if let Some(duration) = options.session_timeout {
+            config.session_timeout = duration.as_millis() as c_int;
+        }
+        if let Some(duration) = options.keepalive_interval {
+            config.keepalive_interval = duration.as_millis() as c_int;
+        }
+    }};
+}
+
 /// Recovery mode for packet loss recovery.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum RecoveryMode {
@@ -22,13 +160,41 @@ impl RecoveryMode {
     }
 }
 
+/// What a queue the binding feeds does with a block that doesn't fit.
+///
+/// Applies to the queues an async receiver fills for its consumers, such
+/// as [port streams](crate::tokio::AsyncReceiver::port_stream) and
+/// [listener](crate::tokio::RistListener) contributors. librist's own
+/// output FIFO always drops on overflow; watch
+/// [`queue_len`](crate::Receiver::queue_len) to stay clear of it. Blocks
+/// dropped either way are counted in
+/// [`ReceiverStats::dropped`](crate::ReceiverStats::dropped).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Drop the oldest queued block to make room, keeping delivery live.
+    DropOldest,
+    /// Drop the new block (default).
+    #[default]
+    DropNewest,
+    /// Stop reading from librist until there is room, so the backlog builds
+    /// up in librist's FIFO instead. One slow consumer holds up the others.
+    Block,
+}
+
 /// Options for configuring a RIST receiver.
+///
+/// Unset options keep the value from the peer's URL. The peer settings are
+/// the commonly tuned part of [`PeerConfig`](crate::PeerConfig); to set any
+/// other, such as the multicast interface, add the peer with
+/// `add_peer_config()` instead. Both are checked the same way.
 #[derive(Debug, Clone, Default)]
 pub struct ReceiverOptions {
     /// Recovery mode for packet loss.
     pub recovery_mode: Option<RecoveryMode>,
-    /// Maximum bitrate for recovery (bps).
+    /// Maximum bitrate for recovery in kbps, as librist takes it.
     pub recovery_maxbitrate: Option<u32>,
+    /// Maximum bitrate for the return channel in kbps.
+    pub recovery_maxbitrate_return: Option<u32>,
     /// Minimum recovery buffer length.
     pub recovery_length_min: Option<Duration>,
     /// Maximum recovery buffer length.
@@ -39,8 +205,42 @@ pub struct ReceiverOptions {
     pub recovery_rtt_min: Option<Duration>,
     /// Maximum RTT for recovery.
     pub recovery_rtt_max: Option<Duration>,
+    /// Minimum retransmission requests per lost packet.
+    pub min_retries: Option<u32>,
+    /// Maximum retransmission requests per lost packet.
+    pub max_retries: Option<u32>,
+    /// Load balancing weight among peers, 0 to duplicate to all.
+    pub weight: Option<u32>,
+    /// Buffer bloat protection mode.
+    pub buffer_bloat_mode: Option<BufferBloatMode>,
+    /// Buffer bloat soft limit.
+    pub buffer_bloat_limit: Option<u32>,
+    /// Buffer bloat hard limit.
+    pub buffer_bloat_hard_limit: Option<u32>,
+    /// Congestion control mode.
+    pub congestion_control: Option<CongestionControl>,
+    /// Timing mode for output timestamps.
+    pub timing_mode: Option<TimingMode>,
+    /// Time without data after which the peer is considered gone.
+    pub session_timeout: Option<Duration>,
+    /// Interval between keepalives.
+    pub keepalive_interval: Option<Duration>,
     /// Output FIFO size (packets). 0 to disable.
     pub fifo_size: Option<u32>,
+    /// Only deliver data from these flow IDs. `None` accepts every flow.
+    pub accepted_flow_ids: Option<Vec<u32>>,
+    /// DSCP code point to mark outgoing packets with.
+    pub dscp: Option<u8>,
+    /// Unicast TTL / hop limit of outgoing packets.
+    pub ttl: Option<u8>,
+    /// Socket receive buffer size in bytes.
+    pub recv_buffer_size: Option<usize>,
+    /// Socket send buffer size in bytes.
+    pub send_buffer_size: Option<usize>,
+    /// Check that an IPv6 listen socket is IPv6-only, or dual-stack.
+    pub check_v6_only: Option<bool>,
+    /// What the binding's own queues do when full.
+    pub overflow_policy: OverflowPolicy,
 }
 
 impl ReceiverOptions {
@@ -55,7 +255,7 @@ impl ReceiverOptions {
         self
     }
 
-    /// Set maximum recovery bitrate in bps.
+    /// Set maximum recovery bitrate in kbps, e.g. `10_000` for 10 Mbps.
     pub fn recovery_maxbitrate(mut self, bitrate: u32) -> Self {
         self.recovery_maxbitrate = Some(bitrate);
         self
@@ -73,12 +273,188 @@ impl ReceiverOptions {
         self
     }
 
+    /// Set how many times a lost packet is requested before giving up.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Set maximum bitrate for the return channel in kbps.
+    pub fn recovery_maxbitrate_return(mut self, bitrate: u32) -> Self {
+        self.recovery_maxbitrate_return = Some(bitrate);
+        self
+    }
+
+    /// Set the reorder buffer size in packets.
+    pub fn recovery_reorder_buffer(mut self, packets: u32) -> Self {
+        self.recovery_reorder_buffer = Some(packets);
+        self
+    }
+
+    /// Set the lowest RTT recovery assumes.
+    pub fn recovery_rtt_min(mut self, rtt: Duration) -> Self {
+        self.recovery_rtt_min = Some(rtt);
+        self
+    }
+
+    /// Set the highest RTT recovery assumes.
+    pub fn recovery_rtt_max(mut self, rtt: Duration) -> Self {
+        self.recovery_rtt_max = Some(rtt);
+        self
+    }
+
+    /// Set how many times a lost packet is requested at least.
+    pub fn min_retries(mut self, retries: u32) -> Self {
+        self.min_retries = Some(retries);
+        self
+    }
+
+    /// Set the peer's load balancing weight.
+    ///
+    /// Peers with a weight share the data in proportion to it; peers with
+    /// weight 0, librist's default, each get all of it.
+    pub fn weight(mut self, weight: u32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+
+    /// Set buffer bloat protection and its soft and hard limits.
+    ///
+    /// Bounds how far retransmissions can build up the queue at a
+    /// bottleneck on the path. The limits are in packets.
+    pub fn buffer_bloat(mut self, mode: BufferBloatMode, limit: u32, hard_limit: u32) -> Self {
+        self.buffer_bloat_mode = Some(mode);
+        self.buffer_bloat_limit = Some(limit);
+        self.buffer_bloat_hard_limit = Some(hard_limit);
+        self
+    }
+
+    /// Set the congestion control mode.
+    pub fn congestion_control(mut self, mode: CongestionControl) -> Self {
+        self.congestion_control = Some(mode);
+        self
+    }
+
+    /// Set how output timestamps are derived.
+    pub fn timing_mode(mut self, mode: TimingMode) -> Self {
+        self.timing_mode = Some(mode);
+        self
+    }
+
+    /// Set how long the peer may stay silent before its session ends.
+    pub fn session_timeout(mut self, timeout: Duration) -> Self {
+        self.session_timeout = Some(timeout);
+        self
+    }
+
+    /// Set how often keepalives are sent.
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
     /// Set FIFO buffer size.
     pub fn fifo_size(mut self, size: u32) -> Self {
         self.fifo_size = Some(size);
         self
     }
 
+    /// Derive the recovery settings from a latency budget and the expected
+    /// stream bitrate in bits per second.
+    ///
+    /// The recovery buffer is set to `latency`, leaving room for several
+    /// retransmission rounds: the RTT bounds are capped at a quarter of the
+    /// budget, and the reorder buffer holds about a twentieth of it in
+    /// 1316-byte packets. Settings made afterwards override the derived ones.
+    ///
+    /// ```
+    /// use rist::ReceiverOptions;
+    /// use std::time::Duration;
+    ///
+    /// let options = ReceiverOptions::new().with_target_latency(Duration::from_millis(500), 10_000_000);
+    /// assert_eq!(options.recovery_length_max, Some(Duration::from_millis(500)));
+    /// assert_eq!(options.recovery_rtt_max, Some(Duration::from_millis(125)));
+    /// ```
+    pub fn with_target_latency(mut self, latency: Duration, expected_bitrate: u64) -> Self {
+        let (rtt_min, rtt_max, reorder) = latency_settings(latency, expected_bitrate);
+        self.recovery_length_min = Some(latency);
+        self.recovery_length_max = Some(latency);
+        self.recovery_rtt_min = Some(rtt_min);
+        self.recovery_rtt_max = Some(rtt_max);
+        self.recovery_reorder_buffer = Some(reorder);
+        self
+    }
+
+    /// Only deliver data from the given flow IDs.
+    ///
+    /// Packets from any other flow, e.g. a stray sender pointed at the same
+    /// port, are dropped before they reach the application. Like
+    /// [`fifo_size`](Self::fifo_size) this applies to the whole receiver.
+    pub fn accept_flow_ids(mut self, flow_ids: &[u32]) -> Self {
+        self.accepted_flow_ids = Some(flow_ids.to_vec());
+        self
+    }
+
+    /// Mark the peer's outgoing packets, i.e. its NACKs and keepalives, with
+    /// the DSCP code point `dscp` (0–63).
+    ///
+    /// See [`SenderOptions::dscp`].
+    pub fn dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
+    }
+
+    /// Set the TTL (IPv4) or hop limit (IPv6) of the peer's unicast packets,
+    /// 1–255.
+    ///
+    /// See [`SenderOptions::ttl`].
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Set the peer's socket receive buffer (`SO_RCVBUF`) in bytes.
+    ///
+    /// OS defaults are sized for far lower rates; at 200 Mbps and up a
+    /// scheduling hiccup overflows them and packets are dropped before
+    /// librist sees them. A few megabytes is typical. The OS may adjust the
+    /// size, see [`PeerInfo::socket_buffers`](crate::PeerInfo::socket_buffers)
+    /// for the value in effect. Like [`dscp`](Self::dscp) this is only
+    /// supported on Unix.
+    pub fn recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.recv_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Set the peer's socket send buffer (`SO_SNDBUF`) in bytes.
+    ///
+    /// See [`recv_buffer_size`](Self::recv_buffer_size).
+    pub fn send_buffer_size(mut self, bytes: usize) -> Self {
+        self.send_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Check that a peer listening on an IPv6 address is IPv6-only
+    /// (`true`), or dual-stack so it also accepts IPv4 (`false`).
+    ///
+    /// This doesn't change the socket: librist binds it before it can be
+    /// reached, and the flag can only be set before binding, so the system
+    /// default applies, e.g. `net.ipv6.bindv6only` on Linux. Adding the
+    /// peer fails if the socket doesn't match, or if the peer's address
+    /// isn't IPv6. See [`RistUrl::listen_v6`](crate::RistUrl::listen_v6)
+    /// for building the URL.
+    pub fn check_v6_only(mut self, v6_only: bool) -> Self {
+        self.check_v6_only = Some(v6_only);
+        self
+    }
+
+    /// Set what the binding's own queues do when full. Blocks dropped are
+    /// counted in [`ReceiverStats::dropped`](crate::ReceiverStats::dropped).
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
     pub(crate) fn apply_to_receiver_ctx(&self, ctx: *mut rist_sys::rist_ctx) -> crate::Result<()> {
         if let Some(size) = self.fifo_size {
             if size != 0 && !size.is_power_of_two() {
@@ -98,43 +474,96 @@ impl ReceiverOptions {
         Ok(())
     }
 
-    #[allow(dead_code)]
+    /// Check the options for values librist would reject or misread.
+    ///
+    /// Fails with [`Error::InvalidOption`](crate::Error::InvalidOption)
+    /// naming the offending field, e.g. when
+    /// [`recovery_length_min`](Self::recovery_length_min) is above
+    /// [`recovery_length_max`](Self::recovery_length_max). Adding or
+    /// updating a peer does this first.
+    pub fn validate(&self) -> crate::Result<()> {
+        validate_peer_settings!(self)
+    }
+
+    /// Capture these options for creating (and later re-creating) a peer.
+    pub(crate) fn configurer(&self) -> crate::Result<PeerConfigurer> {
+        self.validate()?;
+        let options = self.clone();
+        Ok(PeerConfigurer {
+            config: Arc::new(move |config| options.apply_to_peer_config(config)),
+            sockets: SocketOptions {
+                dscp: self.dscp,
+                recv_buffer: self.recv_buffer_size,
+                send_buffer: self.send_buffer_size,
+                ttl: self.ttl,
+                check_v6_only: self.check_v6_only,
+            },
+            recovery_maxbitrate: None,
+            recovery_disabled: false,
+        })
+    }
+
     pub(crate) fn apply_to_peer_config(&self, config: &mut rist_sys::rist_peer_config) {
-        if let Some(mode) = self.recovery_mode {
-            config.recovery_mode = mode.to_raw();
-        }
-        if let Some(bitrate) = self.recovery_maxbitrate {
-            config.recovery_maxbitrate = bitrate;
-        }
-        if let Some(duration) = self.recovery_length_min {
-            config.recovery_length_min = duration.as_millis() as u32;
-        }
-        if let Some(duration) = self.recovery_length_max {
-            config.recovery_length_max = duration.as_millis() as u32;
-        }
-        if let Some(buffer) = self.recovery_reorder_buffer {
-            config.recovery_reorder_buffer = buffer;
-        }
-        if let Some(duration) = self.recovery_rtt_min {
-            config.recovery_rtt_min = duration.as_millis() as u32;
-        }
-        if let Some(duration) = self.recovery_rtt_max {
-            config.recovery_rtt_max = duration.as_millis() as u32;
-        }
+        apply_peer_settings!(self, config);
     }
 }
 
 /// Options for configuring a RIST sender.
+///
+/// Unset options keep the value from the peer's URL. The peer settings are
+/// the commonly tuned part of [`PeerConfig`](crate::PeerConfig); to set any
+/// other, such as the multicast interface, add the peer with
+/// `add_peer_config()` instead. Both are checked the same way.
 #[derive(Debug, Clone, Default)]
 pub struct SenderOptions {
     /// Recovery mode for packet loss.
     pub recovery_mode: Option<RecoveryMode>,
-    /// Maximum bitrate for recovery (bps).
+    /// Maximum bitrate for recovery in kbps, as librist takes it.
     pub recovery_maxbitrate: Option<u32>,
+    /// Maximum bitrate for the return channel in kbps.
+    pub recovery_maxbitrate_return: Option<u32>,
     /// Minimum recovery buffer length.
     pub recovery_length_min: Option<Duration>,
     /// Maximum recovery buffer length.
     pub recovery_length_max: Option<Duration>,
+    /// Reorder buffer size (packets).
+    pub recovery_reorder_buffer: Option<u32>,
+    /// Minimum RTT for recovery.
+    pub recovery_rtt_min: Option<Duration>,
+    /// Maximum RTT for recovery.
+    pub recovery_rtt_max: Option<Duration>,
+    /// Minimum retransmission requests per lost packet.
+    pub min_retries: Option<u32>,
+    /// Maximum retransmission requests per lost packet.
+    pub max_retries: Option<u32>,
+    /// Load balancing weight among peers, 0 to duplicate to all.
+    pub weight: Option<u32>,
+    /// Buffer bloat protection mode.
+    pub buffer_bloat_mode: Option<BufferBloatMode>,
+    /// Buffer bloat soft limit.
+    pub buffer_bloat_limit: Option<u32>,
+    /// Buffer bloat hard limit.
+    pub buffer_bloat_hard_limit: Option<u32>,
+    /// Congestion control mode.
+    pub congestion_control: Option<CongestionControl>,
+    /// Timing mode for output timestamps.
+    pub timing_mode: Option<TimingMode>,
+    /// Time without data after which the peer is considered gone.
+    pub session_timeout: Option<Duration>,
+    /// Interval between keepalives.
+    pub keepalive_interval: Option<Duration>,
+    /// Pace sends to this many bits per second.
+    pub pace_bitrate: Option<u64>,
+    /// DSCP code point to mark outgoing packets with.
+    pub dscp: Option<u8>,
+    /// Unicast TTL / hop limit of outgoing packets.
+    pub ttl: Option<u8>,
+    /// Socket receive buffer size in bytes.
+    pub recv_buffer_size: Option<usize>,
+    /// Socket send buffer size in bytes.
+    pub send_buffer_size: Option<usize>,
+    /// Check that an IPv6 listen socket is IPv6-only, or dual-stack.
+    pub check_v6_only: Option<bool>,
 }
 
 impl SenderOptions {
@@ -149,7 +578,7 @@ impl SenderOptions {
         self
     }
 
-    /// Set maximum recovery bitrate in bps.
+    /// Set maximum recovery bitrate in kbps, e.g. `10_000` for 10 Mbps.
     pub fn recovery_maxbitrate(mut self, bitrate: u32) -> Self {
         self.recovery_maxbitrate = Some(bitrate);
         self
@@ -167,19 +596,382 @@ impl SenderOptions {
         self
     }
 
-    #[allow(dead_code)]
+    /// Set maximum bitrate for the return channel in kbps.
+    pub fn recovery_maxbitrate_return(mut self, bitrate: u32) -> Self {
+        self.recovery_maxbitrate_return = Some(bitrate);
+        self
+    }
+
+    /// Set the reorder buffer size in packets.
+    pub fn recovery_reorder_buffer(mut self, packets: u32) -> Self {
+        self.recovery_reorder_buffer = Some(packets);
+        self
+    }
+
+    /// Set the lowest RTT recovery assumes.
+    pub fn recovery_rtt_min(mut self, rtt: Duration) -> Self {
+        self.recovery_rtt_min = Some(rtt);
+        self
+    }
+
+    /// Set the highest RTT recovery assumes.
+    pub fn recovery_rtt_max(mut self, rtt: Duration) -> Self {
+        self.recovery_rtt_max = Some(rtt);
+        self
+    }
+
+    /// Set how many times a lost packet is requested at least.
+    pub fn min_retries(mut self, retries: u32) -> Self {
+        self.min_retries = Some(retries);
+        self
+    }
+
+    /// Set how many times a lost packet is requested before giving up.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = Some(retries);
+        self
+    }
+
+    /// Set the peer's load balancing weight.
+    ///
+    /// See [`ReceiverOptions::weight`].
+    pub fn weight(mut self, weight: u32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+
+    /// Set buffer bloat protection and its soft and hard limits.
+    ///
+    /// See [`ReceiverOptions::buffer_bloat`].
+    pub fn buffer_bloat(mut self, mode: BufferBloatMode, limit: u32, hard_limit: u32) -> Self {
+        self.buffer_bloat_mode = Some(mode);
+        self.buffer_bloat_limit = Some(limit);
+        self.buffer_bloat_hard_limit = Some(hard_limit);
+        self
+    }
+
+    /// Set the congestion control mode.
+    pub fn congestion_control(mut self, mode: CongestionControl) -> Self {
+        self.congestion_control = Some(mode);
+        self
+    }
+
+    /// Set how output timestamps are derived.
+    pub fn timing_mode(mut self, mode: TimingMode) -> Self {
+        self.timing_mode = Some(mode);
+        self
+    }
+
+    /// Set how long the peer may stay silent before its session ends.
+    pub fn session_timeout(mut self, timeout: Duration) -> Self {
+        self.session_timeout = Some(timeout);
+        self
+    }
+
+    /// Set how often keepalives are sent.
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Derive the recovery settings from a latency budget and the expected
+    /// stream bitrate in bits per second.
+    ///
+    /// See [`ReceiverOptions::with_target_latency`]; use the same budget on
+    /// both ends of the link.
+    pub fn with_target_latency(mut self, latency: Duration, expected_bitrate: u64) -> Self {
+        let (rtt_min, rtt_max, reorder) = latency_settings(latency, expected_bitrate);
+        self.recovery_length_min = Some(latency);
+        self.recovery_length_max = Some(latency);
+        self.recovery_rtt_min = Some(rtt_min);
+        self.recovery_rtt_max = Some(rtt_max);
+        self.recovery_reorder_buffer = Some(reorder);
+        self
+    }
+
+    /// Spread sends evenly at `bitrate` bits per second instead of bursting.
+    ///
+    /// Sends that arrive faster than this wait their turn, which keeps
+    /// bursty input, such as a file read in large chunks, under shaper
+    /// policers on the path. librist writes every packet to all peers, so
+    /// like [`ReceiverOptions::accept_flow_ids`] this applies to the whole
+    /// sender, and the last peer added with it set wins.
+    pub fn pace_bitrate(mut self, bitrate: u64) -> Self {
+        self.pace_bitrate = Some(bitrate);
+        self
+    }
+
+    /// Mark the peer's outgoing packets with the DSCP code point `dscp`
+    /// (0–63), e.g. 46 for Expedited Forwarding or 34 for AF41, so managed
+    /// networks can queue RIST traffic by class.
+    ///
+    /// librist doesn't expose this setting, so it is applied to the peer's
+    /// sockets right after they are created. This is only supported on
+    /// Unix; adding the peer fails elsewhere, or if the value is out of
+    /// range.
+    pub fn dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
+    }
+
+    /// Set the peer's socket send buffer (`SO_SNDBUF`) in bytes.
+    ///
+    /// See [`ReceiverOptions::recv_buffer_size`].
+    pub fn send_buffer_size(mut self, bytes: usize) -> Self {
+        self.send_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Set the peer's socket receive buffer (`SO_RCVBUF`) in bytes.
+    ///
+    /// See [`ReceiverOptions::recv_buffer_size`].
+    pub fn recv_buffer_size(mut self, bytes: usize) -> Self {
+        self.recv_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Set the TTL (IPv4) or hop limit (IPv6) of the peer's unicast packets,
+    /// 1–255.
+    ///
+    /// Keeps a stream from leaving a campus network, or meets a carrier's
+    /// hop requirements. Multicast packets use the separate multicast TTL.
+    /// Like [`dscp`](Self::dscp) this is only supported on Unix.
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Check that a peer listening on an IPv6 address is IPv6-only or
+    /// dual-stack.
+    ///
+    /// See [`ReceiverOptions::check_v6_only`].
+    pub fn check_v6_only(mut self, v6_only: bool) -> Self {
+        self.check_v6_only = Some(v6_only);
+        self
+    }
+
+    /// Check the options for values librist would reject or misread.
+    ///
+    /// Fails with [`Error::InvalidOption`](crate::Error::InvalidOption)
+    /// naming the offending field, e.g. when
+    /// [`recovery_length_min`](Self::recovery_length_min) is above
+    /// [`recovery_length_max`](Self::recovery_length_max). Adding or
+    /// updating a peer does this first.
+    pub fn validate(&self) -> crate::Result<()> {
+        validate_peer_settings!(self)
+    }
+
+    /// Capture these options for creating (and later re-creating) a peer.
+    pub(crate) fn configurer(&self) -> crate::Result<PeerConfigurer> {
+        self.validate()?;
+        let options = self.clone();
+        Ok(PeerConfigurer {
+            config: Arc::new(move |config| options.apply_to_peer_config(config)),
+            sockets: SocketOptions {
+                dscp: self.dscp,
+                recv_buffer: self.recv_buffer_size,
+                send_buffer: self.send_buffer_size,
+                ttl: self.ttl,
+                check_v6_only: self.check_v6_only,
+            },
+            recovery_maxbitrate: None,
+            recovery_disabled: false,
+        })
+    }
+
     pub(crate) fn apply_to_peer_config(&self, config: &mut rist_sys::rist_peer_config) {
-        if let Some(mode) = self.recovery_mode {
-            config.recovery_mode = mode.to_raw();
-        }
-        if let Some(bitrate) = self.recovery_maxbitrate {
-            config.recovery_maxbitrate = bitrate;
+        apply_peer_settings!(self, config);
+    }
+
+    /// Apply the sender-wide options to `pacer`.
+    pub(crate) fn apply_to_pacer(&self, pacer: &Pacer) {
+        if let Some(bitrate) = self.pace_bitrate {
+            pacer.set_bitrate(bitrate);
         }
-        if let Some(duration) = self.recovery_length_min {
-            config.recovery_length_min = duration.as_millis() as u32;
+    }
+}
+
+fn invalid(field: &'static str, reason: String) -> crate::Error {
+    crate::Error::InvalidOption { field, reason }
+}
+
+/// Convert a bandwidth in bits per second to librist's recovery bitrate,
+/// which is in kbps.
+pub(crate) fn recovery_bitrate(bandwidth: u64) -> crate::Result<u32> {
+    match bandwidth / 1000 {
+        0 => Err(invalid(
+            "max_bandwidth",
+            "must be at least 1000 bps".to_string(),
+        )),
+        kbps if kbps > MAX_RECOVERY_BITRATE as u64 => Err(invalid(
+            "max_bandwidth",
+            format!("must be at most {MAX_RECOVERY_BITRATE} kbps"),
+        )),
+        kbps => Ok(kbps as u32),
+    }
+}
+
+/// Reject durations whose milliseconds don't fit librist's field.
+fn check_millis(field: &'static str, duration: Option<Duration>, max: u128) -> crate::Result<()> {
+    match duration {
+        Some(duration) if duration.as_millis() > max => {
+            Err(invalid(field, format!("must be at most {max} ms")))
         }
-        if let Some(duration) = self.recovery_length_max {
-            config.recovery_length_max = duration.as_millis() as u32;
+        _ => Ok(()),
+    }
+}
+
+/// Reject a lower bound above its upper bound, when both are set.
+fn check_order<T: PartialOrd + std::fmt::Debug>(
+    (min_field, min): (&'static str, Option<T>),
+    (max_field, max): (&'static str, Option<T>),
+) -> crate::Result<()> {
+    match (min, max) {
+        (Some(min), Some(max)) if min > max => Err(invalid(
+            min_field,
+            format!("{min:?} is greater than {max_field} {max:?}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// RTT bounds and reorder buffer for a latency budget, see
+/// [`ReceiverOptions::with_target_latency`].
+fn latency_settings(latency: Duration, expected_bitrate: u64) -> (Duration, Duration, u32) {
+    let rtt_max = latency / 4;
+    let rtt_min = (latency / 20).max(Duration::from_millis(1)).min(rtt_max);
+
+    let packets_per_sec = expected_bitrate as f64 / (PAYLOAD_SIZE * 8) as f64;
+    let reorder = (packets_per_sec * (latency / 20).as_secs_f64()).round() as u32;
+    (
+        rtt_min,
+        rtt_max,
+        reorder.clamp(MIN_REORDER_BUFFER, MAX_REORDER_BUFFER),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_latency_preset() {
+        let options =
+            ReceiverOptions::new().with_target_latency(Duration::from_millis(500), 10_000_000);
+        assert_eq!(
+            options.recovery_length_min,
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(options.recovery_rtt_min, Some(Duration::from_millis(25)));
+        assert_eq!(options.recovery_rtt_max, Some(Duration::from_millis(125)));
+        // ~950 packets per second, 25 ms of them
+        assert_eq!(options.recovery_reorder_buffer, Some(25));
+
+        let options =
+            ReceiverOptions::new().with_target_latency(Duration::from_secs(2), 50_000_000);
+        assert_eq!(options.recovery_reorder_buffer, Some(475));
+
+        let options = ReceiverOptions::new().with_target_latency(Duration::from_millis(10), 1);
+        assert_eq!(options.recovery_rtt_min, Some(Duration::from_millis(1)));
+        assert_eq!(options.recovery_rtt_max, Some(Duration::from_micros(2500)));
+    }
+
+    #[test]
+    fn test_sender_receiver_parity() {
+        let receiver = ReceiverOptions::new()
+            .recovery_maxbitrate_return(2_000)
+            .recovery_reorder_buffer(60)
+            .recovery_rtt_min(Duration::from_millis(20))
+            .recovery_rtt_max(Duration::from_millis(300))
+            .min_retries(2)
+            .max_retries(8)
+            .weight(5)
+            .buffer_bloat(BufferBloatMode::Normal, 10, 20)
+            .congestion_control(CongestionControl::Aggressive)
+            .timing_mode(TimingMode::Arrival)
+            .session_timeout(Duration::from_secs(3))
+            .keepalive_interval(Duration::from_millis(250))
+            .with_target_latency(Duration::from_millis(800), 20_000_000);
+        let sender = SenderOptions::new()
+            .recovery_maxbitrate_return(2_000)
+            .recovery_reorder_buffer(60)
+            .recovery_rtt_min(Duration::from_millis(20))
+            .recovery_rtt_max(Duration::from_millis(300))
+            .min_retries(2)
+            .max_retries(8)
+            .weight(5)
+            .buffer_bloat(BufferBloatMode::Normal, 10, 20)
+            .congestion_control(CongestionControl::Aggressive)
+            .timing_mode(TimingMode::Arrival)
+            .session_timeout(Duration::from_secs(3))
+            .keepalive_interval(Duration::from_millis(250))
+            .with_target_latency(Duration::from_millis(800), 20_000_000);
+
+        let mut from_receiver = rist_sys::rist_peer_config::default();
+        receiver.apply_to_peer_config(&mut from_receiver);
+        let mut from_sender = rist_sys::rist_peer_config::default();
+        sender.apply_to_peer_config(&mut from_sender);
+
+        let config = crate::PeerConfig::from(&from_sender);
+        assert_eq!(crate::PeerConfig::from(&from_receiver), config);
+        assert_eq!(config.recovery_length_max, Duration::from_millis(800));
+        assert_eq!(config.recovery_rtt_max, Duration::from_millis(200));
+        assert_eq!(config.min_retries, 2);
+        assert_eq!(config.buffer_bloat_hard_limit, 20);
+        assert_eq!(config.congestion_control, CongestionControl::Aggressive);
+        assert_eq!(config.keepalive_interval, Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_recovery_bitrate() {
+        assert_eq!(recovery_bitrate(10_000_000).unwrap(), 10_000);
+        assert_eq!(recovery_bitrate(1_999).unwrap(), 1);
+        assert!(recovery_bitrate(999).is_err());
+        assert!(recovery_bitrate(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        fn field(result: crate::Result<()>) -> &'static str {
+            match result {
+                Err(crate::Error::InvalidOption { field, .. }) => field,
+                other => panic!("expected InvalidOption, got {other:?}"),
+            }
         }
+
+        assert!(ReceiverOptions::new().validate().is_ok());
+        assert!(SenderOptions::new()
+            .with_target_latency(Duration::from_secs(1), 10_000_000)
+            .validate()
+            .is_ok());
+
+        let options = ReceiverOptions::new()
+            .recovery_length_min(Duration::from_millis(900))
+            .recovery_length_max(Duration::from_millis(500));
+        assert_eq!(field(options.validate()), "recovery_length_min");
+        let options = SenderOptions::new()
+            .recovery_rtt_min(Duration::from_millis(300))
+            .recovery_rtt_max(Duration::from_millis(100));
+        assert_eq!(field(options.validate()), "recovery_rtt_min");
+        let options = SenderOptions::new().recovery_maxbitrate(u32::MAX);
+        assert_eq!(field(options.validate()), "recovery_maxbitrate");
+        let options = ReceiverOptions::new().recovery_maxbitrate(0);
+        assert_eq!(field(options.validate()), "recovery_maxbitrate");
+        let options = ReceiverOptions::new().recovery_length_max(Duration::from_secs(5_000_000));
+        assert_eq!(field(options.validate()), "recovery_length_max");
+        let options = SenderOptions::new().session_timeout(Duration::from_secs(3_000_000));
+        assert_eq!(field(options.validate()), "session_timeout");
+        let options = ReceiverOptions::new().min_retries(9).max_retries(3);
+        assert_eq!(field(options.validate()), "min_retries");
+
+        let err = ReceiverOptions::new()
+            .recovery_rtt_min(Duration::from_millis(300))
+            .recovery_rtt_max(Duration::from_millis(100))
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid option recovery_rtt_min: 300ms is greater than recovery_rtt_max 100ms"
+        );
     }
 }
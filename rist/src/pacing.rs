@@ -0,0 +1,330 @@
+//! Token buckets used to pace and cap sender output.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Largest burst let through at line rate, as time at the paced bitrate.
+const BURST: Duration = Duration::from_millis(5);
+
+/// Smallest burst, so a single packet never waits on itself.
+const MIN_BURST_BITS: f64 = 1500.0 * 8.0;
+
+/// Token bucket in bits.
+///
+/// Tokens may go negative: a caller reserves its packet straight away and
+/// waits for the returned delay, so concurrent senders queue up in order
+/// instead of racing for the next refill.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    bitrate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(bitrate: u64, now: Instant) -> Self {
+        let mut bucket = Self {
+            bitrate: 0.0,
+            capacity: 0.0,
+            tokens: 0.0,
+            last: now,
+        };
+        bucket.set_bitrate(bitrate, now);
+        bucket.tokens = bucket.capacity;
+        bucket
+    }
+
+    /// Change the refill rate, keeping the tokens collected so far.
+    pub(crate) fn set_bitrate(&mut self, bitrate: u64, now: Instant) {
+        self.refill(now);
+        self.bitrate = bitrate.max(1) as f64;
+        self.capacity = (self.bitrate * BURST.as_secs_f64()).max(MIN_BURST_BITS);
+        self.tokens = self.tokens.min(self.capacity);
+    }
+
+    /// Take tokens for `len` bytes, returning how long to wait before sending them.
+    pub(crate) fn reserve(&mut self, len: usize, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= len as f64 * 8.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.bitrate)
+        }
+    }
+
+    /// Like [`reserve`](Self::reserve), but leaves the bucket untouched and
+    /// returns `None` if the wait would be longer than `max`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn reserve_within(
+        &mut self,
+        len: usize,
+        now: Instant,
+        max: Duration,
+    ) -> Option<Duration> {
+        self.refill(now);
+        let deficit = len as f64 * 8.0 - self.tokens;
+        if deficit > 0.0 && deficit / self.bitrate > max.as_secs_f64() {
+            return None;
+        }
+        Some(self.reserve(len, now))
+    }
+
+    /// Take tokens for `len` bytes only if they are available right now.
+    pub(crate) fn try_take(&mut self, len: usize, now: Instant) -> bool {
+        self.refill(now);
+        let bits = len as f64 * 8.0;
+        if self.tokens < bits {
+            return false;
+        }
+        self.tokens -= bits;
+        true
+    }
+
+    /// Give back tokens taken for `len` bytes that were never sent.
+    pub(crate) fn refund(&mut self, len: usize, now: Instant) {
+        self.refill(now);
+        self.tokens = (self.tokens + len as f64 * 8.0).min(self.capacity);
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = self.last.max(now);
+        self.tokens = (self.tokens + elapsed * self.bitrate).min(self.capacity);
+    }
+}
+
+/// Optional pacer shared by all sends on a context.
+#[derive(Debug, Default)]
+pub(crate) struct Pacer(Mutex<Option<TokenBucket>>);
+
+impl Pacer {
+    /// Pace to `bitrate` bits per second from now on.
+    pub(crate) fn set_bitrate(&self, bitrate: u64) {
+        let mut bucket = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        *bucket = Some(TokenBucket::new(bitrate, Instant::now()));
+    }
+
+    /// Change the bitrate if already pacing, keeping the budget built up.
+    pub(crate) fn retune(&self, bitrate: u64) {
+        let mut bucket = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bucket) = bucket.as_mut() {
+            bucket.set_bitrate(bitrate, Instant::now());
+        }
+    }
+
+    /// Take the pacing budget for `len` bytes if it is available right now,
+    /// always `true` when not pacing.
+    pub(crate) fn try_take(&self, len: usize) -> bool {
+        let mut bucket = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match bucket.as_mut() {
+            Some(bucket) => bucket.try_take(len, Instant::now()),
+            None => true,
+        }
+    }
+
+    /// Give back the budget taken for `len` bytes that were never sent.
+    pub(crate) fn refund(&self, len: usize) {
+        let mut bucket = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bucket) = bucket.as_mut() {
+            bucket.refund(len, Instant::now());
+        }
+    }
+
+    /// How long to wait before sending `len` bytes, or `None` without
+    /// reserving anything if that's longer than `max`.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn delay_within(&self, len: usize, max: Duration) -> Option<Duration> {
+        let mut bucket = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match bucket.as_mut() {
+            Some(bucket) => bucket.reserve_within(len, Instant::now(), max),
+            None => Some(Duration::ZERO),
+        }
+    }
+
+    /// How long to wait before sending `len` bytes, or zero when not pacing.
+    pub(crate) fn delay(&self, len: usize) -> Duration {
+        let mut bucket = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match bucket.as_mut() {
+            Some(bucket) => bucket.reserve(len, Instant::now()),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Optional hard cap on a sender's output, shared by all sends on a context.
+///
+/// Media is admitted against whatever the cap leaves after retransmissions,
+/// and anything that doesn't fit is dropped rather than delayed.
+#[derive(Debug, Default)]
+pub(crate) struct BitrateCap {
+    // the cap in bps, and a bucket refilled at the share left for media
+    bucket: Mutex<Option<(u64, TokenBucket)>>,
+    dropped: AtomicU64,
+}
+
+impl BitrateCap {
+    /// Set or remove the cap.
+    pub(crate) fn set(&self, bitrate: Option<u64>) {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        *bucket = bitrate.map(|bitrate| (bitrate, TokenBucket::new(bitrate, Instant::now())));
+    }
+
+    /// The cap in bits per second, if one is set.
+    pub(crate) fn get(&self) -> Option<u64> {
+        let bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.as_ref().map(|(bitrate, _)| *bitrate)
+    }
+
+    /// Admit `len` bytes of media, counting a drop if the cap doesn't allow it.
+    ///
+    /// `retry_bitrate` is only called when a cap is set.
+    pub(crate) fn admit(&self, len: usize, retry_bitrate: impl FnOnce() -> u64) -> bool {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let Some((cap, bucket)) = bucket.as_mut() else {
+            return true;
+        };
+
+        let now = Instant::now();
+        bucket.set_bitrate(cap.saturating_sub(retry_bitrate()), now);
+        let admitted = bucket.try_take(len, now);
+        if !admitted {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        admitted
+    }
+
+    /// Packets dropped by the cap so far.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spreads_burst_evenly() {
+        let start = Instant::now();
+        // 1316-byte packets at 1000 packets per second
+        let mut bucket = TokenBucket::new(1316 * 8 * 1000, start);
+
+        let delays: Vec<Duration> = (0..20).map(|_| bucket.reserve(1316, start)).collect();
+        // the first few fill the 5 ms burst allowance, then one per millisecond
+        assert_eq!(delays[0], Duration::ZERO);
+        let steps: Vec<u128> = delays
+            .windows(2)
+            .skip(6)
+            .map(|w| (w[1] - w[0]).as_micros())
+            .collect();
+        assert!(
+            steps.iter().all(|&us| (999..=1001).contains(&us)),
+            "{steps:?}"
+        );
+    }
+
+    #[test]
+    fn test_refills_after_idle() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(8_000_000, start);
+        for _ in 0..100 {
+            bucket.reserve(1316, start);
+        }
+        assert!(bucket.reserve(1316, start) > Duration::ZERO);
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(bucket.reserve(1316, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_cap_leaves_room_for_retransmissions() {
+        let cap = BitrateCap::default();
+        assert!(cap.admit(1 << 20, || unreachable!()));
+
+        cap.set(Some(8_000_000));
+        assert_eq!(cap.get(), Some(8_000_000));
+        // 5 ms at 8 Mbps is 5000 bytes of burst
+        assert!(cap.admit(4000, || 0));
+        assert!(!cap.admit(4000, || 0));
+
+        // retransmissions using the whole cap leave nothing for media
+        cap.set(Some(8_000_000));
+        assert!(!cap.admit(2000, || 8_000_000));
+        assert_eq!(cap.dropped(), 2);
+
+        cap.set(None);
+        assert!(cap.admit(1 << 20, || 0));
+    }
+
+    #[test]
+    fn test_unpaced_by_default() {
+        let pacer = Pacer::default();
+        assert_eq!(pacer.delay(1 << 20), Duration::ZERO);
+        pacer.set_bitrate(8);
+        assert!(pacer.delay(1 << 20) > Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_retune_only_when_pacing() {
+        let pacer = Pacer::default();
+        pacer.retune(8);
+        assert_eq!(pacer.delay(1 << 20), Duration::ZERO);
+
+        pacer.set_bitrate(8_000_000_000);
+        assert_eq!(pacer.delay(1316), Duration::ZERO);
+        pacer.retune(8);
+        assert!(pacer.delay(1 << 20) > Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_try_take_never_waits() {
+        let pacer = Pacer::default();
+        assert!(pacer.try_take(1 << 20));
+
+        // 5 ms at 8 Mbps is 5000 bytes of burst
+        pacer.set_bitrate(8_000_000);
+        assert!(pacer.try_take(4000));
+        assert!(!pacer.try_take(4000));
+        // a refused packet doesn't borrow from the future
+        assert!(pacer.try_take(900));
+    }
+
+    #[test]
+    fn test_refund_returns_budget() {
+        let pacer = Pacer::default();
+        pacer.set_bitrate(8_000_000);
+        assert!(pacer.try_take(4000));
+        assert!(!pacer.try_take(4000));
+        pacer.refund(4000);
+        assert!(pacer.try_take(4000));
+
+        // never beyond the burst allowance
+        pacer.refund(1 << 20);
+        assert!(!pacer.try_take(8000));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_reserve_within() {
+        let start = Instant::now();
+        // 1000 bytes per millisecond, 5000 bytes of burst
+        let mut bucket = TokenBucket::new(8_000_000, start);
+        let max = Duration::from_millis(2);
+
+        assert_eq!(
+            bucket.reserve_within(5000, start, max),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            bucket.reserve_within(1000, start, max),
+            Some(Duration::from_millis(1))
+        );
+        // another 2000 bytes would wait 3 ms, and isn't reserved
+        assert_eq!(bucket.reserve_within(2000, start, max), None);
+        assert_eq!(bucket.reserve(1000, start), Duration::from_millis(2));
+    }
+}
@@ -0,0 +1,401 @@
+use crate::connection::ConnectionSlot;
+use crate::socket::{self, PeerAddress, PeerSocket, SocketBuffers, SocketOptions};
+use crate::stats::c_buf_to_string;
+use crate::{ConnectionState, Error, Profile, ReceiverStats, Result, SenderStats};
+use std::ffi::CString;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Identifies a peer on a context.
+///
+/// IDs are unique among the live peers of a context, but may be reused once
+/// a peer has been destroyed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerId(usize);
+
+impl PeerId {
+    pub(crate) fn from_raw(peer: *mut rist_sys::rist_peer) -> Self {
+        Self(peer as usize)
+    }
+
+    /// The librist peer this ID refers to, for calling `rist-sys` functions
+    /// directly.
+    ///
+    /// The pointer dangles once the peer has been destroyed, e.g. after
+    /// `stop()` or `reconnect_peer()`, so check that the ID is still listed by
+    /// `peers()` before using it.
+    pub fn as_raw(self) -> *mut rist_sys::rist_peer {
+        self.0 as *mut rist_sys::rist_peer
+    }
+}
+
+/// Latest stats librist reported for a peer, see [`PeerInfo::stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerStats {
+    /// Stats of a sender's peer.
+    Sender(SenderStats),
+    /// Stats of the flow a receiver's peer delivered.
+    Receiver(ReceiverStats),
+}
+
+/// A peer on a context, as listed by `peers()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    /// The peer's ID.
+    pub id: PeerId,
+    /// The URL the peer was added with, or `None` for a remote peer that
+    /// connected to a listening peer.
+    pub url: Option<String>,
+    /// The local canonical name configured for the peer, if any.
+    pub cname: Option<String>,
+    /// Latest connection state.
+    pub state: ConnectionState,
+    /// Buffer sizes in effect on the peer's socket. Only known for local
+    /// peers created with socket options, such as a buffer size.
+    pub socket_buffers: Option<SocketBuffers>,
+    /// The profile the peer speaks, that of its context. librist expects the
+    /// far end to use the same one, and a mismatch usually shows up as a
+    /// peer that never connects.
+    pub profile: Profile,
+    /// The canonical name the far end announces in its RTCP SDES packets,
+    /// once a stats report has carried it.
+    ///
+    /// librist puts no software version on the wire, so this is all it
+    /// learns about the far end's identity; other RIST implementations
+    /// often announce their product and version here. A receiver matches
+    /// it to the peer that delivered the flow. librist keys sender stats by
+    /// an ID of its own, so a sender only fills it in while a single peer
+    /// is connected.
+    pub remote_cname: Option<String>,
+    /// Latest stats for the peer, once a report has been matched to it the
+    /// same way as [`remote_cname`](Self::remote_cname).
+    ///
+    /// A receiver's peer gets the stats of the flow it delivered, which the
+    /// other peers of a bonded flow share. A sender's peer only gets them
+    /// while it is the single connected peer.
+    pub stats: Option<PeerStats>,
+}
+
+/// Applies options to a parsed peer config before the peer is created, and
+/// to the peer's sockets after.
+///
+/// Kept with the peer so it can be re-created with the same settings.
+#[derive(Clone)]
+pub(crate) struct PeerConfigurer {
+    pub(crate) config: Arc<dyn Fn(&mut rist_sys::rist_peer_config) + Send + Sync>,
+    pub(crate) sockets: SocketOptions,
+    /// Recovery bitrate set at runtime, applied over `config`.
+    pub(crate) recovery_maxbitrate: Option<u32>,
+    /// Recovery switched off at runtime, applied over `config`.
+    pub(crate) recovery_disabled: bool,
+}
+
+/// A peer created on a context.
+pub(crate) struct Peer {
+    pub(crate) raw: *mut rist_sys::rist_peer,
+    pub(crate) profile: Profile,
+    pub(crate) url: String,
+    pub(crate) cname: Option<String>,
+    pub(crate) configure: PeerConfigurer,
+    /// Sockets librist opened for the peer, if they were looked up to apply
+    /// socket options.
+    pub(crate) sockets: Vec<PeerSocket>,
+    /// Maximum recovery buffer, how long the peer may still ask for retransmissions.
+    pub(crate) recovery_buffer: Duration,
+    /// Recovery bitrate the peer was created with, in kbps.
+    pub(crate) recovery_bitrate: u32,
+}
+
+// SAFETY: peers are owned by their context, which is thread-safe
+unsafe impl Send for Peer {}
+
+impl Peer {
+    pub(crate) fn id(&self) -> PeerId {
+        PeerId::from_raw(self.raw)
+    }
+
+    /// Whether the peer listens for the far end to connect.
+    pub(crate) fn listens(&self) -> bool {
+        PeerAddress::from_url(&self.url).is_some_and(|address| address.listens())
+    }
+
+    pub(crate) fn socket_buffers(&self) -> Option<SocketBuffers> {
+        self.sockets
+            .iter()
+            .find_map(PeerSocket::fd)
+            .and_then(socket::buffers)
+    }
+}
+
+/// Parse `url`, let `configure` adjust the peer config, and create the peer
+/// on `ctx`, which was created with `profile`.
+pub(crate) fn create_peer(
+    ctx: *mut rist_sys::rist_ctx,
+    profile: Profile,
+    url: &str,
+    configure: PeerConfigurer,
+) -> Result<Peer> {
+    let url_c = CString::new(url)?;
+    let mut peer_config: *mut rist_sys::rist_peer_config = ptr::null_mut();
+
+    let ret = unsafe { rist_sys::rist_parse_address2(url_c.as_ptr(), &mut peer_config) };
+
+    if ret != 0 || peer_config.is_null() {
+        return Err(Error::UrlParse(url.to_string()));
+    }
+
+    if let Err(err) = configure.sockets.validate() {
+        unsafe { rist_sys::rist_peer_config_free2(&mut peer_config) };
+        return Err(err);
+    }
+
+    let (recovery_buffer, recovery_bitrate, cname) = unsafe {
+        (configure.config)(&mut *peer_config);
+        if let Some(bitrate) = configure.recovery_maxbitrate {
+            (*peer_config).recovery_maxbitrate = bitrate;
+        }
+        if configure.recovery_disabled {
+            (*peer_config).recovery_mode = rist_sys::rist_recovery_mode_RIST_RECOVERY_MODE_DISABLED;
+        }
+        if let Err(err) = profile.validate_peer_config(&*peer_config) {
+            rist_sys::rist_peer_config_free2(&mut peer_config);
+            return Err(err);
+        }
+        (
+            Duration::from_millis((*peer_config).recovery_length_max as u64),
+            (*peer_config).recovery_maxbitrate,
+            c_buf_to_string(&(*peer_config).cname),
+        )
+    };
+
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "rist_peer_create",
+        url = %crate::url::redact(url),
+        profile = ?profile,
+    )
+    .entered();
+    let mut peer: *mut rist_sys::rist_peer = ptr::null_mut();
+    let address = PeerAddress::from_url(url).filter(|_| !configure.sockets.is_empty());
+    let (ret, sockets) = match address {
+        Some(address) => socket::track_new_sockets(address, || unsafe {
+            rist_sys::rist_peer_create(ctx, &mut peer, peer_config)
+        }),
+        None => {
+            let ret = unsafe { rist_sys::rist_peer_create(ctx, &mut peer, peer_config) };
+            (ret, Vec::new())
+        }
+    };
+
+    unsafe {
+        rist_sys::rist_peer_config_free2(&mut peer_config);
+    }
+
+    if ret != 0 {
+        return Err(Error::PeerCreation(url.to_string()));
+    }
+
+    if !configure.sockets.is_empty() {
+        if let Err(err) = apply_socket_options(&sockets, &configure.sockets) {
+            unsafe { rist_sys::rist_peer_destroy(ctx, peer) };
+            return Err(err);
+        }
+    }
+
+    Ok(Peer {
+        raw: peer,
+        profile,
+        url: url.to_string(),
+        cname: (!cname.is_empty()).then_some(cname),
+        configure,
+        sockets,
+        recovery_buffer,
+        recovery_bitrate,
+    })
+}
+
+/// Create a peer from `url` like [`create_peer`], add it to `peers` and
+/// track its connection state. Returns the new peer's ID.
+pub(crate) fn add_peer(
+    ctx: *mut rist_sys::rist_ctx,
+    profile: Profile,
+    peers: &mut Vec<Peer>,
+    connection: &ConnectionSlot,
+    url: &str,
+    configure: PeerConfigurer,
+) -> Result<PeerId> {
+    let peer = create_peer(ctx, profile, url, configure)?;
+    let id = peer.id();
+    connection.track(id);
+    peers.push(peer);
+    Ok(id)
+}
+
+/// Apply socket options to the sockets librist opened for a new peer.
+fn apply_socket_options(sockets: &[PeerSocket], options: &SocketOptions) -> Result<()> {
+    let fds: Vec<_> = sockets.iter().filter_map(PeerSocket::fd).collect();
+    if fds.is_empty() {
+        return Err(Error::Configuration(
+            "could not find the peer's sockets to apply socket options".to_string(),
+        ));
+    }
+    fds.into_iter()
+        .try_for_each(|fd| socket::apply(fd, options))
+}
+
+/// Re-create peer `id` from its URL with `configure`, or with its current
+/// settings if `None`, see [`recreate_peer`]. Returns the new peer's ID.
+pub(crate) fn replace_peer(
+    ctx: *mut rist_sys::rist_ctx,
+    peers: &mut Vec<Peer>,
+    connection: &ConnectionSlot,
+    id: PeerId,
+    configure: Option<PeerConfigurer>,
+) -> Result<PeerId> {
+    let pos = peers
+        .iter()
+        .position(|peer| peer.id() == id)
+        .ok_or(Error::UnknownPeer)?;
+    let configure = configure.unwrap_or_else(|| peers[pos].configure.clone());
+    recreate_peer(ctx, peers, connection, pos, configure)
+}
+
+/// Replace `peers[pos]` with a peer created from its URL with `configure`,
+/// returning the new peer's ID.
+///
+/// A calling peer's replacement is created before the old peer is
+/// destroyed, so a failure leaves the old peer in place. A listening peer
+/// has to release its port before the replacement can bind it, so it is
+/// destroyed first and re-created with its old settings on failure. If even
+/// that fails, it is removed from `peers`.
+fn recreate_peer(
+    ctx: *mut rist_sys::rist_ctx,
+    peers: &mut Vec<Peer>,
+    connection: &ConnectionSlot,
+    pos: usize,
+    configure: PeerConfigurer,
+) -> Result<PeerId> {
+    let profile = peers[pos].profile;
+    let url = peers[pos].url.clone();
+    if !peers[pos].listens() {
+        let replacement = create_peer(ctx, profile, &url, configure)?;
+        let new_id = replacement.id();
+        connection.track(new_id);
+        let old = std::mem::replace(&mut peers[pos], replacement);
+        connection.forget(old.id());
+        destroy_peer(ctx, old);
+        return Ok(new_id);
+    }
+
+    let old = peers.remove(pos);
+    let old_configure = old.configure.clone();
+    connection.forget(old.id());
+    destroy_peer(ctx, old);
+    match create_peer(ctx, profile, &url, configure) {
+        Ok(replacement) => {
+            let new_id = replacement.id();
+            connection.track(new_id);
+            peers.insert(pos, replacement);
+            Ok(new_id)
+        }
+        Err(err) => {
+            if let Ok(restored) = create_peer(ctx, profile, &url, old_configure) {
+                connection.track(restored.id());
+                peers.insert(pos, restored);
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Re-create every peer whose recovery bitrate isn't `bitrate` with it,
+/// keeping its other settings. Returns the IDs of all peers, new or kept,
+/// in the order of `peers`.
+///
+/// Re-creating a peer reconnects it: its session and recovery buffer are
+/// reset, and a listening peer drops the senders connected to it. Peers
+/// already at `bitrate` are left alone. If one can't be re-created, the
+/// ones changed before it are changed back, see [`replace_peers`].
+pub(crate) fn set_recovery_bitrate(
+    ctx: *mut rist_sys::rist_ctx,
+    peers: &mut Vec<Peer>,
+    connection: &ConnectionSlot,
+    bitrate: u32,
+) -> Result<Vec<PeerId>> {
+    replace_peers(ctx, peers, connection, |peer| {
+        (peer.recovery_bitrate != bitrate).then(|| PeerConfigurer {
+            recovery_maxbitrate: Some(bitrate),
+            ..peer.configure.clone()
+        })
+    })
+}
+
+/// Re-create every peer whose recovery isn't switched off as `disabled`
+/// says, keeping its other settings, like [`set_recovery_bitrate`]. This
+/// reconnects those peers, dropping the senders of a listening one.
+pub(crate) fn set_recovery_disabled(
+    ctx: *mut rist_sys::rist_ctx,
+    peers: &mut Vec<Peer>,
+    connection: &ConnectionSlot,
+    disabled: bool,
+) -> Result<Vec<PeerId>> {
+    replace_peers(ctx, peers, connection, |peer| {
+        (peer.configure.recovery_disabled != disabled).then(|| PeerConfigurer {
+            recovery_disabled: disabled,
+            ..peer.configure.clone()
+        })
+    })
+}
+
+/// Re-create every peer `reconfigure` returns new settings for, one at a
+/// time with [`recreate_peer`].
+///
+/// If one can't be re-created, the peers changed before it are re-created
+/// with their old settings again, so no peer is left with the new ones.
+fn replace_peers(
+    ctx: *mut rist_sys::rist_ctx,
+    peers: &mut Vec<Peer>,
+    connection: &ConnectionSlot,
+    reconfigure: impl Fn(&Peer) -> Option<PeerConfigurer>,
+) -> Result<Vec<PeerId>> {
+    let mut changed = Vec::new();
+    for pos in 0..peers.len() {
+        let Some(configure) = reconfigure(&peers[pos]) else {
+            continue;
+        };
+        let old = peers[pos].configure.clone();
+        if let Err(err) = recreate_peer(ctx, peers, connection, pos, configure) {
+            // in reverse, so a peer lost on the way doesn't shift the others
+            for (pos, old) in changed.into_iter().rev() {
+                let _ = recreate_peer(ctx, peers, connection, pos, old);
+            }
+            return Err(err);
+        }
+        changed.push((pos, old));
+    }
+    Ok(peers.iter().map(Peer::id).collect())
+}
+
+/// How long to keep peers alive so they can still be served retransmissions.
+pub(crate) fn linger_time(peers: &[Peer]) -> Duration {
+    peers
+        .iter()
+        .map(|peer| peer.recovery_buffer)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Destroy a single peer, leaving the context itself alive.
+pub(crate) fn destroy_peer(ctx: *mut rist_sys::rist_ctx, peer: Peer) {
+    unsafe {
+        rist_sys::rist_peer_destroy(ctx, peer.raw);
+    }
+}
+
+/// Destroy every peer, leaving the context itself alive.
+pub(crate) fn destroy_peers(ctx: *mut rist_sys::rist_ctx, peers: &mut Vec<Peer>) {
+    for peer in peers.drain(..) {
+        destroy_peer(ctx, peer);
+    }
+}
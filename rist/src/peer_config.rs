@@ -0,0 +1,663 @@
+//! The full set of librist peer settings, and parsing them from a URL
+//! without a context.
+
+use crate::peer::PeerConfigurer;
+use crate::socket::SocketOptions;
+use crate::stats::c_buf_to_string;
+use crate::{Error, RecoveryMode, Result, SenderOptions};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// URL schemes librist accepts.
+const SCHEMES: [&str; 3] = ["rist", "udp", "rtp"];
+
+/// How librist reacts to a growing buffer at the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferBloatMode {
+    /// No buffer bloat mitigation (default).
+    #[default]
+    Off,
+    /// Back off retransmissions when the buffer grows.
+    Normal,
+    /// Back off retransmissions sooner.
+    Aggressive,
+}
+
+impl BufferBloatMode {
+    pub(crate) fn to_raw(self) -> rist_sys::rist_buffer_bloat_mode {
+        match self {
+            Self::Off => rist_sys::rist_buffer_bloat_mode_RIST_BUFFER_BLOAT_MODE_OFF,
+            Self::Normal => rist_sys::rist_buffer_bloat_mode_RIST_BUFFER_BLOAT_MODE_NORMAL,
+            Self::Aggressive => rist_sys::rist_buffer_bloat_mode_RIST_BUFFER_BLOAT_MODE_AGGRESSIVE,
+        }
+    }
+
+    pub(crate) fn from_raw(raw: rist_sys::rist_buffer_bloat_mode) -> Self {
+        match raw {
+            rist_sys::rist_buffer_bloat_mode_RIST_BUFFER_BLOAT_MODE_NORMAL => Self::Normal,
+            rist_sys::rist_buffer_bloat_mode_RIST_BUFFER_BLOAT_MODE_AGGRESSIVE => Self::Aggressive,
+            _ => Self::Off,
+        }
+    }
+}
+
+/// Which clock librist releases received packets by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimingMode {
+    /// The sender's timestamps (default).
+    #[default]
+    Source,
+    /// The arrival time.
+    Arrival,
+    /// The sender's timestamps, corrected by the RTCP clock.
+    Rtc,
+}
+
+impl TimingMode {
+    pub(crate) fn to_raw(self) -> rist_sys::rist_timing_mode {
+        match self {
+            Self::Source => rist_sys::rist_timing_mode_RIST_TIMING_MODE_SOURCE,
+            Self::Arrival => rist_sys::rist_timing_mode_RIST_TIMING_MODE_ARRIVAL,
+            Self::Rtc => rist_sys::rist_timing_mode_RIST_TIMING_MODE_RTC,
+        }
+    }
+
+    pub(crate) fn from_raw(raw: rist_sys::rist_timing_mode) -> Self {
+        match raw {
+            rist_sys::rist_timing_mode_RIST_TIMING_MODE_ARRIVAL => Self::Arrival,
+            rist_sys::rist_timing_mode_RIST_TIMING_MODE_RTC => Self::Rtc,
+            _ => Self::Source,
+        }
+    }
+}
+
+/// How retransmissions are throttled under congestion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CongestionControl {
+    /// No congestion control (default).
+    #[default]
+    Off,
+    /// Throttle retransmissions under congestion.
+    Normal,
+    /// Throttle retransmissions sooner.
+    Aggressive,
+}
+
+impl CongestionControl {
+    pub(crate) fn to_raw(self) -> rist_sys::rist_congestion_control_mode {
+        match self {
+            Self::Off => rist_sys::rist_congestion_control_mode_RIST_CONGESTION_CONTROL_MODE_OFF,
+            Self::Normal => {
+                rist_sys::rist_congestion_control_mode_RIST_CONGESTION_CONTROL_MODE_NORMAL
+            }
+            Self::Aggressive => {
+                rist_sys::rist_congestion_control_mode_RIST_CONGESTION_CONTROL_MODE_AGGRESSIVE
+            }
+        }
+    }
+
+    pub(crate) fn from_raw(raw: rist_sys::rist_congestion_control_mode) -> Self {
+        match raw {
+            rist_sys::rist_congestion_control_mode_RIST_CONGESTION_CONTROL_MODE_NORMAL => {
+                Self::Normal
+            }
+            rist_sys::rist_congestion_control_mode_RIST_CONGESTION_CONTROL_MODE_AGGRESSIVE => {
+                Self::Aggressive
+            }
+            _ => Self::Off,
+        }
+    }
+}
+
+impl RecoveryMode {
+    /// `None` for a mode librist leaves unconfigured.
+    pub(crate) fn from_raw(raw: rist_sys::rist_recovery_mode) -> Option<Self> {
+        match raw {
+            rist_sys::rist_recovery_mode_RIST_RECOVERY_MODE_DISABLED => Some(Self::Disabled),
+            rist_sys::rist_recovery_mode_RIST_RECOVERY_MODE_TIME => Some(Self::Time),
+            _ => None,
+        }
+    }
+}
+
+/// Every setting of a librist peer, with Rust types.
+///
+/// Get one from a URL with [`parse_url`] or start from librist's
+/// [`defaults`](Self::defaults), adjust any field and add the peer with
+/// `add_peer_config()`, or convert to and from librist's `rist_peer_config`
+/// with [`to_raw`](Self::to_raw) and `From`.
+///
+/// Durations are whole milliseconds. Empty strings in librist's config come
+/// out as `None`. The secret and SRP password are left out of the `Debug`
+/// output.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::{CongestionControl, Profile, Sender};
+/// use std::time::Duration;
+///
+/// # fn example() -> rist::Result<()> {
+/// let mut config = rist::parse_url("rist://203.0.113.5:5000")?;
+/// config.recovery_length_max = Duration::from_millis(800);
+/// config.congestion_control = CongestionControl::Normal;
+///
+/// let mut sender = Sender::new(Profile::Main)?;
+/// sender.add_peer_config(&config)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct PeerConfig {
+    /// Address the peer sends to or listens on, as librist stores it.
+    pub address: String,
+    /// Multicast interface, if set.
+    pub miface: Option<String>,
+    /// Local port to send from, 0 for any.
+    pub physical_port: u16,
+    /// Virtual destination port.
+    pub virt_dst_port: u16,
+    /// Recovery mode, `None` if unconfigured.
+    pub recovery_mode: Option<RecoveryMode>,
+    /// Maximum bitrate for recovery.
+    pub recovery_maxbitrate: u32,
+    /// Maximum bitrate for recovery in the return direction.
+    pub recovery_maxbitrate_return: u32,
+    /// Minimum recovery buffer length.
+    pub recovery_length_min: Duration,
+    /// Maximum recovery buffer length.
+    pub recovery_length_max: Duration,
+    /// Reorder buffer size.
+    pub recovery_reorder_buffer: u32,
+    /// Minimum RTT for recovery.
+    pub recovery_rtt_min: Duration,
+    /// Maximum RTT for recovery.
+    pub recovery_rtt_max: Duration,
+    /// Load-balancing weight, 0 to duplicate to every peer.
+    pub weight: u32,
+    /// Buffer bloat mitigation.
+    pub buffer_bloat_mode: BufferBloatMode,
+    /// Buffer bloat soft limit.
+    pub buffer_bloat_limit: u32,
+    /// Buffer bloat hard limit.
+    pub buffer_bloat_hard_limit: u32,
+    /// Canonical name sent to the remote side.
+    pub cname: Option<String>,
+    /// AES key size in bits, 0 without encryption.
+    pub key_size: u32,
+    /// Pre-shared passphrase for encryption.
+    pub secret: Option<String>,
+    /// Packets between key rotations, 0 to never rotate.
+    pub key_rotation: u32,
+    /// SRP username (Main profile authentication).
+    pub srp_username: Option<String>,
+    /// SRP password.
+    pub srp_password: Option<String>,
+    /// librist multiplex mode.
+    pub multiplex_mode: i32,
+    /// Multiplex filter.
+    pub multiplex_filter: Option<String>,
+    /// Time without traffic before the peer times out.
+    pub session_timeout: Duration,
+    /// Interval between keepalives.
+    pub keepalive_interval: Duration,
+    /// Clock received packets are released by.
+    pub timing_mode: TimingMode,
+    /// Congestion control of retransmissions.
+    pub congestion_control: CongestionControl,
+    /// Minimum retransmission requests per lost packet.
+    pub min_retries: u32,
+    /// Maximum retransmission requests per lost packet.
+    pub max_retries: u32,
+}
+
+impl PeerConfig {
+    /// librist's default peer settings, with no address.
+    ///
+    /// These come from the linked librist rather than constants in this
+    /// crate, so they follow librist when it changes its defaults. Peers
+    /// created from a URL start from the same values.
+    pub fn defaults() -> Result<Self> {
+        let mut raw = rist_sys::rist_peer_config::default();
+        let ret = unsafe { rist_sys::rist_peer_config_defaults_set(&mut raw) };
+        if ret != 0 {
+            return Err(Error::Configuration(
+                "librist failed to fill in peer config defaults".to_string(),
+            ));
+        }
+        Ok(Self::from_raw(&raw))
+    }
+
+    /// Check the config for values librist would reject or misread.
+    ///
+    /// Fails like [`to_raw`](Self::to_raw), which runs the same checks.
+    /// Adding a peer with the config does this first.
+    pub fn validate(&self) -> Result<()> {
+        self.to_raw().map(|_| ())
+    }
+
+    /// Convert to librist's peer config.
+    ///
+    /// Fails with [`Error::InvalidOption`] on the values
+    /// [`SenderOptions::validate`] rejects, such as a minimum above its
+    /// maximum, if a string is too long for librist's fixed-size buffers or
+    /// contains a NUL byte, or if a number doesn't fit librist's type.
+    pub fn to_raw(&self) -> Result<rist_sys::rist_peer_config> {
+        self.shared_options().validate()?;
+        let mut raw = rist_sys::rist_peer_config {
+            version: rist_sys::RIST_PEER_CONFIG_VERSION as c_int,
+            virt_dst_port: c_int::from(self.virt_dst_port),
+            recovery_mode: self.recovery_mode.map_or(
+                rist_sys::rist_recovery_mode_RIST_RECOVERY_MODE_UNCONFIGURED,
+                |mode| mode.to_raw(),
+            ),
+            recovery_maxbitrate: self.recovery_maxbitrate,
+            recovery_maxbitrate_return: self.recovery_maxbitrate_return,
+            recovery_length_min: to_millis("recovery_length_min", self.recovery_length_min)?,
+            recovery_length_max: to_millis("recovery_length_max", self.recovery_length_max)?,
+            recovery_reorder_buffer: self.recovery_reorder_buffer,
+            recovery_rtt_min: to_millis("recovery_rtt_min", self.recovery_rtt_min)?,
+            recovery_rtt_max: to_millis("recovery_rtt_max", self.recovery_rtt_max)?,
+            weight: self.weight,
+            physical_port: self.physical_port,
+            buffer_bloat_mode: self.buffer_bloat_mode.to_raw(),
+            buffer_bloat_limit: self.buffer_bloat_limit,
+            buffer_bloat_hard_limit: self.buffer_bloat_hard_limit,
+            key_size: to_c_int("key_size", self.key_size)?,
+            key_rotation: to_c_int("key_rotation", self.key_rotation)?,
+            multiplex_mode: self.multiplex_mode,
+            session_timeout: to_c_int(
+                "session_timeout",
+                to_millis("session_timeout", self.session_timeout)?,
+            )?,
+            keepalive_interval: to_c_int(
+                "keepalive_interval",
+                to_millis("keepalive_interval", self.keepalive_interval)?,
+            )?,
+            timing_mode: self.timing_mode.to_raw(),
+            congestion_control_mode: self.congestion_control.to_raw(),
+            min_retries: self.min_retries,
+            max_retries: self.max_retries,
+            ..Default::default()
+        };
+        fill_c_buf(&mut raw.address, "address", Some(&self.address))?;
+        fill_c_buf(&mut raw.miface, "miface", self.miface.as_deref())?;
+        fill_c_buf(&mut raw.cname, "cname", self.cname.as_deref())?;
+        fill_c_buf(&mut raw.secret, "secret", self.secret.as_deref())?;
+        fill_c_buf(
+            &mut raw.srp_username,
+            "srp_username",
+            self.srp_username.as_deref(),
+        )?;
+        fill_c_buf(
+            &mut raw.srp_password,
+            "srp_password",
+            self.srp_password.as_deref(),
+        )?;
+        fill_c_buf(
+            &mut raw.multiplex_filter,
+            "multiplex_filter",
+            self.multiplex_filter.as_deref(),
+        )?;
+        Ok(raw)
+    }
+
+    /// The settings the options have too, so they are checked the same way.
+    fn shared_options(&self) -> SenderOptions {
+        SenderOptions {
+            recovery_mode: self.recovery_mode,
+            recovery_maxbitrate: Some(self.recovery_maxbitrate),
+            recovery_maxbitrate_return: Some(self.recovery_maxbitrate_return),
+            recovery_length_min: Some(self.recovery_length_min),
+            recovery_length_max: Some(self.recovery_length_max),
+            recovery_reorder_buffer: Some(self.recovery_reorder_buffer),
+            recovery_rtt_min: Some(self.recovery_rtt_min),
+            recovery_rtt_max: Some(self.recovery_rtt_max),
+            min_retries: Some(self.min_retries),
+            max_retries: Some(self.max_retries),
+            weight: Some(self.weight),
+            buffer_bloat_mode: Some(self.buffer_bloat_mode),
+            buffer_bloat_limit: Some(self.buffer_bloat_limit),
+            buffer_bloat_hard_limit: Some(self.buffer_bloat_hard_limit),
+            congestion_control: Some(self.congestion_control),
+            timing_mode: Some(self.timing_mode),
+            session_timeout: Some(self.session_timeout),
+            keepalive_interval: Some(self.keepalive_interval),
+            ..Default::default()
+        }
+    }
+
+    /// Create peers with exactly these settings.
+    ///
+    /// The peer is still created from [`address`](Self::address), which
+    /// librist parses again, so it is re-created the same way on
+    /// `reconnect_peer()` and reconnects.
+    pub(crate) fn configurer(&self) -> Result<PeerConfigurer> {
+        let settings = self.to_raw()?;
+        Ok(PeerConfigurer {
+            config: Arc::new(move |config| {
+                let address = config.address;
+                *config = settings;
+                config.address = address;
+            }),
+            sockets: SocketOptions::default(),
+            recovery_maxbitrate: None,
+            recovery_disabled: false,
+        })
+    }
+
+    /// Copy a librist peer config.
+    pub(crate) fn from_raw(raw: &rist_sys::rist_peer_config) -> Self {
+        Self {
+            address: c_buf_to_string(&raw.address),
+            miface: non_empty(&raw.miface),
+            physical_port: raw.physical_port,
+            virt_dst_port: raw.virt_dst_port as u16,
+            recovery_mode: RecoveryMode::from_raw(raw.recovery_mode),
+            recovery_maxbitrate: raw.recovery_maxbitrate,
+            recovery_maxbitrate_return: raw.recovery_maxbitrate_return,
+            recovery_length_min: millis(raw.recovery_length_min),
+            recovery_length_max: millis(raw.recovery_length_max),
+            recovery_reorder_buffer: raw.recovery_reorder_buffer,
+            recovery_rtt_min: millis(raw.recovery_rtt_min),
+            recovery_rtt_max: millis(raw.recovery_rtt_max),
+            weight: raw.weight,
+            buffer_bloat_mode: BufferBloatMode::from_raw(raw.buffer_bloat_mode),
+            buffer_bloat_limit: raw.buffer_bloat_limit,
+            buffer_bloat_hard_limit: raw.buffer_bloat_hard_limit,
+            cname: non_empty(&raw.cname),
+            key_size: raw.key_size.max(0) as u32,
+            secret: non_empty(&raw.secret),
+            key_rotation: raw.key_rotation.max(0) as u32,
+            srp_username: non_empty(&raw.srp_username),
+            srp_password: non_empty(&raw.srp_password),
+            multiplex_mode: raw.multiplex_mode,
+            multiplex_filter: non_empty(&raw.multiplex_filter),
+            session_timeout: millis(raw.session_timeout.max(0) as u32),
+            keepalive_interval: millis(raw.keepalive_interval.max(0) as u32),
+            timing_mode: TimingMode::from_raw(raw.timing_mode),
+            congestion_control: CongestionControl::from_raw(raw.congestion_control_mode),
+            min_retries: raw.min_retries,
+            max_retries: raw.max_retries,
+        }
+    }
+}
+
+impl From<&rist_sys::rist_peer_config> for PeerConfig {
+    fn from(raw: &rist_sys::rist_peer_config) -> Self {
+        Self::from_raw(raw)
+    }
+}
+
+impl std::fmt::Debug for PeerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |value: &Option<String>| value.as_ref().map(|_| "<redacted>");
+        f.debug_struct("PeerConfig")
+            .field("address", &self.address)
+            .field("miface", &self.miface)
+            .field("physical_port", &self.physical_port)
+            .field("virt_dst_port", &self.virt_dst_port)
+            .field("recovery_mode", &self.recovery_mode)
+            .field("recovery_maxbitrate", &self.recovery_maxbitrate)
+            .field(
+                "recovery_maxbitrate_return",
+                &self.recovery_maxbitrate_return,
+            )
+            .field("recovery_length_min", &self.recovery_length_min)
+            .field("recovery_length_max", &self.recovery_length_max)
+            .field("recovery_reorder_buffer", &self.recovery_reorder_buffer)
+            .field("recovery_rtt_min", &self.recovery_rtt_min)
+            .field("recovery_rtt_max", &self.recovery_rtt_max)
+            .field("weight", &self.weight)
+            .field("buffer_bloat_mode", &self.buffer_bloat_mode)
+            .field("buffer_bloat_limit", &self.buffer_bloat_limit)
+            .field("buffer_bloat_hard_limit", &self.buffer_bloat_hard_limit)
+            .field("cname", &self.cname)
+            .field("key_size", &self.key_size)
+            .field("secret", &redacted(&self.secret))
+            .field("key_rotation", &self.key_rotation)
+            .field("srp_username", &self.srp_username)
+            .field("srp_password", &redacted(&self.srp_password))
+            .field("multiplex_mode", &self.multiplex_mode)
+            .field("multiplex_filter", &self.multiplex_filter)
+            .field("session_timeout", &self.session_timeout)
+            .field("keepalive_interval", &self.keepalive_interval)
+            .field("timing_mode", &self.timing_mode)
+            .field("congestion_control", &self.congestion_control)
+            .field("min_retries", &self.min_retries)
+            .field("max_retries", &self.max_retries)
+            .finish()
+    }
+}
+
+/// Parse a peer URL the way librist does when a peer is added, without
+/// needing a context.
+///
+/// Use it to validate operator-supplied URLs up front. Mistakes in the
+/// URL's shape, such as an unknown scheme or a bad port, are reported with
+/// the reason; anything else librist rejects fails with
+/// [`Error::UrlParse`] carrying the URL.
+///
+/// # Example
+///
+/// ```no_run
+/// let config = rist::parse_url("rist://203.0.113.5:5000?cname=studio&buffer=500")?;
+/// assert_eq!(config.cname.as_deref(), Some("studio"));
+/// assert_eq!(config.recovery_length_max.as_millis(), 500);
+/// # Ok::<(), rist::Error>(())
+/// ```
+pub fn parse_url(url: &str) -> Result<PeerConfig> {
+    check_url(url).map_err(|reason| Error::UrlParse(format!("{url}: {reason}")))?;
+
+    let url_c = CString::new(url)?;
+    let mut raw: *mut rist_sys::rist_peer_config = ptr::null_mut();
+    let ret = unsafe { rist_sys::rist_parse_address2(url_c.as_ptr(), &mut raw) };
+    if ret != 0 || raw.is_null() {
+        return Err(Error::UrlParse(url.to_string()));
+    }
+
+    let config = unsafe { PeerConfig::from_raw(&*raw) };
+    unsafe {
+        rist_sys::rist_peer_config_free2(&mut raw);
+    }
+    Ok(config)
+}
+
+/// Check the parts of `url` librist would only reject without a reason.
+fn check_url(url: &str) -> std::result::Result<(), String> {
+    if url.is_empty() {
+        return Err("empty URL".to_string());
+    }
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Err("missing scheme, expected rist://, udp:// or rtp://".to_string());
+    };
+    if !SCHEMES.contains(&scheme) {
+        return Err(format!(
+            "unknown scheme `{scheme}`, expected rist://, udp:// or rtp://"
+        ));
+    }
+
+    let authority = rest.trim_start_matches('@');
+    let authority = authority.split(['?', '/']).next().unwrap_or_default();
+    let port = if let Some(bracketed) = authority.strip_prefix('[') {
+        let Some((_, after)) = bracketed.split_once(']') else {
+            return Err("unclosed `[` in IPv6 address".to_string());
+        };
+        after.strip_prefix(':')
+    } else {
+        authority.rsplit_once(':').map(|(_, port)| port)
+    };
+    if let Some(port) = port {
+        if port.parse::<u16>().is_err() {
+            return Err(format!("invalid port `{port}`"));
+        }
+    }
+    Ok(())
+}
+
+/// Copy `value` into a NUL-terminated C string buffer.
+fn fill_c_buf(buf: &mut [c_char], field: &str, value: Option<&str>) -> Result<()> {
+    let value = value.unwrap_or_default();
+    if value.len() >= buf.len() {
+        return Err(Error::Configuration(format!(
+            "{field} is longer than {} bytes",
+            buf.len() - 1
+        )));
+    }
+    if value.contains('\0') {
+        return Err(Error::Configuration(format!("{field} contains a NUL byte")));
+    }
+    for (dst, src) in buf
+        .iter_mut()
+        .zip(value.bytes().chain(std::iter::repeat(0)))
+    {
+        *dst = src as c_char;
+    }
+    Ok(())
+}
+
+fn to_millis(field: &str, duration: Duration) -> Result<u32> {
+    duration
+        .as_millis()
+        .try_into()
+        .map_err(|_| Error::Configuration(format!("{field} is too long")))
+}
+
+fn to_c_int(field: &str, value: u32) -> Result<c_int> {
+    value
+        .try_into()
+        .map_err(|_| Error::Configuration(format!("{field} is too large")))
+}
+
+fn non_empty(buf: &[c_char]) -> Option<String> {
+    let value = c_buf_to_string(buf);
+    (!value.is_empty()).then_some(value)
+}
+
+fn millis(ms: u32) -> Duration {
+    Duration::from_millis(u64::from(ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c_buf<const N: usize>(value: &str) -> [c_char; N] {
+        let mut buf = [0; N];
+        for (dst, src) in buf.iter_mut().zip(value.bytes()) {
+            *dst = src as c_char;
+        }
+        buf
+    }
+
+    #[test]
+    fn test_check_url_reasons() {
+        assert!(check_url("rist://@:5000").is_ok());
+        assert!(check_url("rist://[fe80::1%eth0]:5000?cname=a").is_ok());
+        assert!(check_url("udp://239.0.0.1:1234").is_ok());
+
+        assert_eq!(check_url("").unwrap_err(), "empty URL");
+        assert!(check_url("10.0.0.1:5000")
+            .unwrap_err()
+            .contains("missing scheme"));
+        assert!(check_url("srt://10.0.0.1:5000")
+            .unwrap_err()
+            .contains("`srt`"));
+        assert!(check_url("rist://[::1:5000")
+            .unwrap_err()
+            .contains("unclosed"));
+        assert_eq!(
+            check_url("rist://10.0.0.1:70000").unwrap_err(),
+            "invalid port `70000`"
+        );
+        assert!(matches!(
+            parse_url("rist://10.0.0.1:x"),
+            Err(Error::UrlParse(msg)) if msg == "rist://10.0.0.1:x: invalid port `x`"
+        ));
+    }
+
+    #[test]
+    fn test_from_raw() {
+        let raw = rist_sys::rist_peer_config {
+            address: c_buf("10.0.0.1:5000"),
+            cname: c_buf("studio"),
+            secret: c_buf("hunter2"),
+            recovery_mode: rist_sys::rist_recovery_mode_RIST_RECOVERY_MODE_TIME,
+            recovery_maxbitrate: 100_000,
+            recovery_length_max: 1000,
+            session_timeout: 2000,
+            key_size: 128,
+            timing_mode: rist_sys::rist_timing_mode_RIST_TIMING_MODE_ARRIVAL,
+            congestion_control_mode:
+                rist_sys::rist_congestion_control_mode_RIST_CONGESTION_CONTROL_MODE_NORMAL,
+            ..Default::default()
+        };
+        let config = PeerConfig::from_raw(&raw);
+        assert_eq!(config.address, "10.0.0.1:5000");
+        assert_eq!(config.cname.as_deref(), Some("studio"));
+        assert_eq!(config.miface, None);
+        assert_eq!(config.recovery_mode, Some(RecoveryMode::Time));
+        assert_eq!(config.recovery_length_max, Duration::from_secs(1));
+        assert_eq!(config.session_timeout, Duration::from_secs(2));
+        assert_eq!(config.key_size, 128);
+        assert_eq!(config.timing_mode, TimingMode::Arrival);
+        assert_eq!(config.congestion_control, CongestionControl::Normal);
+        assert_eq!(config.buffer_bloat_mode, BufferBloatMode::Off);
+
+        let back = config.to_raw().unwrap();
+        assert_eq!(PeerConfig::from(&back), config);
+        assert_eq!(back.secret[..8], c_buf::<8>("hunter2"));
+
+        let debug = format!("{config:?}");
+        assert!(debug.contains("secret: Some(\"<redacted>\")"));
+        assert!(!debug.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = PeerConfig::defaults().unwrap();
+        assert_eq!(config.address, "");
+        assert_eq!(config.recovery_mode, Some(RecoveryMode::Time));
+        assert_eq!(
+            u32::from(config.virt_dst_port),
+            rist_sys::RIST_DEFAULT_VIRT_DST_PORT
+        );
+        assert_eq!(
+            config.recovery_rtt_max,
+            millis(rist_sys::RIST_DEFAULT_RECOVERY_RTT_MAX)
+        );
+        assert!(config.recovery_length_max > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_validates_like_options() {
+        let raw = rist_sys::rist_peer_config {
+            recovery_maxbitrate: 100_000,
+            recovery_length_min: 2000,
+            recovery_length_max: 1000,
+            ..Default::default()
+        };
+        let mut config = PeerConfig::from_raw(&raw);
+        assert!(matches!(
+            config.validate(),
+            Err(Error::InvalidOption {
+                field: "recovery_length_min",
+                ..
+            })
+        ));
+
+        config.recovery_length_min = config.recovery_length_max;
+        assert!(config.validate().is_ok());
+        config.recovery_maxbitrate = 0;
+        assert!(matches!(
+            config.to_raw(),
+            Err(Error::InvalidOption {
+                field: "recovery_maxbitrate",
+                ..
+            })
+        ));
+    }
+}
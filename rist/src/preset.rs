@@ -0,0 +1,227 @@
+//! Named option presets for common endpoints and links.
+
+use crate::{Error, Profile, ReceiverOptions, Result, RistUrl, SenderOptions};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Options for a common endpoint or kind of link.
+///
+/// A preset picks the profile and the recovery buffer, RTT bounds, retry
+/// limit and keepalive settings for both ends, plus the AES key size to use
+/// when the link is encrypted. Settings made on the returned options
+/// override the preset's.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::{Preset, RistUrl, Sender};
+///
+/// # fn example() -> rist::Result<()> {
+/// let preset = Preset::Satellite;
+/// let url = preset.encrypted_url(RistUrl::connect("203.0.113.7", 5000), "s3cret")?;
+///
+/// let mut sender = Sender::new(preset.profile())?;
+/// sender.add_peer_with_options(&url.build()?, &preset.sender_options())?;
+/// sender.start()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Preset {
+    /// AWS Elemental MediaConnect RIST flows, which speak the Simple
+    /// profile without encryption. Uses librist's default buffer and
+    /// timing settings.
+    MediaConnect,
+    /// Open Broadcast Encoder and decoders built on librist: the Main
+    /// profile with librist's default buffer and timing settings, the same
+    /// as [`MediaConnect`](Self::MediaConnect).
+    Obe,
+    /// Contribution inside a facility: a short buffer and tight RTT bounds.
+    LowLatencyLan,
+    /// Geostationary satellite and other long-RTT links: a buffer several
+    /// round trips long and patient timeouts.
+    Satellite,
+}
+
+/// The settings a preset applies to both ends.
+struct Settings {
+    buffer: Duration,
+    rtt_min: Duration,
+    rtt_max: Duration,
+    reorder_buffer: u32,
+    max_retries: u32,
+    keepalive_interval: Duration,
+    session_timeout: Duration,
+}
+
+/// Apply `settings` to a [`SenderOptions`] or [`ReceiverOptions`].
+macro_rules! apply_settings {
+    ($options:expr, $settings:expr) => {{
+        let settings = $settings;
+        $options
+            .recovery_length_min(settings.buffer)
+            .recovery_length_max(settings.buffer)
+            .recovery_rtt_min(settings.rtt_min)
+            .recovery_rtt_max(settings.rtt_max)
+            .recovery_reorder_buffer(settings.reorder_buffer)
+            .max_retries(settings.max_retries)
+            .keepalive_interval(settings.keepalive_interval)
+            .session_timeout(settings.session_timeout)
+    }};
+}
+
+impl Preset {
+    /// Every preset.
+    pub const ALL: [Preset; 4] = [
+        Preset::MediaConnect,
+        Preset::Obe,
+        Preset::LowLatencyLan,
+        Preset::Satellite,
+    ];
+
+    /// The preset's name, as accepted by [`FromStr`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::MediaConnect => "mediaconnect",
+            Preset::Obe => "obe",
+            Preset::LowLatencyLan => "low-latency-lan",
+            Preset::Satellite => "satellite",
+        }
+    }
+
+    /// The profile to create contexts with.
+    pub fn profile(self) -> Profile {
+        match self {
+            Preset::MediaConnect => Profile::Simple,
+            _ => Profile::Main,
+        }
+    }
+
+    /// AES key size in bits to use when encrypting, `None` if the endpoint
+    /// doesn't support encryption.
+    pub fn aes_key_size(self) -> Option<u32> {
+        match self {
+            Preset::MediaConnect => None,
+            Preset::Obe | Preset::LowLatencyLan => Some(128),
+            Preset::Satellite => Some(256),
+        }
+    }
+
+    fn settings(self) -> Settings {
+        match self {
+            // librist's defaults
+            Preset::MediaConnect | Preset::Obe => Settings {
+                buffer: Duration::from_millis(1000),
+                rtt_min: Duration::from_millis(50),
+                rtt_max: Duration::from_millis(500),
+                reorder_buffer: 25,
+                max_retries: 10,
+                keepalive_interval: Duration::from_millis(1000),
+                session_timeout: Duration::from_millis(2000),
+            },
+            Preset::LowLatencyLan => Settings {
+                buffer: Duration::from_millis(50),
+                rtt_min: Duration::from_millis(1),
+                rtt_max: Duration::from_millis(10),
+                reorder_buffer: 15,
+                max_retries: 5,
+                keepalive_interval: Duration::from_millis(100),
+                session_timeout: Duration::from_millis(1000),
+            },
+            Preset::Satellite => Settings {
+                buffer: Duration::from_millis(3000),
+                rtt_min: Duration::from_millis(500),
+                rtt_max: Duration::from_millis(1500),
+                reorder_buffer: 70,
+                max_retries: 5,
+                keepalive_interval: Duration::from_millis(1000),
+                session_timeout: Duration::from_millis(10_000),
+            },
+        }
+    }
+
+    /// Sender options for this preset.
+    pub fn sender_options(self) -> SenderOptions {
+        apply_settings!(SenderOptions::new(), self.settings())
+    }
+
+    /// Receiver options for this preset.
+    pub fn receiver_options(self) -> ReceiverOptions {
+        apply_settings!(ReceiverOptions::new(), self.settings())
+    }
+
+    /// Add the secret and this preset's AES key size to a peer URL.
+    ///
+    /// Fails for presets whose endpoint doesn't support encryption.
+    pub fn encrypted_url(self, url: RistUrl, secret: &str) -> Result<RistUrl> {
+        let key_size = self.aes_key_size().ok_or(Error::UnsupportedByProfile {
+            profile: self.profile(),
+            feature: "encryption",
+        })?;
+        Ok(url.param("secret", secret).param("aes-type", key_size))
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.to_ascii_lowercase().replace('_', "-");
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.name() == name)
+            .ok_or_else(|| Error::Configuration(format!("unknown preset: {s}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_validate() {
+        for preset in Preset::ALL {
+            preset.sender_options().validate().unwrap();
+            preset.receiver_options().validate().unwrap();
+            assert_eq!(preset.name().parse::<Preset>().unwrap(), preset);
+        }
+        assert_eq!(
+            "Low_Latency_LAN".parse::<Preset>().unwrap(),
+            Preset::LowLatencyLan
+        );
+        assert!("mediaconnect2".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn test_satellite_buffer_covers_round_trips() {
+        let options = Preset::Satellite.receiver_options();
+        let buffer = options.recovery_length_max.unwrap();
+        assert!(buffer >= options.recovery_rtt_max.unwrap() * 2);
+    }
+
+    #[test]
+    fn test_encrypted_url() {
+        let url = Preset::Satellite
+            .encrypted_url(RistUrl::connect("10.0.0.2", 5000), "s3cret")
+            .unwrap();
+        assert_eq!(
+            url.build().unwrap(),
+            "rist://10.0.0.2:5000?secret=s3cret&aes-type=256"
+        );
+        assert!(matches!(
+            Preset::MediaConnect.encrypted_url(RistUrl::connect("10.0.0.2", 5000), "s3cret"),
+            Err(Error::UnsupportedByProfile {
+                profile: Profile::Simple,
+                ..
+            })
+        ));
+    }
+}
@@ -20,4 +20,104 @@ impl Profile {
             Profile::Advanced => rist_sys::rist_profile_RIST_PROFILE_ADVANCED,
         }
     }
+
+    /// Reject peer settings that can't be carried on the wire with this profile.
+    ///
+    /// The Simple profile has no GRE tunnel, so it has no room for
+    /// encryption or authentication; librist would otherwise drop them
+    /// silently.
+    pub(crate) fn validate_peer_config(
+        self,
+        config: &rist_sys::rist_peer_config,
+    ) -> crate::Result<()> {
+        if !matches!(config.key_size, 0 | 128 | 256) {
+            return Err(crate::Error::Configuration(format!(
+                "unsupported AES key size {}, expected 128 or 256",
+                config.key_size
+            )));
+        }
+
+        if self == Profile::Simple {
+            if config.key_size != 0 {
+                return Err(self.unsupported("encryption"));
+            }
+            if config.srp_username[0] != 0 {
+                return Err(self.unsupported("SRP authentication"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unsupported(self, feature: &'static str) -> crate::Error {
+        crate::Error::UnsupportedByProfile {
+            profile: self,
+            feature,
+        }
+    }
+}
+
+impl std::str::FromStr for Profile {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "simple" | "0" => Ok(Profile::Simple),
+            "main" | "1" => Ok(Profile::Main),
+            "advanced" | "2" => Ok(Profile::Advanced),
+            _ => Err(crate::Error::Configuration(format!("unknown profile: {s}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn config_with_secret(key_size: i32) -> rist_sys::rist_peer_config {
+        let mut config = rist_sys::rist_peer_config {
+            key_size,
+            ..Default::default()
+        };
+        config.secret[0] = b'x' as _;
+        config
+    }
+
+    #[test]
+    fn test_simple_profile_rejects_encryption() {
+        let config = config_with_secret(128);
+        assert!(matches!(
+            Profile::Simple.validate_peer_config(&config),
+            Err(Error::UnsupportedByProfile {
+                profile: Profile::Simple,
+                feature: "encryption",
+            })
+        ));
+        assert!(Profile::Main.validate_peer_config(&config).is_ok());
+        assert!(Profile::Advanced.validate_peer_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_simple_profile_rejects_srp() {
+        let mut config = rist_sys::rist_peer_config::default();
+        config.srp_username[0] = b'u' as _;
+        assert!(matches!(
+            Profile::Simple.validate_peer_config(&config),
+            Err(Error::UnsupportedByProfile { .. })
+        ));
+        assert!(Profile::Main.validate_peer_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_invalid_key_size() {
+        let config = config_with_secret(192);
+        assert!(matches!(
+            Profile::Main.validate_peer_config(&config),
+            Err(Error::Configuration(_))
+        ));
+        assert!(Profile::Simple
+            .validate_peer_config(&rist_sys::rist_peer_config::default())
+            .is_ok());
+    }
 }
@@ -1,116 +1,537 @@
-use crate::{Error, Profile, ReceiverOptions, Result};
-use std::ffi::CString;
+use crate::builder::ReceiverBuilder;
+use crate::capture::{CaptureSlot, CaptureTap, Direction};
+use crate::connection::{register_connection, ConnectionCallback, ConnectionSlot};
+use crate::flow::{FlowSlot, StreamEvent};
+use crate::logging::LoggingSettings;
+#[cfg(unix)]
+use crate::notify::{DataNotify, NotifyPipe};
+#[cfg(feature = "serde")]
+use crate::oob::{Command, CommandRequest, Message};
+use crate::oob::{Heartbeat, OobSlot, RttProbe};
+use crate::peer::{add_peer, destroy_peers, replace_peer, set_recovery_disabled, Peer};
+use crate::stats::{
+    register_stats, ReceiverStats, StatsMode, StatsSlot, StatsSnapshot, DEFAULT_STATS_INTERVAL,
+};
+use crate::{
+    BlockFlags, BlockMetadata, ConnectionState, Error, FlowInfo, Health, PeerConfig, PeerId,
+    PeerInfo, PeerStats, Profile, ReceiverOptions, Result,
+};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::ops::Deref;
 use std::ptr;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A block owned by librist, freed once the last [`DataBlock`] sharing it
+/// is dropped.
+struct RawBlock(*mut rist_sys::rist_data_block);
+
+impl Drop for RawBlock {
+    fn drop(&mut self) {
+        unsafe {
+            rist_sys::rist_receiver_data_block_free2(&mut self.0);
+        }
+    }
+}
+
+// SAFETY: the block is only read after librist hands it over, and freed once
+unsafe impl Send for RawBlock {}
+unsafe impl Sync for RawBlock {}
 
 /// A received data block from a RIST stream.
+///
+/// Cloning is cheap: clones share the block librist allocated, which is
+/// freed when the last one is dropped. librist doesn't export its own
+/// block reference counting, so the sharing is done on this side.
+#[derive(Clone)]
 pub struct DataBlock {
-    inner: *mut rist_sys::rist_data_block,
+    raw: Arc<RawBlock>,
+    queue_len: usize,
 }
 
 impl DataBlock {
-    /// Create a DataBlock from a raw pointer.
-    pub(crate) fn from_raw(inner: *mut rist_sys::rist_data_block) -> Self {
-        Self { inner }
+    /// Create a DataBlock from a raw pointer, read with `queue_len` blocks
+    /// still queued behind it.
+    pub(crate) fn from_raw(inner: *mut rist_sys::rist_data_block, queue_len: usize) -> Self {
+        Self {
+            raw: Arc::new(RawBlock(inner)),
+            queue_len,
+        }
+    }
+
+    /// Blocks still waiting in librist's output queue when this one was
+    /// read. A number that keeps growing means the reader is falling behind.
+    pub fn queue_len(&self) -> usize {
+        self.queue_len
+    }
+
+    fn inner(&self) -> &rist_sys::rist_data_block {
+        // SAFETY: the block stays valid until `raw` is dropped
+        unsafe { &*self.raw.0 }
     }
 
     /// Get the payload data.
     pub fn payload(&self) -> &[u8] {
-        unsafe {
-            let ptr = (*self.inner).payload as *const u8;
-            let len = (*self.inner).payload_len;
-            std::slice::from_raw_parts(ptr, len)
-        }
+        let block = self.inner();
+        unsafe { std::slice::from_raw_parts(block.payload as *const u8, block.payload_len) }
     }
 
-    /// Get the timestamp (in 90kHz clock units).
+    /// Get the 64-bit NTP timestamp; see
+    /// [`ntp_to_system_time`](crate::ntp_to_system_time) to decode it.
     pub fn timestamp(&self) -> u64 {
-        unsafe { (*self.inner).ts_ntp }
+        self.inner().ts_ntp
     }
 
     /// Get the flow ID.
     pub fn flow_id(&self) -> u32 {
-        unsafe { (*self.inner).flow_id }
+        self.inner().flow_id
     }
-}
 
-impl Drop for DataBlock {
-    fn drop(&mut self) {
-        unsafe {
-            rist_sys::rist_receiver_data_block_free2(&mut self.inner);
+    /// Get the sequence number.
+    pub fn seq(&self) -> u64 {
+        self.inner().seq
+    }
+
+    /// Get the virtual source port.
+    pub fn virt_src_port(&self) -> u16 {
+        self.inner().virt_src_port
+    }
+
+    /// Get the virtual destination port.
+    pub fn virt_dst_port(&self) -> u16 {
+        self.inner().virt_dst_port
+    }
+
+    /// Get the data flags librist set on the block.
+    pub fn flags(&self) -> BlockFlags {
+        BlockFlags::from_raw(self.inner().flags)
+    }
+
+    /// Get the packet metadata, suitable for re-sending with `send_with_metadata`.
+    ///
+    /// The flags are left unset, since a discontinuity in what was received
+    /// isn't necessarily one in what is sent on; copy [`flags`](Self::flags)
+    /// over to pass them on.
+    pub fn metadata(&self) -> BlockMetadata {
+        BlockMetadata {
+            ts_ntp: self.timestamp(),
+            seq: Some(self.seq()),
+            flow_id: self.flow_id(),
+            virt_src_port: self.virt_src_port(),
+            virt_dst_port: self.virt_dst_port(),
+            flags: BlockFlags::default(),
+        }
+    }
+
+    /// The remote peer that delivered this block, if librist reported one.
+    pub(crate) fn peer(&self) -> Option<PeerId> {
+        let peer = self.inner().peer;
+        (!peer.is_null()).then(|| PeerId::from_raw(peer))
+    }
+
+    /// Returns `true` if `flow_ids` is `None` or contains this block's flow.
+    pub(crate) fn accepted_by(&self, flow_ids: Option<&[u32]>) -> bool {
+        match flow_ids {
+            Some(ids) => ids.contains(&self.flow_id()),
+            None => true,
         }
     }
 }
 
-// SAFETY: DataBlock owns its data and can be sent between threads
-unsafe impl Send for DataBlock {}
+impl std::fmt::Debug for DataBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataBlock")
+            .field("flow_id", &self.flow_id())
+            .field("seq", &self.seq())
+            .field("len", &self.payload().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsRef<[u8]> for DataBlock {
+    fn as_ref(&self) -> &[u8] {
+        self.payload()
+    }
+}
+
+impl Borrow<[u8]> for DataBlock {
+    fn borrow(&self) -> &[u8] {
+        self.payload()
+    }
+}
+
+/// Derefs to the payload, so a block can be used wherever a byte slice is
+/// expected.
+impl Deref for DataBlock {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.payload()
+    }
+}
 
 /// RIST receiver for receiving data streams.
 pub struct Receiver {
     ctx: *mut rist_sys::rist_ctx,
+    profile: Profile,
     started: bool,
+    stopped: bool,
+    paused: bool,
+    accepted_flow_ids: Option<Vec<u32>>,
+    queue_len: AtomicUsize,
+    peers: Vec<Peer>,
+    connection: Arc<ConnectionSlot>,
+    oob: Arc<OobSlot>,
+    flows: Arc<FlowSlot>,
+    fifo_drops: FifoDrops,
+    stats: Arc<StatsSlot<ReceiverStats>>,
+    capture: CaptureSlot,
+    #[cfg(unix)]
+    notify: Option<DataNotify>,
+    // librist references the logging settings until the context is destroyed
+    _logging: Option<LoggingSettings>,
 }
 
 impl Receiver {
     /// Create a new RIST receiver with the specified profile.
     pub fn new(profile: Profile) -> Result<Self> {
+        Self::create(profile, None)
+    }
+
+    /// Create a receiver builder.
+    pub fn builder() -> ReceiverBuilder {
+        ReceiverBuilder::new()
+    }
+
+    pub(crate) fn create(profile: Profile, logging: Option<LoggingSettings>) -> Result<Self> {
         let mut ctx: *mut rist_sys::rist_ctx = ptr::null_mut();
+        let logging_ptr = logging
+            .as_ref()
+            .map_or(ptr::null_mut(), LoggingSettings::as_ptr);
 
         let ret =
-            unsafe { rist_sys::rist_receiver_create(&mut ctx, profile.to_raw(), ptr::null_mut()) };
+            unsafe { rist_sys::rist_receiver_create(&mut ctx, profile.to_raw(), logging_ptr) };
 
         if ret != 0 || ctx.is_null() {
             return Err(Error::ContextCreation);
         }
 
-        Ok(Self {
+        Self::wrap(ctx, profile, logging)
+    }
+
+    /// Take ownership of a receiver context created with `rist_receiver_create`.
+    ///
+    /// Use this together with [`as_raw`](Self::as_raw) to configure the
+    /// context with `rist-sys` functions this crate doesn't cover yet. The
+    /// stats and connection callbacks are replaced, and the context is
+    /// destroyed when the receiver is dropped, or straight away on error.
+    ///
+    /// # Safety
+    ///
+    /// `ctx` must be a valid receiver context created with `profile` that has
+    /// not been started and is not owned or destroyed by anything else. Peers created on it
+    /// directly are not listed by [`peers`](Self::peers).
+    pub unsafe fn from_raw(ctx: *mut rist_sys::rist_ctx, profile: Profile) -> Result<Self> {
+        if ctx.is_null() {
+            return Err(Error::ContextCreation);
+        }
+
+        Self::wrap(ctx, profile, None)
+    }
+
+    fn wrap(
+        ctx: *mut rist_sys::rist_ctx,
+        profile: Profile,
+        logging: Option<LoggingSettings>,
+    ) -> Result<Self> {
+        let flows = Arc::new(FlowSlot::new());
+        let drops = Arc::new(AtomicU64::new(0));
+        let receiver = Self {
             ctx,
+            profile,
             started: false,
-        })
+            stopped: false,
+            paused: false,
+            queue_len: AtomicUsize::new(0),
+            capture: CaptureSlot::default(),
+            #[cfg(unix)]
+            notify: None,
+            accepted_flow_ids: None,
+            peers: Vec::new(),
+            connection: Arc::new(ConnectionSlot::new()),
+            oob: Arc::new(OobSlot::new(ctx, profile)),
+            stats: flow_stats(&flows, &drops),
+            fifo_drops: FifoDrops::new(drops),
+            flows,
+            _logging: logging,
+        };
+        receiver.set_stats_interval(DEFAULT_STATS_INTERVAL)?;
+        track_flows(&receiver.connection, &receiver.flows, None);
+        register_connection(ctx, &receiver.connection)?;
+        receiver.oob.register_early()?;
+
+        Ok(receiver)
     }
 
     /// Add a peer by URL (e.g., "rist://@:5000" for listening).
-    pub fn add_peer(&mut self, url: &str) -> Result<()> {
+    pub fn add_peer(&mut self, url: &str) -> Result<PeerId> {
         self.add_peer_with_options(url, &ReceiverOptions::default())
     }
 
     /// Add a peer by URL with custom receiver options.
-    pub fn add_peer_with_options(&mut self, url: &str, options: &ReceiverOptions) -> Result<()> {
-        options.apply_to_receiver_ctx(self.ctx)?;
+    pub fn add_peer_with_options(
+        &mut self,
+        url: &str,
+        options: &ReceiverOptions,
+    ) -> Result<PeerId> {
+        if self.stopped {
+            return Err(Error::Stopped);
+        }
 
-        let url_c = CString::new(url)?;
-        let mut peer_config: *mut rist_sys::rist_peer_config = ptr::null_mut();
+        let configurer = options.configurer()?;
+        options.apply_to_receiver_ctx(self.ctx)?;
+        let id = add_peer(
+            self.ctx,
+            self.profile,
+            &mut self.peers,
+            &self.connection,
+            url,
+            configurer,
+        )?;
+        if let Some(flow_ids) = &options.accepted_flow_ids {
+            self.accepted_flow_ids = Some(flow_ids.clone());
+        }
 
-        let ret = unsafe { rist_sys::rist_parse_address2(url_c.as_ptr(), &mut peer_config) };
+        Ok(id)
+    }
 
-        if ret != 0 || peer_config.is_null() {
-            return Err(Error::UrlParse(url.to_string()));
+    /// Add a peer with every librist setting given by `config`.
+    ///
+    /// Unlike [`add_peer_with_options`](Self::add_peer_with_options) the
+    /// settings in `config` are used as they are, including those the options
+    /// don't cover. Fails if `config` is invalid, see
+    /// [`PeerConfig::validate`](crate::PeerConfig::validate).
+    pub fn add_peer_config(&mut self, config: &PeerConfig) -> Result<PeerId> {
+        if self.stopped {
+            return Err(Error::Stopped);
         }
 
-        unsafe {
-            options.apply_to_peer_config(&mut *peer_config);
+        add_peer(
+            self.ctx,
+            self.profile,
+            &mut self.peers,
+            &self.connection,
+            &config.address,
+            config.configurer()?,
+        )
+    }
+
+    /// Reconnect a peer with new recovery and bitrate settings.
+    ///
+    /// librist fixes a peer's settings when it is created, so the peer is
+    /// destroyed and created again from its URL with `options`. This resets
+    /// its RIST session: a calling peer reconnects and starts over with an
+    /// empty recovery buffer, and a listening peer drops every client
+    /// connected to it, which have to connect again. The context and its
+    /// other peers keep running. The replacement's ID is returned.
+    ///
+    /// A calling peer's replacement is created before it is destroyed, so
+    /// if that fails the peer is left as it was. A listening peer has to
+    /// release its port first, so if that fails it is re-created with its
+    /// old settings, and may get the same ID back either way.
+    ///
+    /// Context-wide settings such as [`fifo_size`](ReceiverOptions::fifo_size)
+    /// and [`accept_flow_ids`](ReceiverOptions::accept_flow_ids) are not
+    /// re-applied.
+    pub fn reconnect_peer(&mut self, peer: PeerId, options: &ReceiverOptions) -> Result<PeerId> {
+        if self.stopped {
+            return Err(Error::Stopped);
         }
 
-        let mut peer: *mut rist_sys::rist_peer = ptr::null_mut();
-        let ret = unsafe { rist_sys::rist_peer_create(self.ctx, &mut peer, peer_config) };
+        replace_peer(
+            self.ctx,
+            &mut self.peers,
+            &self.connection,
+            peer,
+            Some(options.configurer()?),
+        )
+    }
 
-        unsafe {
-            rist_sys::rist_peer_config_free2(&mut peer_config);
+    /// A descriptor that becomes readable whenever data is available, for
+    /// driving the receiver from an epoll, mio or io_uring loop.
+    ///
+    /// librist is told to signal it on the first call; later calls return
+    /// the same descriptor. See the [`notify`](crate::notify) module.
+    #[cfg(unix)]
+    pub fn data_notify(&mut self) -> Result<DataNotify> {
+        if let Some(notify) = &self.notify {
+            return Ok(notify.clone());
         }
 
+        let pipe = NotifyPipe::new().map_err(|err| Error::EventFd(err.to_string()))?;
+        let notify = DataNotify::new(pipe);
+        let ret =
+            unsafe { rist_sys::rist_receiver_data_notify_fd_set(self.ctx, notify.write_fd()) };
         if ret != 0 {
-            return Err(Error::PeerCreation(url.to_string()));
+            return Err(Error::EventFd("failed to set notify fd".to_string()));
         }
+        self.notify = Some(notify.clone());
+        Ok(notify)
+    }
 
-        Ok(())
+    /// Start an application heartbeat over OOB, pinging the far end every
+    /// `interval` and answering its pings.
+    ///
+    /// Gives an end-to-end RTT and "far end alive" status independent of
+    /// RTCP, see [`oob`](crate::oob). Fails on the Simple profile, which has
+    /// no OOB channel. Calling it again only changes the interval.
+    pub fn start_heartbeat(&self, interval: Duration) -> Result<Heartbeat> {
+        self.oob.start_heartbeat(&self.connection, interval)
+    }
+
+    /// Measure the application-level round trip to every connected peer by
+    /// sending `count` OOB pings, 20 ms apart, and waiting up to `timeout`
+    /// after the last one for the answers.
+    ///
+    /// Unlike the RTCP RTT in the stats this works before any media is
+    /// sent, so a link can be qualified before going live. The far end
+    /// answers like heartbeat pings, see [`oob`](crate::oob). Fails with
+    /// [`Error::Send`] when no peer is connected, and on the Simple profile.
+    pub fn rtt_probe(&self, count: u32, timeout: Duration) -> Result<RttProbe> {
+        self.oob.rtt_probe(&self.connection, count, timeout)
+    }
+
+    /// Send a typed message over OOB to every connected peer, returning how
+    /// many it went to.
+    ///
+    /// The message is encoded as JSON and must fit one OOB packet. See
+    /// [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub fn send_message<T: Serialize>(&self, message: &T) -> Result<usize> {
+        self.oob.send_message(&self.connection, message)
+    }
+
+    /// Send a typed message over OOB to `peer` only.
+    #[cfg(feature = "serde")]
+    pub fn send_message_to<T: Serialize>(&self, peer: PeerId, message: &T) -> Result<()> {
+        self.oob.send_message_to(peer, message)
+    }
+
+    /// Receive the next typed message sent over OOB, waiting up to `timeout`
+    /// for one.
+    ///
+    /// Returns `Ok(None)` on timeout, or [`Error::InvalidMessage`] if the
+    /// message isn't a `T`. Messages that arrived before the call are kept,
+    /// up to 64.
+    #[cfg(feature = "serde")]
+    pub fn recv_message<T: DeserializeOwned>(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<Message<T>>> {
+        self.oob.recv_message(timeout)
+    }
+
+    /// Send a remote control command over OOB to every connected peer, and
+    /// wait up to `timeout` for the far end to acknowledge it.
+    ///
+    /// Fails with [`Error::CommandRejected`] if the far end refused it, or
+    /// [`Error::CommandTimeout`] without an answer. See [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub fn send_command(&self, command: Command, timeout: Duration) -> Result<()> {
+        self.oob.send_command(&self.connection, command, timeout)
+    }
+
+    /// Receive the next remote control command, waiting up to `timeout` for
+    /// one.
+    ///
+    /// Answer it with [`ack_command`](Self::ack_command). Commands that
+    /// arrived before the call are kept, up to 64.
+    #[cfg(feature = "serde")]
+    pub fn recv_command(&self, timeout: Duration) -> Result<Option<CommandRequest>> {
+        self.oob.recv_command(timeout)
+    }
+
+    /// Acknowledge a command from [`recv_command`](Self::recv_command), with
+    /// the reason if it was refused.
+    #[cfg(feature = "serde")]
+    pub fn ack_command(
+        &self,
+        request: &CommandRequest,
+        result: std::result::Result<(), String>,
+    ) -> Result<()> {
+        self.oob.ack_command(request, result)
+    }
+
+    /// The underlying librist context, for calling `rist-sys` functions
+    /// directly.
+    ///
+    /// The pointer stays owned by this receiver and is only valid until it is
+    /// dropped. Don't destroy it or replace its callbacks.
+    pub fn as_raw(&self) -> *mut rist_sys::rist_ctx {
+        self.ctx
+    }
+
+    /// Set how often librist reports stats.
+    pub fn set_stats_interval(&self, interval: Duration) -> Result<()> {
+        register_stats(self.ctx, interval, &self.stats)
+    }
+
+    /// Call `callback` with every new stats snapshot.
+    ///
+    /// The callback runs on a librist thread and must not block.
+    pub fn on_stats(&self, callback: impl FnMut(&ReceiverStats) + Send + 'static) {
+        self.stats.set_callback(Some(Box::new(callback)));
+    }
+
+    /// Returns the latest stats for this receiver.
+    ///
+    /// Stats are updated periodically (every 1 second by default).
+    /// Returns `None` if no stats have been collected yet.
+    pub fn raw_stats(&self) -> Option<ReceiverStats> {
+        self.stats.latest()
+    }
+
+    /// Returns the latest stats for this receiver with the interval they cover,
+    /// for bitrate gauges.
+    ///
+    /// Returns `None` if no stats have been collected yet.
+    pub fn stats_snapshot(&self) -> Option<StatsSnapshot<ReceiverStats>> {
+        self.stats.latest_snapshot()
+    }
+
+    /// Forget the stats collected so far, to start a fresh measurement
+    /// window, e.g. after maintenance.
+    ///
+    /// librist's counters are cumulative and keep running; from now on they
+    /// are reported as the increase since this call. Gauges such as
+    /// quality, RTT and bandwidth are unaffected. Until librist's next
+    /// report, [`raw_stats`](Self::raw_stats) returns `None`.
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// Report counters as running totals or as the increase since the
+    /// previous report, see [`StatsMode`]. Applies to
+    /// [`raw_stats`](Self::raw_stats), snapshots and the stats callback.
+    pub fn set_stats_mode(&self, mode: StatsMode) {
+        self.stats.set_mode(mode);
     }
 
     /// Start the receiver.
     pub fn start(&mut self) -> Result<()> {
+        if self.stopped {
+            return Err(Error::Stopped);
+        }
         if self.started {
             return Err(Error::AlreadyStarted);
         }
 
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("rist_start", role = "receiver").entered();
         let ret = unsafe { rist_sys::rist_start(self.ctx) };
 
         if ret != 0 {
@@ -121,38 +542,353 @@ impl Receiver {
         Ok(())
     }
 
+    /// Stop receiving without destroying the context.
+    ///
+    /// All peers are removed, so no new data arrives, but packets already
+    /// queued can still be read and the last stats remain available.
+    /// A stopped receiver cannot be started again.
+    pub fn stop(&mut self) -> Result<()> {
+        if !self.started {
+            return Err(Error::NotStarted);
+        }
+
+        destroy_peers(self.ctx, &mut self.peers);
+        self.connection.clear();
+        self.stopped = true;
+        Ok(())
+    }
+
+    /// Returns `true` if any peer has completed its handshake and not timed out since.
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_connected()
+    }
+
+    /// List the peers of this receiver with their connection state.
+    ///
+    /// Senders connected to a listening peer are listed after the peers
+    /// added with [`add_peer`](Self::add_peer), without a URL.
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        match_stats(
+            self.connection.peers(&self.peers, self.profile),
+            &self.flows,
+            &self.stats,
+        )
+    }
+
+    /// Connection state of `peer`, or `None` if it isn't a live peer of this receiver.
+    ///
+    /// A listening peer stays [`Connecting`](ConnectionState::Connecting);
+    /// librist reports each sender that connects to it as a peer of its own.
+    pub fn peer_state(&self, peer: PeerId) -> Option<ConnectionState> {
+        self.connection.state(peer)
+    }
+
+    /// Returns `true` once [`stop`](Self::stop) has been called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Capture every payload delivered from now on into `tap`, or stop
+    /// with `None`.
+    pub fn set_capture(&self, tap: Option<CaptureTap>) {
+        self.capture.set(tap);
+    }
+
+    /// Report how the receiver is doing, for liveness and readiness probes.
+    pub fn health(&self) -> Health {
+        Health::new(
+            self.started && !self.stopped,
+            &self.peers(),
+            self.flows.last_packet_age(),
+        )
+        .with_receiver_stats(self.stats.latest())
+    }
+
+    /// List the flows this receiver has delivered data from.
+    pub fn flows(&self) -> Vec<FlowInfo> {
+        self.flows.flows()
+    }
+
+    /// Close the flow `flow_id`, e.g. to evict a misbehaving contributor
+    /// without restarting the listener.
+    ///
+    /// The flow's stats and state are dropped and it is reported to
+    /// [`on_flow_expired`](Self::on_flow_expired). Its blocks still queued,
+    /// and any more its senders send, are discarded until a new sender
+    /// delivers the flow, or its senders time out and reconnect. librist
+    /// has no call to drop a single flow, so its recovery buffer keeps
+    /// running until then. Fails with [`Error::UnknownFlow`] for a flow that
+    /// hasn't delivered data.
+    pub fn close_flow(&self, flow_id: u32) -> Result<()> {
+        if self.flows.close(flow_id) {
+            Ok(())
+        } else {
+            Err(Error::UnknownFlow(flow_id))
+        }
+    }
+
+    /// Call `callback` when librist tears down a flow because all of its
+    /// senders timed out, so per-flow resources can be released.
+    ///
+    /// The callback runs on a librist thread and must not block.
+    pub fn on_flow_expired(&self, callback: impl FnMut(&FlowInfo) + Send + 'static) {
+        self.flows.set_callback(Some(Box::new(callback)));
+    }
+
+    /// Call `callback` when a flow delivers nothing for `timeout`, and again
+    /// when it resumes, e.g. to switch to a slate or backup source.
+    ///
+    /// Stalls are detected while [`read`](Self::read) is being called, so
+    /// read with a timeout well below `timeout`.
+    pub fn on_stall(&self, timeout: Duration, callback: impl FnMut(&StreamEvent) + Send + 'static) {
+        self.flows.watch_stalls(timeout, Box::new(callback));
+    }
+
+    /// Check every block read for MPEG-TS sync loss and continuity counter
+    /// errors, reported per flow in [`ReceiverStats::ts`].
+    ///
+    /// Off by default. Turning it off drops the counters, and turning it on
+    /// again starts them from zero.
+    #[cfg(feature = "ts")]
+    pub fn set_ts_monitor(&self, enabled: bool) {
+        self.flows.set_ts_monitor(enabled);
+    }
+
+    /// Pause delivery while keeping the session alive.
+    ///
+    /// While paused, [`read`](Self::read) discards incoming data instead of
+    /// returning it, so nothing piles up in the output FIFO and playback
+    /// resumes from live data. The connection, keepalives and retransmission
+    /// requests carry on as normal, so resuming needs no new handshake. To
+    /// stop retransmission requests too, see
+    /// [`set_nacks_enabled`](Self::set_nacks_enabled).
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resume delivery after [`pause`](Self::pause).
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Stop or restart asking for retransmissions on every live peer, e.g.
+    /// to save the return path while [paused](Self::pause).
+    ///
+    /// librist fixes a peer's recovery mode when it is created, so a peer
+    /// whose setting changes is reconnected like with
+    /// [`reconnect_peer`](Self::reconnect_peer): its session is reset, a
+    /// listening peer drops its clients, and it gets a new ID. Peers already
+    /// set that way are left alone. Enabling restores each peer's own
+    /// recovery settings, and peers added later use their own options. The
+    /// IDs of all peers are returned in the order of [`peers`](Self::peers).
+    /// If a peer can't be re-created, the ones changed before it are
+    /// reconnected with their old setting.
+    pub fn set_nacks_enabled(&mut self, enabled: bool) -> Result<Vec<PeerId>> {
+        if self.stopped {
+            return Err(Error::Stopped);
+        }
+
+        set_recovery_disabled(self.ctx, &mut self.peers, &self.connection, !enabled)
+    }
+
+    /// Returns `true` while the receiver is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Blocks waiting in librist's output queue as of the last
+    /// [`read`](Self::read).
+    ///
+    /// Compare against [`ReceiverOptions::fifo_size()`] to notice falling
+    /// behind before the queue overflows.
+    pub fn queue_len(&self) -> usize {
+        self.queue_len.load(Ordering::Relaxed)
+    }
+
     /// Read data with a timeout.
     ///
     /// Returns `Ok(None)` on timeout, `Ok(Some(data))` on success.
+    /// After [`stop`](Self::stop) this drains whatever is still queued.
+    /// While [paused](Self::pause) this discards data until the timeout and
+    /// returns `Ok(None)`. Data from flows rejected by
+    /// [`accept_flow_ids`](ReceiverOptions::accept_flow_ids) is skipped.
     pub fn read(&self, timeout: Duration) -> Result<Option<DataBlock>> {
         if !self.started {
             return Err(Error::NotStarted);
         }
+        self.flows.check_stalls();
 
-        let timeout_ms: i32 = timeout
+        let mut timeout_ms: i32 = timeout
             .as_millis()
             .try_into()
             .map_err(|_| Error::TimeoutOverflow)?;
 
-        let mut block: *mut rist_sys::rist_data_block = ptr::null_mut();
+        let deadline = Instant::now() + timeout;
+        loop {
+            let Some(block) = read_block(self.ctx, timeout_ms)? else {
+                self.queue_len.store(0, Ordering::Relaxed);
+                return Ok(None);
+            };
+            self.queue_len.store(block.queue_len(), Ordering::Relaxed);
+            self.fifo_drops.record(&block);
+            if !self.paused
+                && block.accepted_by(self.accepted_flow_ids.as_deref())
+                && self.flows.admits(block.flow_id(), block.peer())
+            {
+                self.flows
+                    .seen(block.flow_id(), block.peer(), block.timestamp());
+                #[cfg(feature = "ts")]
+                self.flows.inspect_ts(block.flow_id(), block.payload());
+                self.capture
+                    .record(Direction::Received, block.payload(), &block.metadata());
+                return Ok(Some(block));
+            }
+            timeout_ms = deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis() as i32;
+        }
+    }
+}
+
+/// Counts the blocks librist's output FIFO dropped into the receiver's
+/// drop count.
+///
+/// librist only flags the first block delivered after an overflow, so how
+/// many were lost is taken from the gap in that flow's sequence numbers.
+pub(crate) struct FifoDrops {
+    drops: Arc<AtomicU64>,
+    last_seq: Mutex<HashMap<u32, u64>>,
+}
+
+impl FifoDrops {
+    pub(crate) fn new(drops: Arc<AtomicU64>) -> Self {
+        Self {
+            drops,
+            last_seq: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Note a block read from librist.
+    pub(crate) fn record(&self, block: &DataBlock) {
+        self.note(block.flow_id(), block.seq(), block.flags().overflow);
+    }
 
-        let ret = unsafe { rist_sys::rist_receiver_data_read2(self.ctx, &mut block, timeout_ms) };
+    fn note(&self, flow_id: u32, seq: u64, overflow: bool) {
+        let last = self
+            .last_seq
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(flow_id, seq);
+        if overflow {
+            let lost = match last {
+                Some(last) if seq > last + 1 => seq - last - 1,
+                // a restarted sequence, or no earlier block to go by
+                _ => 1,
+            };
+            self.drops.fetch_add(lost, Ordering::Relaxed);
+        }
+    }
+}
 
-        if ret < 0 {
-            return Err(Error::Read);
+/// A stats slot that adds the jitter measured on `flows` and the blocks
+/// counted in `drops` to librist's stats.
+pub(crate) fn flow_stats(
+    flows: &Arc<FlowSlot>,
+    drops: &Arc<AtomicU64>,
+) -> Arc<StatsSlot<ReceiverStats>> {
+    let flows = Arc::clone(flows);
+    let drops = Arc::clone(drops);
+    Arc::new(StatsSlot::with_fill(Box::new(move |stats| {
+        stats.read_jitter = flows.jitter(stats.flow_id);
+        stats.dropped = drops.load(Ordering::Relaxed);
+        #[cfg(feature = "ts")]
+        {
+            stats.ts = flows.ts_stats(stats.flow_id);
         }
+    })))
+}
+
+/// Fill in the stats and far end's cname from the one flow each peer
+/// delivered.
+pub(crate) fn match_stats(
+    mut peers: Vec<PeerInfo>,
+    flows: &FlowSlot,
+    stats: &StatsSlot<ReceiverStats>,
+) -> Vec<PeerInfo> {
+    let stats = stats.all();
+    for peer in &mut peers {
+        let flow_stats = flows
+            .flow_of(peer.id)
+            .and_then(|flow_id| stats.iter().find(|stats| stats.flow_id == flow_id));
+        peer.remote_cname = flow_stats
+            .map(|stats| stats.cname.clone())
+            .filter(|cname| !cname.is_empty());
+        peer.stats = flow_stats.cloned().map(PeerStats::Receiver);
+    }
+    peers
+}
 
-        if ret == 0 || block.is_null() {
-            return Ok(None);
+/// Expire flows in `flows` when their senders time out on `connection`,
+/// then pass every change on to `then`.
+pub(crate) fn track_flows(
+    connection: &ConnectionSlot,
+    flows: &Arc<FlowSlot>,
+    then: Option<ConnectionCallback>,
+) {
+    let flows = Arc::clone(flows);
+    let then = Mutex::new(then);
+    connection.set_callback(Some(Box::new(move |peer, state| {
+        if state == ConnectionState::TimedOut {
+            flows.peer_timed_out(peer);
         }
+        // caught here, so only `then` is removed and flows keep expiring
+        crate::callback::guard("connection", (), || {
+            crate::callback::call_user(&then, |then| then(peer, state))
+        });
+    })));
+}
+
+/// Read one block from the receiver's output FIFO, waiting up to `timeout_ms`.
+pub(crate) fn read_block(
+    ctx: *mut rist_sys::rist_ctx,
+    timeout_ms: i32,
+) -> Result<Option<DataBlock>> {
+    let mut block: *mut rist_sys::rist_data_block = ptr::null_mut();
+
+    #[cfg(feature = "tracing")]
+    let span = tracing::trace_span!(
+        "rist_receiver_data_read2",
+        timeout_ms,
+        bytes = tracing::field::Empty,
+        flow_id = tracing::field::Empty,
+    )
+    .entered();
+    let ret = unsafe { rist_sys::rist_receiver_data_read2(ctx, &mut block, timeout_ms) };
+
+    if ret < 0 {
+        return Err(Error::Read);
+    }
 
-        Ok(Some(DataBlock::from_raw(block)))
+    if ret == 0 || block.is_null() {
+        return Ok(None);
     }
+
+    // librist returns the number of blocks left in the queue plus one
+    let block = DataBlock::from_raw(block, ret as usize - 1);
+    #[cfg(feature = "tracing")]
+    span.record("bytes", block.payload().len())
+        .record("flow_id", block.flow_id());
+    Ok(Some(block))
 }
 
 impl Drop for Receiver {
     fn drop(&mut self) {
+        self.oob.shutdown();
         unsafe {
+            #[cfg(unix)]
+            if self.notify.is_some() {
+                rist_sys::rist_receiver_data_notify_fd_set(self.ctx, 0);
+            }
             rist_sys::rist_destroy(self.ctx);
         }
     }
@@ -160,3 +896,53 @@ impl Drop for Receiver {
 
 // SAFETY: Receiver owns its context and librist contexts are thread-safe
 unsafe impl Send for Receiver {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fifo_drops_from_sequence_gap() {
+        let drops = Arc::new(AtomicU64::new(0));
+        let fifo = FifoDrops::new(drops.clone());
+        fifo.note(2, 10, false);
+        fifo.note(4, 500, false);
+        // blocks 11..=14 of flow 2 were dropped
+        fifo.note(2, 15, true);
+        assert_eq!(drops.load(Ordering::Relaxed), 4);
+
+        // nothing to measure the gap against
+        fifo.note(6, 7, true);
+        assert_eq!(drops.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn test_peer_stats_from_delivered_flow() {
+        let flows = FlowSlot::new();
+        let stats = StatsSlot::new();
+        let peer = |n: usize| PeerInfo {
+            id: PeerId::from_raw(n as *mut rist_sys::rist_peer),
+            url: None,
+            cname: None,
+            state: ConnectionState::Connected,
+            socket_buffers: None,
+            profile: Profile::Main,
+            remote_cname: None,
+            stats: None,
+        };
+        let report = ReceiverStats {
+            cname: "camera".to_string(),
+            flow_id: 7,
+            received: 100,
+            ..Default::default()
+        };
+        flows.seen(7, Some(peer(1).id), 0);
+        stats.update(report.clone());
+
+        let peers = match_stats(vec![peer(1), peer(2)], &flows, &stats);
+        assert_eq!(peers[0].remote_cname.as_deref(), Some("camera"));
+        assert_eq!(peers[0].stats, Some(PeerStats::Receiver(report)));
+        // delivered no flow yet
+        assert_eq!(peers[1].stats, None);
+    }
+}
@@ -0,0 +1,421 @@
+//! Recording a received stream to disk.
+//!
+//! A [`Recorder`] reads from an [`AsyncReceiver`] and appends every payload
+//! to a `.ts` file named after the time it was opened, starting a new file
+//! once the current one reaches a size or duration limit. Each recorded
+//! block is handed back, so the recorder can sit in front of the rest of an
+//! ingest pipeline.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::recorder::Recorder;
+//! use rist::tokio::AsyncReceiver;
+//! use rist::Profile;
+//! use std::time::Duration;
+//!
+//! # async fn example() -> rist::Result<()> {
+//! let input = AsyncReceiver::bind(Profile::Main, "rist://@:5000")?;
+//! let recorder = Recorder::new(input, "/var/lib/recordings")
+//!     .prefix("studio-a")
+//!     .max_duration(Duration::from_secs(15 * 60))
+//!     .max_bytes(2 << 30);
+//!
+//! while let Some(block) = recorder.record_next().await? {
+//!     // pass block on to the rest of the pipeline
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::buffer::{BufferSource, HeapBuffers};
+use crate::tokio::AsyncReceiver;
+use crate::{DataBlock, Result};
+use ::tokio::fs::{File, OpenOptions};
+use ::tokio::io::AsyncWriteExt;
+use ::tokio::sync::Mutex as AsyncMutex;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Size of the write buffer of each file.
+const WRITE_BUFFER: usize = 8 * 1024;
+
+/// Tees payloads from a receiver into rotating `.ts` files.
+///
+/// Files are named `<prefix>-YYYYMMDDTHHMMSSZ.ts` after the UTC time they
+/// were opened, with a `-N` suffix if that name is taken. Writes are
+/// buffered; call [`flush`](Self::flush) before dropping the recorder to
+/// keep the tail of the current file.
+pub struct Recorder {
+    input: AsyncReceiver,
+    writer: AsyncMutex<SegmentWriter>,
+    packets: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Recorder {
+    /// Create a recorder reading from `input` and writing into `dir`, with
+    /// the prefix `rist` and no rotation limits.
+    pub fn new(input: AsyncReceiver, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            input,
+            writer: AsyncMutex::new(SegmentWriter::new(dir.into())),
+            packets: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Start file names with `prefix` instead of `rist`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.writer.get_mut().prefix = prefix.into();
+        self
+    }
+
+    /// Start a new file before one would grow past `max_bytes`.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.writer.get_mut().max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Start a new file once one has been open for `max_duration`.
+    pub fn max_duration(mut self, max_duration: Duration) -> Self {
+        self.writer.get_mut().max_duration = Some(max_duration);
+        self
+    }
+
+    /// Take the write buffer of each file from `source`, see
+    /// [`buffer`](crate::buffer).
+    pub fn buffer_source(mut self, source: Arc<dyn BufferSource>) -> Self {
+        self.writer.get_mut().buffers = source;
+        self
+    }
+
+    /// The receiver packets are read from.
+    pub fn input(&self) -> &AsyncReceiver {
+        &self.input
+    }
+
+    /// Number of packets recorded so far.
+    pub fn recorded_packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    /// Number of payload bytes recorded so far.
+    pub fn recorded_bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// The file currently being written, if one is open.
+    pub async fn current_path(&self) -> Option<PathBuf> {
+        self.writer
+            .lock()
+            .await
+            .current_path()
+            .map(Path::to_path_buf)
+    }
+
+    /// Record packets until the input is stopped and drained, then flush
+    /// the current file. Ends early if the input or a write fails.
+    pub async fn run(&self) -> Result<()> {
+        while self.record_next().await?.is_some() {}
+        self.flush().await
+    }
+
+    /// Wait for the next packet and append it to the current file.
+    ///
+    /// Returns the recorded block, or `None` once the input is stopped and
+    /// drained.
+    pub async fn record_next(&self) -> Result<Option<DataBlock>> {
+        let Some(block) = self.input.recv().await? else {
+            return Ok(None);
+        };
+
+        self.writer.lock().await.write(block.payload()).await?;
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(block.payload().len() as u64, Ordering::Relaxed);
+        Ok(Some(block))
+    }
+
+    /// Write out anything still buffered for the current file.
+    pub async fn flush(&self) -> Result<()> {
+        self.writer.lock().await.flush().await
+    }
+}
+
+/// An open recording file.
+struct Segment {
+    file: File,
+    buf: Vec<u8>,
+    path: PathBuf,
+    opened: Instant,
+    bytes: u64,
+}
+
+/// Appends payloads to the current segment, rotating on the limits.
+struct SegmentWriter {
+    dir: PathBuf,
+    prefix: String,
+    max_bytes: Option<u64>,
+    max_duration: Option<Duration>,
+    segment: Option<Segment>,
+    buffers: Arc<dyn BufferSource>,
+}
+
+impl Segment {
+    /// Write out the buffered payloads.
+    async fn write_out(&mut self) -> Result<()> {
+        if !self.buf.is_empty() {
+            self.file.write_all(&self.buf).await?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+}
+
+impl SegmentWriter {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            prefix: "rist".to_string(),
+            max_bytes: None,
+            max_duration: None,
+            segment: None,
+            buffers: Arc::new(HeapBuffers),
+        }
+    }
+
+    fn current_path(&self) -> Option<&Path> {
+        self.segment.as_ref().map(|segment| segment.path.as_path())
+    }
+
+    async fn write(&mut self, payload: &[u8]) -> Result<()> {
+        if self.needs_rotation(payload.len() as u64) {
+            self.flush().await?;
+            if let Some(segment) = self.segment.take() {
+                self.buffers.release(segment.buf);
+            }
+        }
+        let segment = match &mut self.segment {
+            Some(segment) => segment,
+            None => self.segment.insert(self.open().await?),
+        };
+        if segment.buf.len() + payload.len() > segment.buf.capacity() {
+            segment.write_out().await?;
+        }
+        if payload.len() > segment.buf.capacity() {
+            segment.file.write_all(payload).await?;
+        } else {
+            segment.buf.extend_from_slice(payload);
+        }
+        segment.bytes += payload.len() as u64;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        if let Some(segment) = &mut self.segment {
+            segment.write_out().await?;
+            segment.file.flush().await?;
+        }
+        Ok(())
+    }
+
+    fn needs_rotation(&self, len: u64) -> bool {
+        let Some(segment) = &self.segment else {
+            return false;
+        };
+        // a payload larger than the limit still gets a file to itself
+        let full = self
+            .max_bytes
+            .is_some_and(|max| segment.bytes > 0 && segment.bytes + len > max);
+        let expired = self
+            .max_duration
+            .is_some_and(|max| segment.opened.elapsed() >= max);
+        full || expired
+    }
+
+    async fn open(&self) -> Result<Segment> {
+        ::tokio::fs::create_dir_all(&self.dir).await?;
+        let stamp = utc_stamp(SystemTime::now());
+        for n in 0u32.. {
+            let name = match n {
+                0 => format!("{}-{stamp}.ts", self.prefix),
+                n => format!("{}-{stamp}-{n}.ts", self.prefix),
+            };
+            let path = self.dir.join(name);
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(file) => {
+                    return Ok(Segment {
+                        file,
+                        buf: self.buffers.acquire(WRITE_BUFFER),
+                        path,
+                        opened: Instant::now(),
+                        bytes: 0,
+                    })
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        unreachable!("ran out of file name suffixes")
+    }
+}
+
+/// Format `time` as `YYYYMMDDTHHMMSSZ` in UTC.
+fn utc_stamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rist-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn recorded(dir: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut files: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, std::fs::read(&path).unwrap())
+            })
+            .collect();
+        // `-N` suffixes come after the unsuffixed name for the same second
+        files.sort_by_key(|(name, _)| {
+            let stem = name.trim_end_matches(".ts");
+            let mut parts = stem.split('-').skip(1);
+            let stamp = parts.next().unwrap_or_default().to_string();
+            let n: u32 = parts.next().map_or(0, |n| n.parse().unwrap());
+            (stamp, n)
+        });
+        files
+    }
+
+    #[test]
+    fn test_utc_stamp() {
+        assert_eq!(utc_stamp(UNIX_EPOCH), "19700101T000000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(utc_stamp(leap_day), "20240229T123456Z");
+    }
+
+    #[tokio::test]
+    async fn test_run_ends_when_input_stops() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let dir = scratch_dir("recorder-stop");
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let input = AsyncReceiver::bind(crate::Profile::Main, &url).unwrap();
+        let recorder = Recorder::new(input, dir.clone());
+
+        let run = recorder.run();
+        ::tokio::pin!(run);
+        assert!(::tokio::time::timeout(Duration::from_millis(50), &mut run)
+            .await
+            .is_err());
+
+        recorder.input().stop().unwrap();
+        ::tokio::time::timeout(Duration::from_secs(1), run)
+            .await
+            .expect("run kept going after the input stopped")
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_rotates_on_size() {
+        let dir = scratch_dir("recorder-size");
+        let mut writer = SegmentWriter::new(dir.clone());
+        writer.prefix = "cam".to_string();
+        writer.max_bytes = Some(4);
+
+        for payload in [&b"ab"[..], b"cd", b"ef", b"oversized"] {
+            writer.write(payload).await.unwrap();
+        }
+        writer.flush().await.unwrap();
+        assert!(writer.current_path().unwrap().starts_with(&dir));
+
+        let files = recorded(&dir);
+        let contents: Vec<&[u8]> = files.iter().map(|(_, data)| data.as_slice()).collect();
+        assert_eq!(contents, [&b"abcd"[..], b"ef", b"oversized"]);
+        assert!(files
+            .iter()
+            .all(|(name, _)| name.starts_with("cam-") && name.ends_with(".ts")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_buffers_from_source() {
+        let dir = scratch_dir("recorder-buffers");
+        let pool = Arc::new(BufferPool::new(WRITE_BUFFER, 4));
+        let mut writer = SegmentWriter::new(dir.clone());
+        writer.buffers = pool.clone();
+        writer.max_bytes = Some(WRITE_BUFFER as u64);
+        let buffer = |writer: &SegmentWriter| writer.segment.as_ref().unwrap().buf.as_ptr();
+
+        writer.write(b"small").await.unwrap();
+        let first = buffer(&writer);
+        // rotates, and is too large to buffer
+        let large = vec![7u8; WRITE_BUFFER + 1];
+        writer.write(&large).await.unwrap();
+        assert_eq!(buffer(&writer), first);
+        writer.write(b"tail").await.unwrap();
+        writer.flush().await.unwrap();
+        assert_eq!(pool.available(), 0);
+
+        let contents: Vec<Vec<u8>> = recorded(&dir).into_iter().map(|(_, data)| data).collect();
+        assert_eq!(contents, [b"small".to_vec(), large, b"tail".to_vec()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotates_on_duration() {
+        let dir = scratch_dir("recorder-duration");
+        let mut writer = SegmentWriter::new(dir.clone());
+        writer.max_duration = Some(Duration::from_millis(20));
+
+        writer.write(b"first").await.unwrap();
+        writer.write(b"-still").await.unwrap();
+        ::tokio::time::sleep(Duration::from_millis(30)).await;
+        writer.write(b"second").await.unwrap();
+        writer.flush().await.unwrap();
+
+        let contents: Vec<Vec<u8>> = recorded(&dir).into_iter().map(|(_, data)| data).collect();
+        assert_eq!(contents, [b"first-still".to_vec(), b"second".to_vec()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! RIST-to-RIST relaying.
+//!
+//! A [`Relay`] bridges one [`AsyncReceiver`] into one or more
+//! [`AsyncSender`]s. Each packet is forwarded with its original NTP
+//! timestamp, sequence number, flow ID and virtual ports, so a cloud hop
+//! doesn't reset timing and downstream deduplication keeps working.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::relay::Relay;
+//! use rist::tokio::{AsyncReceiver, AsyncSender};
+//! use rist::Profile;
+//!
+//! # async fn example() -> rist::Result<()> {
+//! let input = AsyncReceiver::bind(Profile::Main, "rist://@:5000")?;
+//! let output = AsyncSender::connect(Profile::Main, "rist://10.0.0.2:5000").await?;
+//!
+//! let relay = Relay::new(input).with_output(output);
+//! relay.run().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tokio::{AsyncReceiver, AsyncSender};
+use crate::{DataBlock, Error, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Forwards packets from a receiver to a set of senders, preserving metadata.
+pub struct Relay {
+    input: AsyncReceiver,
+    outputs: Vec<AsyncSender>,
+    packets: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Relay {
+    /// Create a relay reading from `input` with no outputs yet.
+    pub fn new(input: AsyncReceiver) -> Self {
+        Self {
+            input,
+            outputs: Vec::new(),
+            packets: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Add an output sender.
+    pub fn with_output(mut self, output: AsyncSender) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Add an output sender.
+    pub fn add_output(&mut self, output: AsyncSender) {
+        self.outputs.push(output);
+    }
+
+    /// The receiver packets are read from.
+    pub fn input(&self) -> &AsyncReceiver {
+        &self.input
+    }
+
+    /// The senders packets are forwarded to.
+    pub fn outputs(&self) -> &[AsyncSender] {
+        &self.outputs
+    }
+
+    /// Number of packets forwarded so far.
+    pub fn forwarded_packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    /// Number of payload bytes forwarded so far.
+    pub fn forwarded_bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Forward packets until the input is stopped and drained, the input
+    /// fails, or an output rejects a packet.
+    pub async fn run(&self) -> Result<()> {
+        while self.forward_next().await?.is_some() {}
+        Ok(())
+    }
+
+    /// Wait for the next packet and forward it to every output.
+    ///
+    /// Returns the forwarded block, or `None` once the input is stopped and
+    /// drained.
+    pub async fn forward_next(&self) -> Result<Option<DataBlock>> {
+        let Some(block) = self.input.recv().await? else {
+            return Ok(None);
+        };
+
+        self.forward(&block)?;
+        Ok(Some(block))
+    }
+
+    /// Forward an already received block to every output.
+    pub fn forward(&self, block: &DataBlock) -> Result<()> {
+        let metadata = block.metadata();
+        for output in &self.outputs {
+            match output.write_with_metadata(block.payload(), &metadata) {
+                // counted by the output, keep feeding the others
+                Ok(_) | Err(Error::BitrateCapExceeded) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(block.payload().len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockMetadata, Profile};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_run_ends_when_input_stops() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let relay = Relay::new(AsyncReceiver::bind(Profile::Main, &url).unwrap());
+
+        let run = relay.run();
+        ::tokio::pin!(run);
+        assert!(::tokio::time::timeout(Duration::from_millis(50), &mut run)
+            .await
+            .is_err());
+
+        relay.input().stop().unwrap();
+        ::tokio::time::timeout(Duration::from_secs(1), run)
+            .await
+            .expect("run kept going after the input stopped")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_relay_preserves_metadata() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let in_port = crate::next_test_port();
+        let out_port = crate::next_test_port();
+
+        let input = AsyncReceiver::bind(Profile::Main, &format!("rist://@:{in_port}")).unwrap();
+        let downstream =
+            AsyncReceiver::bind(Profile::Main, &format!("rist://@:{out_port}")).unwrap();
+        let output = AsyncSender::connect(Profile::Main, &format!("rist://127.0.0.1:{out_port}"))
+            .await
+            .unwrap();
+        let relay = Relay::new(input).with_output(output);
+
+        let source = AsyncSender::connect(Profile::Main, &format!("rist://127.0.0.1:{in_port}"))
+            .await
+            .unwrap();
+        let metadata = BlockMetadata {
+            ts_ntp: 0x1234_5678_9abc_def0,
+            ..Default::default()
+        };
+        source
+            .send_with_metadata(&[0x47u8; 1316], &metadata)
+            .await
+            .unwrap();
+
+        let relayed = ::tokio::time::timeout(Duration::from_secs(2), relay.forward_next())
+            .await
+            .expect("relay timed out")
+            .unwrap()
+            .expect("relay forwarded nothing");
+        assert_eq!(relay.forwarded_packets(), 1);
+        assert_eq!(relay.forwarded_bytes(), 1316);
+
+        let data = downstream
+            .recv_timeout(Duration::from_secs(2))
+            .await
+            .unwrap()
+            .expect("nothing reached the downstream receiver");
+        assert_eq!(data.payload(), relayed.payload());
+        assert_eq!(data.timestamp(), relayed.timestamp());
+        assert_eq!(data.seq(), relayed.seq());
+    }
+}
@@ -0,0 +1,212 @@
+//! Paced playout of recorded transport streams.
+//!
+//! A [`Replayer`] reads an MPEG-TS file, such as one written by the
+//! [`Recorder`](crate::recorder::Recorder), and feeds it to an
+//! [`AsyncSender`] at the pace it was produced at, following the stream's
+//! PCRs, or at a fixed bitrate. Use it to generate realistic test traffic or
+//! to play out a backup stream.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::replay::Replayer;
+//! use rist::tokio::AsyncSender;
+//! use rist::ts::PacingMode;
+//! use rist::Profile;
+//!
+//! # async fn example() -> rist::Result<()> {
+//! let output = AsyncSender::connect(Profile::Main, "rist://10.0.0.2:5000").await?;
+//! let replayer = Replayer::new(output, PacingMode::Pcr { pid: None }).looping(true);
+//! replayer.play("slate.ts").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::tokio::AsyncSender;
+use crate::ts::{PacingMode, Packetizer, TsPacer, PAYLOAD_SIZE};
+use crate::Result;
+use ::tokio::fs::File;
+use ::tokio::io::{AsyncRead, AsyncReadExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// TS packets read ahead of the one being sent.
+const READ_AHEAD: usize = 8192;
+
+/// Size of each read from the input.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Sends a recorded TS stream through a sender, paced like the original.
+///
+/// Packets go out seven to a payload; a payload is sent when the last
+/// packet in it is due.
+pub struct Replayer {
+    output: AsyncSender,
+    mode: PacingMode,
+    looping: bool,
+    packets: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Replayer {
+    /// Create a replayer sending to `output`, paced by `mode`.
+    pub fn new(output: AsyncSender, mode: PacingMode) -> Self {
+        Self {
+            output,
+            mode,
+            looping: false,
+            packets: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Start over from the beginning of the file when it ends, for
+    /// [`play`](Self::play).
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// The sender packets are played out through.
+    pub fn output(&self) -> &AsyncSender {
+        &self.output
+    }
+
+    /// Number of RIST payloads sent so far.
+    pub fn sent_packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    /// Number of payload bytes sent so far.
+    pub fn sent_bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Play out the TS file at `path`, over and over if looping.
+    ///
+    /// Returns once the file has been sent, or fails if it can't be read or
+    /// the sender rejects a payload.
+    pub async fn play(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut pacer = TsPacer::new(self.mode);
+        loop {
+            let file = File::open(path).await?;
+            let sent = self.play_pass(file, &mut pacer).await?;
+            // an empty file would loop without ever waiting
+            if !self.looping || sent == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Play out a TS stream read from `input` until it ends.
+    pub async fn play_from(&self, input: impl AsyncRead + Unpin) -> Result<()> {
+        self.play_pass(input, &mut TsPacer::new(self.mode)).await?;
+        Ok(())
+    }
+
+    /// Send everything in `input`, returning the number of bytes sent.
+    async fn play_pass(
+        &self,
+        mut input: impl AsyncRead + Unpin,
+        pacer: &mut TsPacer,
+    ) -> Result<u64> {
+        let mut packetizer = Packetizer::new();
+        let mut buf = vec![0u8; READ_CHUNK];
+        let mut payload = Vec::with_capacity(PAYLOAD_SIZE);
+        let mut sent = 0;
+        let mut eof = false;
+
+        loop {
+            // read on while nothing is due, the next PCR may still be ahead
+            while !eof && (pacer.len() < READ_AHEAD || pacer.next_release().is_none()) {
+                let n = input.read(&mut buf).await?;
+                let now = Instant::now();
+                if n == 0 {
+                    eof = true;
+                    if let Some(rest) = packetizer.flush() {
+                        pacer.push(rest, now);
+                    }
+                    pacer.flush(now);
+                    break;
+                }
+                packetizer.push(&buf[..n]);
+                while let Some(chunk) = packetizer.next_payload() {
+                    pacer.push(chunk, now);
+                }
+            }
+
+            while let Some(packet) = pacer.poll(Instant::now()) {
+                payload.extend_from_slice(&packet);
+                if payload.len() == PAYLOAD_SIZE {
+                    sent += self.send(&payload).await?;
+                    payload.clear();
+                }
+            }
+
+            match pacer.next_release() {
+                Some(at) => ::tokio::time::sleep_until(at.into()).await,
+                None if eof => break,
+                None => {}
+            }
+        }
+
+        if !payload.is_empty() {
+            sent += self.send(&payload).await?;
+        }
+        Ok(sent)
+    }
+
+    async fn send(&self, payload: &[u8]) -> Result<u64> {
+        self.output.send(payload).await?;
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(payload.len() as u64, Ordering::Relaxed);
+        Ok(payload.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ts::{PACKET_SIZE, SYNC_BYTE};
+    use crate::Profile;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_replays_at_constant_bitrate() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender, receiver, ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        // 21 packets at 100 packets per second take about 200 ms
+        let mut stream = Vec::new();
+        for n in 0..21u8 {
+            let mut packet = [n; PACKET_SIZE];
+            packet[0] = SYNC_BYTE;
+            stream.extend_from_slice(&packet);
+        }
+        let replayer = Replayer::new(
+            sender,
+            PacingMode::ConstantBitrate(PACKET_SIZE as u64 * 8 * 100),
+        );
+        let start = Instant::now();
+        replayer.play_from(stream.as_slice()).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert_eq!(replayer.sent_packets(), 3);
+        assert_eq!(replayer.sent_bytes(), stream.len() as u64);
+
+        let mut received = Vec::new();
+        while received.len() < stream.len() {
+            match receiver.recv_timeout(Duration::from_secs(2)).await {
+                Ok(Some(block)) => received.extend_from_slice(block.payload()),
+                _ => break,
+            }
+        }
+        assert_eq!(received, stream);
+    }
+}
@@ -1,71 +1,410 @@
-use crate::{Error, Profile, Result, SenderOptions};
-use std::ffi::CString;
+use crate::builder::SenderBuilder;
+use crate::capture::{CaptureSlot, CaptureTap, Direction};
+use crate::connection::{register_connection, ConnectionSlot};
+use crate::health::Activity;
+use crate::logging::LoggingSettings;
+#[cfg(feature = "serde")]
+use crate::oob::{Command, CommandRequest, Message};
+use crate::oob::{Heartbeat, OobSlot, RttProbe};
+use crate::options::recovery_bitrate;
+use crate::pacing::{BitrateCap, Pacer};
+use crate::peer::{add_peer, destroy_peers, linger_time, replace_peer, set_recovery_bitrate, Peer};
+use crate::stats::{
+    register_stats, SenderStats, StatsMode, StatsSlot, StatsSnapshot, DEFAULT_STATS_INTERVAL,
+};
+use crate::{
+    BlockMetadata, ConnectionState, Error, Health, PeerConfig, PeerId, PeerInfo, PeerStats,
+    Profile, Result, SenderOptions,
+};
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
 use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 /// RIST sender for sending data streams.
 pub struct Sender {
     ctx: *mut rist_sys::rist_ctx,
+    profile: Profile,
     started: bool,
+    stopped: bool,
+    peers: Vec<Peer>,
+    connection: Arc<ConnectionSlot>,
+    oob: Arc<OobSlot>,
+    stats: Arc<StatsSlot<SenderStats>>,
+    pacer: Pacer,
+    cap: BitrateCap,
+    activity: Activity,
+    capture: CaptureSlot,
+    rejected: AtomicU64,
+    // librist references the logging settings until the context is destroyed
+    _logging: Option<LoggingSettings>,
 }
 
 impl Sender {
     /// Create a new RIST sender with the specified profile.
     pub fn new(profile: Profile) -> Result<Self> {
+        Self::create(profile, 0, None)
+    }
+
+    /// Create a sender whose packets carry the flow ID `flow_id`.
+    ///
+    /// Several senders feeding one receiver can each be given a stable
+    /// identity this way, e.g. one per camera, instead of the random ID
+    /// librist picks otherwise. The ID must be even, as the RIST spec
+    /// reserves the lowest bit; 0 lets librist pick.
+    pub fn with_flow_id(profile: Profile, flow_id: u32) -> Result<Self> {
+        Self::create(profile, flow_id, None)
+    }
+
+    /// Create a sender builder.
+    pub fn builder() -> SenderBuilder {
+        SenderBuilder::new()
+    }
+
+    pub(crate) fn create(
+        profile: Profile,
+        flow_id: u32,
+        logging: Option<LoggingSettings>,
+    ) -> Result<Self> {
+        check_flow_id(flow_id)?;
         let mut ctx: *mut rist_sys::rist_ctx = ptr::null_mut();
+        let logging_ptr = logging
+            .as_ref()
+            .map_or(ptr::null_mut(), LoggingSettings::as_ptr);
 
-        let ret =
-            unsafe { rist_sys::rist_sender_create(&mut ctx, profile.to_raw(), 0, ptr::null_mut()) };
+        let ret = unsafe {
+            rist_sys::rist_sender_create(&mut ctx, profile.to_raw(), flow_id, logging_ptr)
+        };
 
         if ret != 0 || ctx.is_null() {
             return Err(Error::ContextCreation);
         }
 
-        Ok(Self {
+        Self::wrap(ctx, profile, logging)
+    }
+
+    /// Take ownership of a sender context created with `rist_sender_create`.
+    ///
+    /// Use this together with [`as_raw`](Self::as_raw) to configure the
+    /// context with `rist-sys` functions this crate doesn't cover yet. The
+    /// stats and connection callbacks are replaced, and the context is
+    /// destroyed when the sender is dropped, or straight away on error.
+    ///
+    /// # Safety
+    ///
+    /// `ctx` must be a valid sender context created with `profile` that has
+    /// not been started and is not owned or destroyed by anything else. Peers created on it
+    /// directly are not listed by [`peers`](Self::peers).
+    pub unsafe fn from_raw(ctx: *mut rist_sys::rist_ctx, profile: Profile) -> Result<Self> {
+        if ctx.is_null() {
+            return Err(Error::ContextCreation);
+        }
+
+        Self::wrap(ctx, profile, None)
+    }
+
+    fn wrap(
+        ctx: *mut rist_sys::rist_ctx,
+        profile: Profile,
+        logging: Option<LoggingSettings>,
+    ) -> Result<Self> {
+        let sender = Self {
             ctx,
+            profile,
             started: false,
-        })
+            stopped: false,
+            peers: Vec::new(),
+            connection: Arc::new(ConnectionSlot::new()),
+            oob: Arc::new(OobSlot::new(ctx, profile)),
+            stats: Arc::new(StatsSlot::new()),
+            pacer: Pacer::default(),
+            cap: BitrateCap::default(),
+            activity: Activity::default(),
+            capture: CaptureSlot::default(),
+            rejected: AtomicU64::new(0),
+            _logging: logging,
+        };
+        sender.set_stats_interval(DEFAULT_STATS_INTERVAL)?;
+        register_connection(ctx, &sender.connection)?;
+        sender.oob.register_early()?;
+
+        Ok(sender)
     }
 
     /// Add a peer by URL (e.g., "rist://192.168.1.1:5000").
-    pub fn add_peer(&mut self, url: &str) -> Result<()> {
+    pub fn add_peer(&mut self, url: &str) -> Result<PeerId> {
         self.add_peer_with_options(url, &SenderOptions::default())
     }
 
     /// Add a peer by URL with custom sender options.
-    pub fn add_peer_with_options(&mut self, url: &str, options: &SenderOptions) -> Result<()> {
-        let url_c = CString::new(url)?;
-        let mut peer_config: *mut rist_sys::rist_peer_config = ptr::null_mut();
+    pub fn add_peer_with_options(&mut self, url: &str, options: &SenderOptions) -> Result<PeerId> {
+        if self.stopped {
+            return Err(Error::Stopped);
+        }
 
-        let ret = unsafe { rist_sys::rist_parse_address2(url_c.as_ptr(), &mut peer_config) };
+        let id = add_peer(
+            self.ctx,
+            self.profile,
+            &mut self.peers,
+            &self.connection,
+            url,
+            options.configurer()?,
+        )?;
+        options.apply_to_pacer(&self.pacer);
 
-        if ret != 0 || peer_config.is_null() {
-            return Err(Error::UrlParse(url.to_string()));
+        Ok(id)
+    }
+
+    /// Add a peer with every librist setting given by `config`.
+    ///
+    /// Unlike [`add_peer_with_options`](Self::add_peer_with_options) the
+    /// settings in `config` are used as they are, including those the options
+    /// don't cover. Fails if `config` is invalid, see
+    /// [`PeerConfig::validate`](crate::PeerConfig::validate).
+    pub fn add_peer_config(&mut self, config: &PeerConfig) -> Result<PeerId> {
+        if self.stopped {
+            return Err(Error::Stopped);
         }
 
-        unsafe {
-            options.apply_to_peer_config(&mut *peer_config);
+        add_peer(
+            self.ctx,
+            self.profile,
+            &mut self.peers,
+            &self.connection,
+            &config.address,
+            config.configurer()?,
+        )
+    }
+
+    /// Reconnect a peer with new recovery and bitrate settings.
+    ///
+    /// librist fixes a peer's settings when it is created, so the peer is
+    /// destroyed and created again from its URL with `options`. This resets
+    /// its RIST session: a calling peer reconnects and starts over with an
+    /// empty recovery buffer, and a listening peer drops every client
+    /// connected to it, which have to connect again. The context and its
+    /// other peers keep running. The replacement's ID is returned.
+    ///
+    /// A calling peer's replacement is created before it is destroyed, so
+    /// if that fails the peer is left as it was. A listening peer has to
+    /// release its port first, so if that fails it is re-created with its
+    /// old settings, and may get the same ID back either way.
+    pub fn reconnect_peer(&mut self, peer: PeerId, options: &SenderOptions) -> Result<PeerId> {
+        if self.stopped {
+            return Err(Error::Stopped);
         }
 
-        let mut peer: *mut rist_sys::rist_peer = ptr::null_mut();
-        let ret = unsafe { rist_sys::rist_peer_create(self.ctx, &mut peer, peer_config) };
+        let id = replace_peer(
+            self.ctx,
+            &mut self.peers,
+            &self.connection,
+            peer,
+            Some(options.configurer()?),
+        )?;
+        options.apply_to_pacer(&self.pacer);
+        Ok(id)
+    }
 
-        unsafe {
-            rist_sys::rist_peer_config_free2(&mut peer_config);
+    /// Limit the bandwidth librist may use for each peer to `bandwidth` bits
+    /// per second, reconnecting every peer whose limit changes.
+    ///
+    /// This sets every live peer's
+    /// [`recovery_maxbitrate`](SenderOptions::recovery_maxbitrate), so
+    /// retransmissions back off first, and if sends are paced with
+    /// [`pace_bitrate`](SenderOptions::pace_bitrate) they are paced at
+    /// `bandwidth` from now on. Peers added later use their own options.
+    ///
+    /// librist fixes a peer's settings when it is created, so a peer whose
+    /// bitrate changes is reconnected like with
+    /// [`reconnect_peer`](Self::reconnect_peer): its session is reset, a
+    /// listening peer drops its clients, and it gets a new ID. Peers already
+    /// at the bitrate are left alone, so an ABR controller calling this
+    /// repeatedly should only call it when its decision changes, in steps
+    /// of at least 1 kbps. The IDs of all peers are returned in the order
+    /// of [`peers`](Self::peers). If a peer can't be re-created, the ones
+    /// changed before it are reconnected with their old limit.
+    ///
+    /// Fails if `bandwidth` is below 1000 bps.
+    pub fn set_max_bandwidth(&mut self, bandwidth: u64) -> Result<Vec<PeerId>> {
+        if self.stopped {
+            return Err(Error::Stopped);
         }
 
+        let ids = set_recovery_bitrate(
+            self.ctx,
+            &mut self.peers,
+            &self.connection,
+            recovery_bitrate(bandwidth)?,
+        )?;
+        self.pacer.retune(bandwidth);
+        Ok(ids)
+    }
+
+    /// Start an application heartbeat over OOB, pinging the far end every
+    /// `interval` and answering its pings.
+    ///
+    /// Gives an end-to-end RTT and "far end alive" status independent of
+    /// RTCP, see [`oob`](crate::oob). Fails on the Simple profile, which has
+    /// no OOB channel. Calling it again only changes the interval.
+    pub fn start_heartbeat(&self, interval: Duration) -> Result<Heartbeat> {
+        self.oob.start_heartbeat(&self.connection, interval)
+    }
+
+    /// Measure the application-level round trip to every connected peer by
+    /// sending `count` OOB pings, 20 ms apart, and waiting up to `timeout`
+    /// after the last one for the answers.
+    ///
+    /// Unlike the RTCP RTT in the stats this works before any media is
+    /// sent, so a link can be qualified before going live. The far end
+    /// answers like heartbeat pings, see [`oob`](crate::oob). Fails with
+    /// [`Error::Send`] when no peer is connected, and on the Simple profile.
+    pub fn rtt_probe(&self, count: u32, timeout: Duration) -> Result<RttProbe> {
+        self.oob.rtt_probe(&self.connection, count, timeout)
+    }
+
+    /// Send a typed message over OOB to every connected peer, returning how
+    /// many it went to.
+    ///
+    /// The message is encoded as JSON and must fit one OOB packet. See
+    /// [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub fn send_message<T: Serialize>(&self, message: &T) -> Result<usize> {
+        self.oob.send_message(&self.connection, message)
+    }
+
+    /// Send a typed message over OOB to `peer` only.
+    #[cfg(feature = "serde")]
+    pub fn send_message_to<T: Serialize>(&self, peer: PeerId, message: &T) -> Result<()> {
+        self.oob.send_message_to(peer, message)
+    }
+
+    /// Receive the next typed message sent over OOB, waiting up to `timeout`
+    /// for one.
+    ///
+    /// Returns `Ok(None)` on timeout, or [`Error::InvalidMessage`] if the
+    /// message isn't a `T`. Messages that arrived before the call are kept,
+    /// up to 64.
+    #[cfg(feature = "serde")]
+    pub fn recv_message<T: DeserializeOwned>(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<Message<T>>> {
+        self.oob.recv_message(timeout)
+    }
+
+    /// Send a remote control command over OOB to every connected peer, and
+    /// wait up to `timeout` for the far end to acknowledge it.
+    ///
+    /// Fails with [`Error::CommandRejected`] if the far end refused it, or
+    /// [`Error::CommandTimeout`] without an answer. See [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub fn send_command(&self, command: Command, timeout: Duration) -> Result<()> {
+        self.oob.send_command(&self.connection, command, timeout)
+    }
+
+    /// Receive the next remote control command, waiting up to `timeout` for
+    /// one.
+    ///
+    /// Answer it with [`ack_command`](Self::ack_command). Commands that
+    /// arrived before the call are kept, up to 64.
+    #[cfg(feature = "serde")]
+    pub fn recv_command(&self, timeout: Duration) -> Result<Option<CommandRequest>> {
+        self.oob.recv_command(timeout)
+    }
+
+    /// Acknowledge a command from [`recv_command`](Self::recv_command), with
+    /// the reason if it was refused.
+    #[cfg(feature = "serde")]
+    pub fn ack_command(
+        &self,
+        request: &CommandRequest,
+        result: std::result::Result<(), String>,
+    ) -> Result<()> {
+        self.oob.ack_command(request, result)
+    }
+
+    /// The underlying librist context, for calling `rist-sys` functions
+    /// directly.
+    ///
+    /// The pointer stays owned by this sender and is only valid until it is
+    /// dropped. Don't destroy it or replace its callbacks.
+    pub fn as_raw(&self) -> *mut rist_sys::rist_ctx {
+        self.ctx
+    }
+
+    /// The flow ID this sender's packets carry.
+    ///
+    /// librist picks a random one unless one was given at creation.
+    pub fn flow_id(&self) -> Result<u32> {
+        let mut flow_id = 0;
+        let ret = unsafe { rist_sys::rist_sender_flow_id_get(self.ctx, &mut flow_id) };
         if ret != 0 {
-            return Err(Error::PeerCreation(url.to_string()));
+            return Err(Error::Configuration(
+                "failed to get sender flow ID".to_string(),
+            ));
         }
+        Ok(flow_id)
+    }
 
-        Ok(())
+    /// Set how often librist reports stats.
+    pub fn set_stats_interval(&self, interval: Duration) -> Result<()> {
+        register_stats(self.ctx, interval, &self.stats)
+    }
+
+    /// Call `callback` with every new stats snapshot.
+    ///
+    /// The callback runs on a librist thread and must not block.
+    pub fn on_stats(&self, callback: impl FnMut(&SenderStats) + Send + 'static) {
+        self.stats.set_callback(Some(Box::new(callback)));
+    }
+
+    /// Returns the latest stats for this sender.
+    ///
+    /// Stats are updated periodically (every 1 second by default).
+    /// Returns `None` if no stats have been collected yet.
+    pub fn raw_stats(&self) -> Option<SenderStats> {
+        self.stats.latest()
+    }
+
+    /// Returns the latest stats for this sender with the interval they cover,
+    /// for bitrate gauges.
+    ///
+    /// Returns `None` if no stats have been collected yet.
+    pub fn stats_snapshot(&self) -> Option<StatsSnapshot<SenderStats>> {
+        self.stats.latest_snapshot()
+    }
+
+    /// Forget the stats collected so far, to start a fresh measurement
+    /// window, e.g. after maintenance.
+    ///
+    /// librist's counters are cumulative and keep running; from now on they
+    /// are reported as the increase since this call. Gauges such as
+    /// quality, RTT and bandwidth are unaffected. Until librist's next
+    /// report, [`raw_stats`](Self::raw_stats) returns `None`.
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// Report counters as running totals or as the increase since the
+    /// previous report, see [`StatsMode`]. Applies to
+    /// [`raw_stats`](Self::raw_stats), snapshots and the stats callback.
+    pub fn set_stats_mode(&self, mode: StatsMode) {
+        self.stats.set_mode(mode);
     }
 
     /// Start the sender.
     pub fn start(&mut self) -> Result<()> {
+        if self.stopped {
+            return Err(Error::Stopped);
+        }
         if self.started {
             return Err(Error::AlreadyStarted);
         }
 
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("rist_start", role = "sender").entered();
         let ret = unsafe { rist_sys::rist_start(self.ctx) };
 
         if ret != 0 {
@@ -80,63 +419,331 @@ impl Sender {
     ///
     /// Returns the number of bytes written on success.
     pub fn send(&self, data: &[u8]) -> Result<usize> {
+        self.send_with_metadata(data, &BlockMetadata::default())
+    }
+
+    /// Send data stamped with the current wall-clock time, so receivers see
+    /// when it originated.
+    pub fn send_timestamped(&self, data: &[u8]) -> Result<usize> {
+        self.send_with_metadata(data, &BlockMetadata::now())
+    }
+
+    /// Send data with a specific flow ID.
+    pub fn send_with_flow_id(&self, data: &[u8], flow_id: u32) -> Result<usize> {
+        let metadata = BlockMetadata {
+            flow_id,
+            ..Default::default()
+        };
+        self.send_with_metadata(data, &metadata)
+    }
+
+    /// Send data with explicit packet metadata.
+    ///
+    /// Use this to forward a received block with its original timestamp,
+    /// sequence number, flow ID and virtual ports.
+    pub fn send_with_metadata(&self, data: &[u8], metadata: &BlockMetadata) -> Result<usize> {
         if !self.started {
             return Err(Error::NotStarted);
         }
+        if self.stopped {
+            return Err(Error::Stopped);
+        }
 
-        let block = rist_sys::rist_data_block {
-            payload: data.as_ptr() as *const _,
-            payload_len: data.len(),
-            ts_ntp: 0,
-            flow_id: 0,
-            flags: 0,
-            seq: 0,
-            virt_src_port: 0,
-            virt_dst_port: 0,
-            peer: ptr::null_mut(),
-            ref_: ptr::null_mut(),
-        };
-
-        let ret = unsafe { rist_sys::rist_sender_data_write(self.ctx, &block) };
+        self.write_paced(data, metadata)
+    }
 
-        if ret < 0 {
-            return Err(Error::Send);
+    /// Send data only if it can be queued right away.
+    ///
+    /// Fails with [`Error::QueueFull`] instead of waiting when
+    /// [`pace_bitrate`](SenderOptions::pace_bitrate) has no room for it yet,
+    /// or when librist's send queue refuses it, e.g. because the network
+    /// can't keep up. Rejected packets are counted in
+    /// [`rejected_sends`](Self::rejected_sends).
+    pub fn try_send(&self, data: &[u8]) -> Result<usize> {
+        if !self.started {
+            return Err(Error::NotStarted);
+        }
+        if self.stopped {
+            return Err(Error::Stopped);
         }
 
-        Ok(ret as usize)
+        check_payload(data)?;
+        if !self.pacer.try_take(data.len()) {
+            return Err(queue_full(&self.rejected));
+        }
+        let metadata = BlockMetadata::default();
+        // a packet that isn't sent doesn't use up pacing budget
+        let written = self
+            .admit(data.len())
+            .and_then(|()| try_write_block(self.ctx, &self.rejected, data, &metadata))
+            .inspect_err(|_| self.pacer.refund(data.len()))?;
+        self.activity.touch();
+        self.capture.record(Direction::Sent, data, &metadata);
+        Ok(written)
     }
 
-    /// Send data with a specific flow ID.
-    pub fn send_with_flow_id(&self, data: &[u8], flow_id: u32) -> Result<usize> {
+    /// Send data to `peer`, failing rather than sending it anywhere else.
+    ///
+    /// librist writes every block to all of a context's peers, so this
+    /// fails with [`Error::Configuration`] unless `peer` is the sender's
+    /// only peer, and with [`Error::UnknownPeer`] if it isn't a live peer.
+    /// A listening peer still sends to every client connected to it. To
+    /// serve peers different content, give each its own sender.
+    pub fn send_to(&self, peer: PeerId, data: &[u8]) -> Result<usize> {
         if !self.started {
             return Err(Error::NotStarted);
         }
+        if self.stopped {
+            return Err(Error::Stopped);
+        }
 
-        let block = rist_sys::rist_data_block {
-            payload: data.as_ptr() as *const _,
-            payload_len: data.len(),
-            ts_ntp: 0,
-            flow_id,
-            flags: 0,
-            seq: 0,
-            virt_src_port: 0,
-            virt_dst_port: 0,
-            peer: ptr::null_mut(),
-            ref_: ptr::null_mut(),
-        };
+        check_sole_peer(&self.peers, peer)?;
+        self.write_paced(data, &BlockMetadata::default())
+    }
 
-        let ret = unsafe { rist_sys::rist_sender_data_write(self.ctx, &block) };
+    /// Write a block once it fits under the bitrate cap and
+    /// [`SenderOptions::pace_bitrate`].
+    ///
+    /// A block dropped by the cap doesn't wait for or use up pacing budget,
+    /// and one that fails to write gives its budget back.
+    fn write_paced(&self, data: &[u8], metadata: &BlockMetadata) -> Result<usize> {
+        self.admit(data.len())?;
+        let delay = self.pacer.delay(data.len());
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+        let written =
+            write_block(self.ctx, data, metadata).inspect_err(|_| self.pacer.refund(data.len()))?;
+        self.activity.touch();
+        self.capture.record(Direction::Sent, data, metadata);
+        Ok(written)
+    }
 
-        if ret < 0 {
-            return Err(Error::Send);
+    /// Drop `len` bytes with [`Error::BitrateCapExceeded`] if they don't fit
+    /// under the bitrate cap.
+    fn admit(&self, len: usize) -> Result<()> {
+        if self.cap.admit(len, || max_retry_bandwidth(&self.stats)) {
+            Ok(())
+        } else {
+            Err(Error::BitrateCapExceeded)
         }
+    }
+
+    /// Cap the total output bitrate, media plus retransmissions, in bits
+    /// per second. `None` removes the cap.
+    ///
+    /// Retransmissions are sent by librist, so the cap is kept by dropping
+    /// media: sends that don't fit in what the busiest peer's retransmissions
+    /// leave over fail with [`Error::BitrateCapExceeded`] and are counted in
+    /// [`bitrate_cap_drops`](Self::bitrate_cap_drops). The retransmission
+    /// bitrate comes from the stats reports, so it lags by up to one stats
+    /// interval. Can be changed at any time.
+    pub fn set_bitrate_cap(&self, bitrate: Option<u64>) {
+        self.cap.set(bitrate);
+    }
+
+    /// The current output bitrate cap, if any.
+    pub fn bitrate_cap(&self) -> Option<u64> {
+        self.cap.get()
+    }
+
+    /// Number of sends dropped by the output bitrate cap.
+    pub fn bitrate_cap_drops(&self) -> u64 {
+        self.cap.dropped()
+    }
+
+    /// Packets rejected by [`try_send`](Self::try_send) so far.
+    pub fn rejected_sends(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Stop sending without destroying the context.
+    ///
+    /// New sends are rejected immediately. The peers are kept alive for their
+    /// recovery buffer length so retransmission requests for data already
+    /// sent can still be served, then removed. The last stats remain
+    /// available. Blocks for up to the largest recovery buffer.
+    pub fn stop(&mut self) -> Result<()> {
+        if !self.started {
+            return Err(Error::NotStarted);
+        }
+        if self.stopped {
+            return Ok(());
+        }
+
+        self.stopped = true;
+        std::thread::sleep(linger_time(&self.peers));
+        destroy_peers(self.ctx, &mut self.peers);
+        self.connection.clear();
+        Ok(())
+    }
+
+    /// Returns `true` if any peer has completed its handshake and not timed out since.
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_connected()
+    }
+
+    /// List the peers of this sender with their connection state and what
+    /// is known about the far end.
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        match_stats(
+            self.connection.peers(&self.peers, self.profile),
+            &self.stats,
+        )
+    }
+
+    /// Latest stats for every peer, ordered by librist's peer ID.
+    ///
+    /// librist reports stats under its own peer ID rather than [`PeerId`],
+    /// so they are only tied to a peer in [`PeerInfo::stats`] on a
+    /// point-to-point link. Otherwise match them up by
+    /// [`SenderStats::cname`] if needed.
+    pub fn peer_stats(&self) -> Vec<SenderStats> {
+        self.stats.all()
+    }
+
+    /// Connection state of `peer`, or `None` if it isn't a live peer of this sender.
+    pub fn peer_state(&self, peer: PeerId) -> Option<ConnectionState> {
+        self.connection.state(peer)
+    }
+
+    /// Returns `true` once [`stop`](Self::stop) has been called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Capture every payload sent from now on into `tap`, or stop with
+    /// `None`.
+    pub fn set_capture(&self, tap: Option<CaptureTap>) {
+        self.capture.set(tap);
+    }
+
+    /// Report how the sender is doing, for liveness and readiness probes.
+    pub fn health(&self) -> Health {
+        Health::new(
+            self.started && !self.stopped,
+            &self.peers(),
+            self.activity.age(),
+        )
+        .with_quality(self.stats.latest().map(|stats| stats.quality))
+    }
+}
+
+/// Reject flow IDs with the lowest bit set, which RIST reserves.
+pub(crate) fn check_flow_id(flow_id: u32) -> Result<()> {
+    if !flow_id.is_multiple_of(2) {
+        return Err(Error::Configuration(format!(
+            "flow ID must be even, got {flow_id}"
+        )));
+    }
+    Ok(())
+}
 
-        Ok(ret as usize)
+/// Highest retransmission bitrate reported for any one peer.
+pub(crate) fn max_retry_bandwidth(stats: &StatsSlot<SenderStats>) -> u64 {
+    stats
+        .all()
+        .iter()
+        .map(|peer| peer.retry_bandwidth as u64)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Fill in the stats and far end's cname on a point-to-point link.
+///
+/// librist keys sender stats by its own peer ID, so they can only be
+/// matched to a peer when there is one report and one connected peer.
+pub(crate) fn match_stats(
+    mut peers: Vec<PeerInfo>,
+    stats: &StatsSlot<SenderStats>,
+) -> Vec<PeerInfo> {
+    let stats = stats.all();
+    let mut connected = peers
+        .iter_mut()
+        .filter(|peer| peer.state == ConnectionState::Connected);
+    if let ([stats], Some(peer), None) = (stats.as_slice(), connected.next(), connected.next()) {
+        peer.remote_cname = Some(stats.cname.clone()).filter(|cname| !cname.is_empty());
+        peer.stats = Some(PeerStats::Sender(stats.clone()));
+    }
+    peers
+}
+
+/// Check that a block written to the context would only reach `peer`.
+pub(crate) fn check_sole_peer(peers: &[Peer], peer: PeerId) -> Result<()> {
+    if !peers.iter().any(|p| p.id() == peer) {
+        return Err(Error::UnknownPeer);
+    }
+    match peers.len() {
+        1 => Ok(()),
+        n => Err(Error::Configuration(format!(
+            "librist sends to every peer, and this sender has {n}"
+        ))),
     }
 }
 
+/// Reject payloads librist would refuse whatever its queue holds.
+pub(crate) fn check_payload(data: &[u8]) -> Result<()> {
+    if data.is_empty() || data.len() > rist_sys::RIST_MAX_PACKET_SIZE as usize {
+        return Err(Error::Send);
+    }
+    Ok(())
+}
+
+/// Count a packet rejected by `try_send`.
+pub(crate) fn queue_full(rejected: &AtomicU64) -> Error {
+    rejected.fetch_add(1, Ordering::Relaxed);
+    Error::QueueFull
+}
+
+/// Write a block passed by [`check_payload`], treating a refused write as
+/// a full queue.
+pub(crate) fn try_write_block(
+    ctx: *mut rist_sys::rist_ctx,
+    rejected: &AtomicU64,
+    data: &[u8],
+    metadata: &BlockMetadata,
+) -> Result<usize> {
+    // with the payload checked, librist only refuses a write when its
+    // queue is full
+    write_block(ctx, data, metadata).map_err(|err| match err {
+        Error::Send => queue_full(rejected),
+        err => err,
+    })
+}
+
+/// Write a single block to every peer of a started sender context.
+pub(crate) fn write_block(
+    ctx: *mut rist_sys::rist_ctx,
+    data: &[u8],
+    metadata: &BlockMetadata,
+) -> Result<usize> {
+    let marked = metadata
+        .flags
+        .discontinuity
+        .then(|| crate::ts::mark_discontinuity(data))
+        .flatten();
+    let data = marked.as_deref().unwrap_or(data);
+    let block = metadata.to_raw_block(data);
+
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!(
+        "rist_sender_data_write",
+        bytes = data.len(),
+        flow_id = metadata.flow_id,
+    )
+    .entered();
+    let ret = unsafe { rist_sys::rist_sender_data_write(ctx, &block) };
+
+    if ret < 0 {
+        return Err(Error::Send);
+    }
+
+    Ok(ret as usize)
+}
+
 impl Drop for Sender {
     fn drop(&mut self) {
+        self.oob.shutdown();
         unsafe {
             rist_sys::rist_destroy(self.ctx);
         }
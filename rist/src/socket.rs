@@ -0,0 +1,541 @@
+//! Socket-level settings librist doesn't expose.
+//!
+//! librist opens a peer's UDP sockets inside `rist_peer_create` and never
+//! hands them out, so they are found by comparing the process's UDP sockets
+//! before and after the peer is created. Of the new ones, only sockets bound
+//! (listening peers) or connected (calling peers) to the address in the
+//! peer's URL are taken, so a socket opened elsewhere in the process at the
+//! same moment is left alone. A socket is checked again before it is used
+//! later, in case it was closed and its descriptor reused.
+
+use crate::{Error, Result};
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+
+/// A socket file descriptor.
+#[cfg(unix)]
+pub(crate) type RawSocket = std::os::fd::RawFd;
+#[cfg(not(unix))]
+pub(crate) type RawSocket = i32;
+
+/// Serialises [`track_new_sockets`] so peers don't claim each other's sockets.
+static TRACKING: Mutex<()> = Mutex::new(());
+
+/// Socket settings applied to every socket of a peer once it is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SocketOptions {
+    /// DSCP code point, written to the upper six bits of the TOS / traffic
+    /// class byte.
+    pub(crate) dscp: Option<u8>,
+    /// `SO_RCVBUF`, in bytes.
+    pub(crate) recv_buffer: Option<usize>,
+    /// `SO_SNDBUF`, in bytes.
+    pub(crate) send_buffer: Option<usize>,
+    /// Unicast TTL / hop limit.
+    pub(crate) ttl: Option<u8>,
+    /// Whether an IPv6 socket must be IPv6-only (`true`) or dual-stack.
+    pub(crate) check_v6_only: Option<bool>,
+}
+
+/// Socket buffer sizes of a peer, as reported by the OS.
+///
+/// These can differ from what was asked for: Linux doubles the requested
+/// size to make room for bookkeeping and caps it at `net.core.rmem_max` /
+/// `net.core.wmem_max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketBuffers {
+    /// Receive buffer size in bytes.
+    pub recv: usize,
+    /// Send buffer size in bytes.
+    pub send: usize,
+}
+
+impl SocketOptions {
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Reject values the kernel would silently truncate.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(dscp) = self.dscp {
+            if dscp > 63 {
+                return Err(Error::Configuration(format!(
+                    "DSCP must be between 0 and 63, got {dscp}"
+                )));
+            }
+        }
+        if self.ttl == Some(0) {
+            return Err(Error::Configuration(
+                "TTL must be between 1 and 255".to_string(),
+            ));
+        }
+        for (name, size) in [("receive", self.recv_buffer), ("send", self.send_buffer)] {
+            if matches!(size, Some(size) if size == 0 || size > i32::MAX as usize) {
+                return Err(Error::Configuration(format!(
+                    "socket {name} buffer size must be between 1 and {} bytes",
+                    i32::MAX
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The address a peer's sockets are bound to, if it listens, or connected
+/// to, if it calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PeerAddress {
+    listen: bool,
+    /// `None` for a host name, or a wildcard listen address.
+    ip: Option<IpAddr>,
+    port: u16,
+}
+
+impl PeerAddress {
+    /// Read the address from a peer URL such as `rist://@[::]:5000?...`.
+    pub(crate) fn from_url(url: &str) -> Option<Self> {
+        let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let (listen, rest) = match rest.strip_prefix('@') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let authority = rest.split(['/', '?']).next()?;
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(v6) => {
+                let (host, port) = v6.split_once("]:")?;
+                // librist takes a zone after the address
+                (host.split('%').next()?, port)
+            }
+            None => authority.rsplit_once(':')?,
+        };
+        let ip = host
+            .parse::<IpAddr>()
+            .ok()
+            .filter(|ip| !ip.is_unspecified());
+        Some(Self {
+            listen,
+            ip,
+            port: port.parse().ok()?,
+        })
+    }
+
+    /// Whether the peer listens, rather than calls.
+    pub(crate) fn listens(&self) -> bool {
+        self.listen
+    }
+
+    /// Whether a socket bound to `local` and connected to `remote` belongs
+    /// to the peer. The Simple profile puts RTCP on the next port up.
+    fn matches(&self, local: SocketAddr, remote: Option<SocketAddr>) -> bool {
+        let addr = if self.listen { Some(local) } else { remote };
+        let Some(addr) = addr else {
+            return false;
+        };
+        let port = addr.port() == self.port || Some(addr.port()) == self.port.checked_add(1);
+        let ip = match self.ip {
+            Some(ip) => addr.ip() == ip || (self.listen && addr.ip().is_unspecified()),
+            None => true,
+        };
+        port && ip
+    }
+}
+
+/// One of a peer's sockets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PeerSocket {
+    fd: RawSocket,
+    /// Where the socket was bound when it was found.
+    local: SocketAddr,
+}
+
+impl PeerSocket {
+    /// The socket's descriptor, `None` if it no longer refers to the socket
+    /// that was found, e.g. because librist closed it and the number was
+    /// reused.
+    pub(crate) fn fd(&self) -> Option<RawSocket> {
+        (local_addr(self.fd) == Some(self.local)).then_some(self.fd)
+    }
+}
+
+/// Run `create` and return its result with the UDP sockets it opened for
+/// the peer at `address`.
+pub(crate) fn track_new_sockets<T>(
+    address: PeerAddress,
+    create: impl FnOnce() -> T,
+) -> (T, Vec<PeerSocket>) {
+    let _guard = TRACKING.lock().unwrap_or_else(|e| e.into_inner());
+    let before = udp_sockets();
+    let result = create();
+    let sockets = udp_sockets()
+        .difference(&before)
+        .filter_map(|&fd| {
+            let local = local_addr(fd)?;
+            address
+                .matches(local, remote_addr(fd))
+                .then_some(PeerSocket { fd, local })
+        })
+        .collect();
+    (result, sockets)
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::{BTreeSet, RawSocket as RawFd, SocketBuffers, SocketOptions};
+    use crate::{Error, Result};
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+    /// The process's UDP sockets.
+    pub(crate) fn udp_sockets() -> BTreeSet<RawFd> {
+        let Ok(entries) = std::fs::read_dir("/dev/fd") else {
+            return BTreeSet::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .filter(|&fd| is_udp(fd))
+            .collect()
+    }
+
+    fn is_udp(fd: RawFd) -> bool {
+        matches!(
+            get_int(fd, libc::SOL_SOCKET, libc::SO_TYPE),
+            Ok(libc::SOCK_DGRAM)
+        )
+    }
+
+    fn family(fd: RawFd) -> io::Result<libc::c_int> {
+        let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        let ret = unsafe { libc::getsockname(fd, &mut addr as *mut _ as *mut _, &mut len) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(addr.ss_family as libc::c_int)
+    }
+
+    /// The address a UDP socket is bound to.
+    pub(crate) fn local_addr(fd: RawFd) -> Option<SocketAddr> {
+        if !is_udp(fd) {
+            return None;
+        }
+        sock_addr(|addr, len| unsafe { libc::getsockname(fd, addr, len) })
+    }
+
+    /// The address a UDP socket is connected to.
+    pub(crate) fn remote_addr(fd: RawFd) -> Option<SocketAddr> {
+        sock_addr(|addr, len| unsafe { libc::getpeername(fd, addr, len) })
+    }
+
+    fn sock_addr(
+        get: impl FnOnce(*mut libc::sockaddr, *mut libc::socklen_t) -> libc::c_int,
+    ) -> Option<SocketAddr> {
+        let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        if get(&mut addr as *mut _ as *mut _, &mut len) != 0 {
+            return None;
+        }
+        match addr.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { &*(&addr as *const _ as *const libc::sockaddr_in) };
+                Some(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                    u16::from_be(addr.sin_port),
+                )))
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(&addr as *const _ as *const libc::sockaddr_in6) };
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                    u16::from_be(addr.sin6_port),
+                    addr.sin6_flowinfo,
+                    addr.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_int(
+        fd: RawFd,
+        level: libc::c_int,
+        name: libc::c_int,
+    ) -> io::Result<libc::c_int> {
+        let mut value: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret =
+            unsafe { libc::getsockopt(fd, level, name, &mut value as *mut _ as *mut _, &mut len) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(value)
+    }
+
+    fn set_int(
+        fd: RawFd,
+        level: libc::c_int,
+        name: libc::c_int,
+        value: libc::c_int,
+    ) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                &value as *const _ as *const _,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Apply `options` to one of a peer's sockets.
+    pub(crate) fn apply(fd: RawFd, options: &SocketOptions) -> Result<()> {
+        let v6 = family(fd).map_err(socket_error)? == libc::AF_INET6;
+
+        if let Some(v6_only) = options.check_v6_only {
+            check_v6_only(fd, v6, v6_only)?;
+        }
+
+        if let Some(dscp) = options.dscp {
+            let tos = libc::c_int::from(dscp) << 2;
+            if v6 {
+                set_int(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos)
+            } else {
+                set_int(fd, libc::IPPROTO_IP, libc::IP_TOS, tos)
+            }
+            .map_err(socket_error)?;
+        }
+        if let Some(ttl) = options.ttl {
+            let ttl = libc::c_int::from(ttl);
+            if v6 {
+                set_int(fd, libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS, ttl)
+            } else {
+                set_int(fd, libc::IPPROTO_IP, libc::IP_TTL, ttl)
+            }
+            .map_err(socket_error)?;
+        }
+        // validated to fit in a c_int
+        if let Some(size) = options.recv_buffer {
+            set_int(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, size as libc::c_int)
+                .map_err(socket_error)?;
+        }
+        if let Some(size) = options.send_buffer {
+            set_int(fd, libc::SOL_SOCKET, libc::SO_SNDBUF, size as libc::c_int)
+                .map_err(socket_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that the IPv6-only flag of `fd` is `v6_only`.
+    ///
+    /// librist binds the socket before it can be reached, and the flag can
+    /// only be changed before binding, so this only checks that the system
+    /// default (`net.ipv6.bindv6only` on Linux) matches.
+    fn check_v6_only(fd: RawFd, v6: bool, v6_only: bool) -> Result<()> {
+        if !v6 {
+            return Err(Error::Configuration(
+                "the IPv6-only setting needs an IPv6 address, e.g. rist://@[::]:5000".to_string(),
+            ));
+        }
+        let current = get_int(fd, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY).map_err(socket_error)?;
+        if (current != 0) == v6_only {
+            return Ok(());
+        }
+        let wanted = if v6_only { "IPv6-only" } else { "dual-stack" };
+        Err(Error::Configuration(format!(
+            "the bound socket is not {wanted}; change the system default \
+             (net.ipv6.bindv6only) or listen on separate IPv4 and IPv6 peers"
+        )))
+    }
+
+    /// The buffer sizes currently in effect on `fd`.
+    pub(crate) fn buffers(fd: RawFd) -> Option<SocketBuffers> {
+        let recv = get_int(fd, libc::SOL_SOCKET, libc::SO_RCVBUF).ok()?;
+        let send = get_int(fd, libc::SOL_SOCKET, libc::SO_SNDBUF).ok()?;
+        Some(SocketBuffers {
+            recv: usize::try_from(recv).ok()?,
+            send: usize::try_from(send).ok()?,
+        })
+    }
+
+    fn socket_error(err: io::Error) -> Error {
+        Error::Configuration(format!("failed to configure peer socket: {err}"))
+    }
+}
+
+#[cfg(unix)]
+pub(crate) use imp::{apply, buffers};
+#[cfg(unix)]
+use imp::{local_addr, remote_addr, udp_sockets};
+
+#[cfg(not(unix))]
+fn udp_sockets() -> BTreeSet<RawSocket> {
+    BTreeSet::new()
+}
+
+#[cfg(not(unix))]
+fn local_addr(_fd: RawSocket) -> Option<SocketAddr> {
+    None
+}
+
+#[cfg(not(unix))]
+fn remote_addr(_fd: RawSocket) -> Option<SocketAddr> {
+    None
+}
+
+#[cfg(not(unix))]
+pub(crate) fn apply(_fd: RawSocket, _options: &SocketOptions) -> Result<()> {
+    Err(Error::Configuration(
+        "socket options are only supported on Unix".to_string(),
+    ))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn buffers(_fd: RawSocket) -> Option<SocketBuffers> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::os::fd::AsRawFd;
+
+    #[test]
+    fn test_finds_new_udp_sockets() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let port = crate::next_test_port();
+        let address = PeerAddress::from_url(&format!("rist://@127.0.0.1:{port}")).unwrap();
+        let ((socket, other), found) = track_new_sockets(address, || {
+            let socket = UdpSocket::bind(("127.0.0.1", port)).unwrap();
+            // opened by someone else at the same time
+            let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+            (socket, other)
+        });
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].fd(), Some(socket.as_raw_fd()));
+        assert!(found.iter().all(|s| s.fd != other.as_raw_fd()));
+
+        // the descriptor no longer refers to the socket
+        drop(socket);
+        assert_eq!(found[0].fd(), None);
+    }
+
+    #[test]
+    fn test_peer_address() {
+        let listen = PeerAddress::from_url("rist://@[::]:5000?cname=a").unwrap();
+        assert_eq!(listen.ip, None);
+        assert!(listen.matches("[::]:5001".parse().unwrap(), None));
+        assert!(!listen.matches("[::]:5002".parse().unwrap(), None));
+
+        let call = PeerAddress::from_url("rist://192.0.2.1:5000").unwrap();
+        let local = "0.0.0.0:40000".parse().unwrap();
+        assert!(call.matches(local, Some("192.0.2.1:5000".parse().unwrap())));
+        assert!(!call.matches(local, Some("192.0.2.2:5000".parse().unwrap())));
+        assert!(!call.matches(local, None));
+
+        let zoned = PeerAddress::from_url("rist://[fe80::1%eth0]:5000").unwrap();
+        assert_eq!(zoned.ip, Some("fe80::1".parse().unwrap()));
+        assert_eq!(PeerAddress::from_url("rist://@:5000").unwrap().port, 5000);
+        assert_eq!(PeerAddress::from_url("not a url"), None);
+    }
+
+    #[test]
+    fn test_applies_dscp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = SocketOptions {
+            dscp: Some(46),
+            ..Default::default()
+        };
+        apply(socket.as_raw_fd(), &options).unwrap();
+
+        let tos = imp::get_int(socket.as_raw_fd(), libc::IPPROTO_IP, libc::IP_TOS).unwrap();
+        assert_eq!(tos, 46 << 2);
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_dscp() {
+        let options = SocketOptions {
+            dscp: Some(64),
+            ..Default::default()
+        };
+        assert!(matches!(options.validate(), Err(Error::Configuration(_))));
+    }
+
+    #[test]
+    fn test_applies_ttl() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = SocketOptions {
+            ttl: Some(4),
+            ..Default::default()
+        };
+        apply(socket.as_raw_fd(), &options).unwrap();
+        assert_eq!(socket.ttl().unwrap(), 4);
+
+        let options = SocketOptions {
+            ttl: Some(0),
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn test_checks_v6_only() {
+        let v4 = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = SocketOptions {
+            check_v6_only: Some(true),
+            ..Default::default()
+        };
+        assert!(apply(v4.as_raw_fd(), &options).is_err());
+
+        // no IPv6 in the sandbox is fine
+        let Ok(v6) = UdpSocket::bind("[::1]:0") else {
+            return;
+        };
+        let current = imp::get_int(v6.as_raw_fd(), libc::IPPROTO_IPV6, libc::IPV6_V6ONLY).unwrap();
+        let options = SocketOptions {
+            check_v6_only: Some(current != 0),
+            ..Default::default()
+        };
+        assert!(apply(v6.as_raw_fd(), &options).is_ok());
+        // a mismatch is reported, not changed
+        let options = SocketOptions {
+            check_v6_only: Some(current == 0),
+            ..Default::default()
+        };
+        assert!(apply(v6.as_raw_fd(), &options).is_err());
+        assert_eq!(
+            imp::get_int(v6.as_raw_fd(), libc::IPPROTO_IPV6, libc::IPV6_V6ONLY).unwrap(),
+            current
+        );
+    }
+
+    #[test]
+    fn test_applies_buffer_sizes() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let before = buffers(socket.as_raw_fd()).unwrap();
+        let options = SocketOptions {
+            recv_buffer: Some(before.recv * 2),
+            send_buffer: Some(before.send * 2),
+            ..Default::default()
+        };
+        apply(socket.as_raw_fd(), &options).unwrap();
+
+        // the kernel may cap the size, but never below what was there
+        let after = buffers(socket.as_raw_fd()).unwrap();
+        assert!(after.recv >= before.recv);
+        assert!(after.send >= before.send);
+
+        let options = SocketOptions {
+            recv_buffer: Some(0),
+            ..Default::default()
+        };
+        assert!(options.validate().is_err());
+    }
+}
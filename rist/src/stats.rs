@@ -1,11 +1,52 @@
 //! Statistics for RIST connections.
 
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::CStr;
+use std::ops::Deref;
+use std::os::raw::{c_char, c_void};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often librist reports stats unless configured otherwise.
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether reported counters are running totals or per-report increases.
+///
+/// Gauges such as quality, RTT and bandwidth are reported as they are in
+/// either mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatsMode {
+    /// Counters are totals since the context was created, or since
+    /// `reset_stats()` (default).
+    #[default]
+    Cumulative,
+    /// Counters are the increase since the previous report for the same
+    /// peer or flow, as most dashboards want them.
+    Interval,
+}
+
+impl std::str::FromStr for StatsMode {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "cumulative" => Ok(StatsMode::Cumulative),
+            "interval" => Ok(StatsMode::Interval),
+            _ => Err(crate::Error::Configuration(format!(
+                "unknown stats mode: {s}"
+            ))),
+        }
+    }
+}
+
 /// Re-export raw stats types for direct access.
 pub use rist_sys::{rist_stats, rist_stats_receiver_flow, rist_stats_sender_peer};
 
 /// Statistics for a receiver flow.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ReceiverStats {
+    /// Canonical name of the flow.
+    pub cname: String,
     /// Number of connected peers.
     pub peer_count: u32,
     /// Flow ID.
@@ -24,17 +65,51 @@ pub struct ReceiverStats {
     pub reordered: u32,
     /// Recovered packets.
     pub recovered: u32,
+    /// Packets recovered by the first retransmission request. The rest of
+    /// [`recovered`](Self::recovered) took more, see
+    /// [`recovered_after_retries`](Self::recovered_after_retries).
+    pub recovered_one_retry: u32,
     /// Lost packets (unrecoverable).
     pub lost: u32,
     /// Quality percentage (0-100).
     pub quality: f64,
     /// Round-trip time in ms.
     pub rtt: u32,
+    /// Flow status as reported by librist.
+    pub status: i32,
+    /// Shortest time between two arriving packets.
+    pub min_inter_packet_spacing: Duration,
+    /// Time between the two latest arriving packets.
+    pub cur_inter_packet_spacing: Duration,
+    /// Longest time between two arriving packets.
+    pub max_inter_packet_spacing: Duration,
+    /// Interarrival jitter of the blocks as they are read from the
+    /// receiver, see [`JitterEstimator`].
+    ///
+    /// librist reports neither jitter nor when a packet arrived, so this is
+    /// timed on reading. Besides network jitter it picks up how evenly
+    /// librist releases blocks from its recovery buffer and how promptly
+    /// the application reads them.
+    pub read_jitter: Duration,
+    /// Blocks dropped because a queue was full, since the receiver was
+    /// created or its stats were reset: librist's output FIFO, counted from
+    /// the sequence gap before each block it flags as following an
+    /// overflow, and the queues the binding feeds, see
+    /// [`OverflowPolicy`](crate::OverflowPolicy).
+    pub dropped: u64,
+    /// MPEG-TS counters of the flow's payloads, while
+    /// [`Receiver::set_ts_monitor`](crate::Receiver::set_ts_monitor) is on.
+    ///
+    /// Continuity errors while [`lost`](Self::lost) stays flat point at the
+    /// source rather than the network.
+    #[cfg(feature = "ts")]
+    pub ts: Option<crate::ts::TsStats>,
 }
 
 impl From<&rist_sys::rist_stats_receiver_flow> for ReceiverStats {
     fn from(raw: &rist_sys::rist_stats_receiver_flow) -> Self {
         Self {
+            cname: c_buf_to_string(&raw.cname),
             peer_count: raw.peer_count,
             flow_id: raw.flow_id,
             bandwidth: raw.bandwidth,
@@ -44,17 +119,36 @@ impl From<&rist_sys::rist_stats_receiver_flow> for ReceiverStats {
             missing: raw.missing,
             reordered: raw.reordered,
             recovered: raw.recovered,
+            recovered_one_retry: raw.recovered_one_retry,
             lost: raw.lost,
             quality: raw.quality,
             rtt: raw.rtt,
+            status: raw.status,
+            // librist reports the spacing in microseconds
+            min_inter_packet_spacing: Duration::from_micros(raw.min_inter_packet_spacing),
+            cur_inter_packet_spacing: Duration::from_micros(raw.cur_inter_packet_spacing),
+            max_inter_packet_spacing: Duration::from_micros(raw.max_inter_packet_spacing),
+            read_jitter: Duration::ZERO,
+            dropped: 0,
+            #[cfg(feature = "ts")]
+            ts: None,
         }
     }
 }
 
+impl ReceiverStats {
+    /// Packets that needed more than one retransmission request to recover.
+    pub fn recovered_after_retries(&self) -> u32 {
+        self.recovered.saturating_sub(self.recovered_one_retry)
+    }
+}
+
 /// Statistics for a sender peer.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct SenderStats {
-    /// Peer ID.
+    /// Canonical name of the remote peer.
+    pub cname: String,
+    /// Peer ID, as assigned by librist.
     pub peer_id: u32,
     /// Current bandwidth in bps.
     pub bandwidth: usize,
@@ -75,6 +169,7 @@ pub struct SenderStats {
 impl From<&rist_sys::rist_stats_sender_peer> for SenderStats {
     fn from(raw: &rist_sys::rist_stats_sender_peer) -> Self {
         Self {
+            cname: c_buf_to_string(&raw.cname),
             peer_id: raw.peer_id,
             bandwidth: raw.bandwidth,
             retry_bandwidth: raw.retry_bandwidth,
@@ -86,3 +181,883 @@ impl From<&rist_sys::rist_stats_sender_peer> for SenderStats {
         }
     }
 }
+
+/// Stats that carry librist's bitrate gauges.
+pub trait BitrateStats {
+    /// Current bitrate in bits per second.
+    fn bandwidth_bps(&self) -> u64;
+    /// Current bitrate of retransmissions in bits per second.
+    fn retry_bandwidth_bps(&self) -> u64;
+}
+
+impl BitrateStats for ReceiverStats {
+    fn bandwidth_bps(&self) -> u64 {
+        self.bandwidth as u64
+    }
+
+    fn retry_bandwidth_bps(&self) -> u64 {
+        self.retry_bandwidth as u64
+    }
+}
+
+impl BitrateStats for SenderStats {
+    fn bandwidth_bps(&self) -> u64 {
+        self.bandwidth as u64
+    }
+
+    fn retry_bandwidth_bps(&self) -> u64 {
+        self.retry_bandwidth as u64
+    }
+}
+
+/// One stats report, with the interval librist measured it over and when
+/// it arrived.
+///
+/// Derefs to the stats themselves.
+#[derive(Debug, Clone)]
+pub struct StatsSnapshot<T> {
+    /// The reported stats.
+    pub stats: T,
+    /// The stats interval in effect when the report was made.
+    pub window: Duration,
+    /// When the report arrived, for measuring intervals.
+    pub captured: Instant,
+    /// When the report arrived by the wall clock, for time series.
+    pub captured_at: SystemTime,
+    /// Time since the previous report for the same flow or peer, `None` for
+    /// the first one. Usually close to [`window`](Self::window), but
+    /// librist reports late when its threads are busy.
+    pub since_previous: Option<Duration>,
+}
+
+impl<T> Deref for StatsSnapshot<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.stats
+    }
+}
+
+impl<T> StatsSnapshot<T> {
+    /// [`captured_at`](Self::captured_at) in milliseconds since the Unix
+    /// epoch, 0 if the clock is set before it.
+    pub fn timestamp_millis(&self) -> u64 {
+        self.captured_at
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+impl<T: BitrateStats> StatsSnapshot<T> {
+    /// Bitrate over the window, in bits per second.
+    pub fn bitrate_bps(&self) -> u64 {
+        self.stats.bandwidth_bps()
+    }
+
+    /// Bitrate of retransmissions over the window, in bits per second.
+    pub fn retry_bitrate_bps(&self) -> u64 {
+        self.stats.retry_bandwidth_bps()
+    }
+
+    /// Bitrate over the window, in megabits per second.
+    pub fn bitrate_mbps(&self) -> f64 {
+        self.bitrate_bps() as f64 / 1_000_000.0
+    }
+
+    /// Share of the bitrate spent on retransmissions, from 0.0 to 1.0.
+    pub fn retry_ratio(&self) -> f64 {
+        match self.bitrate_bps() {
+            0 => 0.0,
+            bps => self.retry_bitrate_bps() as f64 / bps as f64,
+        }
+    }
+
+    /// Bytes carried during the window, estimated from the bitrate.
+    pub fn window_bytes(&self) -> u64 {
+        (self.bitrate_bps() as f64 * self.window.as_secs_f64() / 8.0) as u64
+    }
+}
+
+/// Stats whose counters can be diffed between two reports.
+pub trait CounterStats: Clone {
+    /// These stats with every counter replaced by its increase since
+    /// `earlier`. A counter that went down was reset in between, so its
+    /// current value is the increase. Gauges such as quality, RTT and
+    /// bandwidth are left as they are.
+    fn counters_since(&self, earlier: &Self) -> Self;
+}
+
+/// Increase of a counter that may have been reset in between.
+fn increase<N: Copy + PartialOrd + std::ops::Sub<Output = N>>(later: N, earlier: N) -> N {
+    if later >= earlier {
+        later - earlier
+    } else {
+        later
+    }
+}
+
+impl CounterStats for ReceiverStats {
+    fn counters_since(&self, earlier: &Self) -> Self {
+        Self {
+            sent: increase(self.sent, earlier.sent),
+            received: increase(self.received, earlier.received),
+            missing: increase(self.missing, earlier.missing),
+            reordered: increase(self.reordered, earlier.reordered),
+            recovered: increase(self.recovered, earlier.recovered),
+            recovered_one_retry: increase(self.recovered_one_retry, earlier.recovered_one_retry),
+            lost: increase(self.lost, earlier.lost),
+            dropped: increase(self.dropped, earlier.dropped),
+            ..self.clone()
+        }
+    }
+}
+
+impl CounterStats for SenderStats {
+    fn counters_since(&self, earlier: &Self) -> Self {
+        Self {
+            sent: increase(self.sent, earlier.sent),
+            received: increase(self.received, earlier.received),
+            retransmitted: increase(self.retransmitted, earlier.retransmitted),
+            ..self.clone()
+        }
+    }
+}
+
+impl<T: CounterStats> StatsSnapshot<T> {
+    /// What happened between `earlier` and this snapshot.
+    ///
+    /// ```no_run
+    /// use rist::{Profile, Receiver};
+    /// use std::time::Duration;
+    ///
+    /// # fn example() -> rist::Result<()> {
+    /// let receiver = Receiver::new(Profile::Main)?;
+    /// let mut last = None;
+    /// loop {
+    ///     std::thread::sleep(Duration::from_secs(60));
+    ///     let Some(now) = receiver.stats_snapshot() else { continue };
+    ///     if let Some(earlier) = &last {
+    ///         let minute = now.delta(earlier);
+    ///         println!("lost {} packets ({:.3}%)", minute.lost, minute.loss_ratio() * 100.0);
+    ///     }
+    ///     last = Some(now);
+    /// }
+    /// # }
+    /// ```
+    pub fn delta(&self, earlier: &StatsSnapshot<T>) -> StatsDelta<T> {
+        StatsDelta {
+            counts: self.stats.counters_since(&earlier.stats),
+            elapsed: self.captured.saturating_duration_since(earlier.captured),
+        }
+    }
+}
+
+/// Counts between two [`StatsSnapshot`]s, from [`StatsSnapshot::delta`].
+///
+/// Derefs to the counts.
+#[derive(Debug, Clone)]
+pub struct StatsDelta<T> {
+    /// The later stats, with every counter replaced by its increase.
+    pub counts: T,
+    /// Time between the two snapshots.
+    pub elapsed: Duration,
+}
+
+impl<T> Deref for StatsDelta<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.counts
+    }
+}
+
+impl<T> StatsDelta<T> {
+    /// `count` per second over the interval, 0.0 if no time passed.
+    pub fn rate(&self, count: u64) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => count as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+impl StatsDelta<ReceiverStats> {
+    /// Packets received per second.
+    pub fn received_per_sec(&self) -> f64 {
+        self.rate(self.counts.received)
+    }
+
+    /// Unrecoverable packets per second.
+    pub fn lost_per_sec(&self) -> f64 {
+        self.rate(u64::from(self.counts.lost))
+    }
+
+    /// Share of packets lost for good, from 0.0 to 1.0.
+    pub fn loss_ratio(&self) -> f64 {
+        let lost = u64::from(self.counts.lost);
+        match self.counts.received + lost {
+            0 => 0.0,
+            total => lost as f64 / total as f64,
+        }
+    }
+}
+
+impl StatsDelta<SenderStats> {
+    /// Packets sent per second.
+    pub fn sent_per_sec(&self) -> f64 {
+        self.rate(self.counts.sent)
+    }
+
+    /// Packets retransmitted per second.
+    pub fn retransmitted_per_sec(&self) -> f64 {
+        self.rate(self.counts.retransmitted)
+    }
+
+    /// Retransmissions per packet sent.
+    pub fn retransmit_ratio(&self) -> f64 {
+        match self.counts.sent {
+            0 => 0.0,
+            sent => self.counts.retransmitted as f64 / sent as f64,
+        }
+    }
+}
+
+/// Moving average of the bitrates in a series of [`StatsSnapshot`]s.
+///
+/// Each snapshot is weighted by its window, and only the latest `span` worth
+/// of snapshots count.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::stats::BitrateAverage;
+/// use rist::{Profile, Receiver};
+/// use std::time::Duration;
+///
+/// # fn example() -> rist::Result<()> {
+/// let receiver = Receiver::new(Profile::Main)?;
+/// let mut average = BitrateAverage::new(Duration::from_secs(10));
+/// loop {
+///     std::thread::sleep(Duration::from_secs(1));
+///     if let Some(snapshot) = receiver.stats_snapshot() {
+///         average.push(&snapshot);
+///         println!("10s average: {} bps", average.bitrate_bps());
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BitrateAverage {
+    span: Duration,
+    // (window, bitrate, retry bitrate)
+    samples: VecDeque<(Duration, u64, u64)>,
+}
+
+impl BitrateAverage {
+    /// Average over the latest `span` of snapshots.
+    pub fn new(span: Duration) -> Self {
+        Self {
+            span,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Add a snapshot, dropping those that no longer fall within the span.
+    pub fn push<T: BitrateStats>(&mut self, snapshot: &StatsSnapshot<T>) {
+        self.samples.push_back((
+            snapshot.window,
+            snapshot.bitrate_bps(),
+            snapshot.retry_bitrate_bps(),
+        ));
+        while let Some(&(oldest, _, _)) = self.samples.front() {
+            if self.covered().saturating_sub(oldest) < self.span {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    /// Time covered by the snapshots currently averaged.
+    pub fn covered(&self) -> Duration {
+        self.samples.iter().map(|(window, _, _)| *window).sum()
+    }
+
+    /// Average bitrate in bits per second, 0 before the first snapshot.
+    pub fn bitrate_bps(&self) -> u64 {
+        self.average(|(_, bps, _)| *bps)
+    }
+
+    /// Average bitrate of retransmissions in bits per second.
+    pub fn retry_bitrate_bps(&self) -> u64 {
+        self.average(|(_, _, retry)| *retry)
+    }
+
+    fn average(&self, field: impl Fn(&(Duration, u64, u64)) -> u64) -> u64 {
+        let covered = self.covered().as_secs_f64();
+        if covered == 0.0 {
+            return 0;
+        }
+        let weighted: f64 = self
+            .samples
+            .iter()
+            .map(|sample| field(sample) as f64 * sample.0.as_secs_f64())
+            .sum();
+        (weighted / covered) as u64
+    }
+}
+
+/// Callback invoked with each new stats snapshot.
+pub(crate) type StatsCallback<T> = Box<dyn FnMut(&T) + Send>;
+
+/// Adds what librist doesn't measure to a stats snapshot.
+pub(crate) type StatsFill<T> = Box<dyn Fn(&mut T) + Send + Sync>;
+
+/// RFC 3550 interarrival jitter of a packet stream.
+///
+/// For packets carrying an NTP timestamp (`ts_ntp`, see
+/// [`send_timestamped`](crate::Sender::send_timestamped)) this is the
+/// smoothed variation in transit time, exactly as in RFC 3550 section
+/// 6.4.1. Packets without one fall back to the variation between
+/// consecutive arrival intervals, which also picks up any irregularity in
+/// how the sender paced them.
+#[derive(Debug, Clone, Default)]
+pub struct JitterEstimator {
+    last: Option<(Instant, u64)>,
+    // arrival interval before the last packet, for untimestamped packets
+    last_interval: Option<f64>,
+    // seconds
+    jitter: f64,
+}
+
+impl JitterEstimator {
+    /// Create an estimator with no packets seen.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a packet that arrived at `arrival`, with its `ts_ntp` (0 if
+    /// it has none).
+    pub fn observe(&mut self, arrival: Instant, ts_ntp: u64) {
+        let Some((last_arrival, last_ts)) = self.last.replace((arrival, ts_ntp)) else {
+            return;
+        };
+        let interval = arrival
+            .saturating_duration_since(last_arrival)
+            .as_secs_f64();
+
+        let deviation = if ts_ntp != 0 && last_ts != 0 {
+            // NTP64 is seconds in 32.32 fixed point
+            let sent_interval = ts_ntp.wrapping_sub(last_ts) as i64 as f64 / 4_294_967_296.0;
+            Some(interval - sent_interval)
+        } else {
+            self.last_interval.map(|last| interval - last)
+        };
+        self.last_interval = Some(interval);
+
+        if let Some(deviation) = deviation {
+            self.jitter += (deviation.abs() - self.jitter) / 16.0;
+        }
+    }
+
+    /// The current jitter estimate.
+    pub fn jitter(&self) -> Duration {
+        Duration::from_secs_f64(self.jitter)
+    }
+}
+
+/// Stats types that can be extracted from a librist stats container.
+pub(crate) trait FromRawStats: Sized {
+    fn from_container(container: &rist_sys::rist_stats) -> Option<Self>;
+
+    /// librist's ID for the peer or flow these stats describe.
+    fn key(&self) -> u32;
+}
+
+impl FromRawStats for ReceiverStats {
+    fn from_container(container: &rist_sys::rist_stats) -> Option<Self> {
+        if container.stats_type != rist_sys::rist_stats_type_RIST_STATS_RECEIVER_FLOW {
+            return None;
+        }
+        Some(Self::from(unsafe { &container.stats.receiver_flow }))
+    }
+
+    fn key(&self) -> u32 {
+        self.flow_id
+    }
+}
+
+impl FromRawStats for SenderStats {
+    fn from_container(container: &rist_sys::rist_stats) -> Option<Self> {
+        if container.stats_type != rist_sys::rist_stats_type_RIST_STATS_SENDER_PEER {
+            return None;
+        }
+        Some(Self::from(unsafe { &container.stats.sender_peer }))
+    }
+
+    fn key(&self) -> u32 {
+        self.peer_id
+    }
+}
+
+/// What a [`StatsSlot`] knows about one peer or flow.
+struct KeyStats<T> {
+    /// The stats as librist reported them.
+    raw: T,
+    /// The stats as passed on, counted from the baseline.
+    reported: T,
+    arrived: Instant,
+}
+
+/// Latest stats for a context, overall and per peer or flow, plus an
+/// optional user callback.
+///
+/// librist holds a raw pointer to this while the stats callback is
+/// registered, so it must outlive the context.
+pub(crate) struct StatsSlot<T> {
+    latest: Mutex<Option<StatsSnapshot<T>>>,
+    interval: Mutex<Duration>,
+    by_key: Mutex<BTreeMap<u32, KeyStats<T>>>,
+    // raw stats as of the last reset or, in interval mode, the last
+    // report, which counters are counted from
+    baseline: Mutex<BTreeMap<u32, T>>,
+    mode: Mutex<StatsMode>,
+    callback: Mutex<Option<StatsCallback<T>>>,
+    fill: Option<StatsFill<T>>,
+}
+
+impl<T: FromRawStats + CounterStats> StatsSlot<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            latest: Mutex::new(None),
+            interval: Mutex::new(DEFAULT_STATS_INTERVAL),
+            by_key: Mutex::new(BTreeMap::new()),
+            baseline: Mutex::new(BTreeMap::new()),
+            mode: Mutex::new(StatsMode::default()),
+            callback: Mutex::new(None),
+            fill: None,
+        }
+    }
+
+    /// Create a slot that passes every snapshot through `fill` first.
+    pub(crate) fn with_fill(fill: StatsFill<T>) -> Self {
+        Self {
+            fill: Some(fill),
+            ..Self::new()
+        }
+    }
+
+    pub(crate) fn set_callback(&self, callback: Option<StatsCallback<T>>) {
+        *self.callback.lock().unwrap_or_else(|e| e.into_inner()) = callback;
+    }
+
+    pub(crate) fn latest(&self) -> Option<T> {
+        self.latest_snapshot().map(|snapshot| snapshot.stats)
+    }
+
+    pub(crate) fn latest_snapshot(&self) -> Option<StatsSnapshot<T>> {
+        self.latest.lock().ok().and_then(|guard| guard.clone())
+    }
+
+    fn set_interval(&self, interval: Duration) {
+        if let Ok(mut guard) = self.interval.lock() {
+            *guard = interval;
+        }
+    }
+
+    /// Latest stats for every peer or flow, ordered by librist's ID.
+    pub(crate) fn all(&self) -> Vec<T> {
+        self.by_key
+            .lock()
+            .map(|guard| guard.values().map(|key| key.reported.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether counters are reported as totals or per-report increases.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn mode(&self) -> StatsMode {
+        *self.mode.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Report counters as totals or per-report increases from now on.
+    ///
+    /// Switching to cumulative also undoes any reset.
+    pub(crate) fn set_mode(&self, mode: StatsMode) {
+        let by_key = self.by_key.lock().unwrap_or_else(|e| e.into_inner());
+        let mut baseline = self.baseline.lock().unwrap_or_else(|e| e.into_inner());
+        match mode {
+            StatsMode::Cumulative => baseline.clear(),
+            StatsMode::Interval => {
+                for (key, stats) in by_key.iter() {
+                    baseline.insert(*key, stats.raw.clone());
+                }
+            }
+        }
+        *self.mode.lock().unwrap_or_else(|e| e.into_inner()) = mode;
+    }
+
+    /// Forget the stats collected so far, and count librist's cumulative
+    /// counters from their current values.
+    pub(crate) fn reset(&self) {
+        let mut by_key = self.by_key.lock().unwrap_or_else(|e| e.into_inner());
+        let mut baseline = self.baseline.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, stats) in std::mem::take(&mut *by_key) {
+            baseline.insert(key, stats.raw);
+        }
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    pub(crate) fn update(&self, mut raw: T) {
+        if let Some(fill) = &self.fill {
+            fill(&mut raw);
+        }
+        let key = raw.key();
+        let stats = {
+            let mut baseline = self.baseline.lock().unwrap_or_else(|e| e.into_inner());
+            let stats = match baseline.get(&key) {
+                Some(baseline) => raw.counters_since(baseline),
+                None => raw.clone(),
+            };
+            let mode = *self.mode.lock().unwrap_or_else(|e| e.into_inner());
+            if mode == StatsMode::Interval {
+                baseline.insert(key, raw.clone());
+            }
+            stats
+        };
+        crate::callback::call_user(&self.callback, |callback| callback(&stats));
+        let captured = Instant::now();
+        let since_previous = self.by_key.lock().ok().and_then(|mut guard| {
+            let key_stats = KeyStats {
+                raw,
+                reported: stats.clone(),
+                arrived: captured,
+            };
+            guard
+                .insert(key, key_stats)
+                .map(|previous| captured.saturating_duration_since(previous.arrived))
+        });
+        let window = self
+            .interval
+            .lock()
+            .map(|guard| *guard)
+            .unwrap_or(DEFAULT_STATS_INTERVAL);
+        if let Ok(mut guard) = self.latest.lock() {
+            *guard = Some(StatsSnapshot {
+                stats,
+                window,
+                captured,
+                captured_at: SystemTime::now(),
+                since_previous,
+            });
+        }
+    }
+}
+
+/// Stats callback for librist, `arg` is a `*const StatsSlot<T>`.
+unsafe extern "C" fn stats_callback<T: FromRawStats + CounterStats>(
+    arg: *mut c_void,
+    stats_container: *const rist_sys::rist_stats,
+) -> i32 {
+    if arg.is_null() || stats_container.is_null() {
+        return 0;
+    }
+
+    let slot = &*(arg as *const StatsSlot<T>);
+    crate::callback::guard("stats", (), || {
+        if let Some(stats) = T::from_container(&*stats_container) {
+            slot.update(stats);
+        }
+    });
+
+    // Free the stats container
+    rist_sys::rist_stats_free(stats_container);
+
+    0
+}
+
+/// Register `slot` to receive stats from `ctx` every `interval`.
+pub(crate) fn register_stats<T: FromRawStats + CounterStats>(
+    ctx: *mut rist_sys::rist_ctx,
+    interval: Duration,
+    slot: &Arc<StatsSlot<T>>,
+) -> crate::Result<()> {
+    let interval_ms: i32 = interval
+        .as_millis()
+        .try_into()
+        .map_err(|_| crate::Error::TimeoutOverflow)?;
+    let slot_ptr = Arc::as_ptr(slot) as *mut c_void;
+
+    let ret = unsafe {
+        rist_sys::rist_stats_callback_set(ctx, interval_ms, Some(stats_callback::<T>), slot_ptr)
+    };
+    if ret != 0 {
+        return Err(crate::Error::Configuration(
+            "failed to set stats callback".to_string(),
+        ));
+    }
+    slot.set_interval(interval);
+
+    Ok(())
+}
+
+/// Convert a fixed-size, NUL-terminated C string buffer to a `String`.
+pub(crate) fn c_buf_to_string(buf: &[c_char]) -> String {
+    let bytes: Vec<u8> = buf.iter().map(|&c| c as u8).collect();
+    CStr::from_bytes_until_nul(&bytes)
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = (1 << 32) / 1000;
+
+    #[test]
+    fn test_jitter_from_timestamps() {
+        let start = Instant::now();
+        let mut estimator = JitterEstimator::new();
+        // sent every 10ms, arriving with a steady 5ms of extra delay: no jitter
+        for i in 0..10u64 {
+            estimator.observe(
+                start + Duration::from_millis(i * 10 + 5),
+                (1 << 40) + i * 10 * MS,
+            );
+        }
+        assert!(estimator.jitter() < Duration::from_micros(10));
+
+        // one packet 16ms late moves the estimate by 1/16 of that
+        estimator.observe(
+            start + Duration::from_millis(100 + 5 + 16),
+            (1 << 40) + 100 * MS,
+        );
+        let jitter = estimator.jitter();
+        assert!(jitter > Duration::from_micros(950) && jitter < Duration::from_micros(1050));
+    }
+
+    #[test]
+    fn test_jitter_from_arrivals() {
+        let start = Instant::now();
+        let mut estimator = JitterEstimator::new();
+        for i in 0..10u64 {
+            estimator.observe(start + Duration::from_millis(i * 10), 0);
+        }
+        assert_eq!(estimator.jitter(), Duration::ZERO);
+
+        // alternating 5ms and 15ms intervals
+        let mut at = start + Duration::from_millis(100);
+        for i in 0..200 {
+            at += Duration::from_millis(if i % 2 == 0 { 5 } else { 15 });
+            estimator.observe(at, 0);
+        }
+        let jitter = estimator.jitter();
+        assert!(jitter > Duration::from_millis(9) && jitter < Duration::from_millis(11));
+    }
+
+    fn snapshot(bandwidth: usize, retry_bandwidth: usize, secs: u64) -> StatsSnapshot<SenderStats> {
+        StatsSnapshot {
+            stats: SenderStats {
+                bandwidth,
+                retry_bandwidth,
+                ..Default::default()
+            },
+            window: Duration::from_secs(secs),
+            captured: Instant::now(),
+            captured_at: SystemTime::now(),
+            since_previous: None,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_bitrates() {
+        let busy = snapshot(8_000_000, 2_000_000, 2);
+        assert_eq!(busy.bitrate_bps(), 8_000_000);
+        assert_eq!(busy.retry_bitrate_bps(), 2_000_000);
+        assert_eq!(busy.bitrate_mbps(), 8.0);
+        assert_eq!(busy.retry_ratio(), 0.25);
+        assert_eq!(busy.window_bytes(), 2_000_000);
+        assert_eq!(snapshot(0, 0, 1).retry_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_bitrate_average_is_time_weighted() {
+        let mut average = BitrateAverage::new(Duration::from_secs(4));
+        assert_eq!(average.bitrate_bps(), 0);
+
+        average.push(&snapshot(1000, 0, 1));
+        average.push(&snapshot(4000, 100, 3));
+        assert_eq!(average.bitrate_bps(), 3250);
+        assert_eq!(average.retry_bitrate_bps(), 75);
+
+        // the first snapshot falls out of the 4s span
+        average.push(&snapshot(2000, 0, 1));
+        assert_eq!(average.covered(), Duration::from_secs(4));
+        assert_eq!(average.bitrate_bps(), 3500);
+    }
+
+    #[test]
+    fn test_delta_counts_and_rates() {
+        let at = Instant::now();
+        let take = |secs: u64, received: u64, lost: u32, rtt: u32| StatsSnapshot {
+            stats: ReceiverStats {
+                received,
+                lost,
+                rtt,
+                ..Default::default()
+            },
+            window: DEFAULT_STATS_INTERVAL,
+            captured: at + Duration::from_secs(secs),
+            captured_at: UNIX_EPOCH + Duration::from_secs(secs),
+            since_previous: None,
+        };
+
+        let minute = take(60, 7000, 30, 25).delta(&take(0, 1000, 10, 40));
+        assert_eq!(minute.elapsed, Duration::from_secs(60));
+        assert_eq!(minute.received, 6000);
+        assert_eq!(minute.lost, 20);
+        assert_eq!(minute.rtt, 25);
+        assert_eq!(minute.received_per_sec(), 100.0);
+        assert_eq!(minute.loss_ratio(), 20.0 / 6020.0);
+
+        // counters that went down were reset in between
+        let reset = take(120, 500, 2, 25).delta(&take(60, 7000, 30, 25));
+        assert_eq!(reset.received, 500);
+        assert_eq!(reset.lost, 2);
+
+        // snapshots taken out of order give no rate
+        assert_eq!(take(0, 0, 0, 0).delta(&take(60, 0, 0, 0)).rate(100), 0.0);
+    }
+
+    #[test]
+    fn test_receiver_flow_fields() {
+        // SAFETY: the raw stats are plain integers and a char array
+        let mut raw: rist_sys::rist_stats_receiver_flow = unsafe { std::mem::zeroed() };
+        raw.recovered = 12;
+        raw.recovered_one_retry = 9;
+        raw.status = 1;
+        raw.min_inter_packet_spacing = 150;
+        raw.cur_inter_packet_spacing = 1100;
+        raw.max_inter_packet_spacing = 40_000;
+
+        let stats = ReceiverStats::from(&raw);
+        assert_eq!(stats.recovered_one_retry, 9);
+        assert_eq!(stats.recovered_after_retries(), 3);
+        assert_eq!(stats.status, 1);
+        assert_eq!(stats.min_inter_packet_spacing, Duration::from_micros(150));
+        assert_eq!(stats.cur_inter_packet_spacing, Duration::from_micros(1100));
+        assert_eq!(stats.max_inter_packet_spacing, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_sender_delta() {
+        let mut later = snapshot(0, 0, 1);
+        later.stats.sent = 1100;
+        later.stats.retransmitted = 55;
+        let mut earlier = snapshot(0, 0, 1);
+        earlier.stats.sent = 100;
+        earlier.stats.retransmitted = 5;
+        later.captured = earlier.captured + Duration::from_secs(10);
+
+        let delta = later.delta(&earlier);
+        assert_eq!(delta.sent_per_sec(), 100.0);
+        assert_eq!(delta.retransmitted_per_sec(), 5.0);
+        assert_eq!(delta.retransmit_ratio(), 0.05);
+    }
+
+    #[test]
+    fn test_fill_applies_to_snapshots() {
+        let slot = StatsSlot::<ReceiverStats>::with_fill(Box::new(|stats| {
+            stats.read_jitter = Duration::from_millis(u64::from(stats.flow_id));
+        }));
+        slot.update(ReceiverStats {
+            flow_id: 3,
+            ..Default::default()
+        });
+        assert_eq!(slot.latest().unwrap().read_jitter, Duration::from_millis(3));
+        assert_eq!(
+            slot.latest_snapshot().unwrap().window,
+            DEFAULT_STATS_INTERVAL
+        );
+        assert_eq!(slot.all()[0].read_jitter, Duration::from_millis(3));
+    }
+
+    #[test]
+    fn test_reset_counts_from_baseline() {
+        let slot = StatsSlot::<ReceiverStats>::new();
+        let report = |flow_id, received, quality| ReceiverStats {
+            flow_id,
+            received,
+            quality,
+            ..Default::default()
+        };
+        slot.update(report(1, 1000, 100.0));
+        slot.update(report(2, 500, 100.0));
+
+        slot.reset();
+        assert!(slot.latest().is_none());
+        assert!(slot.all().is_empty());
+
+        slot.update(report(1, 1200, 98.0));
+        let stats = slot.latest().unwrap();
+        assert_eq!(stats.received, 200);
+        assert_eq!(stats.quality, 98.0);
+        slot.update(report(1, 1300, 99.0));
+        assert_eq!(slot.latest().unwrap().received, 300);
+
+        // a flow without a report before the reset counts from zero
+        slot.update(report(3, 50, 100.0));
+        assert_eq!(slot.latest().unwrap().received, 50);
+        assert_eq!(slot.all().len(), 2);
+    }
+
+    #[test]
+    fn test_interval_mode() {
+        let slot = StatsSlot::<SenderStats>::new();
+        let report = |sent, rtt| SenderStats {
+            sent,
+            rtt,
+            ..Default::default()
+        };
+        slot.update(report(1000, 20));
+
+        slot.set_mode(StatsMode::Interval);
+        slot.update(report(1100, 25));
+        assert_eq!(slot.latest().unwrap().sent, 100);
+        slot.update(report(1150, 30));
+        let stats = slot.latest().unwrap();
+        assert_eq!(stats.sent, 50);
+        assert_eq!(stats.rtt, 30);
+
+        slot.set_mode(StatsMode::Cumulative);
+        slot.update(report(1200, 30));
+        assert_eq!(slot.latest().unwrap().sent, 1200);
+        assert_eq!(
+            "Interval".parse::<StatsMode>().unwrap(),
+            StatsMode::Interval
+        );
+    }
+
+    #[test]
+    fn test_snapshots_are_timestamped() {
+        let slot = StatsSlot::<ReceiverStats>::new();
+        let before = SystemTime::now();
+        slot.update(ReceiverStats::default());
+        let first = slot.latest_snapshot().unwrap();
+        assert_eq!(first.since_previous, None);
+        assert!(first.captured_at >= before);
+        assert!(first.timestamp_millis() > 0);
+
+        std::thread::sleep(Duration::from_millis(10));
+        slot.update(ReceiverStats {
+            flow_id: 1,
+            ..Default::default()
+        });
+        // another flow's first report
+        assert_eq!(slot.latest_snapshot().unwrap().since_previous, None);
+        slot.update(ReceiverStats::default());
+        let second = slot.latest_snapshot().unwrap();
+        assert!(second.since_previous.unwrap() >= Duration::from_millis(10));
+        assert!(second.captured_at > first.captured_at);
+    }
+}
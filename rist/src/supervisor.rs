@@ -0,0 +1,396 @@
+//! Self-healing contexts for long-running services.
+//!
+//! A [`Supervised`] context is built by a factory closure. When the context
+//! fails, e.g. reads keep returning errors or it was stopped underneath the
+//! service, it is dropped and the factory is called again, so the new
+//! context binds the same ports with the same options.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::supervisor::{Supervised, SupervisorEvent};
+//! use rist::Receiver;
+//! use std::time::Duration;
+//!
+//! # fn example() -> rist::Result<()> {
+//! let mut receiver = Supervised::new(|| Receiver::builder().peer("rist://@:5000").build())?;
+//! receiver.on_event(|event| {
+//!     if let SupervisorEvent::Restarted { restarts } = event {
+//!         eprintln!("receiver restarted ({restarts} so far)");
+//!     }
+//! });
+//!
+//! loop {
+//!     if let Some(block) = receiver.read(Duration::from_millis(100))? {
+//!         // process block.payload()
+//!     }
+//! }
+//! # }
+//! ```
+
+use crate::{DataBlock, Error, Receiver, Result};
+use std::time::{Duration, Instant};
+
+/// When a [`Supervised`] context is considered dead and how it is rebuilt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupervisorPolicy {
+    /// Consecutive read errors after which the context is rebuilt.
+    pub max_errors: u32,
+    /// Delay between failed attempts to rebuild the context. The first
+    /// attempt is made straight away.
+    pub retry_delay: Duration,
+    /// Give up after this many consecutive failed rebuilds. `None` retries
+    /// forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for SupervisorPolicy {
+    fn default() -> Self {
+        Self {
+            max_errors: 3,
+            retry_delay: Duration::from_secs(1),
+            max_attempts: None,
+        }
+    }
+}
+
+impl SupervisorPolicy {
+    /// Create the default policy: rebuild after 3 errors in a row, retrying
+    /// every second forever.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild the context after `errors` consecutive read errors.
+    pub fn max_errors(mut self, errors: u32) -> Self {
+        self.max_errors = errors.max(1);
+        self
+    }
+
+    /// Set the delay between failed rebuilds.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Give up after `attempts` consecutive failed rebuilds.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+}
+
+/// What happened to a [`Supervised`] context, passed to
+/// [`on_event`](Supervised::on_event).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SupervisorEvent {
+    /// The context failed with `error` and was dropped.
+    Failed {
+        /// The error that ended it.
+        error: String,
+    },
+    /// A new context is up.
+    Restarted {
+        /// Restarts so far, including this one.
+        restarts: u64,
+    },
+    /// Building a new context failed; it is retried after the policy's
+    /// retry delay unless the attempts are used up.
+    RestartFailed {
+        /// Consecutive failed attempts, including this one.
+        attempt: u32,
+        /// Why it failed.
+        error: String,
+    },
+}
+
+type Factory<T> = Box<dyn FnMut() -> Result<T> + Send>;
+type EventCallback = Box<dyn FnMut(&SupervisorEvent) + Send>;
+
+/// A context rebuilt by its factory whenever it fails.
+pub struct Supervised<T> {
+    factory: Factory<T>,
+    inner: Option<T>,
+    policy: SupervisorPolicy,
+    on_event: Option<EventCallback>,
+    errors: u32,
+    restarts: u64,
+    failed_attempts: u32,
+    next_attempt: Instant,
+    last_error: Option<String>,
+}
+
+impl<T> Supervised<T> {
+    /// Build the first context with `factory`, failing if that fails.
+    ///
+    /// `factory` is called again for every restart, and should create and
+    /// start the context the same way each time.
+    pub fn new(mut factory: impl FnMut() -> Result<T> + Send + 'static) -> Result<Self> {
+        let inner = factory()?;
+        Ok(Self {
+            factory: Box::new(factory),
+            inner: Some(inner),
+            policy: SupervisorPolicy::default(),
+            on_event: None,
+            errors: 0,
+            restarts: 0,
+            failed_attempts: 0,
+            next_attempt: Instant::now(),
+            last_error: None,
+        })
+    }
+
+    /// Set when the context is rebuilt.
+    pub fn policy(mut self, policy: SupervisorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Call `callback` with every failure and restart.
+    pub fn on_event(&mut self, callback: impl FnMut(&SupervisorEvent) + Send + 'static) {
+        self.on_event = Some(Box::new(callback));
+    }
+
+    /// The current context, `None` while it is being rebuilt.
+    pub fn get(&self) -> Option<&T> {
+        self.inner.as_ref()
+    }
+
+    /// Restarts so far.
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+
+    /// Drop the current context and build a new one now, e.g. after
+    /// noticing a problem the supervisor can't see.
+    ///
+    /// If building fails, the next read tries again after the retry delay.
+    pub fn restart(&mut self) -> Result<()> {
+        self.fail("restart requested".to_string());
+        self.next_attempt = Instant::now();
+        self.rebuild()
+    }
+
+    fn emit(&mut self, event: SupervisorEvent) {
+        if let Some(callback) = self.on_event.as_mut() {
+            callback(&event);
+        }
+    }
+
+    /// Count a read error, returning `true` if the context should be
+    /// rebuilt.
+    fn is_fatal(&mut self, error: &Error) -> bool {
+        match error {
+            // the context can't be started again
+            Error::Stopped | Error::NotStarted => true,
+            Error::Read => {
+                self.errors += 1;
+                self.errors >= self.policy.max_errors
+            }
+            // the caller's mistake, not the context's
+            _ => false,
+        }
+    }
+
+    /// Drop the context after it failed with `error`.
+    fn fail(&mut self, error: String) {
+        self.inner = None;
+        self.errors = 0;
+        self.emit(SupervisorEvent::Failed { error });
+    }
+
+    /// Build a new context if there is none and an attempt is due.
+    ///
+    /// Fails only once the policy's attempts are used up.
+    fn rebuild(&mut self) -> Result<()> {
+        if self.inner.is_some() || Instant::now() < self.next_attempt {
+            return Ok(());
+        }
+        if let Some(error) = &self.last_error {
+            if self
+                .policy
+                .max_attempts
+                .is_some_and(|max| self.failed_attempts >= max)
+            {
+                return Err(Error::RestartsExhausted {
+                    attempts: self.failed_attempts,
+                    error: error.clone(),
+                });
+            }
+        }
+
+        match (self.factory)() {
+            Ok(inner) => {
+                self.inner = Some(inner);
+                self.restarts += 1;
+                self.failed_attempts = 0;
+                self.last_error = None;
+                self.emit(SupervisorEvent::Restarted {
+                    restarts: self.restarts,
+                });
+            }
+            Err(e) => {
+                self.failed_attempts += 1;
+                self.next_attempt = Instant::now() + self.policy.retry_delay;
+                self.last_error = Some(e.to_string());
+                self.emit(SupervisorEvent::RestartFailed {
+                    attempt: self.failed_attempts,
+                    error: e.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Supervised<Receiver> {
+    /// Read data with a timeout, like [`Receiver::read`].
+    ///
+    /// Errors that end the context are not returned: the context is rebuilt
+    /// instead, and this returns `Ok(None)` if no data arrived by the
+    /// timeout. Other errors are returned as they are. Fails for good once
+    /// the policy's rebuild attempts are used up.
+    pub fn read(&mut self, timeout: Duration) -> Result<Option<DataBlock>> {
+        let start = Instant::now();
+        loop {
+            self.rebuild()?;
+            let remaining = timeout.saturating_sub(start.elapsed());
+
+            let Some(receiver) = &self.inner else {
+                let wait = self.next_attempt.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Ok(None);
+                }
+                std::thread::sleep(wait.min(remaining));
+                continue;
+            };
+
+            match receiver.read(remaining) {
+                Ok(block) => {
+                    self.errors = 0;
+                    return Ok(block);
+                }
+                Err(e) if self.is_fatal(&e) => {
+                    self.fail(e.to_string());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Supervised<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Supervised")
+            .field("running", &self.inner.is_some())
+            .field("policy", &self.policy)
+            .field("restarts", &self.restarts)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// A supervisor of plain numbers, built from a counter, whose factory
+    /// fails while `broken` is set.
+    fn counter(broken: Arc<AtomicU32>) -> Supervised<u32> {
+        let builds = AtomicU32::new(0);
+        Supervised::new(move || {
+            if broken.load(Ordering::Relaxed) > 0 {
+                broken.fetch_sub(1, Ordering::Relaxed);
+                return Err(Error::ContextCreation);
+            }
+            Ok(builds.fetch_add(1, Ordering::Relaxed))
+        })
+        .unwrap()
+    }
+
+    fn record(supervised: &mut Supervised<u32>) -> Arc<Mutex<Vec<SupervisorEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        supervised.on_event(move |event| sink.lock().unwrap().push(event.clone()));
+        events
+    }
+
+    #[test]
+    fn test_rebuilds_after_repeated_errors() {
+        let mut supervised = counter(Arc::default());
+        let events = record(&mut supervised);
+
+        assert!(!supervised.is_fatal(&Error::Read));
+        assert!(!supervised.is_fatal(&Error::TimeoutOverflow));
+        assert!(!supervised.is_fatal(&Error::Read));
+        assert!(supervised.is_fatal(&Error::Read));
+        supervised.fail(Error::Read.to_string());
+        assert!(supervised.get().is_none());
+
+        supervised.rebuild().unwrap();
+        assert_eq!(supervised.get(), Some(&1));
+        assert_eq!(supervised.restarts(), 1);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                SupervisorEvent::Failed {
+                    error: "failed to read data".to_string()
+                },
+                SupervisorEvent::Restarted { restarts: 1 },
+            ]
+        );
+
+        // a stopped context is dead straight away
+        assert!(supervised.is_fatal(&Error::Stopped));
+    }
+
+    #[test]
+    fn test_retries_failed_rebuilds() {
+        let broken = Arc::new(AtomicU32::new(0));
+        let mut supervised = counter(broken.clone()).policy(
+            SupervisorPolicy::new()
+                .retry_delay(Duration::from_millis(20))
+                .max_attempts(3),
+        );
+        let events = record(&mut supervised);
+
+        broken.store(2, Ordering::Relaxed);
+        supervised.restart().unwrap();
+        assert!(supervised.get().is_none());
+        // not due yet
+        supervised.rebuild().unwrap();
+        assert_eq!(events.lock().unwrap().len(), 2);
+
+        std::thread::sleep(Duration::from_millis(25));
+        supervised.rebuild().unwrap();
+        assert!(supervised.get().is_none());
+        std::thread::sleep(Duration::from_millis(25));
+        supervised.rebuild().unwrap();
+        assert_eq!(supervised.get(), Some(&1));
+        assert!(matches!(
+            events.lock().unwrap()[2],
+            SupervisorEvent::RestartFailed { attempt: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let broken = Arc::new(AtomicU32::new(0));
+        let mut supervised = counter(broken.clone()).policy(
+            SupervisorPolicy::new()
+                .retry_delay(Duration::ZERO)
+                .max_attempts(2),
+        );
+
+        broken.store(u32::MAX, Ordering::Relaxed);
+        supervised.fail("gone".to_string());
+        supervised.rebuild().unwrap();
+        supervised.rebuild().unwrap();
+        assert!(matches!(
+            supervised.rebuild(),
+            Err(Error::RestartsExhausted { attempts: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+//! Helpers for integration-testing RIST pipelines.
+//!
+//! [`loopback`] spins up a started sender and receiver talking over an
+//! ephemeral localhost port, so tests don't need to hardcode ports.
+//!
+//! [`loopback_impaired`] puts an [`ImpairmentProxy`] between the two ends to
+//! drop, reorder and delay packets deterministically, which exercises the
+//! retransmission path.
+//!
+//! [`conformance`] checks what goes on the wire against TR-06 and reports
+//! pass/fail per item.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::testing::{loopback, LoopbackOptions};
+//! use rist::Profile;
+//! use std::time::Duration;
+//!
+//! let pair = loopback(Profile::Main, &LoopbackOptions::default())?;
+//! pair.sender.send(&[0x47; 1316])?;
+//! let block = pair.receiver.read(Duration::from_secs(1))?;
+//! # Ok::<(), rist::Error>(())
+//! ```
+
+pub mod conformance;
+mod impairment;
+
+pub use impairment::{Impairment, ImpairmentProxy, ImpairmentStats};
+
+use crate::{Profile, Receiver, ReceiverOptions, Result, Sender, SenderOptions};
+use std::net::UdpSocket;
+
+/// Options for both ends of a loopback pair.
+#[derive(Debug, Clone, Default)]
+pub struct LoopbackOptions {
+    /// Options applied to the receiver's listening peer.
+    pub receiver: ReceiverOptions,
+    /// Options applied to the sender's peer.
+    pub sender: SenderOptions,
+}
+
+/// A started sender connected to a started receiver over localhost.
+pub struct Loopback {
+    /// The sending end.
+    pub sender: Sender,
+    /// The receiving end.
+    pub receiver: Receiver,
+    /// The localhost UDP port the receiver listens on.
+    pub port: u16,
+}
+
+/// Find a UDP port on localhost that is currently free.
+///
+/// The port is released before returning, so another process could take it
+/// in between; good enough for tests.
+pub fn free_port() -> Result<u16> {
+    UdpSocket::bind("127.0.0.1:0")
+        .and_then(|socket| socket.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| crate::Error::Configuration(format!("no free UDP port: {e}")))
+}
+
+/// Create a started sender and receiver pair on an ephemeral localhost port.
+pub fn loopback(profile: Profile, options: &LoopbackOptions) -> Result<Loopback> {
+    let port = free_port()?;
+    let (sender, receiver) = sync_pair(profile, options, port, port)?;
+
+    Ok(Loopback {
+        sender,
+        receiver,
+        port,
+    })
+}
+
+/// A loopback pair whose traffic passes through an [`ImpairmentProxy`].
+pub struct ImpairedLoopback {
+    /// The sending end.
+    pub sender: Sender,
+    /// The receiving end.
+    pub receiver: Receiver,
+    /// The proxy between them.
+    pub proxy: ImpairmentProxy,
+}
+
+/// Create a started sender and receiver pair with impaired traffic between them.
+///
+/// Requires a single-port profile (Main or Advanced).
+pub fn loopback_impaired(
+    profile: Profile,
+    options: &LoopbackOptions,
+    impairment: Impairment,
+) -> Result<ImpairedLoopback> {
+    let port = free_port()?;
+    let proxy = ImpairmentProxy::start(port, impairment)?;
+    let (sender, receiver) = sync_pair(profile, options, port, proxy.port())?;
+
+    Ok(ImpairedLoopback {
+        sender,
+        receiver,
+        proxy,
+    })
+}
+
+fn sync_pair(
+    profile: Profile,
+    options: &LoopbackOptions,
+    listen_port: u16,
+    send_port: u16,
+) -> Result<(Sender, Receiver)> {
+    let mut receiver = Receiver::new(profile)?;
+    receiver.add_peer_with_options(
+        &format!("rist://@127.0.0.1:{listen_port}"),
+        &options.receiver,
+    )?;
+    receiver.start()?;
+
+    let mut sender = Sender::new(profile)?;
+    sender.add_peer_with_options(&format!("rist://127.0.0.1:{send_port}"), &options.sender)?;
+    sender.start()?;
+
+    Ok((sender, receiver))
+}
+
+/// An async sender connected to an async receiver over localhost.
+#[cfg(feature = "tokio")]
+pub struct AsyncLoopback {
+    /// The sending end.
+    pub sender: crate::tokio::AsyncSender,
+    /// The receiving end.
+    pub receiver: crate::tokio::AsyncReceiver,
+    /// The localhost UDP port the receiver listens on.
+    pub port: u16,
+}
+
+/// Async version of [`loopback`].
+#[cfg(feature = "tokio")]
+pub async fn async_loopback(profile: Profile, options: &LoopbackOptions) -> Result<AsyncLoopback> {
+    use crate::tokio::{AsyncReceiver, AsyncSender};
+
+    let port = free_port()?;
+    let receiver = AsyncReceiver::bind_with_options(
+        profile,
+        &format!("rist://@127.0.0.1:{port}"),
+        options.receiver.clone(),
+    )?;
+    let sender = AsyncSender::connect_with_options(
+        profile,
+        &format!("rist://127.0.0.1:{port}"),
+        options.sender.clone(),
+    )
+    .await?;
+
+    Ok(AsyncLoopback {
+        sender,
+        receiver,
+        port,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_loopback_impaired_recovers_drops() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let options = LoopbackOptions {
+            receiver: ReceiverOptions::new()
+                .recovery_length_min(Duration::from_millis(500))
+                .recovery_length_max(Duration::from_millis(500)),
+            ..Default::default()
+        };
+        let impairment = Impairment::new().drop_percent(5.0).seed(7);
+        let pair = loopback_impaired(Profile::Main, &options, impairment).unwrap();
+
+        let total_packets: u64 = 200;
+        for _ in 0..total_packets {
+            pair.sender.send(&[0x47u8; 1316]).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let mut received_count = 0;
+        while let Ok(Some(_)) = pair.receiver.read(Duration::from_millis(1000)) {
+            received_count += 1;
+            if received_count == total_packets {
+                break;
+            }
+        }
+
+        let stats = pair.proxy.stats();
+        assert!(stats.dropped > 0, "expected the proxy to drop packets");
+        assert!(
+            received_count > total_packets.saturating_sub(stats.dropped),
+            "expected retransmissions to recover dropped packets"
+        );
+    }
+}
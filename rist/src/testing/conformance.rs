@@ -0,0 +1,805 @@
+//! Interoperability checks against the VSF TR-06 wire format.
+//!
+//! [`run`] streams numbered packets from a sender to a receiver, through
+//! proxies that drop some of them and keep a copy of everything on the
+//! wire. The copies are then checked against what TR-06-1 (Simple profile)
+//! and TR-06-2 (Main profile) require: RTP header layout, RTCP and GRE
+//! keepalives, NACKs and retransmissions, and recovery of every dropped
+//! packet. Each item passes or fails on its own, so a vendor certifying a
+//! stack built on this crate gets a per-item [`Report`].
+//!
+//! By default both ends are this crate, which only shows that it agrees
+//! with itself. To check it against the reference implementation, make one
+//! end librist's `ristsender` or `ristreceiver` tool with
+//! [`Endpoint::Reference`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::testing::conformance::{run, ConformanceOptions, Endpoint};
+//!
+//! // this crate's sender against librist's receiver
+//! let options = ConformanceOptions {
+//!     receiver: Endpoint::Reference("/usr/local/bin/ristreceiver".into()),
+//!     ..Default::default()
+//! };
+//! let report = run(&options);
+//! println!("{report}");
+//! assert!(report.passed());
+//! ```
+
+use super::impairment::PacketLog;
+use super::{Impairment, ImpairmentProxy};
+use crate::{Error, Profile, Receiver, ReceiverOptions, Result, Sender};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::net::UdpSocket;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Payload length of the test packets, seven TS packets.
+const PAYLOAD_LEN: usize = 1316;
+
+/// RTP payload type for MPEG-2 TS.
+const RTP_MPEG_TS: u8 = 33;
+
+/// RTCP packet types librist sends: SR, RR, SDES, BYE, APP, RTPFB.
+const RTCP_SR: u8 = 200;
+const RTCP_APP: u8 = 204;
+const RTCP_RTPFB: u8 = 205;
+
+/// GRE protocol types used by TR-06-2.
+const GRE_KEEPALIVE: u16 = 0x88b5;
+const GRE_REDUCED: u16 = 0x88b6;
+const GRE_FULL: u16 = 0x0800;
+const GRE_EAPOL: u16 = 0x888e;
+
+/// Free ports tried before giving up, in case another process takes one
+/// between finding it and binding it.
+const PORT_ATTEMPTS: usize = 5;
+
+/// How long a reference tool gets to bind its sockets before it is used.
+const REFERENCE_STARTUP: Duration = Duration::from_millis(500);
+
+/// One end of the session under test.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Endpoint {
+    /// A [`Sender`] or [`Receiver`] of this crate.
+    #[default]
+    Crate,
+    /// librist's reference tool at this path: `ristsender` on the sending
+    /// end, `ristreceiver` on the receiving end. It is fed and drained over
+    /// localhost UDP.
+    Reference(PathBuf),
+}
+
+/// How the conformance run stresses the link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConformanceOptions {
+    /// Packets streamed per profile.
+    pub packets: u32,
+    /// Share of sender-to-receiver packets the proxy drops, in percent, so
+    /// NACKs and retransmissions get exercised.
+    pub drop_percent: f64,
+    /// Receiver recovery buffer.
+    pub recovery: Duration,
+    /// How long to keep the session up after streaming, so keepalives are
+    /// seen even on a quiet link.
+    pub linger: Duration,
+    /// The sending end.
+    pub sender: Endpoint,
+    /// The receiving end.
+    pub receiver: Endpoint,
+}
+
+impl Default for ConformanceOptions {
+    fn default() -> Self {
+        Self {
+            packets: 500,
+            drop_percent: 5.0,
+            recovery: Duration::from_millis(500),
+            linger: Duration::from_secs(2),
+            sender: Endpoint::Crate,
+            receiver: Endpoint::Crate,
+        }
+    }
+}
+
+/// Outcome of one conformance item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    /// The profile the item was checked on.
+    pub profile: Profile,
+    /// Short name of the item, e.g. `rtp-header`.
+    pub name: &'static str,
+    /// `Err` with what was wrong if the item failed.
+    pub outcome: std::result::Result<(), String>,
+}
+
+/// Per-item results of a conformance run.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Report {
+    /// Every item checked, in order.
+    pub results: Vec<CheckResult>,
+}
+
+impl Report {
+    /// Returns `true` if every item passed.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.outcome.is_ok())
+    }
+
+    /// The items that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().filter(|result| result.outcome.is_err())
+    }
+
+    fn check(
+        &mut self,
+        profile: Profile,
+        name: &'static str,
+        outcome: std::result::Result<(), String>,
+    ) {
+        self.results.push(CheckResult {
+            profile,
+            name,
+            outcome,
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            let profile = format!("{:?}", result.profile);
+            match &result.outcome {
+                Ok(()) => writeln!(f, "PASS  {profile:<8} {}", result.name)?,
+                Err(reason) => writeln!(f, "FAIL  {profile:<8} {}: {reason}", result.name)?,
+            }
+        }
+        let passed = self.results.len() - self.failures().count();
+        write!(f, "{passed}/{} passed", self.results.len())
+    }
+}
+
+/// Run every conformance item on the Simple and Main profiles.
+///
+/// Items of a profile whose session can't be set up at all fail with the
+/// reason, rather than aborting the run.
+pub fn run(options: &ConformanceOptions) -> Report {
+    let mut report = Report::default();
+
+    match session(Profile::Simple, options) {
+        Ok(session) => check_simple(&mut report, &session, options),
+        Err(e) => report.check(Profile::Simple, "session", Err(e.to_string())),
+    }
+    match session(Profile::Main, options) {
+        Ok(session) => check_main(&mut report, &session, options),
+        Err(e) => report.check(Profile::Main, "session", Err(e.to_string())),
+    }
+
+    report
+}
+
+/// What one streaming session left on the wire.
+struct Session {
+    data: Arc<PacketLog>,
+    // Simple profile only, the RTCP port
+    rtcp: Option<Arc<PacketLog>>,
+    delivered: BTreeSet<u32>,
+}
+
+fn payload(n: u32) -> Vec<u8> {
+    let mut payload = vec![0x47u8; PAYLOAD_LEN];
+    payload[1..5].copy_from_slice(&n.to_be_bytes());
+    payload
+}
+
+fn session(profile: Profile, options: &ConformanceOptions) -> Result<Session> {
+    let impairment = Impairment::new()
+        .drop_percent(options.drop_percent)
+        .seed(0x7206);
+    let data = Arc::new(PacketLog::default());
+    let rtcp = (profile == Profile::Simple).then(|| Arc::new(PacketLog::default()));
+
+    let (front, rtcp_front) = bind_pair()?;
+    let send_port = front.local_addr().map_err(conformance_error)?.port();
+    let (receiver, listen_port) = on_free_port(|port| {
+        ReceivingEnd::start(profile, &options.receiver, port, options.recovery)
+    })?;
+
+    let _proxy = ImpairmentProxy::start_on(front, listen_port, impairment, Some(data.clone()))?;
+    let _rtcp_proxy = match &rtcp {
+        Some(log) => Some(ImpairmentProxy::start_on(
+            rtcp_front,
+            listen_port + 1,
+            Impairment::new(),
+            Some(log.clone()),
+        )?),
+        None => None,
+    };
+
+    let sender = SendingEnd::start(profile, &options.sender, send_port)?;
+
+    let mut delivered = BTreeSet::new();
+    for n in 0..options.packets {
+        sender.send(&payload(n))?;
+        thread::sleep(Duration::from_millis(1));
+        while let Some(payload) = receiver.read(Duration::ZERO)? {
+            delivered.extend(packet_number(&payload));
+        }
+    }
+    while delivered.len() < options.packets as usize {
+        match receiver.read(options.recovery * 2)? {
+            Some(payload) => delivered.extend(packet_number(&payload)),
+            None => break,
+        }
+    }
+    thread::sleep(options.linger);
+
+    Ok(Session {
+        data,
+        rtcp,
+        delivered,
+    })
+}
+
+/// The receiving end of a session.
+enum ReceivingEnd {
+    Crate(Receiver),
+    // ristreceiver, forwarding what it receives to `output`
+    Reference {
+        _tool: ReferenceTool,
+        output: UdpSocket,
+    },
+}
+
+impl ReceivingEnd {
+    /// Start listening on `port`, and the port after it on the Simple
+    /// profile.
+    fn start(profile: Profile, endpoint: &Endpoint, port: u16, recovery: Duration) -> Result<Self> {
+        match endpoint {
+            Endpoint::Crate => {
+                let mut receiver = Receiver::new(profile)?;
+                let options = ReceiverOptions::new()
+                    .recovery_length_min(recovery)
+                    .recovery_length_max(recovery);
+                receiver.add_peer_with_options(&format!("rist://@127.0.0.1:{port}"), &options)?;
+                receiver.start()?;
+                Ok(Self::Crate(receiver))
+            }
+            Endpoint::Reference(path) => {
+                let output = UdpSocket::bind("127.0.0.1:0").map_err(conformance_error)?;
+                let output_port = output.local_addr().map_err(conformance_error)?.port();
+                let tool = ReferenceTool::spawn(
+                    path,
+                    profile,
+                    &format!("rist://@127.0.0.1:{port}?buffer={}", recovery.as_millis()),
+                    &format!("udp://127.0.0.1:{output_port}"),
+                )?;
+                Ok(Self::Reference {
+                    _tool: tool,
+                    output,
+                })
+            }
+        }
+    }
+
+    /// The next payload delivered within `timeout`.
+    fn read(&self, timeout: Duration) -> Result<Option<Vec<u8>>> {
+        let output = match self {
+            Self::Crate(receiver) => {
+                return Ok(receiver
+                    .read(timeout)?
+                    .map(|block| block.payload().to_vec()))
+            }
+            Self::Reference { output, .. } => output,
+        };
+        // a zero read timeout is rejected, so poll without blocking instead
+        output
+            .set_nonblocking(timeout.is_zero())
+            .and_then(|_| output.set_read_timeout((!timeout.is_zero()).then_some(timeout)))
+            .map_err(conformance_error)?;
+        let mut buf = vec![0u8; rist_sys::RIST_MAX_PACKET_SIZE as usize];
+        match output.recv(&mut buf) {
+            Ok(n) => {
+                buf.truncate(n);
+                Ok(Some(buf))
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(conformance_error(e)),
+        }
+    }
+}
+
+/// The sending end of a session.
+enum SendingEnd {
+    Crate(Box<Sender>),
+    // ristsender, sending what it reads from `input`
+    Reference {
+        _tool: ReferenceTool,
+        input: UdpSocket,
+    },
+}
+
+impl SendingEnd {
+    /// Start sending to `127.0.0.1:port`.
+    fn start(profile: Profile, endpoint: &Endpoint, port: u16) -> Result<Self> {
+        let url = format!("rist://127.0.0.1:{port}");
+        match endpoint {
+            Endpoint::Crate => {
+                let mut sender = Sender::new(profile)?;
+                sender.add_peer(&url)?;
+                sender.start()?;
+                Ok(Self::Crate(Box::new(sender)))
+            }
+            Endpoint::Reference(path) => {
+                let (tool, input_port) = on_free_port(|input_port| {
+                    ReferenceTool::spawn(
+                        path,
+                        profile,
+                        &format!("udp://@127.0.0.1:{input_port}"),
+                        &url,
+                    )
+                })?;
+                let input = UdpSocket::bind("127.0.0.1:0")
+                    .and_then(|input| input.connect(("127.0.0.1", input_port)).map(|_| input))
+                    .map_err(conformance_error)?;
+                Ok(Self::Reference { _tool: tool, input })
+            }
+        }
+    }
+
+    fn send(&self, payload: &[u8]) -> Result<()> {
+        match self {
+            Self::Crate(sender) => sender.send(payload).map(|_| ()),
+            Self::Reference { input, .. } => {
+                input.send(payload).map(|_| ()).map_err(conformance_error)
+            }
+        }
+    }
+}
+
+/// A running librist tool, killed when dropped.
+struct ReferenceTool(Child);
+
+impl ReferenceTool {
+    /// Run the tool at `path` from `input` to `output`, failing if it exits
+    /// during startup, e.g. because its port was taken.
+    fn spawn(path: &Path, profile: Profile, input: &str, output: &str) -> Result<Self> {
+        let child = Command::new(path)
+            .args(["-p", &profile.to_raw().to_string()])
+            .args(["-i", input, "-o", output])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(conformance_error)?;
+        let mut tool = Self(child);
+
+        thread::sleep(REFERENCE_STARTUP);
+        match tool.0.try_wait().map_err(conformance_error)? {
+            Some(status) => Err(Error::Configuration(format!(
+                "conformance: {} exited with {status}",
+                path.display()
+            ))),
+            None => Ok(tool),
+        }
+    }
+}
+
+impl Drop for ReferenceTool {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Call `start` with an even localhost port whose successor is free too,
+/// trying other ports if it fails, since the port is only free when found.
+/// Returns what `start` returned and the port it got.
+fn on_free_port<T>(mut start: impl FnMut(u16) -> Result<T>) -> Result<(T, u16)> {
+    let mut last_error = None;
+    for _ in 0..PORT_ATTEMPTS {
+        // the Simple profile uses an even port for RTP and the next one for RTCP
+        let port = {
+            let (socket, _) = bind_pair()?;
+            socket.local_addr().map_err(conformance_error)?.port()
+        };
+        match start(port) {
+            Ok(started) => return Ok((started, port)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.expect("at least one attempt"))
+}
+
+/// The number a test payload carries.
+fn packet_number(payload: &[u8]) -> Option<u32> {
+    (payload.len() == PAYLOAD_LEN)
+        .then(|| u32::from_be_bytes([payload[1], payload[2], payload[3], payload[4]]))
+}
+
+/// Bind an even localhost port and the odd one after it.
+fn bind_pair() -> Result<(UdpSocket, UdpSocket)> {
+    for _ in 0..100 {
+        let socket = UdpSocket::bind("127.0.0.1:0").map_err(conformance_error)?;
+        let port = socket.local_addr().map_err(conformance_error)?.port();
+        if port % 2 != 0 || port == u16::MAX {
+            continue;
+        }
+        if let Ok(next) = UdpSocket::bind(("127.0.0.1", port + 1)) {
+            return Ok((socket, next));
+        }
+    }
+    Err(conformance_error(io::Error::new(
+        io::ErrorKind::AddrInUse,
+        "no free pair of UDP ports",
+    )))
+}
+
+fn conformance_error(e: io::Error) -> Error {
+    Error::Configuration(format!("conformance: {e}"))
+}
+
+fn check_recovery(
+    report: &mut Report,
+    profile: Profile,
+    session: &Session,
+    options: &ConformanceOptions,
+) {
+    let missing = options.packets as usize - session.delivered.len();
+    let outcome = if missing == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "{missing} of {} packets never delivered",
+            options.packets
+        ))
+    };
+    report.check(profile, "recovery", outcome);
+}
+
+/// An RTP header, RFC 3550 section 5.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RtpHeader {
+    version: u8,
+    csrc_count: u8,
+    extension: bool,
+    payload_type: u8,
+    sequence: u16,
+    ssrc: u32,
+}
+
+impl RtpHeader {
+    const LEN: usize = 12;
+
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < Self::LEN {
+            return None;
+        }
+        Some(Self {
+            version: packet[0] >> 6,
+            csrc_count: packet[0] & 0x0f,
+            extension: packet[0] & 0x10 != 0,
+            payload_type: packet[1] & 0x7f,
+            sequence: u16::from_be_bytes([packet[2], packet[3]]),
+            ssrc: u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]),
+        })
+    }
+
+    /// Retransmissions set the lowest SSRC bit, TR-06-1 section 5.3.
+    fn is_retransmission(&self) -> bool {
+        self.ssrc & 1 == 1
+    }
+}
+
+/// Packet types of the packets in an RTCP compound packet, or `None` if it
+/// isn't one.
+fn rtcp_types(mut packet: &[u8]) -> Option<Vec<u8>> {
+    let mut types = Vec::new();
+    while !packet.is_empty() {
+        if packet.len() < 4 || packet[0] >> 6 != 2 {
+            return None;
+        }
+        let len = (u16::from_be_bytes([packet[2], packet[3]]) as usize + 1) * 4;
+        if len > packet.len() {
+            return None;
+        }
+        types.push(packet[1]);
+        packet = &packet[len..];
+    }
+    (!types.is_empty()).then_some(types)
+}
+
+fn check_simple(report: &mut Report, session: &Session, options: &ConformanceOptions) {
+    let profile = Profile::Simple;
+    let packets = session.data.forwarded();
+    let headers: Vec<(RtpHeader, &[u8])> = packets
+        .iter()
+        .filter_map(|packet| {
+            RtpHeader::parse(packet).map(|header| (header, &packet[RtpHeader::LEN..]))
+        })
+        .collect();
+    let originals: Vec<&(RtpHeader, &[u8])> = headers
+        .iter()
+        .filter(|(header, _)| !header.is_retransmission())
+        .collect();
+
+    let outcome = if headers.len() != packets.len() {
+        Err(format!(
+            "{} packets too short for RTP",
+            packets.len() - headers.len()
+        ))
+    } else if originals.is_empty() {
+        Err("no RTP packets seen".to_string())
+    } else if let Some((header, _)) = headers.iter().find(|(header, _)| header.version != 2) {
+        Err(format!("RTP version {}", header.version))
+    } else if let Some((header, _)) = headers
+        .iter()
+        .find(|(header, _)| header.payload_type != RTP_MPEG_TS)
+    {
+        Err(format!(
+            "payload type {}, expected {RTP_MPEG_TS}",
+            header.payload_type
+        ))
+    } else if headers
+        .iter()
+        .any(|(header, _)| header.csrc_count != 0 || header.extension)
+    {
+        Err("CSRCs or header extension present".to_string())
+    } else if originals
+        .iter()
+        .any(|(_, payload)| packet_number(payload).is_none())
+    {
+        Err("payload does not follow the 12-byte header".to_string())
+    } else {
+        Ok(())
+    };
+    report.check(profile, "rtp-header", outcome);
+
+    let gaps = originals
+        .windows(2)
+        .filter(|pair| pair[1].0.sequence != pair[0].0.sequence.wrapping_add(1))
+        .count();
+    let outcome = if gaps == 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "{gaps} sequence number jumps between original packets"
+        ))
+    };
+    report.check(profile, "rtp-sequence", outcome);
+
+    let retransmissions: Vec<&RtpHeader> = headers
+        .iter()
+        .map(|(header, _)| header)
+        .filter(|header| header.is_retransmission())
+        .collect();
+    let ssrc = originals.first().map(|(header, _)| header.ssrc);
+    let outcome = match (ssrc, retransmissions.first()) {
+        (None, _) => Err("no RTP packets seen".to_string()),
+        (_, None) => Err("no retransmissions seen".to_string()),
+        (Some(ssrc), Some(_)) if retransmissions.iter().any(|header| header.ssrc != ssrc | 1) => {
+            Err(format!("retransmission SSRC differs from {:#x} | 1", ssrc))
+        }
+        _ => Ok(()),
+    };
+    report.check(profile, "retransmission-ssrc", outcome);
+
+    let (sent, returned) = match &session.rtcp {
+        Some(log) => (log.forwarded(), log.returned()),
+        None => Default::default(),
+    };
+    for (name, rtcp) in [("rtcp-sender", &sent), ("rtcp-receiver", &returned)] {
+        let outcome = if rtcp.is_empty() {
+            Err("no RTCP packets seen".to_string())
+        } else if rtcp.iter().any(|packet| rtcp_types(packet).is_none()) {
+            Err("malformed RTCP compound packet".to_string())
+        } else {
+            Ok(())
+        };
+        report.check(profile, name, outcome);
+    }
+
+    let sender_reports = sent
+        .iter()
+        .filter_map(|packet| rtcp_types(packet))
+        .any(|types| types.contains(&RTCP_SR));
+    let outcome = if sender_reports {
+        Ok(())
+    } else {
+        Err("sender sent no SR".to_string())
+    };
+    report.check(profile, "rtcp-sender-report", outcome);
+
+    let nacks = returned
+        .iter()
+        .filter_map(|packet| rtcp_types(packet))
+        .any(|types| types.iter().any(|t| *t == RTCP_APP || *t == RTCP_RTPFB));
+    let outcome = if nacks {
+        Ok(())
+    } else {
+        Err("receiver sent no NACK".to_string())
+    };
+    report.check(profile, "nack", outcome);
+
+    check_recovery(report, profile, session, options);
+}
+
+/// A GRE header, RFC 2890, as TR-06-2 puts it on UDP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GreHeader {
+    version: u8,
+    protocol: u16,
+    len: usize,
+}
+
+impl GreHeader {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < 4 {
+            return None;
+        }
+        let flags = u16::from_be_bytes([packet[0], packet[1]]);
+        // checksum, key and sequence number each add four bytes
+        let optional = [0x8000, 0x2000, 0x1000]
+            .iter()
+            .filter(|bit| flags & **bit != 0)
+            .count();
+        let len = 4 + 4 * optional;
+        (packet.len() >= len).then(|| Self {
+            version: (flags & 0x0007) as u8,
+            protocol: u16::from_be_bytes([packet[2], packet[3]]),
+            len,
+        })
+    }
+}
+
+fn check_main(report: &mut Report, session: &Session, options: &ConformanceOptions) {
+    let profile = Profile::Main;
+    let forwarded = session.data.forwarded();
+    let returned = session.data.returned();
+
+    let mut outcome = Ok(());
+    for packet in forwarded.iter().chain(&returned) {
+        outcome = match GreHeader::parse(packet) {
+            None => Err("packet too short for GRE".to_string()),
+            Some(gre) if gre.version != 0 => Err(format!("GRE version {}", gre.version)),
+            Some(gre)
+                if ![GRE_KEEPALIVE, GRE_REDUCED, GRE_FULL, GRE_EAPOL].contains(&gre.protocol) =>
+            {
+                Err(format!("unknown GRE protocol type {:#06x}", gre.protocol))
+            }
+            Some(_) => continue,
+        };
+        break;
+    }
+    report.check(profile, "gre-header", outcome);
+
+    // reduced overhead mode: GRE, UDP ports, then the RTP packet
+    let data: Vec<&[u8]> = forwarded
+        .iter()
+        .filter_map(|packet| {
+            let gre = GreHeader::parse(packet)?;
+            (gre.protocol == GRE_REDUCED).then(|| &packet[gre.len..])
+        })
+        .filter(|rest| rest.len() > 4 + RtpHeader::LEN)
+        .collect();
+    let outcome = if data.is_empty() {
+        Err("no reduced overhead data packets seen".to_string())
+    } else if data
+        .iter()
+        .any(|rest| !matches!(RtpHeader::parse(&rest[4..]), Some(header) if header.version == 2))
+    {
+        Err("data packet without an RTP header after the port fields".to_string())
+    } else if !data
+        .iter()
+        .any(|rest| packet_number(&rest[4 + RtpHeader::LEN..]).is_some())
+    {
+        Err("payload does not follow the RTP header".to_string())
+    } else {
+        Ok(())
+    };
+    report.check(profile, "gre-data", outcome);
+
+    let keepalive = |packets: &[Vec<u8>]| {
+        packets
+            .iter()
+            .filter_map(|packet| GreHeader::parse(packet))
+            .any(|gre| gre.protocol == GRE_KEEPALIVE)
+    };
+    for (name, packets) in [
+        ("keepalive-sender", &forwarded),
+        ("keepalive-receiver", &returned),
+    ] {
+        let outcome = if keepalive(packets) {
+            Ok(())
+        } else {
+            Err("no GRE keepalive seen".to_string())
+        };
+        report.check(profile, name, outcome);
+    }
+
+    check_recovery(report, profile, session, options);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtp(sequence: u16, ssrc: u32) -> Vec<u8> {
+        let mut packet = vec![0x80, RTP_MPEG_TS, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        packet[2..4].copy_from_slice(&sequence.to_be_bytes());
+        packet[8..12].copy_from_slice(&ssrc.to_be_bytes());
+        packet.extend_from_slice(&payload(u32::from(sequence)));
+        packet
+    }
+
+    #[test]
+    fn test_parse_rtp() {
+        let header = RtpHeader::parse(&rtp(7, 0x1234)).unwrap();
+        assert_eq!(header.version, 2);
+        assert_eq!(header.payload_type, RTP_MPEG_TS);
+        assert_eq!(header.sequence, 7);
+        assert!(!header.is_retransmission());
+        assert!(RtpHeader::parse(&rtp(7, 0x1235))
+            .unwrap()
+            .is_retransmission());
+        assert_eq!(RtpHeader::parse(&[0x80; 11]), None);
+    }
+
+    #[test]
+    fn test_rtcp_compound() {
+        // an empty RR followed by an SDES with one 4-byte chunk
+        let compound = [
+            0x80, 201, 0, 1, 0, 0, 0, 1, // RR
+            0x81, 202, 0, 1, 0, 0, 0, 1, // SDES
+        ];
+        assert_eq!(rtcp_types(&compound), Some(vec![201, 202]));
+        assert_eq!(rtcp_types(&compound[..12]), None);
+        assert_eq!(rtcp_types(&[0x40, 201, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_parse_gre() {
+        let keepalive = [0x00, 0x00, 0x88, 0xb5, 1, 2, 3];
+        let gre = GreHeader::parse(&keepalive).unwrap();
+        assert_eq!((gre.version, gre.protocol, gre.len), (0, GRE_KEEPALIVE, 4));
+
+        // key and sequence number present
+        let data = [0x30, 0x00, 0x88, 0xb6, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(GreHeader::parse(&data).unwrap().len, 12);
+        assert_eq!(GreHeader::parse(&data[..8]), None);
+    }
+
+    #[test]
+    fn test_report_lists_failures() {
+        let mut report = Report::default();
+        report.check(Profile::Simple, "rtp-header", Ok(()));
+        report.check(Profile::Main, "gre-data", Err("missing".to_string()));
+        assert!(!report.passed());
+        assert_eq!(report.failures().count(), 1);
+        assert_eq!(
+            report.to_string(),
+            "PASS  Simple   rtp-header\nFAIL  Main     gre-data: missing\n1/2 passed"
+        );
+    }
+
+    #[test]
+    #[ignore = "streams over localhost for several seconds"]
+    fn test_conformance() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let report = run(&ConformanceOptions::default());
+        assert!(report.passed(), "{report}");
+    }
+}
@@ -0,0 +1,496 @@
+//! UDP proxy that impairs traffic between a RIST sender and receiver.
+
+use crate::{Error, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the proxy threads wake up to release delayed packets and check
+/// for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Packets a [`PacketLog`] keeps per direction.
+const LOG_LIMIT: usize = 4096;
+
+/// Network impairments applied to the sender-to-receiver direction.
+///
+/// Decisions come from a PRNG seeded with `seed`, so the same settings drop
+/// and reorder the same packets on every run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Impairment {
+    /// Percentage of packets to drop (0-100).
+    pub drop_percent: f64,
+    /// Percentage of packets to hold back so later packets overtake them (0-100).
+    pub reorder_percent: f64,
+    /// How long a reordered packet is held back.
+    pub reorder_delay: Duration,
+    /// Maximum extra delay added to each packet, chosen uniformly.
+    pub jitter: Duration,
+    /// Also apply the impairments to receiver-to-sender traffic (NACKs, RTCP).
+    pub impair_return_path: bool,
+    /// PRNG seed.
+    pub seed: u64,
+}
+
+impl Default for Impairment {
+    fn default() -> Self {
+        Self {
+            drop_percent: 0.0,
+            reorder_percent: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            jitter: Duration::ZERO,
+            impair_return_path: false,
+            seed: 0x5eed,
+        }
+    }
+}
+
+impl Impairment {
+    /// Create an impairment that passes everything through unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the drop percentage.
+    pub fn drop_percent(mut self, percent: f64) -> Self {
+        self.drop_percent = percent;
+        self
+    }
+
+    /// Set the reorder percentage.
+    pub fn reorder_percent(mut self, percent: f64) -> Self {
+        self.reorder_percent = percent;
+        self
+    }
+
+    /// Set how long reordered packets are held back.
+    pub fn reorder_delay(mut self, delay: Duration) -> Self {
+        self.reorder_delay = delay;
+        self
+    }
+
+    /// Set the maximum per-packet jitter.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Apply impairments to the return path too.
+    pub fn impair_return_path(mut self, enabled: bool) -> Self {
+        self.impair_return_path = enabled;
+        self
+    }
+
+    /// Set the PRNG seed.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// Counters kept by an [`ImpairmentProxy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImpairmentStats {
+    /// Packets forwarded towards the receiver.
+    pub forwarded: u64,
+    /// Packets dropped on the way to the receiver.
+    pub dropped: u64,
+    /// Packets held back to be reordered.
+    pub reordered: u64,
+    /// Packets forwarded back towards the sender.
+    pub returned: u64,
+}
+
+/// Copies of the first packets a proxy saw, for inspecting the wire format.
+#[derive(Default)]
+pub(crate) struct PacketLog {
+    forwarded: Mutex<Vec<Vec<u8>>>,
+    returned: Mutex<Vec<Vec<u8>>>,
+}
+
+impl PacketLog {
+    fn record(log: &Mutex<Vec<Vec<u8>>>, data: &[u8]) {
+        let mut log = log.lock().unwrap_or_else(|e| e.into_inner());
+        if log.len() < LOG_LIMIT {
+            log.push(data.to_vec());
+        }
+    }
+
+    /// Packets from the sender, including those the proxy dropped.
+    pub(crate) fn forwarded(&self) -> Vec<Vec<u8>> {
+        self.forwarded
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Packets from the receiver back to the sender.
+    pub(crate) fn returned(&self) -> Vec<Vec<u8>> {
+        self.returned
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    reordered: AtomicU64,
+    returned: AtomicU64,
+}
+
+/// What to do with one packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Drop,
+    Forward { delay: Duration, reordered: bool },
+}
+
+const PASS: Verdict = Verdict::Forward {
+    delay: Duration::ZERO,
+    reordered: false,
+};
+
+/// Deterministic xorshift64* generator.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform value in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+struct Impairer {
+    impairment: Impairment,
+    rng: Rng,
+}
+
+impl Impairer {
+    fn new(impairment: Impairment) -> Self {
+        Self {
+            impairment,
+            rng: Rng::new(impairment.seed),
+        }
+    }
+
+    fn judge(&mut self) -> Verdict {
+        // always draw the same number of values so one knob doesn't shift
+        // the decisions of another
+        let drop = self.rng.next_f64() * 100.0;
+        let reorder = self.rng.next_f64() * 100.0;
+        let jitter = self.rng.next_f64();
+
+        if drop < self.impairment.drop_percent {
+            return Verdict::Drop;
+        }
+
+        let mut delay = self.impairment.jitter.mul_f64(jitter);
+        let reordered = reorder < self.impairment.reorder_percent;
+        if reordered {
+            delay += self.impairment.reorder_delay;
+        }
+        Verdict::Forward { delay, reordered }
+    }
+}
+
+/// A packet waiting for its release time.
+struct Delayed {
+    release: Instant,
+    order: u64,
+    data: Vec<u8>,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        (self.release, self.order) == (other.release, other.order)
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.release, self.order).cmp(&(other.release, other.order))
+    }
+}
+
+/// A UDP proxy applying [`Impairment`]s between a sender and a receiver.
+///
+/// Point the sender at [`port`](Self::port); traffic is forwarded to the
+/// receiver's port, and replies are sent back to whichever address the
+/// sender last used. Only single-port profiles (Main and Advanced) are
+/// supported; Simple profile's separate RTCP port is not proxied.
+pub struct ImpairmentProxy {
+    port: u16,
+    counters: Arc<Counters>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ImpairmentProxy {
+    /// Start a proxy forwarding to `127.0.0.1:target_port`.
+    pub fn start(target_port: u16, impairment: Impairment) -> Result<Self> {
+        let front = UdpSocket::bind("127.0.0.1:0").map_err(proxy_error)?;
+        Self::start_on(front, target_port, impairment, None)
+    }
+
+    /// Start a proxy listening on `front`, copying packets to `log`.
+    pub(crate) fn start_on(
+        front: UdpSocket,
+        target_port: u16,
+        impairment: Impairment,
+        log: Option<Arc<PacketLog>>,
+    ) -> Result<Self> {
+        let back = UdpSocket::bind("127.0.0.1:0").map_err(proxy_error)?;
+        back.connect(("127.0.0.1", target_port))
+            .map_err(proxy_error)?;
+        for socket in [&front, &back] {
+            socket
+                .set_read_timeout(Some(POLL_INTERVAL))
+                .map_err(proxy_error)?;
+        }
+        let port = front.local_addr().map_err(proxy_error)?.port();
+
+        let counters = Arc::new(Counters::default());
+        let stop = Arc::new(AtomicBool::new(false));
+        let sender_addr = Arc::new(Mutex::new(None::<SocketAddr>));
+        let front = Arc::new(front);
+        let back = Arc::new(back);
+
+        let forward = {
+            let (front, back) = (front.clone(), back.clone());
+            let (counters, stop, sender_addr) =
+                (counters.clone(), stop.clone(), sender_addr.clone());
+            let log = log.clone();
+            let mut impairer = Impairer::new(impairment);
+            thread::spawn(move || {
+                pump(
+                    &stop,
+                    |buf| {
+                        let (n, from) = front.recv_from(buf)?;
+                        *sender_addr.lock().unwrap() = Some(from);
+                        Ok(n)
+                    },
+                    |data| back.send(data).map(|_| ()),
+                    |data| {
+                        if let Some(log) = &log {
+                            PacketLog::record(&log.forwarded, data);
+                        }
+                        let verdict = impairer.judge();
+                        match verdict {
+                            Verdict::Drop => {
+                                counters.dropped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Verdict::Forward { reordered, .. } => {
+                                if reordered {
+                                    counters.reordered.fetch_add(1, Ordering::Relaxed);
+                                }
+                                counters.forwarded.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                        verdict
+                    },
+                )
+            })
+        };
+
+        let back_path = {
+            let (counters, stop) = (counters.clone(), stop.clone());
+            let mut impairer = Impairer::new(impairment.seed(impairment.seed.wrapping_add(1)));
+            thread::spawn(move || {
+                pump(
+                    &stop,
+                    |buf| back.recv(buf),
+                    |data| match *sender_addr.lock().unwrap() {
+                        Some(addr) => front.send_to(data, addr).map(|_| ()),
+                        None => Ok(()),
+                    },
+                    |data| {
+                        if let Some(log) = &log {
+                            PacketLog::record(&log.returned, data);
+                        }
+                        counters.returned.fetch_add(1, Ordering::Relaxed);
+                        if impairment.impair_return_path {
+                            impairer.judge()
+                        } else {
+                            PASS
+                        }
+                    },
+                )
+            })
+        };
+
+        Ok(Self {
+            port,
+            counters,
+            stop,
+            threads: vec![forward, back_path],
+        })
+    }
+
+    /// The localhost port senders should target.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Counters of what the proxy has done so far.
+    pub fn stats(&self) -> ImpairmentStats {
+        ImpairmentStats {
+            forwarded: self.counters.forwarded.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            reordered: self.counters.reordered.load(Ordering::Relaxed),
+            returned: self.counters.returned.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for ImpairmentProxy {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Move packets from `recv` to `send` until `stop` is set, applying `judge`.
+fn pump(
+    stop: &AtomicBool,
+    mut recv: impl FnMut(&mut [u8]) -> io::Result<usize>,
+    mut send: impl FnMut(&[u8]) -> io::Result<()>,
+    mut judge: impl FnMut(&[u8]) -> Verdict,
+) {
+    let mut buf = [0u8; rist_sys::RIST_MAX_PACKET_SIZE as usize];
+    let mut queue: BinaryHeap<Reverse<Delayed>> = BinaryHeap::new();
+    let mut order = 0u64;
+
+    while !stop.load(Ordering::Relaxed) {
+        match recv(&mut buf) {
+            Ok(n) => {
+                let data = &buf[..n];
+                match judge(data) {
+                    Verdict::Drop => {}
+                    Verdict::Forward { delay, .. } if delay.is_zero() && queue.is_empty() => {
+                        let _ = send(data);
+                    }
+                    Verdict::Forward { delay, .. } => {
+                        queue.push(Reverse(Delayed {
+                            release: Instant::now() + delay,
+                            order,
+                            data: data.to_vec(),
+                        }));
+                        order += 1;
+                    }
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            // the peer socket may not exist yet; keep going
+            Err(_) => {}
+        }
+
+        let now = Instant::now();
+        while queue.peek().is_some_and(|Reverse(d)| d.release <= now) {
+            let Reverse(delayed) = queue.pop().unwrap();
+            let _ = send(&delayed.data);
+        }
+    }
+}
+
+fn proxy_error(e: io::Error) -> Error {
+    Error::Configuration(format!("impairment proxy: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impairment_is_deterministic() {
+        let impairment = Impairment::new()
+            .drop_percent(10.0)
+            .reorder_percent(5.0)
+            .seed(42);
+
+        let run = || {
+            let mut impairer = Impairer::new(impairment);
+            (0..1000).map(|_| impairer.judge()).collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn test_impairment_drop_rate() {
+        let mut impairer = Impairer::new(Impairment::new().drop_percent(10.0));
+        let dropped = (0..10_000)
+            .filter(|_| impairer.judge() == Verdict::Drop)
+            .count();
+        assert!((800..1200).contains(&dropped), "dropped {dropped}");
+    }
+
+    #[test]
+    fn test_proxy_forwards_both_directions() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let target_port = receiver.local_addr().unwrap().port();
+        let proxy = ImpairmentProxy::start(target_port, Impairment::new()).unwrap();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        sender
+            .send_to(b"ping", ("127.0.0.1", proxy.port()))
+            .unwrap();
+
+        let mut buf = [0u8; 16];
+        let (n, proxy_addr) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ping");
+
+        receiver.send_to(b"pong", proxy_addr).unwrap();
+        let n = sender.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"pong");
+
+        let stats = proxy.stats();
+        assert_eq!(stats.forwarded, 1);
+        assert_eq!(stats.returned, 1);
+        assert_eq!(stats.dropped, 0);
+    }
+
+    #[test]
+    fn test_no_impairment_passes_everything() {
+        let mut impairer = Impairer::new(Impairment::new());
+        assert!((0..1000).all(|_| impairer.judge() == PASS));
+    }
+}
@@ -22,11 +22,27 @@
 //! # }
 //! ```
 
+mod buffered;
+mod channel;
+#[cfg(feature = "framed")]
+mod framed;
+mod handle;
+mod listener;
+mod ports;
+mod queue;
 mod receiver;
+mod reconnect;
 mod sender;
 
+pub use buffered::BufferedSender;
+#[cfg(feature = "framed")]
+pub use framed::RistFramed;
+pub use handle::SenderHandle;
+pub use listener::{IncomingPeer, RistListener};
+pub use ports::PortStream;
 pub use receiver::AsyncReceiver;
-pub use sender::AsyncSender;
+pub use reconnect::ReconnectPolicy;
+pub use sender::{AsyncSender, Connect};
 
 #[cfg(test)]
 mod tests {
@@ -54,6 +70,21 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_receiver_bind_all() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let first = format!("rist://@:{}", crate::next_test_port());
+        let second = format!("rist://@:{}", crate::next_test_port());
+        if let Ok(receiver) = AsyncReceiver::bind_all(Profile::Main, &[&first, &second]) {
+            assert_eq!(receiver.peers().len(), 2);
+        }
+
+        assert!(matches!(
+            AsyncReceiver::bind_all(Profile::Main, &[]),
+            Err(crate::Error::Configuration(_))
+        ));
+    }
+
     #[tokio::test]
     async fn test_receiver_bind_rejects_invalid_fifo_size() {
         let _guard = crate::TEST_MUTEX.lock().unwrap();
@@ -82,6 +113,148 @@ mod tests {
         assert!(inner.unwrap().is_none()); // No data received
     }
 
+    #[tokio::test]
+    async fn test_receiver_recv_deadline() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let receiver = AsyncReceiver::bind(Profile::Main, &url).unwrap();
+
+        // a deadline in the past returns straight away
+        let start = std::time::Instant::now();
+        assert!(receiver.recv_deadline(start).await.unwrap().is_none());
+
+        let deadline = start + Duration::from_millis(100);
+        let result = timeout(Duration::from_millis(500), receiver.recv_deadline(deadline)).await;
+        assert!(result.unwrap().unwrap().is_none());
+        assert!(std::time::Instant::now() >= deadline);
+    }
+
+    #[cfg(feature = "framed")]
+    #[tokio::test]
+    async fn test_framed_ends_when_stopped() {
+        use futures_core::Stream;
+        use std::pin::Pin;
+
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let mut frames = RistFramed::new(AsyncReceiver::bind(Profile::Main, &url).unwrap());
+
+        let next = std::future::poll_fn(|cx| Pin::new(&mut frames).poll_next(cx));
+        assert!(timeout(Duration::from_millis(100), next).await.is_err());
+
+        frames.get_ref().stop().unwrap();
+        let next = std::future::poll_fn(|cx| Pin::new(&mut frames).poll_next(cx));
+        assert!(next.await.is_none());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[tokio::test]
+    async fn test_recv_into_takes_leftover_first() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender,
+            mut receiver,
+            ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        sender.send(b"head rest").await.unwrap();
+        // an AsyncRead read with room for part of the payload keeps the rest
+        let mut head = [0u8; 5];
+        let read = timeout(Duration::from_secs(2), receiver.read(&mut head));
+        assert_eq!(read.await.unwrap().unwrap(), 5);
+        assert_eq!(&head, b"head ");
+
+        let mut buf = bytes::BytesMut::from(&b"> "[..]);
+        assert_eq!(receiver.recv_into(&mut buf).await.unwrap(), 4);
+        assert_eq!(&buf[..], b"> rest");
+
+        let next = receiver.recv_into(&mut buf);
+        assert!(timeout(Duration::from_millis(100), next).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ordered_reads_end_when_stopped() {
+        use ::tokio::io::{AsyncRead, ReadBuf};
+        use std::pin::Pin;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake, Waker};
+
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let mut receiver = AsyncReceiver::bind(Profile::Main, &url).unwrap();
+        receiver.set_ordered_reads(Some(
+            crate::continuity::Reorderer::new(8).max_hold(Duration::from_millis(50)),
+        ));
+
+        struct Flag(AtomicBool);
+        impl Wake for Flag {
+            fn wake(self: Arc<Self>) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+        let woken = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut storage = [0u8; 16];
+        let mut buf = ReadBuf::new(&mut storage);
+
+        let read = Pin::new(&mut receiver).poll_read(&mut cx, &mut buf);
+        assert!(read.is_pending());
+
+        // a read already waiting is woken by stop, and then sees end of file
+        receiver.stop().unwrap();
+        timeout(Duration::from_secs(1), async {
+            while !woken.0.load(Ordering::SeqCst) {
+                ::tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        let read = Pin::new(&mut receiver).poll_read(&mut cx, &mut buf);
+        assert!(matches!(read, Poll::Ready(Ok(()))));
+        assert!(buf.filled().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_recv_ends_when_stopped() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let receiver = std::sync::Arc::new(AsyncReceiver::bind(Profile::Main, &url).unwrap());
+
+        let waiting = ::tokio::spawn({
+            let receiver = std::sync::Arc::clone(&receiver);
+            async move { receiver.recv().await }
+        });
+        ::tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        receiver.stop().unwrap();
+        let result = timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("recv still waiting after stop")
+            .unwrap();
+        assert!(matches!(result, Ok(None)));
+        // and later calls return straight away
+        assert!(matches!(receiver.recv().await, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn test_receiver_poll_recv_pending() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://@:{}", crate::next_test_port());
+        let receiver = AsyncReceiver::bind(Profile::Main, &url).unwrap();
+
+        let result = timeout(
+            Duration::from_millis(100),
+            std::future::poll_fn(|cx| receiver.poll_recv(cx)),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn test_sender_connect_empty_url() {
         let _guard = crate::TEST_MUTEX.lock().unwrap();
@@ -132,6 +305,65 @@ mod tests {
         let _ = receiver.raw_stats();
     }
 
+    #[tokio::test]
+    async fn test_recv_burst() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender, receiver, ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        for n in 0..10u8 {
+            sender.send(&[n; 1316]).await.unwrap();
+        }
+        ::tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut received = Vec::new();
+        while received.len() < 10 {
+            match ::tokio::time::timeout(Duration::from_secs(1), receiver.recv_burst()).await {
+                Ok(Ok(blocks)) => received.extend(blocks),
+                _ => break,
+            }
+        }
+        let firsts: Vec<u8> = received.iter().map(|block| block.payload()[0]).collect();
+        assert_eq!(firsts, (0..10).collect::<Vec<u8>>());
+        assert!(receiver.try_recv().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_channel_adapters() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender, receiver, ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        let (tx, rx) = ::tokio::sync::mpsc::channel(16);
+        let send_task = AsyncSender::from_channel(sender, rx);
+        let (mut blocks, recv_task) = receiver.into_channel(16);
+
+        for _ in 0..10 {
+            tx.send([0x47u8; 1316]).await.unwrap();
+        }
+        drop(tx);
+        let _sender = send_task.await.unwrap().unwrap();
+
+        let block = timeout(Duration::from_secs(2), blocks.recv())
+            .await
+            .expect("expected a block through the channel")
+            .unwrap();
+        assert_eq!(block.payload().len(), 1316);
+
+        drop(blocks);
+        timeout(Duration::from_secs(2), recv_task)
+            .await
+            .expect("receiver task should stop once the channel is dropped")
+            .unwrap()
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_stats_available() {
         let _guard = crate::TEST_MUTEX.lock().unwrap();
@@ -171,4 +403,197 @@ mod tests {
         // depending on timing
         assert!(read_result.is_ok() || read_result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_sender_handle_sends_from_tasks() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender, receiver, ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        let sender = sender.into_handle();
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let sender = sender.clone();
+                ::tokio::spawn(async move { sender.send(&[0x47u8; 1316]).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(sender.handle_count(), 1);
+
+        let mut received = 0;
+        while let Ok(Some(_)) = receiver.recv_timeout(Duration::from_millis(500)).await {
+            received += 1;
+            if received == 4 {
+                break;
+            }
+        }
+        assert_eq!(received, 4);
+
+        let sender = sender.try_unwrap().unwrap();
+        sender.shutdown(Duration::from_millis(100)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buffered_sender_coalesces_writes() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender, receiver, ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        let buffered = BufferedSender::new(sender, Duration::from_millis(50));
+        for _ in 0..10 {
+            buffered.write(&[0xaa; 100]).await.unwrap();
+        }
+        assert_eq!(buffered.buffered().await, 1000);
+        buffered.flush().await.unwrap();
+        let block = receiver.recv_timeout(Duration::from_secs(2)).await.unwrap();
+        assert_eq!(block.unwrap().payload().len(), 1000);
+
+        // the rest goes out once the oldest byte has waited max_delay
+        for _ in 0..14 {
+            buffered.write(&[0xbb; 100]).await.unwrap();
+        }
+        let block = receiver.recv_timeout(Duration::from_secs(2)).await.unwrap();
+        assert_eq!(block.unwrap().payload().len(), 1300);
+        let block = receiver.recv_timeout(Duration::from_secs(2)).await.unwrap();
+        assert_eq!(block.unwrap().payload().len(), 100);
+        assert_eq!(buffered.buffered().await, 0);
+
+        let sender = buffered.into_inner().await.unwrap();
+        assert_eq!(sender.handle_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sender_shutdown_respects_timeout() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let crate::testing::AsyncLoopback {
+            sender, receiver, ..
+        } = crate::testing::async_loopback(Profile::Main, &Default::default())
+            .await
+            .unwrap();
+
+        for _ in 0..10 {
+            sender.send(&[0x47u8; 1316]).await.unwrap();
+        }
+
+        let started = std::time::Instant::now();
+        sender.shutdown(Duration::from_millis(200)).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        while let Ok(Some(data)) = receiver.recv_timeout(Duration::from_millis(100)).await {
+            assert_eq!(data.payload().len(), 1316);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_timeout_skips_instead_of_waiting() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let options = crate::testing::LoopbackOptions {
+            // 100 kB/s, so a second packet waits about 11 ms
+            sender: crate::SenderOptions::default().pace_bitrate(800_000),
+            ..Default::default()
+        };
+        let crate::testing::AsyncLoopback { sender, .. } =
+            crate::testing::async_loopback(Profile::Main, &options)
+                .await
+                .unwrap();
+
+        let packet = [0x47u8; 1316];
+        sender
+            .send_timeout(&packet, Duration::from_millis(1))
+            .await
+            .unwrap();
+        let started = std::time::Instant::now();
+        let result = sender.send_timeout(&packet, Duration::from_millis(1)).await;
+        assert!(matches!(result, Err(crate::Error::SendTimeout)));
+        assert!(started.elapsed() < Duration::from_millis(5));
+
+        // the skipped packet left the pacing budget alone
+        sender
+            .send_timeout(&packet, Duration::from_millis(100))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bitrate_cap_drops_without_pacing() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let options = crate::testing::LoopbackOptions {
+            sender: crate::SenderOptions::default().pace_bitrate(200_000),
+            ..Default::default()
+        };
+        let crate::testing::AsyncLoopback { sender, .. } =
+            crate::testing::async_loopback(Profile::Main, &options)
+                .await
+                .unwrap();
+
+        // one packet fits under the cap, the rest are dropped
+        sender.set_bitrate_cap(Some(8_000));
+        let packet = [0x47u8; 1316];
+        sender.send(&packet).await.unwrap();
+        let started = std::time::Instant::now();
+        for _ in 0..19 {
+            let result = sender.send(&packet).await;
+            assert!(matches!(result, Err(crate::Error::BitrateCapExceeded)));
+        }
+        // 19 paced packets would take about a second
+        assert!(started.elapsed() < Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_without_receiver() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let url = format!("rist://127.0.0.1:{}", crate::next_test_port());
+        let result = AsyncSender::connect(Profile::Main, &url)
+            .connect_timeout(Duration::from_millis(200))
+            .await;
+        assert!(matches!(result, Err(crate::Error::ConnectTimeout)));
+    }
+
+    #[tokio::test]
+    async fn test_wait_connected() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let port = crate::next_test_port();
+        let _receiver = AsyncReceiver::bind(Profile::Main, &format!("rist://@:{port}")).unwrap();
+        let sender = AsyncSender::connect(Profile::Main, &format!("rist://127.0.0.1:{port}"))
+            .connect_timeout(Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        // already connected, so this resolves straight away
+        timeout(Duration::from_millis(100), sender.wait_connected())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_add_peer_to_running_contexts() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let first = crate::next_test_port();
+        let second = crate::next_test_port();
+        let receiver = AsyncReceiver::bind(Profile::Main, &format!("rist://@:{first}")).unwrap();
+        let extra = receiver.add_peer(&format!("rist://@:{second}")).unwrap();
+        assert!(receiver.peers().iter().any(|peer| peer.id == extra));
+
+        let sender = AsyncSender::connect(Profile::Main, &format!("rist://127.0.0.1:{first}"))
+            .await
+            .unwrap();
+        sender
+            .add_peer(&format!("rist://127.0.0.1:{second}"))
+            .unwrap();
+        assert_eq!(sender.peers().len(), 2);
+
+        sender.send(&[0x47u8; 1316]).await.unwrap();
+        if let Ok(Some(data)) = receiver.recv_timeout(Duration::from_millis(500)).await {
+            assert_eq!(data.payload().len(), 1316);
+        }
+    }
 }
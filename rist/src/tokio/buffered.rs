@@ -0,0 +1,212 @@
+//! Coalescing small writes into full payloads.
+
+use super::SenderHandle;
+use crate::ts::PAYLOAD_SIZE;
+use crate::{Error, Result};
+use ::tokio::sync::{Mutex, Notify};
+use ::tokio::task::JoinHandle;
+use ::tokio::time::Instant;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Collects small writes into full payloads before sending them.
+///
+/// For applications that produce many tiny messages, e.g. KLV metadata,
+/// where sending each as its own packet would waste most of the bandwidth
+/// on headers. Writes are appended to a buffer that is sent as one payload
+/// once it is full, once `max_delay` has passed since the first write
+/// into it, or on [`flush`](Self::flush).
+///
+/// A write is never split across payloads unless it is larger than a
+/// payload by itself: one that doesn't fit in the room left sends the
+/// buffer first. Data still buffered when this is dropped is lost, so
+/// flush before dropping it or use [`into_inner`](Self::into_inner).
+///
+/// ```no_run
+/// use rist::tokio::{AsyncSender, BufferedSender};
+/// use rist::Profile;
+/// use std::time::Duration;
+///
+/// # async fn example(messages: Vec<Vec<u8>>) -> rist::Result<()> {
+/// let sender = AsyncSender::connect(Profile::Main, "rist://127.0.0.1:5000").await?;
+/// let buffered = BufferedSender::new(sender, Duration::from_millis(20));
+/// for message in &messages {
+///     buffered.write(message).await?;
+/// }
+/// buffered.flush().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BufferedSender {
+    shared: Arc<Shared>,
+    timer: JoinHandle<()>,
+}
+
+struct Shared {
+    sender: SenderHandle,
+    payload_size: usize,
+    max_delay: Duration,
+    pending: Mutex<Pending>,
+    // wakes the timer when the first byte goes into an empty buffer
+    armed: Notify,
+}
+
+#[derive(Default)]
+struct Pending {
+    buf: Vec<u8>,
+    // when the buffer is due to be sent, set while it holds data
+    due: Option<Instant>,
+    // a failed send by the timer, reported by the next write or flush
+    error: Option<Error>,
+}
+
+impl BufferedSender {
+    /// Coalesce writes into [`PAYLOAD_SIZE`]-byte payloads, the size of
+    /// seven TS packets, sending each within `max_delay` of its first
+    /// write.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn new(sender: impl Into<SenderHandle>, max_delay: Duration) -> Self {
+        Self::with_payload_size(sender, PAYLOAD_SIZE, max_delay)
+    }
+
+    /// Coalesce writes into payloads of up to `payload_size` bytes,
+    /// limited to what librist can send in one packet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn with_payload_size(
+        sender: impl Into<SenderHandle>,
+        payload_size: usize,
+        max_delay: Duration,
+    ) -> Self {
+        let payload_size = payload_size.clamp(1, rist_sys::RIST_MAX_PACKET_SIZE as usize);
+        let shared = Arc::new(Shared {
+            sender: sender.into(),
+            payload_size,
+            max_delay,
+            pending: Mutex::new(Pending {
+                buf: Vec::with_capacity(payload_size),
+                ..Pending::default()
+            }),
+            armed: Notify::new(),
+        });
+        let timer = ::tokio::spawn(run_timer(shared.clone()));
+        Self { shared, timer }
+    }
+
+    /// Buffer `data`, sending whatever payloads it completes.
+    ///
+    /// Fails with the error of a send the timer made since the last call,
+    /// if it failed; that data is lost.
+    pub async fn write(&self, data: &[u8]) -> Result<()> {
+        let size = self.shared.payload_size;
+        let mut pending = self.shared.pending.lock().await;
+        if let Some(error) = pending.error.take() {
+            return Err(error);
+        }
+        if pending.buf.len() + data.len() > size {
+            self.shared.send(&mut pending).await?;
+        }
+
+        let mut chunks = data.chunks(size).peekable();
+        while let Some(chunk) = chunks.next() {
+            if chunk.len() == size && chunks.peek().is_some() {
+                self.shared.sender.send(chunk).await?;
+                continue;
+            }
+            if pending.buf.is_empty() {
+                pending.due = Some(Instant::now() + self.shared.max_delay);
+                self.shared.armed.notify_one();
+            }
+            pending.buf.extend_from_slice(chunk);
+        }
+        if pending.buf.len() == size {
+            self.shared.send(&mut pending).await?;
+        }
+        Ok(())
+    }
+
+    /// Send whatever is buffered now.
+    ///
+    /// Fails with the error of a send the timer made since the last call,
+    /// if it failed.
+    pub async fn flush(&self) -> Result<()> {
+        let mut pending = self.shared.pending.lock().await;
+        if let Some(error) = pending.error.take() {
+            return Err(error);
+        }
+        self.shared.send(&mut pending).await
+    }
+
+    /// Bytes waiting to be sent.
+    pub async fn buffered(&self) -> usize {
+        self.shared.pending.lock().await.buf.len()
+    }
+
+    /// The sender underneath.
+    pub fn get_ref(&self) -> &SenderHandle {
+        &self.shared.sender
+    }
+
+    /// Flush, stop the timer and hand back the sender.
+    pub async fn into_inner(mut self) -> Result<SenderHandle> {
+        self.flush().await?;
+        self.timer.abort();
+        // the timer task holds on to the sender until it is gone
+        let _ = (&mut self.timer).await;
+        Ok(self.shared.sender.clone())
+    }
+}
+
+impl Shared {
+    /// Send the buffer, if it holds anything. It is emptied even if the
+    /// send fails.
+    async fn send(&self, pending: &mut Pending) -> Result<()> {
+        pending.due = None;
+        if pending.buf.is_empty() {
+            return Ok(());
+        }
+        let result = self.sender.send(&pending.buf).await;
+        pending.buf.clear();
+        result.map(|_| ())
+    }
+}
+
+/// Send the buffer whenever it has waited `max_delay`.
+async fn run_timer(shared: Arc<Shared>) {
+    loop {
+        let due = shared.pending.lock().await.due;
+        let Some(due) = due else {
+            shared.armed.notified().await;
+            continue;
+        };
+        ::tokio::time::sleep_until(due).await;
+
+        let mut pending = shared.pending.lock().await;
+        // sent or refilled in the meantime
+        if pending.due.is_some_and(|current| current <= Instant::now()) {
+            if let Err(e) = shared.send(&mut pending).await {
+                pending.error = Some(e);
+            }
+        }
+    }
+}
+
+impl Drop for BufferedSender {
+    fn drop(&mut self) {
+        self.timer.abort();
+    }
+}
+
+impl std::fmt::Debug for BufferedSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferedSender")
+            .field("payload_size", &self.shared.payload_size)
+            .field("max_delay", &self.shared.max_delay)
+            .finish_non_exhaustive()
+    }
+}
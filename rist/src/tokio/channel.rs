@@ -0,0 +1,113 @@
+//! Adapters between RIST endpoints and `tokio::sync::mpsc` channels.
+
+use super::{AsyncReceiver, AsyncSender};
+use crate::{DataBlock, Error, Result};
+use ::tokio::sync::mpsc;
+use ::tokio::task::JoinHandle;
+
+impl AsyncReceiver {
+    /// Move the receiver into a task that forwards every block into a
+    /// bounded channel of `capacity` blocks.
+    ///
+    /// The task stops when the returned channel receiver is dropped, or when
+    /// reading fails, in which case the error is returned by the
+    /// [`JoinHandle`]. While the channel is full the task stops reading and
+    /// librist's FIFO absorbs the backlog.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero or if called outside a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rist::tokio::AsyncReceiver;
+    /// use rist::Profile;
+    ///
+    /// # async fn example() -> rist::Result<()> {
+    /// let receiver = AsyncReceiver::bind(Profile::Main, "rist://@:5000")?;
+    /// let (mut blocks, _task) = receiver.into_channel(64);
+    /// while let Some(block) = blocks.recv().await {
+    ///     println!("received {} bytes", block.payload().len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_channel(
+        self,
+        capacity: usize,
+    ) -> (mpsc::Receiver<DataBlock>, JoinHandle<Result<()>>) {
+        let (tx, rx) = mpsc::channel(capacity);
+
+        let task = ::tokio::spawn(async move {
+            loop {
+                let block = ::tokio::select! {
+                    _ = tx.closed() => return Ok(()),
+                    block = self.recv() => block?,
+                };
+                // the receiver was stopped and drained
+                let Some(block) = block else {
+                    return Ok(());
+                };
+                if tx.send(block).await.is_err() {
+                    return Ok(());
+                }
+            }
+        });
+
+        (rx, task)
+    }
+}
+
+impl AsyncSender {
+    /// Move `sender` into a task that sends every payload arriving on `rx`.
+    ///
+    /// Sends go through [`send`](Self::send), so pacing applies. Payloads
+    /// dropped by the [bitrate cap](Self::set_bitrate_cap) are skipped. Once
+    /// every channel sender is dropped the task hands the sender back
+    /// through the [`JoinHandle`], so it can be
+    /// [shut down](Self::shutdown) cleanly. Any other send error ends the
+    /// task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rist::tokio::AsyncSender;
+    /// use rist::Profile;
+    /// use std::time::Duration;
+    ///
+    /// # async fn example() -> rist::Result<()> {
+    /// let sender = AsyncSender::connect(Profile::Main, "rist://127.0.0.1:5000").await?;
+    /// let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(64);
+    /// let task = AsyncSender::from_channel(sender, rx);
+    ///
+    /// tx.send(vec![0x47; 1316]).await.ok();
+    /// drop(tx);
+    ///
+    /// let sender = task.await.map_err(|e| rist::Error::JoinError(e.to_string()))??;
+    /// sender.shutdown(Duration::from_secs(1)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_channel<T>(
+        sender: AsyncSender,
+        mut rx: mpsc::Receiver<T>,
+    ) -> JoinHandle<Result<Self>>
+    where
+        T: AsRef<[u8]> + Send + 'static,
+    {
+        ::tokio::spawn(async move {
+            while let Some(payload) = rx.recv().await {
+                match sender.send(payload.as_ref()).await {
+                    Ok(_) | Err(Error::BitrateCapExceeded) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(sender)
+        })
+    }
+}
@@ -0,0 +1,92 @@
+//! Reading a receiver as a `Stream` of whole blocks.
+
+use super::AsyncReceiver;
+use crate::{DataBlock, Result};
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An [`AsyncReceiver`] as a [`Stream`] of whole data blocks.
+///
+/// The receiver's `AsyncRead` impl hands out a byte stream, losing where
+/// one packet ends and the next begins. This yields every block as one
+/// item instead, so stream combinators and codecs see the packets as they
+/// were sent. Read errors are yielded as items; the stream ends once the
+/// receiver is [stopped](AsyncReceiver::stop) and the blocks it had queued
+/// are read.
+///
+/// Sending needs no counterpart: every [`send`](super::AsyncSender::send)
+/// already goes out as one packet.
+///
+/// ```no_run
+/// use futures_core::Stream;
+/// use rist::tokio::{AsyncReceiver, RistFramed};
+/// use rist::Profile;
+/// use std::future::poll_fn;
+/// use std::pin::Pin;
+///
+/// # async fn example() -> rist::Result<()> {
+/// let receiver = AsyncReceiver::bind(Profile::Main, "rist://@:5000")?;
+/// let mut frames = RistFramed::new(receiver);
+/// while let Some(block) = poll_fn(|cx| Pin::new(&mut frames).poll_next(cx)).await {
+///     println!("packet of {} bytes", block?.payload().len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RistFramed {
+    receiver: AsyncReceiver,
+    done: bool,
+}
+
+impl RistFramed {
+    /// Wrap `receiver`.
+    pub fn new(receiver: AsyncReceiver) -> Self {
+        Self {
+            receiver,
+            done: false,
+        }
+    }
+
+    /// The wrapped receiver.
+    pub fn get_ref(&self) -> &AsyncReceiver {
+        &self.receiver
+    }
+
+    /// Unwrap the receiver.
+    pub fn into_inner(self) -> AsyncReceiver {
+        self.receiver
+    }
+}
+
+impl Stream for RistFramed {
+    type Item = Result<DataBlock>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        loop {
+            return match this.receiver.poll_recv(cx) {
+                Poll::Ready(Ok(Some(block))) => Poll::Ready(Some(Ok(block))),
+                Poll::Ready(Ok(None)) => continue,
+                Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+                // nothing left queued, and nothing more will arrive
+                Poll::Pending if this.receiver.is_stopped() => {
+                    this.done = true;
+                    Poll::Ready(None)
+                }
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
+
+impl std::fmt::Debug for RistFramed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RistFramed")
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,76 @@
+//! Sharing one sender between tasks.
+
+use super::AsyncSender;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A cloneable handle to an [`AsyncSender`], for sending from several tasks
+/// into the same RIST context.
+///
+/// Cloning only bumps a reference count. Every sending method of
+/// [`AsyncSender`] takes `&self` and is available through `Deref`, so tasks
+/// send concurrently without a lock; pacing and the bitrate cap are shared.
+/// The context is destroyed when the last handle is dropped, or shut down
+/// gracefully once [`try_unwrap`](Self::try_unwrap) hands the sender back.
+///
+/// ```no_run
+/// use rist::tokio::AsyncSender;
+/// use rist::Profile;
+///
+/// # async fn example() -> rist::Result<()> {
+/// let sender = AsyncSender::connect(Profile::Main, "rist://127.0.0.1:5000")
+///     .await?
+///     .into_handle();
+///
+/// for _ in 0..4 {
+///     let sender = sender.clone();
+///     tokio::spawn(async move { sender.send(&[0x47; 1316]).await });
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SenderHandle(Arc<AsyncSender>);
+
+impl SenderHandle {
+    /// Get the sender back if this is the last handle, e.g. to
+    /// [`shutdown`](AsyncSender::shutdown) it.
+    pub fn try_unwrap(self) -> Result<AsyncSender, Self> {
+        Arc::try_unwrap(self.0).map_err(Self)
+    }
+
+    /// Handles to the sender, this one included.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+}
+
+impl From<AsyncSender> for SenderHandle {
+    fn from(sender: AsyncSender) -> Self {
+        sender.into_handle()
+    }
+}
+
+impl Deref for SenderHandle {
+    type Target = AsyncSender;
+
+    fn deref(&self) -> &AsyncSender {
+        &self.0
+    }
+}
+
+impl AsyncSender {
+    /// Turn the sender into a [`SenderHandle`] that can be cloned into
+    /// every task that sends.
+    pub fn into_handle(self) -> SenderHandle {
+        SenderHandle(Arc::new(self))
+    }
+}
+
+impl std::fmt::Debug for SenderHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SenderHandle")
+            .field("handles", &self.handle_count())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,424 @@
+//! Accepting RIST contributors one at a time, like a `TcpListener`.
+
+use super::queue::{queue, QueueReceiver, QueueSender};
+use super::AsyncReceiver;
+use crate::connection::ConnectionState;
+use crate::{DataBlock, Error, OverflowPolicy, PeerId, Profile, ReceiverOptions, Result};
+use ::tokio::sync::{mpsc, Mutex as AsyncMutex};
+use ::tokio::task::JoinHandle;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CStr;
+use std::net::{IpAddr, SocketAddr};
+use std::os::raw::{c_char, c_int, c_void};
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+
+/// Blocks queued per contributor before the overflow policy applies.
+const PEER_QUEUE: usize = 1024;
+
+enum Event {
+    Connected(PeerId),
+    Closed(PeerId),
+}
+
+/// Remote addresses reported by the librist auth handler.
+///
+/// librist holds a raw pointer to this while the handler is registered, so
+/// it must outlive the context.
+struct AuthSlot {
+    addrs: Mutex<HashMap<PeerId, SocketAddr>>,
+    events: mpsc::UnboundedSender<Event>,
+}
+
+unsafe extern "C" fn auth_connect(
+    arg: *mut c_void,
+    conn_ip: *const c_char,
+    conn_port: u16,
+    _local_ip: *const c_char,
+    _local_port: u16,
+    peer: *mut rist_sys::rist_peer,
+) -> c_int {
+    if arg.is_null() || peer.is_null() {
+        return 0;
+    }
+    let slot = &*(arg as *const AuthSlot);
+    let id = PeerId::from_raw(peer);
+
+    crate::callback::guard("auth", (), || {
+        let ip = (!conn_ip.is_null())
+            .then(|| {
+                CStr::from_ptr(conn_ip)
+                    .to_str()
+                    .ok()?
+                    .parse::<IpAddr>()
+                    .ok()
+            })
+            .flatten();
+        if let (Some(ip), Ok(mut addrs)) = (ip, slot.addrs.lock()) {
+            addrs.insert(id, SocketAddr::new(ip, conn_port));
+        }
+        let _ = slot.events.send(Event::Connected(id));
+    });
+    // accept everyone
+    0
+}
+
+unsafe extern "C" fn auth_disconnect(arg: *mut c_void, peer: *mut rist_sys::rist_peer) -> c_int {
+    if arg.is_null() || peer.is_null() {
+        return 0;
+    }
+    let slot = &*(arg as *const AuthSlot);
+    crate::callback::guard("auth", (), || {
+        let _ = slot.events.send(Event::Closed(PeerId::from_raw(peer)));
+    });
+    0
+}
+
+// field order matters: the receiver's context must be destroyed before the
+// auth slot it points into
+struct Shared {
+    receiver: AsyncReceiver,
+    auth: Arc<AuthSlot>,
+}
+
+/// A contributor that connected to a [`RistListener`].
+///
+/// Receives only the data this contributor sends.
+pub struct IncomingPeer {
+    id: PeerId,
+    remote_addr: Option<SocketAddr>,
+    blocks: QueueReceiver<DataBlock>,
+}
+
+impl IncomingPeer {
+    /// The contributor's peer on the listener's context.
+    pub fn id(&self) -> PeerId {
+        self.id
+    }
+
+    /// The contributor's address, if librist reported it.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Receive the next block from this contributor.
+    ///
+    /// Returns `None` once the contributor has timed out or the listener
+    /// was dropped, after any blocks still queued.
+    pub async fn recv(&mut self) -> Option<DataBlock> {
+        self.blocks.recv().await
+    }
+}
+
+impl std::fmt::Debug for IncomingPeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IncomingPeer")
+            .field("id", &self.id)
+            .field("remote_addr", &self.remote_addr)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Listens for RIST contributors and hands each one out as its own
+/// [`IncomingPeer`].
+///
+/// One librist context serves every contributor; a background task splits
+/// its output by the peer that delivered each block. Each contributor has
+/// a queue of 1024 blocks; when it is full the receiver's
+/// [`OverflowPolicy`] applies. Under [`OverflowPolicy::Block`] one slow
+/// contributor holds up the others, the drop policies lose only that
+/// contributor's blocks.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::tokio::RistListener;
+/// use rist::Profile;
+///
+/// # async fn example() -> rist::Result<()> {
+/// let listener = RistListener::bind(Profile::Main, "rist://@:5000")?;
+/// loop {
+///     let mut peer = listener.accept().await?;
+///     tokio::spawn(async move {
+///         println!("contributor connected from {:?}", peer.remote_addr());
+///         while let Some(block) = peer.recv().await {
+///             // handle this contributor's data
+///         }
+///     });
+/// }
+/// # }
+/// ```
+pub struct RistListener {
+    shared: Arc<Shared>,
+    incoming: AsyncMutex<mpsc::UnboundedReceiver<IncomingPeer>>,
+    task: JoinHandle<()>,
+}
+
+impl RistListener {
+    /// Listen on `url`, e.g. `rist://@:5000`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn bind(profile: Profile, url: &str) -> Result<Self> {
+        Self::bind_with_options(profile, url, ReceiverOptions::default())
+    }
+
+    /// Listen on `url` with custom options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn bind_with_options(
+        profile: Profile,
+        url: &str,
+        options: ReceiverOptions,
+    ) -> Result<Self> {
+        let receiver = AsyncReceiver::bind_with_options(profile, url, options)?;
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        let timeouts = events_tx.clone();
+        receiver.watch_connections(Box::new(move |peer, state| {
+            let event = match state {
+                ConnectionState::Connected => Event::Connected(peer),
+                ConnectionState::TimedOut => Event::Closed(peer),
+                ConnectionState::Connecting => return,
+            };
+            let _ = timeouts.send(event);
+        }));
+
+        let auth = Arc::new(AuthSlot {
+            addrs: Mutex::new(HashMap::new()),
+            events: events_tx,
+        });
+        // contributors connecting before this are still picked up by the
+        // connection callback, just without an address
+        let ret = unsafe {
+            rist_sys::rist_auth_handler_set(
+                receiver.as_raw(),
+                Some(auth_connect),
+                Some(auth_disconnect),
+                Arc::as_ptr(&auth) as *mut c_void,
+            )
+        };
+        if ret != 0 {
+            return Err(Error::Configuration(
+                "failed to set auth handler".to_string(),
+            ));
+        }
+
+        let shared = Arc::new(Shared { receiver, auth });
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+        let task = ::tokio::spawn(dispatch(shared.clone(), events_rx, incoming_tx));
+
+        Ok(Self {
+            shared,
+            incoming: AsyncMutex::new(incoming_rx),
+            task,
+        })
+    }
+
+    /// Wait for the next contributor to connect.
+    ///
+    /// Fails with [`Error::Stopped`] once the listener can no longer accept
+    /// anyone, e.g. because reading from librist failed.
+    pub async fn accept(&self) -> Result<IncomingPeer> {
+        self.incoming
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or(Error::Stopped)
+    }
+
+    /// The receiver every contributor connects to, for stats and peers.
+    pub fn receiver(&self) -> &AsyncReceiver {
+        &self.shared.receiver
+    }
+}
+
+impl Drop for RistListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Route blocks to the contributor that sent them, announcing new ones on
+/// `incoming`.
+async fn dispatch(
+    shared: Arc<Shared>,
+    mut events: mpsc::UnboundedReceiver<Event>,
+    incoming: mpsc::UnboundedSender<IncomingPeer>,
+) {
+    let mut router = Router::new(
+        shared.receiver.overflow_policy,
+        shared.receiver.drops.clone(),
+    );
+
+    loop {
+        ::tokio::select! {
+            event = events.recv() => match event {
+                Some(Event::Connected(peer)) => router.connected(peer, &shared.auth, &incoming),
+                Some(Event::Closed(peer)) => router.closed(peer, &shared.auth),
+                None => return,
+            },
+            block = shared.receiver.recv() => match block {
+                Ok(Some(block)) => router.route(block, &shared.auth, &incoming).await,
+                // stopped and drained, nothing more will arrive
+                Ok(None) | Err(_) => return,
+            },
+        }
+    }
+}
+
+/// Per-contributor queues, keyed by peer.
+struct Router {
+    queues: HashMap<PeerId, QueueSender<DataBlock>>,
+    // timed out; late blocks still in librist's FIFO must not revive them
+    closed: HashSet<PeerId>,
+    policy: OverflowPolicy,
+    drops: Arc<AtomicU64>,
+}
+
+impl Router {
+    fn new(policy: OverflowPolicy, drops: Arc<AtomicU64>) -> Self {
+        Self {
+            queues: HashMap::new(),
+            closed: HashSet::new(),
+            policy,
+            drops,
+        }
+    }
+
+    fn connected(
+        &mut self,
+        peer: PeerId,
+        auth: &AuthSlot,
+        incoming: &mpsc::UnboundedSender<IncomingPeer>,
+    ) {
+        // IDs are reused once a peer is destroyed
+        self.closed.remove(&peer);
+        self.open(peer, auth, incoming);
+    }
+
+    fn closed(&mut self, peer: PeerId, auth: &AuthSlot) {
+        self.queues.remove(&peer);
+        self.closed.insert(peer);
+        if let Ok(mut addrs) = auth.addrs.lock() {
+            addrs.remove(&peer);
+        }
+    }
+
+    async fn route(
+        &mut self,
+        block: DataBlock,
+        auth: &AuthSlot,
+        incoming: &mpsc::UnboundedSender<IncomingPeer>,
+    ) {
+        let Some(peer) = block.peer() else {
+            return;
+        };
+        if self.closed.contains(&peer) {
+            return;
+        }
+        let Some(queue) = self.open(peer, auth, incoming) else {
+            return;
+        };
+        if queue.push_wait(block).await.is_err() {
+            // the contributor's handle was dropped, stop routing to it
+            self.queues.remove(&peer);
+            self.closed.insert(peer);
+        }
+    }
+
+    /// The queue for `peer`, announcing the peer if it is new.
+    fn open(
+        &mut self,
+        peer: PeerId,
+        auth: &AuthSlot,
+        incoming: &mpsc::UnboundedSender<IncomingPeer>,
+    ) -> Option<&QueueSender<DataBlock>> {
+        match self.queues.entry(peer) {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let (tx, rx) = queue(PEER_QUEUE, self.policy, self.drops.clone());
+                let remote_addr = auth
+                    .addrs
+                    .lock()
+                    .ok()
+                    .and_then(|addrs| addrs.get(&peer).copied());
+                let accepted = incoming.send(IncomingPeer {
+                    id: peer,
+                    remote_addr,
+                    blocks: rx,
+                });
+                // nobody left to accept it
+                accepted.ok()?;
+                Some(entry.insert(tx))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokio::queue::TryRecv;
+
+    fn peer(n: usize) -> PeerId {
+        PeerId::from_raw(n as *mut rist_sys::rist_peer)
+    }
+
+    fn router() -> Router {
+        Router::new(OverflowPolicy::default(), Arc::new(AtomicU64::new(0)))
+    }
+
+    fn auth() -> AuthSlot {
+        AuthSlot {
+            addrs: Mutex::new(HashMap::new()),
+            events: mpsc::unbounded_channel().0,
+        }
+    }
+
+    #[test]
+    fn test_announces_each_peer_once() {
+        let auth = auth();
+        auth.addrs
+            .lock()
+            .unwrap()
+            .insert(peer(1), "192.0.2.1:4000".parse().unwrap());
+        let (incoming_tx, mut incoming_rx) = mpsc::unbounded_channel();
+        let mut router = router();
+
+        router.connected(peer(1), &auth, &incoming_tx);
+        router.connected(peer(1), &auth, &incoming_tx);
+        router.connected(peer(2), &auth, &incoming_tx);
+
+        let first = incoming_rx.try_recv().unwrap();
+        assert_eq!(first.id(), peer(1));
+        assert_eq!(first.remote_addr(), Some("192.0.2.1:4000".parse().unwrap()));
+        let second = incoming_rx.try_recv().unwrap();
+        assert_eq!(second.id(), peer(2));
+        assert_eq!(second.remote_addr(), None);
+        assert!(incoming_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_closed_peer_ends_its_stream() {
+        let auth = auth();
+        let (incoming_tx, mut incoming_rx) = mpsc::unbounded_channel();
+        let mut router = router();
+
+        router.connected(peer(1), &auth, &incoming_tx);
+        let mut incoming = incoming_rx.try_recv().unwrap();
+        router.closed(peer(1), &auth);
+
+        assert_eq!(incoming.blocks.try_recv().err(), Some(TryRecv::Closed));
+        assert!(router.closed.contains(&peer(1)));
+
+        // a new peer reusing the ID is announced again
+        router.connected(peer(1), &auth, &incoming_tx);
+        assert!(incoming_rx.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,222 @@
+//! Splitting a receiver into one stream per virtual destination port.
+
+use super::queue::{admit, waking_queue, QueueReceiver, QueueSender, Wake};
+use super::AsyncReceiver;
+use crate::{DataBlock, OverflowPolicy, Result};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+
+/// Blocks queued per port stream, and for ports without one, before the
+/// overflow policy applies.
+const PORT_QUEUE: usize = 1024;
+
+/// Where a receiver's blocks go, by virtual destination port.
+pub(crate) struct PortRouter {
+    streams: Mutex<HashMap<u16, QueueSender<DataBlock>>>,
+    // blocks for ports without a stream, read while driving a port stream
+    unclaimed: Mutex<VecDeque<DataBlock>>,
+    policy: OverflowPolicy,
+    drops: Arc<AtomicU64>,
+    // restarts reading from librist once a backlogged queue has room
+    wake: Wake,
+}
+
+impl PortRouter {
+    pub(crate) fn new(policy: OverflowPolicy, drops: Arc<AtomicU64>, wake: Wake) -> Self {
+        Self {
+            streams: Mutex::new(HashMap::new()),
+            unclaimed: Mutex::new(VecDeque::new()),
+            policy,
+            drops,
+            wake,
+        }
+    }
+
+    fn register(&self, port: u16) -> QueueReceiver<DataBlock> {
+        let wake = (self.policy == OverflowPolicy::Block).then(|| self.wake.clone());
+        let (tx, rx) = waking_queue(PORT_QUEUE, self.policy, self.drops.clone(), wake);
+        if let Ok(mut streams) = self.streams.lock() {
+            // replaces, and so ends, any earlier stream for the port
+            streams.insert(port, tx);
+        }
+        rx
+    }
+
+    /// Hand `block` to its port's stream, or give it back if the port has
+    /// none.
+    pub(crate) fn route(&self, block: DataBlock) -> Option<DataBlock> {
+        let Ok(mut streams) = self.streams.lock() else {
+            return Some(block);
+        };
+        let port = block.virt_dst_port();
+        let Some(stream) = streams.get(&port) else {
+            return Some(block);
+        };
+        match stream.push(block) {
+            Ok(()) => None,
+            Err(block) => {
+                streams.remove(&port);
+                Some(block)
+            }
+        }
+    }
+
+    /// Whether reading has to wait for a full queue under
+    /// [`OverflowPolicy::Block`].
+    pub(crate) fn is_backlogged(&self) -> bool {
+        if self.policy != OverflowPolicy::Block {
+            return false;
+        }
+        let unclaimed_full = self
+            .unclaimed
+            .lock()
+            .map(|unclaimed| unclaimed.len() >= PORT_QUEUE)
+            .unwrap_or(false);
+        unclaimed_full
+            || self
+                .streams
+                .lock()
+                .map(|streams| streams.values().any(QueueSender::is_full))
+                .unwrap_or(false)
+    }
+
+    fn stash(&self, block: DataBlock) {
+        if let Ok(mut unclaimed) = self.unclaimed.lock() {
+            admit(&mut unclaimed, block, PORT_QUEUE, self.policy, &self.drops);
+        }
+    }
+
+    /// The oldest block read for a port without a stream.
+    pub(crate) fn take_unclaimed(&self) -> Option<DataBlock> {
+        let mut unclaimed = self.unclaimed.lock().ok()?;
+        let was_full = unclaimed.len() >= PORT_QUEUE;
+        let block = unclaimed.pop_front();
+        drop(unclaimed);
+        if was_full && self.policy == OverflowPolicy::Block {
+            (self.wake)();
+        }
+        block
+    }
+}
+
+/// The blocks for one virtual destination port of an [`AsyncReceiver`].
+///
+/// Created by [`AsyncReceiver::port_stream`].
+pub struct PortStream<'a> {
+    receiver: &'a AsyncReceiver,
+    port: u16,
+    blocks: QueueReceiver<DataBlock>,
+}
+
+impl PortStream<'_> {
+    /// The virtual destination port this stream delivers.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Receive the next block for this port.
+    ///
+    /// Reads from the receiver itself while waiting, passing blocks for
+    /// other ports on to their own streams. Returns `Ok(None)` if another
+    /// stream for the same port replaced this one, or once the receiver is
+    /// stopped and the blocks queued for this port were read.
+    pub async fn recv(&mut self) -> Result<Option<DataBlock>> {
+        loop {
+            ::tokio::select! {
+                block = self.blocks.recv() => return Ok(block),
+                block = self.receiver.recv_unrouted() => match block? {
+                    Some(block) => self.receiver.ports.stash(block),
+                    None => return Ok(self.blocks.try_recv().ok()),
+                },
+            }
+        }
+    }
+}
+
+impl AsyncReceiver {
+    /// Receive only the blocks sent to virtual destination port `port`,
+    /// for senders that multiplex several services over one session.
+    ///
+    /// From then on blocks for `port` only go to the returned stream, and
+    /// [`recv`](Self::recv) only returns blocks for ports without a stream.
+    /// Each stream queues up to 1024 blocks; what happens to a stream that
+    /// falls further behind is set by
+    /// [`ReceiverOptions::overflow_policy`](crate::ReceiverOptions::overflow_policy).
+    /// By default it loses blocks instead of holding up the other ports.
+    /// Dropping the stream hands its port back to `recv`.
+    ///
+    /// Waiting streams read from librist on everyone's behalf. A block for
+    /// a port without a stream that one of them reads reaches a `recv`
+    /// already waiting with the next incoming block.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use rist::tokio::AsyncReceiver;
+    /// use rist::Profile;
+    ///
+    /// # async fn example() -> rist::Result<()> {
+    /// let receiver = AsyncReceiver::bind(Profile::Main, "rist://@:5000")?;
+    /// let mut video = receiver.port_stream(1968);
+    /// let mut audio = receiver.port_stream(1970);
+    ///
+    /// tokio::try_join!(
+    ///     async {
+    ///         while let Some(block) = video.recv().await? {
+    ///             // video packets only
+    ///         }
+    ///         Ok::<_, rist::Error>(())
+    ///     },
+    ///     async {
+    ///         while let Some(block) = audio.recv().await? {
+    ///             // audio packets only
+    ///         }
+    ///         Ok(())
+    ///     },
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn port_stream(&self, port: u16) -> PortStream<'_> {
+        PortStream {
+            receiver: self,
+            port,
+            blocks: self.ports.register(port),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::queue::TryRecv;
+    use super::*;
+
+    fn router(policy: OverflowPolicy) -> PortRouter {
+        PortRouter::new(policy, Arc::new(AtomicU64::new(0)), Arc::new(|| {}))
+    }
+
+    #[test]
+    fn test_register_replaces_earlier_stream() {
+        let router = router(OverflowPolicy::default());
+        assert!(router.take_unclaimed().is_none());
+        assert!(router.streams.lock().unwrap().is_empty());
+
+        let _stream = router.register(1968);
+        assert!(router.streams.lock().unwrap().contains_key(&1968));
+
+        // a second stream for the port ends the first
+        let mut first = router.register(1970);
+        let _second = router.register(1970);
+        assert_eq!(first.try_recv().unwrap_err(), TryRecv::Closed);
+    }
+
+    #[test]
+    fn test_block_policy_backlogs_only_when_full() {
+        assert!(!router(OverflowPolicy::DropNewest).is_backlogged());
+
+        let router = router(OverflowPolicy::Block);
+        let _stream = router.register(1968);
+        assert!(!router.is_backlogged());
+    }
+}
@@ -0,0 +1,301 @@
+//! Bounded block queues between the task reading from librist and the
+//! consumers it feeds, with a configurable [`OverflowPolicy`].
+
+use crate::OverflowPolicy;
+use ::tokio::sync::Notify;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Add `block` to `blocks` following `policy`, counting anything dropped in
+/// `drops`.
+///
+/// Under [`OverflowPolicy::Block`] the block is always added, even past
+/// `capacity`; the caller is expected to hold off further blocks.
+pub(crate) fn admit<T>(
+    blocks: &mut VecDeque<T>,
+    block: T,
+    capacity: usize,
+    policy: OverflowPolicy,
+    drops: &AtomicU64,
+) {
+    if blocks.len() >= capacity {
+        match policy {
+            OverflowPolicy::DropOldest => {
+                blocks.pop_front();
+                drops.fetch_add(1, Ordering::Relaxed);
+            }
+            OverflowPolicy::DropNewest => {
+                drops.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            OverflowPolicy::Block => {}
+        }
+    }
+    blocks.push_back(block);
+}
+
+struct State<T> {
+    blocks: VecDeque<T>,
+    sender_open: bool,
+    receiver_open: bool,
+}
+
+/// Called when a consumer makes room in a full queue.
+pub(crate) type Wake = Arc<dyn Fn() + Send + Sync>;
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    drops: Arc<AtomicU64>,
+    readable: Notify,
+    writable: Notify,
+    wake: Option<Wake>,
+}
+
+/// Create a queue of `capacity` blocks, counting overflow in `drops`.
+pub(crate) fn queue<T>(
+    capacity: usize,
+    policy: OverflowPolicy,
+    drops: Arc<AtomicU64>,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    waking_queue(capacity, policy, drops, None)
+}
+
+/// Like [`queue`], also calling `wake` whenever room is made in the full
+/// queue, for producers that don't wait in [`QueueSender::push_wait`].
+pub(crate) fn waking_queue<T>(
+    capacity: usize,
+    policy: OverflowPolicy,
+    drops: Arc<AtomicU64>,
+    wake: Option<Wake>,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            blocks: VecDeque::new(),
+            sender_open: true,
+            receiver_open: true,
+        }),
+        capacity,
+        policy,
+        drops,
+        readable: Notify::new(),
+        writable: Notify::new(),
+        wake,
+    });
+    (
+        QueueSender {
+            shared: shared.clone(),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+/// The producing end of a [`queue`].
+pub(crate) struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    /// Queue `block` without waiting, giving it back if the receiver is
+    /// gone.
+    pub(crate) fn push(&self, block: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        {
+            let Ok(mut state) = shared.state.lock() else {
+                return Err(block);
+            };
+            if !state.receiver_open {
+                return Err(block);
+            }
+            admit(
+                &mut state.blocks,
+                block,
+                shared.capacity,
+                shared.policy,
+                &shared.drops,
+            );
+        }
+        shared.readable.notify_one();
+        Ok(())
+    }
+
+    /// Like [`push`](Self::push), but under [`OverflowPolicy::Block`] wait
+    /// for room first.
+    pub(crate) async fn push_wait(&self, block: T) -> Result<(), T> {
+        if self.shared.policy == OverflowPolicy::Block {
+            loop {
+                let writable = self.shared.writable.notified();
+                match self.shared.state.lock() {
+                    Ok(state)
+                        if state.receiver_open && state.blocks.len() >= self.shared.capacity => {}
+                    _ => break,
+                }
+                writable.await;
+            }
+        }
+        self.push(block)
+    }
+
+    /// Whether the queue is at or over capacity.
+    pub(crate) fn is_full(&self) -> bool {
+        self.shared
+            .state
+            .lock()
+            .map(|state| state.receiver_open && state.blocks.len() >= self.shared.capacity)
+            .unwrap_or(false)
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.sender_open = false;
+        }
+        self.shared.readable.notify_one();
+    }
+}
+
+/// The consuming end of a [`queue`].
+pub(crate) struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    /// The next block, or `None` once the sender is gone and the queue is
+    /// empty.
+    pub(crate) async fn recv(&mut self) -> Option<T> {
+        let shared = self.shared.clone();
+        loop {
+            let readable = shared.readable.notified();
+            match self.try_recv() {
+                Ok(block) => return Some(block),
+                Err(Closed) => return None,
+                Err(Empty) => {}
+            }
+            readable.await;
+        }
+    }
+
+    /// The next block if one is queued.
+    pub(crate) fn try_recv(&mut self) -> Result<T, TryRecv> {
+        let Ok(mut state) = self.shared.state.lock() else {
+            return Err(Closed);
+        };
+        let was_full = state.blocks.len() >= self.shared.capacity;
+        match state.blocks.pop_front() {
+            Some(block) => {
+                drop(state);
+                self.shared.writable.notify_one();
+                if was_full {
+                    if let Some(wake) = &self.shared.wake {
+                        wake();
+                    }
+                }
+                Ok(block)
+            }
+            None if state.sender_open => Err(Empty),
+            None => Err(Closed),
+        }
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.receiver_open = false;
+            state.blocks.clear();
+        }
+        self.shared.writable.notify_one();
+    }
+}
+
+/// Why [`QueueReceiver::try_recv`] returned nothing.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum TryRecv {
+    /// Nothing queued right now.
+    Empty,
+    /// Nothing queued and the sender is gone.
+    Closed,
+}
+
+use TryRecv::{Closed, Empty};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn drops() -> Arc<AtomicU64> {
+        Arc::new(AtomicU64::new(0))
+    }
+
+    #[test]
+    fn test_drop_policies() {
+        for (policy, kept) in [
+            (OverflowPolicy::DropOldest, vec![2, 3]),
+            (OverflowPolicy::DropNewest, vec![1, 2]),
+            (OverflowPolicy::Block, vec![1, 2, 3]),
+        ] {
+            let dropped = drops();
+            let (tx, mut rx) = queue(2, policy, dropped.clone());
+            for n in 1..=3 {
+                tx.push(n).unwrap();
+            }
+            let received: Vec<u32> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+            assert_eq!(received, kept, "{policy:?}");
+            assert_eq!(dropped.load(Ordering::Relaxed), 3 - kept.len() as u64);
+        }
+    }
+
+    #[test]
+    fn test_closed_ends() {
+        let (tx, mut rx) = queue(4, OverflowPolicy::default(), drops());
+        tx.push(1).unwrap();
+        drop(tx);
+        // queued blocks are still delivered
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecv::Closed));
+
+        let (tx, rx) = queue(4, OverflowPolicy::default(), drops());
+        drop(rx);
+        assert_eq!(tx.push(1), Err(1));
+        assert!(!tx.is_full());
+    }
+
+    #[test]
+    fn test_wakes_when_room_is_made() {
+        let woken = Arc::new(AtomicU64::new(0));
+        let counter = woken.clone();
+        let wake: Wake = Arc::new(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let (tx, mut rx) = waking_queue(2, OverflowPolicy::Block, drops(), Some(wake));
+        tx.push(1).unwrap();
+        rx.try_recv().unwrap();
+        assert_eq!(woken.load(Ordering::Relaxed), 0);
+
+        tx.push(2).unwrap();
+        tx.push(3).unwrap();
+        rx.try_recv().unwrap();
+        assert_eq!(woken.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_room() {
+        let (tx, mut rx) = queue(1, OverflowPolicy::Block, drops());
+        tx.push_wait(1).await.unwrap();
+        assert!(tx.is_full());
+
+        let consumer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let first = rx.recv().await;
+            let second = rx.recv().await;
+            (first, second)
+        });
+        tx.push_wait(2).await.unwrap();
+        drop(tx);
+        assert_eq!(consumer.await.unwrap(), (Some(1), Some(2)));
+    }
+}
@@ -1,138 +1,76 @@
-use crate::stats::ReceiverStats;
-use crate::{DataBlock, Error, Profile, ReceiverOptions, Result};
+use super::ports::PortRouter;
+use crate::buffer::{BufferSlot, BufferSource};
+use crate::capture::{CaptureSlot, CaptureTap, Direction};
+use crate::connection::{register_connection, ConnectionCallback, ConnectionSlot};
+use crate::continuity::{Ordered, Reorderer};
+use crate::flow::{FlowSlot, StreamEvent};
+use crate::notify::NotifyPipe;
+#[cfg(feature = "serde")]
+use crate::oob::{Command, CommandRequest, Message};
+use crate::oob::{Heartbeat, OobSlot, RttProbe};
+use crate::peer::{add_peer, destroy_peers, replace_peer, set_recovery_disabled, Peer};
+use crate::receiver::{flow_stats, match_stats, read_block, track_flows, FifoDrops};
+use crate::stats::{
+    register_stats, ReceiverStats, StatsMode, StatsSlot, StatsSnapshot, DEFAULT_STATS_INTERVAL,
+};
+use crate::{
+    DataBlock, Error, FlowInfo, Health, OverflowPolicy, PeerConfig, PeerId, PeerInfo, Profile,
+    ReceiverOptions, Result,
+};
 use ::tokio::io::unix::AsyncFd;
 use ::tokio::io::{AsyncRead, ReadBuf};
-use std::ffi::CString;
+use ::tokio::task::JoinHandle;
+use ::tokio::time::Sleep;
+#[cfg(feature = "bytes")]
+use bytes::BytesMut;
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
 use std::io;
-use std::os::raw::c_void;
-use std::os::unix::io::{AsRawFd, RawFd};
 use std::pin::Pin;
 use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
-use std::time::Duration;
-
-/// Wrapper for a pipe read-end that can be used with AsyncFd.
-/// librist will write to the write-end when data is available.
-struct NotifyPipe {
-    read_fd: RawFd,
-    write_fd: RawFd,
-}
-
-impl NotifyPipe {
-    fn new() -> io::Result<Self> {
-        let mut fds = [0i32; 2];
-        let ret = unsafe { libc::pipe(fds.as_mut_ptr()) };
-        if ret < 0 {
-            return Err(io::Error::last_os_error());
-        }
-
-        // Set read end to non-blocking
-        let flags = unsafe { libc::fcntl(fds[0], libc::F_GETFL) };
-        if flags < 0 {
-            unsafe {
-                libc::close(fds[0]);
-                libc::close(fds[1]);
-            }
-            return Err(io::Error::last_os_error());
-        }
-        let ret = unsafe { libc::fcntl(fds[0], libc::F_SETFL, flags | libc::O_NONBLOCK) };
-        if ret < 0 {
-            unsafe {
-                libc::close(fds[0]);
-                libc::close(fds[1]);
-            }
-            return Err(io::Error::last_os_error());
-        }
-
-        Ok(Self {
-            read_fd: fds[0],
-            write_fd: fds[1],
-        })
-    }
-
-    /// Get the write fd to pass to librist
-    fn write_fd(&self) -> RawFd {
-        self.write_fd
-    }
-
-    /// Consume pending notifications (drain the pipe)
-    fn consume(&self) -> io::Result<()> {
-        let mut buf = [0u8; 64];
-        loop {
-            let ret = unsafe { libc::read(self.read_fd, buf.as_mut_ptr() as *mut _, buf.len()) };
-            if ret < 0 {
-                let err = io::Error::last_os_error();
-                if err.kind() == io::ErrorKind::WouldBlock {
-                    return Ok(()); // No more data
-                }
-                return Err(err);
-            }
-            if ret == 0 {
-                return Ok(()); // EOF
-            }
-            // Loop to drain all pending bytes
-        }
-    }
-}
-
-impl AsRawFd for NotifyPipe {
-    fn as_raw_fd(&self) -> RawFd {
-        self.read_fd
-    }
-}
-
-impl Drop for NotifyPipe {
-    fn drop(&mut self) {
-        unsafe {
-            libc::close(self.read_fd);
-            libc::close(self.write_fd);
-        }
-    }
-}
+use std::time::{Duration, Instant};
 
 /// Async RIST receiver.
 pub struct AsyncReceiver {
     raw_ctx: *mut rist_sys::rist_ctx,
-    stats: Arc<Mutex<Option<ReceiverStats>>>,
-    // prevent the boxed callback data from being dropped
-    _stats_data: Option<Box<Arc<Mutex<Option<ReceiverStats>>>>>,
+    profile: Profile,
+    // the stats callback points into this allocation, so it must outlive the context
+    stats: Arc<StatsSlot<ReceiverStats>>,
+    // must outlive the context, like `stats`
+    connection: Arc<ConnectionSlot>,
+    oob: Arc<OobSlot>,
+    flows: Arc<FlowSlot>,
+    peers: Mutex<Vec<Peer>>,
+    stopped: AtomicBool,
+    paused: AtomicBool,
+    queue_len: AtomicUsize,
+    capture: CaptureSlot,
+    accepted_flow_ids: Option<Vec<u32>>,
+    pub(super) overflow_policy: OverflowPolicy,
+    // blocks dropped by queues this receiver feeds
+    pub(super) drops: Arc<AtomicU64>,
+    fifo_drops: FifoDrops,
     // Buffer for AsyncRead
     read_buf: Mutex<Vec<u8>>,
+    buffers: BufferSlot,
+    // puts AsyncRead blocks in sequence order, if enabled
+    reorder: Mutex<Option<Reorderer>>,
+    // wakes an AsyncRead read when the reorderer gives up on a gap
+    reorder_timer: Option<Pin<Box<Sleep>>>,
     // AsyncFd for native async notification
     async_fd: AsyncFd<NotifyPipe>,
+    pub(super) ports: PortRouter,
+    stall_task: Mutex<Option<JoinHandle<()>>>,
 }
 
 // SAFETY: librist contexts are thread-safe
 unsafe impl Send for AsyncReceiver {}
 unsafe impl Sync for AsyncReceiver {}
 
-/// Stats callback for librist
-unsafe extern "C" fn stats_callback(
-    arg: *mut c_void,
-    stats_container: *const rist_sys::rist_stats,
-) -> i32 {
-    if arg.is_null() || stats_container.is_null() {
-        return 0;
-    }
-
-    let stats_arc = &*(arg as *const Arc<Mutex<Option<ReceiverStats>>>);
-    let stats = &*stats_container;
-
-    // Check if this is receiver stats
-    if stats.stats_type == rist_sys::rist_stats_type_RIST_STATS_RECEIVER_FLOW {
-        let receiver_stats = ReceiverStats::from(&stats.stats.receiver_flow);
-        if let Ok(mut guard) = stats_arc.lock() {
-            *guard = Some(receiver_stats);
-        }
-    }
-
-    // Free the stats container
-    rist_sys::rist_stats_free(stats_container);
-
-    0
-}
-
 impl AsyncReceiver {
     /// Bind a receiver to listen on the given URL.
     ///
@@ -149,6 +87,31 @@ impl AsyncReceiver {
         url: &str,
         options: ReceiverOptions,
     ) -> Result<Self> {
+        Self::bind_all_with_options(profile, &[url], options)
+    }
+
+    /// Bind one receiver to several URLs, e.g. a unicast and a multicast
+    /// listener, or two ports.
+    ///
+    /// Data from every URL arrives on the same receiver, told apart by
+    /// flow ID if needed.
+    pub fn bind_all(profile: Profile, urls: &[&str]) -> Result<Self> {
+        Self::bind_all_with_options(profile, urls, ReceiverOptions::default())
+    }
+
+    /// Bind one receiver to several URLs, configuring every peer with
+    /// `options`.
+    pub fn bind_all_with_options(
+        profile: Profile,
+        urls: &[&str],
+        options: ReceiverOptions,
+    ) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(Error::Configuration(
+                "receiver needs at least one peer".to_string(),
+            ));
+        }
+
         let mut raw_ctx: *mut rist_sys::rist_ctx = ptr::null_mut();
 
         let ret = unsafe {
@@ -199,59 +162,102 @@ impl AsyncReceiver {
             }
         };
 
-        // Set up stats callback
-        let stats = Arc::new(Mutex::new(None));
-        let stats_data = Box::new(stats.clone());
-        let stats_ptr = &*stats_data as *const Arc<Mutex<Option<ReceiverStats>>> as *mut c_void;
-
-        unsafe {
-            // Set stats callback with 1 second interval
-            rist_sys::rist_stats_callback_set(raw_ctx, 1000, Some(stats_callback), stats_ptr);
-        }
-
+        let flows = Arc::new(FlowSlot::new());
+        let drops = Arc::new(AtomicU64::new(0));
+        // the pipe lives as long as the receiver, and so its port streams
+        let notify_fd = async_fd.get_ref().write_fd();
         let mut receiver = Self {
             raw_ctx,
-            stats,
-            _stats_data: Some(stats_data),
+            profile,
+            stats: flow_stats(&flows, &drops),
+            connection: Arc::new(ConnectionSlot::new()),
+            oob: Arc::new(OobSlot::new(raw_ctx, profile)),
+            flows,
+            peers: Mutex::new(Vec::new()),
+            stopped: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            queue_len: AtomicUsize::new(0),
+            capture: CaptureSlot::default(),
+            accepted_flow_ids: options.accepted_flow_ids.clone(),
+            overflow_policy: options.overflow_policy,
+            drops: drops.clone(),
+            fifo_drops: FifoDrops::new(drops.clone()),
             read_buf: Mutex::new(Vec::new()),
+            buffers: BufferSlot::default(),
+            reorder: Mutex::new(None),
+            reorder_timer: None,
             async_fd,
+            ports: PortRouter::new(
+                options.overflow_policy,
+                drops,
+                Arc::new(move || NotifyPipe::signal(notify_fd)),
+            ),
+            stall_task: Mutex::new(None),
         };
-        receiver.add_peer_with_options(url, &options)?;
+        register_stats(raw_ctx, DEFAULT_STATS_INTERVAL, &receiver.stats)?;
+        track_flows(&receiver.connection, &receiver.flows, None);
+        register_connection(raw_ctx, &receiver.connection)?;
+        receiver.oob.register_early()?;
+        for url in urls {
+            receiver.add_peer_with_options(url, &options)?;
+        }
         receiver.start()?;
 
         Ok(receiver)
     }
 
-    fn add_peer_with_options(&mut self, url: &str, options: &ReceiverOptions) -> Result<()> {
-        let url_c = CString::new(url)?;
-        let mut peer_config: *mut rist_sys::rist_peer_config = ptr::null_mut();
-
-        let ret = unsafe { rist_sys::rist_parse_address2(url_c.as_ptr(), &mut peer_config) };
-
-        if ret != 0 || peer_config.is_null() {
-            return Err(Error::UrlParse(url.to_string()));
-        }
+    /// Add another peer to the running receiver, e.g. a second listening
+    /// port or an extra path for bonding.
+    pub fn add_peer(&self, url: &str) -> Result<PeerId> {
+        self.add_peer_with_options(url, &ReceiverOptions::default())
+    }
 
-        // Apply options to peer config
-        unsafe {
-            options.apply_to_peer_config(&mut *peer_config);
+    /// Add another peer to the running receiver with custom options.
+    ///
+    /// Context-wide settings such as [`fifo_size`](ReceiverOptions::fifo_size)
+    /// and [`accept_flow_ids`](ReceiverOptions::accept_flow_ids) only take
+    /// effect when passed to [`bind_with_options`](Self::bind_with_options).
+    pub fn add_peer_with_options(&self, url: &str, options: &ReceiverOptions) -> Result<PeerId> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
         }
 
-        let mut peer: *mut rist_sys::rist_peer = ptr::null_mut();
-        let ret = unsafe { rist_sys::rist_peer_create(self.raw_ctx, &mut peer, peer_config) };
+        let id = add_peer(
+            self.raw_ctx,
+            self.profile,
+            &mut self.peers.lock().unwrap_or_else(|e| e.into_inner()),
+            &self.connection,
+            url,
+            options.configurer()?,
+        )?;
 
-        unsafe {
-            rist_sys::rist_peer_config_free2(&mut peer_config);
-        }
+        Ok(id)
+    }
 
-        if ret != 0 {
-            return Err(Error::PeerCreation(url.to_string()));
+    /// Add a peer with every librist setting given by `config`.
+    ///
+    /// Unlike [`add_peer_with_options`](Self::add_peer_with_options) the
+    /// settings in `config` are used as they are, including those the options
+    /// don't cover. Fails if `config` is invalid, see
+    /// [`PeerConfig::validate`](crate::PeerConfig::validate).
+    pub fn add_peer_config(&self, config: &PeerConfig) -> Result<PeerId> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
         }
 
-        Ok(())
+        add_peer(
+            self.raw_ctx,
+            self.profile,
+            &mut self.peers.lock().unwrap_or_else(|e| e.into_inner()),
+            &self.connection,
+            &config.address,
+            config.configurer()?,
+        )
     }
 
     fn start(&mut self) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("rist_start", role = "receiver").entered();
         let ret = unsafe { rist_sys::rist_start(self.raw_ctx) };
 
         if ret != 0 {
@@ -261,68 +267,555 @@ impl AsyncReceiver {
         Ok(())
     }
 
+    /// Stop receiving without dropping the receiver.
+    ///
+    /// All peers are removed, so no new data arrives, but packets already
+    /// queued can still be received and the last stats remain available.
+    /// Once they are read, the `AsyncRead` impl reports end of file.
+    pub fn stop(&self) -> Result<()> {
+        if let Ok(mut peers) = self.peers.lock() {
+            destroy_peers(self.raw_ctx, &mut peers);
+        }
+        self.connection.clear();
+        self.stopped.store(true, Ordering::Release);
+        // wake a pending read so it sees the receiver stopped
+        NotifyPipe::signal(self.async_fd.get_ref().write_fd());
+        Ok(())
+    }
+
+    /// Returns `true` once [`stop`](Self::stop) has been called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Capture every payload delivered from now on into `tap`, or stop
+    /// with `None`.
+    pub fn set_capture(&self, tap: Option<CaptureTap>) {
+        self.capture.set(tap);
+    }
+
+    /// Take the buffer that keeps what an `AsyncRead` read had no room for
+    /// from `source`, see [`buffer`](crate::buffer).
+    pub fn set_buffer_source(&self, source: Arc<dyn BufferSource>) {
+        self.buffers.set(source);
+    }
+
+    /// Put the blocks read through `AsyncRead` back in sequence order with
+    /// `reorderer`, or stop with `None`.
+    ///
+    /// Once the reorderer gives up on a missing block, after its window
+    /// fills or its [`max_hold`](Reorderer::max_hold) passes, the read fails
+    /// with [`io::ErrorKind::InvalidData`] wrapping a
+    /// [`SeqGap`](crate::continuity::SeqGap); the next read continues after
+    /// the gap, so a consumer that can live with gaps just reads on. Without
+    /// a `max_hold`, a gap near the end of a burst holds up the blocks
+    /// behind it until enough others arrive. After [`stop`](Self::stop),
+    /// everything held is read out, with its gaps, before end of file.
+    ///
+    /// Blocks arriving too late are dropped. Only the `AsyncRead` impl is
+    /// ordered, and only for a single flow, see [`Reorderer`]. Blocks held
+    /// when this is changed are lost.
+    pub fn set_ordered_reads(&self, reorderer: Option<Reorderer>) {
+        *self.reorder.lock().unwrap_or_else(|e| e.into_inner()) = reorderer;
+    }
+
+    /// Report how the receiver is doing, for liveness and readiness probes.
+    pub fn health(&self) -> Health {
+        Health::new(
+            !self.is_stopped(),
+            &self.peers(),
+            self.flows.last_packet_age(),
+        )
+        .with_receiver_stats(self.stats.latest())
+    }
+
+    /// Returns `true` if any sender is connected to this receiver.
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_connected()
+    }
+
+    /// Reconnect a peer with new recovery and bitrate settings.
+    ///
+    /// librist fixes a peer's settings when it is created, so the peer is
+    /// destroyed and created again from its URL with `options`. This resets
+    /// its RIST session: a calling peer reconnects and starts over with an
+    /// empty recovery buffer, and a listening peer drops every client
+    /// connected to it, which have to connect again. The context and its
+    /// other peers keep running. The replacement's ID is returned.
+    ///
+    /// A calling peer's replacement is created before it is destroyed, so
+    /// if that fails the peer is left as it was. A listening peer has to
+    /// release its port first, so if that fails it is re-created with its
+    /// old settings, and may get the same ID back either way.
+    pub fn reconnect_peer(&self, peer: PeerId, options: &ReceiverOptions) -> Result<PeerId> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        replace_peer(
+            self.raw_ctx,
+            &mut peers,
+            &self.connection,
+            peer,
+            Some(options.configurer()?),
+        )
+    }
+
+    /// List the peers of this receiver with their connection state.
+    ///
+    /// Senders connected to the listening peer are listed after it, without
+    /// a URL.
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        let peers = self
+            .peers
+            .lock()
+            .map(|peers| self.connection.peers(&peers, self.profile))
+            .unwrap_or_default();
+        match_stats(peers, &self.flows, &self.stats)
+    }
+
+    /// List the flows this receiver has delivered data from.
+    pub fn flows(&self) -> Vec<FlowInfo> {
+        self.flows.flows()
+    }
+
+    /// Close the flow `flow_id`, e.g. to evict a misbehaving contributor
+    /// without restarting the listener.
+    ///
+    /// Works like [`Receiver::close_flow`](crate::Receiver::close_flow).
+    /// Blocks already handed to a [`port_stream`](Self::port_stream) are
+    /// not taken back.
+    pub fn close_flow(&self, flow_id: u32) -> Result<()> {
+        if self.flows.close(flow_id) {
+            Ok(())
+        } else {
+            Err(Error::UnknownFlow(flow_id))
+        }
+    }
+
+    /// Call `callback` when librist tears down a flow because all of its
+    /// senders timed out, so per-flow resources can be released.
+    ///
+    /// The callback runs on a librist thread and must not block.
+    pub fn on_flow_expired(&self, callback: impl FnMut(&FlowInfo) + Send + 'static) {
+        self.flows.set_callback(Some(Box::new(callback)));
+    }
+
+    /// Check every block read for MPEG-TS sync loss and continuity counter
+    /// errors, reported per flow in
+    /// [`ReceiverStats::ts`](crate::ReceiverStats::ts).
+    ///
+    /// See [`Receiver::set_ts_monitor`](crate::Receiver::set_ts_monitor).
+    #[cfg(feature = "ts")]
+    pub fn set_ts_monitor(&self, enabled: bool) {
+        self.flows.set_ts_monitor(enabled);
+    }
+
+    /// Call `callback` when a flow delivers nothing for `timeout`, and again
+    /// when it resumes, e.g. to switch to a slate or backup source.
+    ///
+    /// Stalls are checked by a background task, so they are reported even
+    /// while nobody is reading. A flow counts as delivering when its blocks
+    /// are read, so a reader that falls `timeout` behind looks stalled too.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    pub fn on_stall(&self, timeout: Duration, callback: impl FnMut(&StreamEvent) + Send + 'static) {
+        self.flows.watch_stalls(timeout, Box::new(callback));
+
+        let flows = Arc::downgrade(&self.flows);
+        let period = (timeout / 4).max(Duration::from_millis(10));
+        let task = ::tokio::spawn(async move {
+            let mut ticker = ::tokio::time::interval(period);
+            loop {
+                ticker.tick().await;
+                let Some(flows) = flows.upgrade() else {
+                    return;
+                };
+                flows.check_stalls();
+            }
+        });
+        if let Ok(mut guard) = self.stall_task.lock() {
+            if let Some(previous) = guard.replace(task) {
+                previous.abort();
+            }
+        }
+    }
+
+    /// Also call `callback` with every connection status change.
+    pub(crate) fn watch_connections(&self, callback: ConnectionCallback) {
+        track_flows(&self.connection, &self.flows, Some(callback));
+    }
+
+    /// Pause delivery while keeping the session alive.
+    ///
+    /// While paused, incoming data is discarded instead of returned, so
+    /// [`recv`](Self::recv) waits until [`resume`](Self::resume) and then
+    /// continues with live data. The connection and retransmission requests
+    /// carry on as normal, so resuming needs no new handshake. To stop
+    /// retransmission requests too, see
+    /// [`set_nacks_enabled`](Self::set_nacks_enabled).
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Release);
+    }
+
+    /// Resume delivery after [`pause`](Self::pause).
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+    }
+
+    /// Stop or restart asking for retransmissions on every live peer,
+    /// re-creating those whose setting changes; see
+    /// [`Receiver::set_nacks_enabled`](crate::Receiver::set_nacks_enabled).
+    pub fn set_nacks_enabled(&self, enabled: bool) -> Result<Vec<PeerId>> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        set_recovery_disabled(self.raw_ctx, &mut peers, &self.connection, !enabled)
+    }
+
+    /// Returns `true` while the receiver is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
     /// Receive data asynchronously using native eventfd notification.
     ///
-    /// Returns `Ok(None)` on timeout or when no data is available.
+    /// Waits for the next block. Returns `Ok(None)` once the receiver is
+    /// [stopped](Self::stop) and the blocks queued before were read, so a
+    /// receive loop ends when the receiver stops.
     pub async fn recv(&self) -> Result<Option<DataBlock>> {
-        if let Some(block) = self.try_recv()? {
+        self.wait_for(None, || self.try_recv()).await
+    }
+
+    /// Like [`recv`](Self::recv), but skips blocks already set aside for
+    /// `recv` by a port stream.
+    pub(super) async fn recv_unrouted(&self) -> Result<Option<DataBlock>> {
+        self.wait_for(None, || self.read_next()).await
+    }
+
+    /// Wait for data and return every block available right away.
+    ///
+    /// Saves a wakeup per block when librist delivers bursts, e.g. after
+    /// recovering a run of lost packets. Like [`try_recv`](Self::try_recv)
+    /// this leaves out blocks for ports with a
+    /// [`port_stream`](Self::port_stream).
+    pub async fn recv_burst(&self) -> Result<Vec<DataBlock>> {
+        let Some(first) = self.recv().await? else {
+            return Ok(Vec::new());
+        };
+        let mut blocks = vec![first];
+        while let Some(block) = self.try_recv()? {
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    /// Call `read` until it returns a block, waiting for librist's
+    /// notification in between, or until `deadline`, or until the receiver
+    /// is stopped and `read` comes up empty.
+    ///
+    /// Readiness is only cleared once `read` comes up empty, so a burst is
+    /// read one block per call without going back to the reactor. A block
+    /// is only taken from librist right before returning it, so dropping
+    /// the future at an `.await` loses nothing.
+    async fn wait_for(
+        &self,
+        deadline: Option<Instant>,
+        mut read: impl FnMut() -> Result<Option<DataBlock>>,
+    ) -> Result<Option<DataBlock>> {
+        if let Some(block) = read()? {
             return Ok(Some(block));
         }
+        if self.is_stopped() {
+            return Ok(None);
+        }
+
+        let expired = async {
+            match deadline {
+                Some(deadline) => {
+                    ::tokio::time::sleep_until(::tokio::time::Instant::from_std(deadline)).await
+                }
+                None => std::future::pending().await,
+            }
+        };
+        ::tokio::pin!(expired);
 
         loop {
-            // Wait for the eventfd to be readable (librist signals data available)
-            let mut guard = self
-                .async_fd
-                .readable()
-                .await
-                .map_err(|e| Error::EventFd(e.to_string()))?;
-
-            // Consume the event
+            // Wait for the pipe to be readable (librist signals data
+            // available); data that is already there wins over the deadline
+            let mut guard = ::tokio::select! {
+                biased;
+                guard = self.async_fd.readable() => {
+                    guard.map_err(|e| Error::EventFd(e.to_string()))?
+                }
+                () = &mut expired => return Ok(None),
+            };
+
+            // Still readable from an earlier burst, or freshly signalled
+            if let Some(block) = read()? {
+                return Ok(Some(block));
+            }
+
+            // Drained: consume the notifications, then check once more for
+            // data that arrived before they were consumed
             if let Err(e) = guard.get_inner().consume() {
                 if e.kind() != io::ErrorKind::WouldBlock {
                     return Err(Error::EventFd(e.to_string()));
                 }
             }
+            if let Some(block) = read()? {
+                return Ok(Some(block));
+            }
+            // stop signals the pipe, so a waiting read gets here
+            if self.is_stopped() {
+                return Ok(None);
+            }
+            guard.clear_ready();
+        }
+    }
 
-            // Read with timeout=0 (non-blocking) since we know data is available
-            let result = self.try_recv();
+    /// Poll for the next block, like [`recv`](Self::recv) without a future.
+    ///
+    /// For hand-written futures and streams: returns `Poll::Pending` and
+    /// wakes `cx` once librist signals new data. Only the waker of the
+    /// latest call is woken, so poll from one task at a time.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Result<Option<DataBlock>>> {
+        loop {
+            match self.try_recv() {
+                Ok(None) => {}
+                result => return Poll::Ready(result),
+            }
 
-            // Clear readiness so we wait again next time
-            guard.clear_ready();
+            match self.async_fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(mut guard)) => {
+                    // the next attempt picks up data that arrived before
+                    // the notifications were consumed
+                    if let Err(e) = guard.get_inner().consume() {
+                        if e.kind() != io::ErrorKind::WouldBlock {
+                            return Poll::Ready(Err(Error::EventFd(e.to_string())));
+                        }
+                    }
+                    guard.clear_ready();
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::EventFd(e.to_string()))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Wait for the next block and append its payload to `buf`, returning
+    /// the number of bytes appended.
+    ///
+    /// Unlike the `AsyncRead` impl this copies each payload once, straight
+    /// into `buf`, which grows to fit a whole payload, so nothing is kept
+    /// back for the next read. Bytes an earlier `AsyncRead` read had no
+    /// room for come first. Cancel-safe like [`recv`](Self::recv).
+    #[cfg(feature = "bytes")]
+    pub async fn recv_into(&self, buf: &mut BytesMut) -> Result<usize> {
+        std::future::poll_fn(|cx| self.poll_recv_into(cx, buf)).await
+    }
 
-            match result? {
-                Some(block) => return Ok(Some(block)),
-                None => continue,
+    /// Poll for the next block, appending its payload to `buf`; see
+    /// [`recv_into`](Self::recv_into) and [`poll_recv`](Self::poll_recv).
+    #[cfg(feature = "bytes")]
+    pub fn poll_recv_into(&self, cx: &mut Context<'_>, buf: &mut BytesMut) -> Poll<Result<usize>> {
+        {
+            let mut rest = self.read_buf.lock().unwrap_or_else(|e| e.into_inner());
+            if !rest.is_empty() {
+                buf.extend_from_slice(&rest);
+                let n = rest.len();
+                self.buffers.get().release(std::mem::take(&mut *rest));
+                return Poll::Ready(Ok(n));
+            }
+        }
+
+        loop {
+            match std::task::ready!(self.poll_recv(cx)) {
+                Ok(Some(block)) => {
+                    buf.extend_from_slice(block.payload());
+                    return Poll::Ready(Ok(block.payload().len()));
+                }
+                Ok(None) => {}
+                Err(e) => return Poll::Ready(Err(e)),
             }
         }
     }
 
-    /// Receive data with a custom timeout.
+    /// Receive data, waiting up to `timeout`.
+    ///
+    /// Returns `Ok(None)` if nothing arrived in time. Like
+    /// [`recv_deadline`](Self::recv_deadline) this is cancel-safe.
     pub async fn recv_timeout(&self, timeout: Duration) -> Result<Option<DataBlock>> {
-        // Use tokio timeout wrapping the native async recv
-        match ::tokio::time::timeout(timeout, self.recv()).await {
-            Ok(result) => result,
-            Err(_) => Ok(None), // Timeout
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.recv_deadline(deadline).await,
+            None => self.recv().await,
         }
     }
 
+    /// Receive data, waiting until `deadline` at the latest.
+    ///
+    /// Returns `Ok(None)` if nothing arrived by then, or once the receiver
+    /// is stopped and drained; a block that is already waiting is returned
+    /// even if the deadline has passed. This is
+    /// cancel-safe: if the future is dropped, e.g. by another `select!`
+    /// branch, no block is lost, so a loop can race it against other events
+    /// with the same deadline every iteration.
+    pub async fn recv_deadline(&self, deadline: Instant) -> Result<Option<DataBlock>> {
+        self.wait_for(Some(deadline), || self.try_recv()).await
+    }
+
     /// Try to receive data without blocking.
     /// Returns Ok(None) if no data is immediately available.
+    ///
+    /// Data from flows rejected by
+    /// [`accept_flow_ids`](ReceiverOptions::accept_flow_ids) is skipped.
+    ///
+    /// Data for ports with a [`port_stream`](Self::port_stream) goes to
+    /// that stream instead.
     pub fn try_recv(&self) -> Result<Option<DataBlock>> {
-        let mut block: *mut rist_sys::rist_data_block = ptr::null_mut();
+        if let Some(block) = self.ports.take_unclaimed() {
+            return Ok(Some(block));
+        }
+        self.read_next()
+    }
 
+    /// Read the next block for a port without a stream from librist,
+    /// without blocking.
+    fn read_next(&self) -> Result<Option<DataBlock>> {
         // timeout=0 means non-blocking
-        let ret = unsafe { rist_sys::rist_receiver_data_read2(self.raw_ctx, &mut block, 0) };
-
-        if ret < 0 {
-            return Err(Error::Read);
+        loop {
+            // under OverflowPolicy::Block a full queue holds everything up,
+            // until its consumer makes room and signals the notify pipe
+            if self.ports.is_backlogged() {
+                return Ok(None);
+            }
+            let Some(block) = read_block(self.raw_ctx, 0)? else {
+                break;
+            };
+            self.queue_len.store(block.queue_len(), Ordering::Relaxed);
+            self.fifo_drops.record(&block);
+            // while paused, discard everything queued so resuming starts from live data
+            if self.is_paused()
+                || !block.accepted_by(self.accepted_flow_ids.as_deref())
+                || !self.flows.admits(block.flow_id(), block.peer())
+            {
+                continue;
+            }
+            self.flows
+                .seen(block.flow_id(), block.peer(), block.timestamp());
+            #[cfg(feature = "ts")]
+            self.flows.inspect_ts(block.flow_id(), block.payload());
+            self.capture
+                .record(Direction::Received, block.payload(), &block.metadata());
+            if let Some(block) = self.ports.route(block) {
+                return Ok(Some(block));
+            }
         }
 
-        if ret == 0 || block.is_null() {
-            return Ok(None);
-        }
+        self.queue_len.store(0, Ordering::Relaxed);
+        Ok(None)
+    }
+
+    /// Blocks waiting in librist's output queue as of the last read.
+    ///
+    /// Compare against [`ReceiverOptions::fifo_size()`] to notice falling
+    /// behind before the queue overflows.
+    pub fn queue_len(&self) -> usize {
+        self.queue_len.load(Ordering::Relaxed)
+    }
+
+    /// Start an application heartbeat over OOB, pinging the far end every
+    /// `interval` and answering its pings.
+    ///
+    /// Gives an end-to-end RTT and "far end alive" status independent of
+    /// RTCP, see [`oob`](crate::oob). Fails on the Simple profile, which has
+    /// no OOB channel. Calling it again only changes the interval.
+    pub fn start_heartbeat(&self, interval: Duration) -> Result<Heartbeat> {
+        self.oob.start_heartbeat(&self.connection, interval)
+    }
+
+    /// Measure the application-level round trip to every connected peer
+    /// over OOB.
+    ///
+    /// Works like [`Sender::rtt_probe`](crate::Sender::rtt_probe) without
+    /// blocking the runtime.
+    pub async fn rtt_probe(&self, count: u32, timeout: Duration) -> Result<RttProbe> {
+        self.oob
+            .rtt_probe_async(&self.connection, count, timeout)
+            .await
+    }
 
-        Ok(Some(DataBlock::from_raw(block)))
+    /// Send a typed message over OOB to every connected peer, returning how
+    /// many it went to.
+    ///
+    /// The message is encoded as JSON and must fit one OOB packet. See
+    /// [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub fn send_message<T: Serialize>(&self, message: &T) -> Result<usize> {
+        self.oob.send_message(&self.connection, message)
+    }
+
+    /// Send a typed message over OOB to `peer` only.
+    #[cfg(feature = "serde")]
+    pub fn send_message_to<T: Serialize>(&self, peer: PeerId, message: &T) -> Result<()> {
+        self.oob.send_message_to(peer, message)
+    }
+
+    /// Receive the next typed message sent over OOB, waiting until one
+    /// arrives.
+    ///
+    /// Fails with [`Error::InvalidMessage`] if the message isn't a `T`.
+    /// Messages that arrived before the call are kept, up to 64. Wrap the
+    /// call in `tokio::time::timeout` to give up.
+    #[cfg(feature = "serde")]
+    pub async fn recv_message<T: DeserializeOwned>(&self) -> Result<Message<T>> {
+        self.oob.recv_message_async().await
+    }
+
+    /// Send a remote control command over OOB to every connected peer, and
+    /// wait up to `timeout` for the far end to acknowledge it.
+    ///
+    /// Fails with [`Error::CommandRejected`] if the far end refused it, or
+    /// [`Error::CommandTimeout`] without an answer. See [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub async fn send_command(&self, command: Command, timeout: Duration) -> Result<()> {
+        self.oob
+            .send_command_async(&self.connection, command, timeout)
+            .await
+    }
+
+    /// Receive the next remote control command, waiting until one arrives.
+    ///
+    /// Answer it with [`ack_command`](Self::ack_command). Commands that
+    /// arrived before the call are kept, up to 64.
+    #[cfg(feature = "serde")]
+    pub async fn recv_command(&self) -> Result<CommandRequest> {
+        self.oob.recv_command_async().await
+    }
+
+    /// Acknowledge a command from [`recv_command`](Self::recv_command), with
+    /// the reason if it was refused.
+    #[cfg(feature = "serde")]
+    pub fn ack_command(
+        &self,
+        request: &CommandRequest,
+        result: std::result::Result<(), String>,
+    ) -> Result<()> {
+        self.oob.ack_command(request, result)
+    }
+
+    /// The underlying librist context, for calling `rist-sys` functions
+    /// directly.
+    ///
+    /// The pointer stays owned by this receiver and is only valid until it is
+    /// dropped. Don't destroy it or replace its callbacks.
+    pub fn as_raw(&self) -> *mut rist_sys::rist_ctx {
+        self.raw_ctx
     }
 
     /// Returns the latest stats for this receiver.
@@ -330,12 +823,42 @@ impl AsyncReceiver {
     /// Stats are updated periodically (every 1 second by default).
     /// Returns `None` if no stats have been collected yet.
     pub fn raw_stats(&self) -> Option<ReceiverStats> {
-        self.stats.lock().ok().and_then(|guard| guard.clone())
+        self.stats.latest()
+    }
+
+    /// Returns the latest stats for this receiver with the interval they cover,
+    /// for bitrate gauges.
+    ///
+    /// Returns `None` if no stats have been collected yet.
+    pub fn stats_snapshot(&self) -> Option<StatsSnapshot<ReceiverStats>> {
+        self.stats.latest_snapshot()
+    }
+
+    /// Forget the stats collected so far, to start a fresh measurement
+    /// window, e.g. after maintenance.
+    ///
+    /// librist's counters are cumulative and keep running; from now on they
+    /// are reported as the increase since this call. Gauges such as
+    /// quality, RTT and bandwidth are unaffected. Until librist's next
+    /// report, [`raw_stats`](Self::raw_stats) returns `None`.
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// Report counters as running totals or as the increase since the
+    /// previous report, see [`StatsMode`]. Applies to
+    /// [`raw_stats`](Self::raw_stats), snapshots and the stats callback.
+    pub fn set_stats_mode(&self, mode: StatsMode) {
+        self.stats.set_mode(mode);
     }
 }
 
 impl Drop for AsyncReceiver {
     fn drop(&mut self) {
+        self.oob.shutdown();
+        if let Some(task) = self.stall_task.get_mut().ok().and_then(Option::take) {
+            task.abort();
+        }
         unsafe {
             rist_sys::rist_receiver_data_notify_fd_set(self.raw_ctx, 0);
             rist_sys::rist_destroy(self.raw_ctx);
@@ -358,47 +881,110 @@ impl AsyncRead for AsyncReceiver {
                     let to_read = std::cmp::min(buf.remaining(), read_buf.len());
                     buf.put_slice(&read_buf[..to_read]);
                     read_buf.drain(..to_read);
+                    if read_buf.is_empty() {
+                        this.buffers.get().release(std::mem::take(&mut *read_buf));
+                    }
                     return Poll::Ready(Ok(()));
                 }
             }
 
-            // Buffer is empty, read from RIST (non-blocking with 0 timeout)
-            let mut block: *mut rist_sys::rist_data_block = ptr::null_mut();
-            let ret = unsafe { rist_sys::rist_receiver_data_read2(this.raw_ctx, &mut block, 0) };
-
-            if ret < 0 {
-                return Poll::Ready(Err(io::Error::other("read failed")));
+            // In ordered mode, hand out what is next in sequence
+            let ordered = this
+                .reorder
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .as_mut()
+                .and_then(Reorderer::pop);
+            match ordered {
+                Some(Ordered::Item(block)) => {
+                    this.fill(block.payload(), buf);
+                    return Poll::Ready(Ok(()));
+                }
+                Some(Ordered::Gap(gap)) => {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, gap)))
+                }
+                None => {}
             }
 
-            if ret > 0 && !block.is_null() {
-                // Copy data from block
-                let data_block = DataBlock::from_raw(block);
-                let payload = data_block.payload();
-
-                let to_read = std::cmp::min(buf.remaining(), payload.len());
-                buf.put_slice(&payload[..to_read]);
-
-                // Store remaining data in buffer
-                if to_read < payload.len() {
-                    if let Ok(mut read_buf) = this.read_buf.lock() {
-                        read_buf.extend_from_slice(&payload[to_read..]);
+            // Buffer is empty, wait for the next block from RIST
+            let data_block = match this.poll_recv(cx) {
+                Poll::Ready(Ok(block)) => block,
+                Poll::Ready(Err(Error::EventFd(e))) => {
+                    return Poll::Ready(Err(io::Error::other(e)))
+                }
+                Poll::Ready(Err(_)) => return Poll::Ready(Err(io::Error::other("read failed"))),
+                // nothing left queued, and nothing more will arrive
+                Poll::Pending if this.is_stopped() => {
+                    let mut reorder = this.reorder.lock().unwrap_or_else(|e| e.into_inner());
+                    match reorder.as_mut() {
+                        Some(reorderer) if reorderer.held() > 0 => {
+                            reorderer.flush();
+                            continue;
+                        }
+                        _ => return Poll::Ready(Ok(())),
                     }
                 }
-
-                return Poll::Ready(Ok(()));
+                Poll::Pending => match this.poll_reorder_deadline(cx) {
+                    Poll::Ready(()) => continue,
+                    Poll::Pending => return Poll::Pending,
+                },
+            };
+
+            let Some(data_block) = data_block else {
+                continue;
+            };
+            if let Some(reorderer) = this
+                .reorder
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .as_mut()
+            {
+                reorderer.push(data_block.seq(), data_block);
+                continue;
             }
+            this.fill(data_block.payload(), buf);
+            return Poll::Ready(Ok(()));
+        }
+    }
+}
 
-            match this.async_fd.poll_read_ready(cx) {
-                Poll::Ready(Ok(mut guard)) => {
-                    if let Err(err) = guard.get_inner().consume() {
-                        if err.kind() != io::ErrorKind::WouldBlock {
-                            return Poll::Ready(Err(io::Error::other(err)));
-                        }
-                    }
-                    guard.clear_ready();
+impl AsyncReceiver {
+    /// Wait until the reorderer gives up on the block it is waiting for,
+    /// if it will.
+    fn poll_reorder_deadline(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let deadline = self
+            .reorder
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .and_then(Reorderer::deadline);
+        let Some(deadline) = deadline.map(::tokio::time::Instant::from_std) else {
+            self.reorder_timer = None;
+            return Poll::Pending;
+        };
+        let timer = self
+            .reorder_timer
+            .get_or_insert_with(|| Box::pin(::tokio::time::sleep_until(deadline)));
+        if timer.deadline() != deadline {
+            timer.as_mut().reset(deadline);
+        }
+        timer.as_mut().poll(cx)
+    }
+
+    /// Copy as much of `payload` into `buf` as fits, keeping the rest for
+    /// the next read.
+    fn fill(&self, payload: &[u8], buf: &mut ReadBuf<'_>) {
+        let to_read = std::cmp::min(buf.remaining(), payload.len());
+        buf.put_slice(&payload[..to_read]);
+
+        // Store remaining data in buffer
+        if to_read < payload.len() {
+            if let Ok(mut read_buf) = self.read_buf.lock() {
+                let rest = &payload[to_read..];
+                if read_buf.capacity() == 0 {
+                    *read_buf = self.buffers.get().acquire(rest.len());
                 }
-                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
-                Poll::Pending => return Poll::Pending,
+                read_buf.extend_from_slice(rest);
             }
         }
     }
@@ -0,0 +1,304 @@
+//! Automatic peer re-creation for [`AsyncSender`](super::AsyncSender).
+
+use super::sender::SendCtx;
+use crate::connection::{ConnectionSlot, ConnectionState};
+use crate::peer::{replace_peer, Peer};
+use crate::url::resolvable_host;
+use crate::{Error, PeerId};
+use ::tokio::sync::mpsc::UnboundedReceiver;
+use ::tokio::time::{interval, Interval, MissedTickBehavior};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// When and how often a sender re-creates a peer that timed out.
+///
+/// The delay before attempt `n` is `initial_backoff * multiplier^n`, capped
+/// at `max_backoff` and randomly spread by `jitter` so that many senders
+/// don't reconnect in lockstep after the same receiver restart.
+///
+/// Re-creating a peer resolves its host name again, so a failover done by
+/// changing DNS records takes effect on the next attempt. With
+/// [`re_resolve_every`](Self::re_resolve_every) the name is also checked
+/// while the peer is connected, and the peer is re-created as soon as it
+/// resolves to different addresses.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::tokio::{AsyncSender, ReconnectPolicy};
+/// use rist::Profile;
+/// use std::time::Duration;
+///
+/// # async fn example() -> rist::Result<()> {
+/// let sender = AsyncSender::connect(Profile::Main, "rist://192.168.1.1:5000")
+///     .reconnect(ReconnectPolicy::new().max_attempts(10))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Give up after this many consecutive attempts. `None` retries forever.
+    pub max_attempts: Option<u32>,
+    /// Delay before the first attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_backoff: Duration,
+    /// Factor applied to the delay after every failed attempt.
+    pub multiplier: f64,
+    /// Random spread applied to each delay, as a fraction (0.0 to 1.0).
+    pub jitter: f64,
+    /// How often to re-resolve peer host names while connected. `None`
+    /// only resolves them when a peer is re-created.
+    pub re_resolve: Option<Duration>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: None,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            re_resolve: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Create a policy that retries forever, starting at 500ms and backing
+    /// off up to 30s.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Give up after `attempts` consecutive attempts.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Set the delay before the first attempt.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Set the upper bound for the delay between attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the factor applied to the delay after every failed attempt.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set the random spread applied to each delay, as a fraction (0.0 to 1.0).
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Re-resolve peer host names every `interval`, re-creating a peer whose
+    /// name now points elsewhere. Peers given by IP address are left alone.
+    pub fn re_resolve_every(mut self, interval: Duration) -> Self {
+        self.re_resolve = Some(interval);
+        self
+    }
+
+    /// Delay before attempt `attempt` (0-based), without jitter.
+    fn base_backoff(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.min(i32::MAX as u32) as i32);
+        let secs = self.initial_backoff.as_secs_f64() * factor;
+        Duration::from_secs_f64(secs.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// Delay before attempt `attempt` (0-based), with jitter applied.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        // maps a random u64 onto -1.0..=1.0
+        let unit = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        let spread = 1.0 + self.jitter * (unit * 2.0 - 1.0);
+        self.base_backoff(attempt).mul_f64(spread.max(0.0))
+    }
+
+    fn exhausted(&self, attempts: u32) -> bool {
+        self.max_attempts.is_some_and(|max| attempts >= max)
+    }
+}
+
+/// Re-create peers that time out, following `policy`.
+///
+/// A peer is only replaced while it is still in `peers`, so clearing the
+/// list (on stop, shutdown or drop) makes the task stand down.
+pub(crate) async fn run(
+    ctx: SendCtx,
+    peers: Arc<Mutex<Vec<Peer>>>,
+    connection: Arc<ConnectionSlot>,
+    policy: ReconnectPolicy,
+    mut events: UnboundedReceiver<(PeerId, ConnectionState)>,
+) {
+    let mut attempts = 0;
+    let mut resolver = policy.re_resolve.map(Resolver::new);
+
+    loop {
+        let event = match &mut resolver {
+            Some(resolver) => ::tokio::select! {
+                event = events.recv() => event,
+                _ = resolver.timer.tick() => {
+                    resolver.check(&ctx, &peers, &connection).await;
+                    continue;
+                }
+            },
+            None => events.recv().await,
+        };
+        let Some((peer, state)) = event else {
+            return;
+        };
+
+        if state == ConnectionState::Connected {
+            attempts = 0;
+            continue;
+        }
+
+        // keep retrying this peer until a new one is created or we give up
+        while !policy.exhausted(attempts) {
+            ::tokio::time::sleep(policy.backoff(attempts)).await;
+            attempts += 1;
+
+            let Ok(mut guard) = peers.lock() else {
+                return;
+            };
+            match replace_peer(ctx.as_ptr(), &mut guard, &connection, peer, None) {
+                // creation failed, the old peer is still in place
+                Err(Error::PeerCreation(_)) => continue,
+                // replaced, or the peer is gone
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Re-creates peers whose host name resolves to new addresses.
+struct Resolver {
+    timer: Interval,
+    // peer URL -> addresses it resolved to last time
+    known: HashMap<String, BTreeSet<SocketAddr>>,
+}
+
+impl Resolver {
+    fn new(every: Duration) -> Self {
+        let mut timer = interval(every.max(Duration::from_secs(1)));
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            timer,
+            known: HashMap::new(),
+        }
+    }
+
+    async fn check(
+        &mut self,
+        ctx: &SendCtx,
+        peers: &Mutex<Vec<Peer>>,
+        connection: &ConnectionSlot,
+    ) {
+        let named: Vec<(PeerId, String, String)> = match peers.lock() {
+            Ok(peers) => peers
+                .iter()
+                .filter_map(|peer| Some((peer.id(), peer.url.clone(), resolvable_host(&peer.url)?)))
+                .collect(),
+            Err(_) => return,
+        };
+        self.known
+            .retain(|url, _| named.iter().any(|(_, u, _)| u == url));
+
+        for (id, url, host) in named {
+            // a failed lookup says nothing about where the name points
+            let Ok(addrs) = ::tokio::net::lookup_host(host.as_str()).await else {
+                continue;
+            };
+            let addrs: BTreeSet<SocketAddr> = addrs.collect();
+            if addrs.is_empty() {
+                continue;
+            }
+            if self.record(url, addrs) {
+                let Ok(mut guard) = peers.lock() else {
+                    return;
+                };
+                // a failure leaves the old peer in place; the next change retries
+                let _ = replace_peer(ctx.as_ptr(), &mut guard, connection, id, None);
+            }
+        }
+    }
+
+    /// Remember what `url` resolved to, returning `true` if it changed.
+    fn record(&mut self, url: String, addrs: BTreeSet<SocketAddr>) -> bool {
+        let changed = self.known.get(&url).is_some_and(|known| *known != addrs);
+        self.known.insert(url, addrs);
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = ReconnectPolicy::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1))
+            .jitter(0.0);
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(4), Duration::from_secs(1));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_in_range() {
+        let policy = ReconnectPolicy::new()
+            .initial_backoff(Duration::from_secs(1))
+            .jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy.backoff(0);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_millis(1500));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_re_resolve_detects_changed_addresses() {
+        let mut resolver = Resolver::new(Duration::from_secs(1));
+        let url = "rist://ingest.example.com:5000".to_string();
+        let addrs = |list: &[&str]| -> BTreeSet<SocketAddr> {
+            list.iter().map(|addr| addr.parse().unwrap()).collect()
+        };
+
+        // the first lookup only sets the baseline
+        assert!(!resolver.record(url.clone(), addrs(&["192.0.2.1:5000"])));
+        assert!(!resolver.record(url.clone(), addrs(&["192.0.2.1:5000"])));
+        assert!(resolver.record(url.clone(), addrs(&["192.0.2.2:5000"])));
+        assert!(resolver.record(url, addrs(&["192.0.2.1:5000", "192.0.2.2:5000"])));
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let policy = ReconnectPolicy::new().max_attempts(3);
+        assert!(!policy.exhausted(2));
+        assert!(policy.exhausted(3));
+        assert!(!ReconnectPolicy::new().exhausted(u32::MAX));
+    }
+}
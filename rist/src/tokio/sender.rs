@@ -1,20 +1,47 @@
-use crate::stats::SenderStats;
-use crate::{Error, Profile, Result, SenderOptions};
+use super::reconnect::{self, ReconnectPolicy};
+use crate::buffer::{BufferSlot, BufferSource};
+use crate::capture::{CaptureSlot, CaptureTap, Direction};
+use crate::connection::{register_connection, ConnectionSlot};
+use crate::health::Activity;
+#[cfg(feature = "serde")]
+use crate::oob::{Command, CommandRequest, Message};
+use crate::oob::{Heartbeat, OobSlot, RttProbe};
+use crate::options::recovery_bitrate;
+use crate::pacing::{BitrateCap, Pacer};
+use crate::peer::{
+    add_peer, create_peer, destroy_peers, linger_time, replace_peer, set_recovery_bitrate, Peer,
+};
+use crate::sender::{
+    check_flow_id, check_payload, check_sole_peer, match_stats, max_retry_bandwidth, queue_full,
+    try_write_block, write_block,
+};
+use crate::stats::{
+    register_stats, CounterStats, FromRawStats, SenderStats, StatsMode, StatsSlot, StatsSnapshot,
+    DEFAULT_STATS_INTERVAL,
+};
+use crate::{
+    BlockMetadata, Error, Health, PeerConfig, PeerId, PeerInfo, Profile, Result, SenderOptions,
+};
 use ::tokio::io::AsyncWrite;
+use ::tokio::sync::{mpsc, watch};
 use ::tokio::task::{spawn_blocking, JoinHandle};
-use std::ffi::CString;
+use ::tokio::time::Sleep;
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
 use std::future::Future;
 use std::io;
-use std::os::raw::c_void;
 use std::pin::Pin;
 use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 /// Send-safe wrapper for rist context pointer.
 /// SAFETY: librist contexts are thread-safe.
 #[derive(Clone, Copy)]
-struct SendCtx(usize);
+pub(super) struct SendCtx(usize);
 
 unsafe impl Send for SendCtx {}
 
@@ -23,155 +50,190 @@ impl SendCtx {
         Self(ctx as usize)
     }
 
-    fn as_ptr(self) -> *mut rist_sys::rist_ctx {
+    pub(super) fn as_ptr(self) -> *mut rist_sys::rist_ctx {
         self.0 as *mut rist_sys::rist_ctx
     }
 }
 
-/// Stats callback for librist sender
-unsafe extern "C" fn stats_callback(
-    arg: *mut c_void,
-    stats_container: *const rist_sys::rist_stats,
-) -> i32 {
-    if arg.is_null() || stats_container.is_null() {
-        return 0;
-    }
-
-    let stats_arc = &*(arg as *const Arc<Mutex<Option<SenderStats>>>);
-    let stats = &*stats_container;
-
-    // Check if this is sender stats
-    if stats.stats_type == rist_sys::rist_stats_type_RIST_STATS_SENDER_PEER {
-        let sender_stats = SenderStats::from(&stats.stats.sender_peer);
-        if let Ok(mut guard) = stats_arc.lock() {
-            *guard = Some(sender_stats);
-        }
-    }
-
-    // Free the stats container
-    rist_sys::rist_stats_free(stats_container);
-
-    0
-}
-
 /// Async RIST sender.
 pub struct AsyncSender {
     ctx: SendCtx,
     raw_ctx: *mut rist_sys::rist_ctx,
-    stats: Arc<Mutex<Option<SenderStats>>>,
-    _stats_data: Option<Box<Arc<Mutex<Option<SenderStats>>>>>,
+    profile: Profile,
+    // the stats callback points into this allocation, so it must outlive the context
+    stats: Arc<StatsSlot<SenderStats>>,
+    peers: Arc<Mutex<Vec<Peer>>>,
+    stopped: AtomicBool,
+    // must outlive the context, like `stats`
+    connection: Arc<ConnectionSlot>,
+    oob: Arc<OobSlot>,
+    connection_changed: Arc<watch::Sender<()>>,
+    reconnect_task: Option<JoinHandle<()>>,
+    pacer: Pacer,
+    cap: BitrateCap,
+    activity: Activity,
+    capture: CaptureSlot,
+    buffers: BufferSlot,
+    rejected: AtomicU64,
+    // pacing delay of a write already counted against the pacer
+    write_delay: Option<Pin<Box<Sleep>>>,
 }
 
 // SAFETY: The sender context is thread-safe in librist
 unsafe impl Send for AsyncSender {}
 unsafe impl Sync for AsyncSender {}
 
+type ConnectFuture = Pin<Box<dyn Future<Output = Result<AsyncSender>> + Send>>;
+
 enum ConnectState {
     Idle,
-    Busy(JoinHandle<Result<AsyncSender>>),
+    Busy(ConnectFuture),
 }
 
 /// Future for connecting a sender.
 pub struct Connect {
     profile: Profile,
+    flow_id: u32,
     url: String,
     options: SenderOptions,
+    reconnect: Option<ReconnectPolicy>,
+    timeout: Option<Duration>,
     state: ConnectState,
 }
 
-impl Future for Connect {
-    type Output = Result<AsyncSender>;
-
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match &mut self.state {
-            ConnectState::Idle => {
-                let profile = self.profile;
-                let url = self.url.clone();
-                let options = self.options.clone();
+impl Connect {
+    /// Re-create the peer with `policy` whenever it times out, for example
+    /// because the receiver restarted.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
 
-                let mut handle = spawn_blocking(move || {
-                    let mut ctx: *mut rist_sys::rist_ctx = ptr::null_mut();
+    /// Send with the flow ID `flow_id`, see
+    /// [`Sender::with_flow_id`](crate::Sender::with_flow_id).
+    pub fn flow_id(mut self, flow_id: u32) -> Self {
+        self.flow_id = flow_id;
+        self
+    }
 
-                    let ret = unsafe {
-                        rist_sys::rist_sender_create(&mut ctx, profile.to_raw(), 0, ptr::null_mut())
-                    };
+    /// Resolve only once the peer handshake completes, failing with
+    /// [`Error::ConnectTimeout`] if that takes longer than `timeout`.
+    ///
+    /// Without this the future resolves as soon as the context starts,
+    /// before any data can actually flow.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-                    if ret != 0 || ctx.is_null() {
-                        return Err(Error::ContextCreation);
-                    }
+    fn start(&self) -> ConnectFuture {
+        let profile = self.profile;
+        let flow_id = self.flow_id;
+        let url = self.url.clone();
+        let options = self.options.clone();
+        let reconnect = self.reconnect.clone();
+        let timeout = self.timeout;
 
-                    // Set up stats callback
-                    let stats = Arc::new(Mutex::new(None));
-                    let stats_data = Box::new(stats.clone());
-                    let stats_ptr =
-                        &*stats_data as *const Arc<Mutex<Option<SenderStats>>> as *mut c_void;
-
-                    unsafe {
-                        rist_sys::rist_stats_callback_set(
-                            ctx,
-                            1000,
-                            Some(stats_callback),
-                            stats_ptr,
-                        );
-                    }
+        let handle = spawn_blocking(move || {
+            check_flow_id(flow_id)?;
+            let mut ctx: *mut rist_sys::rist_ctx = ptr::null_mut();
 
-                    // Add peer
-                    let url_c = CString::new(url.as_str())?;
-                    let mut peer_config: *mut rist_sys::rist_peer_config = ptr::null_mut();
+            let ret = unsafe {
+                rist_sys::rist_sender_create(&mut ctx, profile.to_raw(), flow_id, ptr::null_mut())
+            };
 
-                    let ret =
-                        unsafe { rist_sys::rist_parse_address2(url_c.as_ptr(), &mut peer_config) };
+            if ret != 0 || ctx.is_null() {
+                return Err(Error::ContextCreation);
+            }
 
-                    if ret != 0 || peer_config.is_null() {
-                        unsafe { rist_sys::rist_destroy(ctx) };
-                        return Err(Error::UrlParse(url));
+            let notify = Arc::new(watch::Sender::new(()));
+            // Dropping the sender on error destroys the context
+            let mut sender = AsyncSender {
+                ctx: SendCtx::new(ctx),
+                raw_ctx: ctx,
+                profile,
+                stats: Arc::new(StatsSlot::new()),
+                peers: Arc::new(Mutex::new(Vec::new())),
+                stopped: AtomicBool::new(false),
+                connection: Arc::new(ConnectionSlot::new()),
+                oob: Arc::new(OobSlot::new(ctx, profile)),
+                connection_changed: notify.clone(),
+                reconnect_task: None,
+                pacer: Pacer::default(),
+                cap: BitrateCap::default(),
+                activity: Activity::default(),
+                capture: CaptureSlot::default(),
+                buffers: BufferSlot::default(),
+                rejected: AtomicU64::new(0),
+                write_delay: None,
+            };
+            options.apply_to_pacer(&sender.pacer);
+            register_stats(ctx, DEFAULT_STATS_INTERVAL, &sender.stats)?;
+
+            let (events_tx, events_rx) = mpsc::unbounded_channel();
+            let reconnect_events = reconnect.is_some().then_some(events_tx);
+            sender
+                .connection
+                .set_callback(Some(Box::new(move |peer, state| {
+                    notify.send_replace(());
+                    if let Some(events) = &reconnect_events {
+                        let _ = events.send((peer, state));
                     }
+                })));
+            register_connection(ctx, &sender.connection)?;
+            sender.oob.register_early()?;
+
+            let peer = create_peer(ctx, profile, &url, options.configurer()?)?;
+            sender.connection.track(peer.id());
+            sender.peers.lock().unwrap().push(peer);
+
+            #[cfg(feature = "tracing")]
+            let _span = tracing::debug_span!("rist_start", role = "sender").entered();
+            let ret = unsafe { rist_sys::rist_start(ctx) };
+            if ret != 0 {
+                return Err(Error::Start);
+            }
 
-                    // Apply options to peer config
-                    unsafe {
-                        options.apply_to_peer_config(&mut *peer_config);
-                    }
+            if let Some(policy) = reconnect {
+                sender.reconnect_task = Some(::tokio::spawn(reconnect::run(
+                    SendCtx::new(ctx),
+                    sender.peers.clone(),
+                    sender.connection.clone(),
+                    policy,
+                    events_rx,
+                )));
+            }
 
-                    let mut peer: *mut rist_sys::rist_peer = ptr::null_mut();
-                    let ret = unsafe { rist_sys::rist_peer_create(ctx, &mut peer, peer_config) };
+            Ok(sender)
+        });
 
-                    unsafe {
-                        rist_sys::rist_peer_config_free2(&mut peer_config);
-                    }
+        Box::pin(async move {
+            let sender = handle
+                .await
+                .map_err(|e| Error::JoinError(e.to_string()))??;
 
-                    if ret != 0 {
-                        unsafe { rist_sys::rist_destroy(ctx) };
-                        return Err(Error::PeerCreation(url));
-                    }
+            if let Some(timeout) = timeout {
+                ::tokio::time::timeout(timeout, sender.wait_connected())
+                    .await
+                    .map_err(|_| Error::ConnectTimeout)??;
+            }
 
-                    // Start
-                    let ret = unsafe { rist_sys::rist_start(ctx) };
-                    if ret != 0 {
-                        unsafe { rist_sys::rist_destroy(ctx) };
-                        return Err(Error::Start);
-                    }
+            Ok(sender)
+        })
+    }
+}
 
-                    Ok(AsyncSender {
-                        ctx: SendCtx::new(ctx),
-                        raw_ctx: ctx,
-                        stats,
-                        _stats_data: Some(stats_data),
-                    })
-                });
-
-                let ret = Pin::new(&mut handle).poll(cx);
-                self.state = ConnectState::Busy(handle);
-                match ret {
-                    Poll::Ready(Ok(r)) => Poll::Ready(r),
-                    Poll::Ready(Err(e)) => Poll::Ready(Err(Error::JoinError(e.to_string()))),
-                    Poll::Pending => Poll::Pending,
-                }
-            }
-            ConnectState::Busy(ref mut handle) => match Pin::new(handle).poll(cx) {
-                Poll::Ready(Ok(r)) => Poll::Ready(r),
-                Poll::Ready(Err(e)) => Poll::Ready(Err(Error::JoinError(e.to_string()))),
-                Poll::Pending => Poll::Pending,
-            },
+impl Future for Connect {
+    type Output = Result<AsyncSender>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let ConnectState::Idle = self.state {
+            self.state = ConnectState::Busy(self.start());
+        }
+
+        match &mut self.state {
+            ConnectState::Busy(future) => future.as_mut().poll(cx),
+            ConnectState::Idle => unreachable!("connect future started above"),
         }
     }
 }
@@ -190,41 +252,558 @@ impl AsyncSender {
     pub fn connect_with_options(profile: Profile, url: &str, options: SenderOptions) -> Connect {
         Connect {
             profile,
+            flow_id: 0,
             url: url.to_string(),
             options,
+            reconnect: None,
+            timeout: None,
             state: ConnectState::Idle,
         }
     }
 
     /// Send data.
     pub async fn send(&self, data: &[u8]) -> Result<usize> {
+        self.send_with_metadata(data, &BlockMetadata::default())
+            .await
+    }
+
+    /// Send data stamped with the current wall-clock time, so receivers see
+    /// when it originated.
+    pub async fn send_timestamped(&self, data: &[u8]) -> Result<usize> {
+        self.send_with_metadata(data, &BlockMetadata::now()).await
+    }
+
+    /// Send data with explicit packet metadata.
+    ///
+    /// Use this to forward a received block with its original timestamp,
+    /// sequence number, flow ID and virtual ports. A block dropped by the
+    /// [bitrate cap](Self::set_bitrate_cap) doesn't wait for pacing, and one
+    /// that fails to write gives its pacing budget back.
+    pub async fn send_with_metadata(&self, data: &[u8], metadata: &BlockMetadata) -> Result<usize> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        self.admit(data.len())?;
+        let delay = self.pacer.delay(data.len());
+        self.write_after(delay, None, data, metadata)
+            .await
+            .inspect_err(|_| self.pacer.refund(data.len()))
+    }
+
+    /// Send data, giving up with [`Error::SendTimeout`] if it can't be
+    /// queued within `timeout`.
+    ///
+    /// For real-time pipelines that would rather skip a packet than stall:
+    /// a packet that would wait longer than `timeout` for
+    /// [`pace_bitrate`](SenderOptions::pace_bitrate) fails straight away
+    /// without using up pacing budget, and one still waiting for the
+    /// blocking pool at the deadline is never written. A packet that isn't
+    /// written gives its pacing budget back.
+    pub async fn send_timeout(&self, data: &[u8], timeout: Duration) -> Result<usize> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        self.admit(data.len())?;
+        let deadline = Instant::now().checked_add(timeout);
+        let delay = self
+            .pacer
+            .delay_within(data.len(), timeout)
+            .ok_or(Error::SendTimeout)?;
+        self.write_after(delay, deadline, data, &BlockMetadata::default())
+            .await
+            .inspect_err(|_| self.pacer.refund(data.len()))
+    }
+
+    async fn write_after(
+        &self,
+        delay: Duration,
+        deadline: Option<Instant>,
+        data: &[u8],
+        metadata: &BlockMetadata,
+    ) -> Result<usize> {
+        if !delay.is_zero() {
+            ::tokio::time::sleep(delay).await;
+        }
+
         let ctx = self.ctx;
-        let data = data.to_vec();
-
-        spawn_blocking(move || {
-            let block = rist_sys::rist_data_block {
-                payload: data.as_ptr() as *const _,
-                payload_len: data.len(),
-                ts_ntp: 0,
-                flow_id: 0,
-                flags: 0,
-                seq: 0,
-                virt_src_port: 0,
-                virt_dst_port: 0,
-                peer: ptr::null_mut(),
-                ref_: ptr::null_mut(),
+        let buffers = self.buffers.get();
+        let mut payload = buffers.acquire(data.len());
+        payload.extend_from_slice(data);
+        let block_metadata = *metadata;
+
+        let written = spawn_blocking(move || {
+            let written = if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                Err(Error::SendTimeout)
+            } else {
+                write_block(ctx.as_ptr(), &payload, &block_metadata)
             };
+            buffers.release(payload);
+            written
+        })
+        .await
+        .map_err(|e| Error::JoinError(e.to_string()))??;
+        self.activity.touch();
+        self.capture.record(Direction::Sent, data, metadata);
+        Ok(written)
+    }
 
-            let ret = unsafe { rist_sys::rist_sender_data_write(ctx.as_ptr(), &block) };
+    /// Send data only if it can be queued right away.
+    ///
+    /// Fails with [`Error::QueueFull`] instead of waiting when
+    /// [`pace_bitrate`](SenderOptions::pace_bitrate) has no room for it yet,
+    /// or when librist's send queue refuses it. Rejected packets are counted
+    /// in [`rejected_sends`](Self::rejected_sends). Never blocks, so it can
+    /// be called outside the blocking pool.
+    pub fn try_send(&self, data: &[u8]) -> Result<usize> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        check_payload(data)?;
+        if !self.pacer.try_take(data.len()) {
+            return Err(queue_full(&self.rejected));
+        }
+        let metadata = BlockMetadata::default();
+        // a packet that isn't sent doesn't use up pacing budget
+        let written = self
+            .admit(data.len())
+            .and_then(|()| try_write_block(self.raw_ctx, &self.rejected, data, &metadata))
+            .inspect_err(|_| self.pacer.refund(data.len()))?;
+        self.activity.touch();
+        self.capture.record(Direction::Sent, data, &metadata);
+        Ok(written)
+    }
+
+    /// Packets rejected by [`try_send`](Self::try_send) so far.
+    pub fn rejected_sends(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Write a block without going through the blocking pool.
+    ///
+    /// `rist_sender_data_write` only queues the packet, so this is cheap
+    /// enough to call from async code that already holds the payload.
+    pub(crate) fn write_with_metadata(
+        &self,
+        data: &[u8],
+        metadata: &BlockMetadata,
+    ) -> Result<usize> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        self.admit(data.len())?;
+        let written = write_block(self.raw_ctx, data, metadata)?;
+        self.activity.touch();
+        self.capture.record(Direction::Sent, data, metadata);
+        Ok(written)
+    }
 
-            if ret < 0 {
-                return Err(Error::Send);
+    /// Send data to `peer`, failing rather than sending it anywhere else.
+    ///
+    /// Works like [`Sender::send_to`](crate::Sender::send_to): librist
+    /// writes every block to all peers, so `peer` has to be the only one.
+    /// `rist_sender_data_write` only queues the packet, so this doesn't go
+    /// through the blocking pool. For the same reason it is not held back
+    /// by [`SenderOptions::pace_bitrate`].
+    pub fn send_to(&self, peer: PeerId, data: &[u8]) -> Result<usize> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        // hold the lock so no peer is added mid-write
+        let peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        check_sole_peer(&peers, peer)?;
+        self.admit(data.len())?;
+        let written = write_block(self.raw_ctx, data, &BlockMetadata::default())?;
+        self.activity.touch();
+        self.capture
+            .record(Direction::Sent, data, &BlockMetadata::default());
+        Ok(written)
+    }
+
+    /// Drop `len` bytes with [`Error::BitrateCapExceeded`] if they don't fit
+    /// under the bitrate cap.
+    fn admit(&self, len: usize) -> Result<()> {
+        if self.cap.admit(len, || max_retry_bandwidth(&self.stats)) {
+            Ok(())
+        } else {
+            Err(Error::BitrateCapExceeded)
+        }
+    }
+
+    /// Cap the total output bitrate, media plus retransmissions, in bits
+    /// per second. `None` removes the cap.
+    ///
+    /// Works like [`Sender::set_bitrate_cap`](crate::Sender::set_bitrate_cap):
+    /// media that doesn't fit is dropped with [`Error::BitrateCapExceeded`]
+    /// and counted in [`bitrate_cap_drops`](Self::bitrate_cap_drops).
+    pub fn set_bitrate_cap(&self, bitrate: Option<u64>) {
+        self.cap.set(bitrate);
+    }
+
+    /// The current output bitrate cap, if any.
+    pub fn bitrate_cap(&self) -> Option<u64> {
+        self.cap.get()
+    }
+
+    /// Number of sends dropped by the output bitrate cap.
+    pub fn bitrate_cap_drops(&self) -> u64 {
+        self.cap.dropped()
+    }
+
+    /// Stop sending without dropping the sender.
+    ///
+    /// New sends are rejected immediately. The peers stay up for their
+    /// recovery buffer length so retransmission requests for data already
+    /// sent can still be served, then they are removed. The last stats
+    /// remain available.
+    pub async fn stop(&self) -> Result<()> {
+        if self.stopped.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let linger = self
+            .peers
+            .lock()
+            .map(|peers| linger_time(&peers))
+            .unwrap_or_default();
+        ::tokio::time::sleep(linger).await;
+
+        if let Ok(mut peers) = self.peers.lock() {
+            destroy_peers(self.raw_ctx, &mut peers);
+        }
+        self.connection.clear();
+        self.connection_changed.send_replace(());
+        Ok(())
+    }
+
+    /// Stop sending, wait for outstanding retransmissions, then destroy the context.
+    ///
+    /// New sends are rejected immediately. The sender then waits until a
+    /// new stats report shows no retransmission traffic since the peer's
+    /// previous one, the peers' recovery buffer has passed, or `timeout`
+    /// expires, whichever comes first, so the tail of a transfer can still
+    /// be recovered by the receiver.
+    pub async fn shutdown(self, timeout: Duration) -> Result<()> {
+        self.stopped.store(true, Ordering::Release);
+
+        let linger = self
+            .peers
+            .lock()
+            .map(|peers| linger_time(&peers))
+            .unwrap_or_default();
+        let deadline = Instant::now() + timeout.min(linger);
+
+        // each peer's last report, which cumulative counters are compared with
+        let mut previous: HashMap<u32, SenderStats> = self
+            .stats
+            .all()
+            .into_iter()
+            .map(|stats| (stats.key(), stats))
+            .collect();
+        let mut last_seen = self.stats.latest_snapshot().map(|report| report.captured);
+        let cumulative = self.stats.mode() == StatsMode::Cumulative;
+
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            // wait for a fresh report before judging whether retransmissions are done
+            ::tokio::time::sleep(remaining.min(DEFAULT_STATS_INTERVAL)).await;
+            let Some(report) = self.stats.latest_snapshot() else {
+                continue;
+            };
+            if last_seen == Some(report.captured) {
+                continue;
+            }
+            last_seen = Some(report.captured);
+
+            let stats = report.stats;
+            let retransmitted = match previous.insert(stats.key(), stats.clone()) {
+                Some(earlier) if cumulative => stats.counters_since(&earlier).retransmitted,
+                // a peer's first report has nothing to compare a total with
+                None if cumulative => continue,
+                _ => stats.retransmitted,
+            };
+            if retransmitted == 0 && stats.retry_bandwidth == 0 {
+                break;
             }
+        }
 
-            Ok(ret as usize)
-        })
-        .await
-        .map_err(|e| Error::JoinError(e.to_string()))?
+        if let Ok(mut peers) = self.peers.lock() {
+            destroy_peers(self.raw_ctx, &mut peers);
+        }
+        self.connection.clear();
+        self.connection_changed.send_replace(());
+        Ok(())
+    }
+
+    /// Returns `true` if the peer has completed its handshake and not timed out since.
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_connected()
+    }
+
+    /// Add another destination to the running sender.
+    ///
+    /// Data sent afterwards goes to every peer. With a [`ReconnectPolicy`]
+    /// the new peer is re-created on timeout like the first one.
+    pub fn add_peer(&self, url: &str) -> Result<PeerId> {
+        self.add_peer_with_options(url, &SenderOptions::default())
+    }
+
+    /// Add another destination to the running sender with custom options.
+    pub fn add_peer_with_options(&self, url: &str, options: &SenderOptions) -> Result<PeerId> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        let id = add_peer(
+            self.raw_ctx,
+            self.profile,
+            &mut self.peers.lock().unwrap_or_else(|e| e.into_inner()),
+            &self.connection,
+            url,
+            options.configurer()?,
+        )?;
+        options.apply_to_pacer(&self.pacer);
+
+        Ok(id)
+    }
+
+    /// Add a peer with every librist setting given by `config`.
+    ///
+    /// Unlike [`add_peer_with_options`](Self::add_peer_with_options) the
+    /// settings in `config` are used as they are, including those the options
+    /// don't cover. Fails if `config` is invalid, see
+    /// [`PeerConfig::validate`](crate::PeerConfig::validate).
+    pub fn add_peer_config(&self, config: &PeerConfig) -> Result<PeerId> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        add_peer(
+            self.raw_ctx,
+            self.profile,
+            &mut self.peers.lock().unwrap_or_else(|e| e.into_inner()),
+            &self.connection,
+            &config.address,
+            config.configurer()?,
+        )
+    }
+
+    /// Reconnect a peer with new recovery and bitrate settings.
+    ///
+    /// librist fixes a peer's settings when it is created, so the peer is
+    /// destroyed and created again from its URL with `options`. This resets
+    /// its RIST session: a calling peer reconnects and starts over with an
+    /// empty recovery buffer, and a listening peer drops every client
+    /// connected to it, which have to connect again. The context and its
+    /// other peers keep running. The replacement's ID is returned.
+    ///
+    /// A calling peer's replacement is created before it is destroyed, so
+    /// if that fails the peer is left as it was. A listening peer has to
+    /// release its port first, so if that fails it is re-created with its
+    /// old settings, and may get the same ID back either way.
+    pub fn reconnect_peer(&self, peer: PeerId, options: &SenderOptions) -> Result<PeerId> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        let id = replace_peer(
+            self.raw_ctx,
+            &mut peers,
+            &self.connection,
+            peer,
+            Some(options.configurer()?),
+        )?;
+        options.apply_to_pacer(&self.pacer);
+        Ok(id)
+    }
+
+    /// Limit the bandwidth librist may use for each peer to `bandwidth` bits
+    /// per second, reconnecting every peer whose limit changes.
+    ///
+    /// Works like [`Sender::set_max_bandwidth`](crate::Sender::set_max_bandwidth):
+    /// every live peer not already at the new recovery bitrate is re-created
+    /// with it, resetting its session, and paced sends follow it. With a
+    /// [`ReconnectPolicy`] reconnects keep the new bitrate.
+    pub fn set_max_bandwidth(&self, bandwidth: u64) -> Result<Vec<PeerId>> {
+        if self.is_stopped() {
+            return Err(Error::Stopped);
+        }
+
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        let ids = set_recovery_bitrate(
+            self.raw_ctx,
+            &mut peers,
+            &self.connection,
+            recovery_bitrate(bandwidth)?,
+        )?;
+        self.pacer.retune(bandwidth);
+        Ok(ids)
+    }
+
+    /// List the peers of this sender with their connection state.
+    ///
+    /// With a [`ReconnectPolicy`] the peer's ID changes each time it is
+    /// re-created.
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        let peers = self
+            .peers
+            .lock()
+            .map(|peers| self.connection.peers(&peers, self.profile))
+            .unwrap_or_default();
+        match_stats(peers, &self.stats)
+    }
+
+    /// Latest stats for every peer, ordered by librist's peer ID, see
+    /// [`Sender::peer_stats`](crate::Sender::peer_stats).
+    pub fn peer_stats(&self) -> Vec<SenderStats> {
+        self.stats.all()
+    }
+
+    /// Wait until the peer handshake has completed and data will flow.
+    ///
+    /// Resolves immediately if a peer is already connected.
+    pub async fn wait_connected(&self) -> Result<()> {
+        let mut changed = self.connection_changed.subscribe();
+        loop {
+            if self.connection.is_connected() {
+                return Ok(());
+            }
+            if self.is_stopped() {
+                return Err(Error::Stopped);
+            }
+            // the sender half lives as long as `self`, so this can't fail
+            let _ = changed.changed().await;
+        }
+    }
+
+    /// Returns `true` once [`stop`](Self::stop) has been called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    /// Capture every payload sent from now on into `tap`, or stop with
+    /// `None`.
+    pub fn set_capture(&self, tap: Option<CaptureTap>) {
+        self.capture.set(tap);
+    }
+
+    /// Take the buffers each payload is copied into for the librist write
+    /// from `source`, see [`buffer`](crate::buffer).
+    pub fn set_buffer_source(&self, source: Arc<dyn BufferSource>) {
+        self.buffers.set(source);
+    }
+
+    /// Report how the sender is doing, for liveness and readiness probes.
+    pub fn health(&self) -> Health {
+        Health::new(!self.is_stopped(), &self.peers(), self.activity.age())
+            .with_quality(self.stats.latest().map(|stats| stats.quality))
+    }
+
+    /// Start an application heartbeat over OOB, pinging the far end every
+    /// `interval` and answering its pings.
+    ///
+    /// Gives an end-to-end RTT and "far end alive" status independent of
+    /// RTCP, see [`oob`](crate::oob). Fails on the Simple profile, which has
+    /// no OOB channel. Calling it again only changes the interval.
+    pub fn start_heartbeat(&self, interval: Duration) -> Result<Heartbeat> {
+        self.oob.start_heartbeat(&self.connection, interval)
+    }
+
+    /// Measure the application-level round trip to every connected peer
+    /// over OOB.
+    ///
+    /// Works like [`Sender::rtt_probe`](crate::Sender::rtt_probe) without
+    /// blocking the runtime.
+    pub async fn rtt_probe(&self, count: u32, timeout: Duration) -> Result<RttProbe> {
+        self.oob
+            .rtt_probe_async(&self.connection, count, timeout)
+            .await
+    }
+
+    /// Send a typed message over OOB to every connected peer, returning how
+    /// many it went to.
+    ///
+    /// The message is encoded as JSON and must fit one OOB packet. See
+    /// [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub fn send_message<T: Serialize>(&self, message: &T) -> Result<usize> {
+        self.oob.send_message(&self.connection, message)
+    }
+
+    /// Send a typed message over OOB to `peer` only.
+    #[cfg(feature = "serde")]
+    pub fn send_message_to<T: Serialize>(&self, peer: PeerId, message: &T) -> Result<()> {
+        self.oob.send_message_to(peer, message)
+    }
+
+    /// Receive the next typed message sent over OOB, waiting until one
+    /// arrives.
+    ///
+    /// Fails with [`Error::InvalidMessage`] if the message isn't a `T`.
+    /// Messages that arrived before the call are kept, up to 64. Wrap the
+    /// call in `tokio::time::timeout` to give up.
+    #[cfg(feature = "serde")]
+    pub async fn recv_message<T: DeserializeOwned>(&self) -> Result<Message<T>> {
+        self.oob.recv_message_async().await
+    }
+
+    /// Send a remote control command over OOB to every connected peer, and
+    /// wait up to `timeout` for the far end to acknowledge it.
+    ///
+    /// Fails with [`Error::CommandRejected`] if the far end refused it, or
+    /// [`Error::CommandTimeout`] without an answer. See [`oob`](crate::oob).
+    #[cfg(feature = "serde")]
+    pub async fn send_command(&self, command: Command, timeout: Duration) -> Result<()> {
+        self.oob
+            .send_command_async(&self.connection, command, timeout)
+            .await
+    }
+
+    /// Receive the next remote control command, waiting until one arrives.
+    ///
+    /// Answer it with [`ack_command`](Self::ack_command). Commands that
+    /// arrived before the call are kept, up to 64.
+    #[cfg(feature = "serde")]
+    pub async fn recv_command(&self) -> Result<CommandRequest> {
+        self.oob.recv_command_async().await
+    }
+
+    /// Acknowledge a command from [`recv_command`](Self::recv_command), with
+    /// the reason if it was refused.
+    #[cfg(feature = "serde")]
+    pub fn ack_command(
+        &self,
+        request: &CommandRequest,
+        result: std::result::Result<(), String>,
+    ) -> Result<()> {
+        self.oob.ack_command(request, result)
+    }
+
+    /// The underlying librist context, for calling `rist-sys` functions
+    /// directly.
+    ///
+    /// The pointer stays owned by this sender and is only valid until it is
+    /// dropped. Don't destroy it or replace its callbacks.
+    pub fn as_raw(&self) -> *mut rist_sys::rist_ctx {
+        self.raw_ctx
+    }
+
+    /// The flow ID this sender's packets carry.
+    ///
+    /// librist picks a random one unless one was given at creation.
+    pub fn flow_id(&self) -> Result<u32> {
+        let mut flow_id = 0;
+        let ret = unsafe { rist_sys::rist_sender_flow_id_get(self.raw_ctx, &mut flow_id) };
+        if ret != 0 {
+            return Err(Error::Configuration(
+                "failed to get sender flow ID".to_string(),
+            ));
+        }
+        Ok(flow_id)
     }
 
     /// Returns the latest stats for this sender.
@@ -232,35 +811,61 @@ impl AsyncSender {
     /// Stats are updated periodically (every 1 second by default).
     /// Returns `None` if no stats have been collected yet.
     pub fn raw_stats(&self) -> Option<SenderStats> {
-        self.stats.lock().ok().and_then(|guard| guard.clone())
+        self.stats.latest()
+    }
+
+    /// Returns the latest stats for this sender with the interval they cover,
+    /// for bitrate gauges.
+    ///
+    /// Returns `None` if no stats have been collected yet.
+    pub fn stats_snapshot(&self) -> Option<StatsSnapshot<SenderStats>> {
+        self.stats.latest_snapshot()
+    }
+
+    /// Forget the stats collected so far, to start a fresh measurement
+    /// window, e.g. after maintenance.
+    ///
+    /// librist's counters are cumulative and keep running; from now on they
+    /// are reported as the increase since this call. Gauges such as
+    /// quality, RTT and bandwidth are unaffected. Until librist's next
+    /// report, [`raw_stats`](Self::raw_stats) returns `None`.
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    /// Report counters as running totals or as the increase since the
+    /// previous report, see [`StatsMode`]. Applies to
+    /// [`raw_stats`](Self::raw_stats), snapshots and the stats callback.
+    pub fn set_stats_mode(&self, mode: StatsMode) {
+        self.stats.set_mode(mode);
     }
 }
 
 impl AsyncWrite for AsyncSender {
     fn poll_write(
         self: Pin<&mut Self>,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        let block = rist_sys::rist_data_block {
-            payload: buf.as_ptr() as *const _,
-            payload_len: buf.len(),
-            ts_ntp: 0,
-            flow_id: 0,
-            flags: 0,
-            seq: 0,
-            virt_src_port: 0,
-            virt_dst_port: 0,
-            peer: ptr::null_mut(),
-            ref_: ptr::null_mut(),
-        };
-
-        let ret = unsafe { rist_sys::rist_sender_data_write(self.raw_ctx, &block) };
-
-        if ret < 0 {
-            Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, "send failed")))
-        } else {
-            Poll::Ready(Ok(ret as usize))
+        let this = self.get_mut();
+        if this.write_delay.is_none() {
+            let delay = this.pacer.delay(buf.len());
+            if !delay.is_zero() {
+                this.write_delay = Some(Box::pin(::tokio::time::sleep(delay)));
+            }
+        }
+        if let Some(delay) = this.write_delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.write_delay = None;
+        }
+
+        match this.write_with_metadata(buf, &BlockMetadata::default()) {
+            Ok(n) => Poll::Ready(Ok(n)),
+            // dropped and counted by the cap; the stream itself is fine
+            Err(Error::BitrateCapExceeded) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::Error::other("send failed"))),
         }
     }
 
@@ -275,6 +880,14 @@ impl AsyncWrite for AsyncSender {
 
 impl Drop for AsyncSender {
     fn drop(&mut self) {
+        self.oob.shutdown();
+        if let Some(task) = self.reconnect_task.take() {
+            task.abort();
+        }
+
+        // hold the lock so a running reconnect attempt can't touch the context
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        peers.clear();
         unsafe {
             rist_sys::rist_destroy(self.raw_ctx);
         }
@@ -0,0 +1,104 @@
+//! MPEG-TS helpers.
+//!
+//! RIST usually carries MPEG transport streams, seven 188-byte TS packets
+//! per datagram. [`Packetizer`] cuts an arbitrary TS byte stream, such as a
+//! file or pipe read in odd-sized chunks, into payloads of that shape.
+//!
+//! With the `ts` feature, [`TsMonitor`] checks received payloads for sync
+//! loss and continuity counter errors, and [`TsPacer`] smooths bursty
+//! receiver output back to the stream's PCR timing or a constant bitrate.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rist::ts::Packetizer;
+//! use rist::{Profile, Sender};
+//! use std::io::Read;
+//!
+//! # fn example(mut input: impl Read, sender: Sender) -> Result<(), Box<dyn std::error::Error>> {
+//! let mut packetizer = Packetizer::new();
+//! let mut buf = [0u8; 4096];
+//! loop {
+//!     let n = input.read(&mut buf)?;
+//!     if n == 0 {
+//!         break;
+//!     }
+//!     packetizer.push(&buf[..n]);
+//!     while let Some(payload) = packetizer.next_payload() {
+//!         sender.send(payload)?;
+//!     }
+//! }
+//! if let Some(rest) = packetizer.flush() {
+//!     sender.send(rest)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "ts")]
+mod monitor;
+#[cfg(feature = "ts")]
+mod pacing;
+mod packetizer;
+
+#[cfg(feature = "ts")]
+pub use monitor::{PidStats, TsMonitor, TsStats};
+#[cfg(feature = "ts")]
+pub use pacing::{PacingMode, TsPacer, TsPacket};
+pub use packetizer::Packetizer;
+
+/// Size of one MPEG-TS packet.
+pub const PACKET_SIZE: usize = 188;
+
+/// First byte of every MPEG-TS packet.
+pub const SYNC_BYTE: u8 = 0x47;
+
+/// TS packets carried in one RIST payload by convention.
+pub const PACKETS_PER_PAYLOAD: usize = 7;
+
+/// Size of a RIST payload carrying [`PACKETS_PER_PAYLOAD`] TS packets.
+pub const PAYLOAD_SIZE: usize = PACKET_SIZE * PACKETS_PER_PAYLOAD;
+
+/// A copy of `payload` with the discontinuity indicator set on every packet
+/// that has an adaptation field, `None` if `payload` isn't whole TS packets.
+///
+/// Packets without an adaptation field are left alone, as adding one would
+/// change their size.
+pub(crate) fn mark_discontinuity(payload: &[u8]) -> Option<Vec<u8>> {
+    if payload.is_empty()
+        || !payload.len().is_multiple_of(PACKET_SIZE)
+        || payload.chunks(PACKET_SIZE).any(|p| p[0] != SYNC_BYTE)
+    {
+        return None;
+    }
+    let mut marked = payload.to_vec();
+    for packet in marked.chunks_mut(PACKET_SIZE) {
+        if packet[3] & 0x20 != 0 && packet[4] > 0 {
+            packet[5] |= 0x80;
+        }
+    }
+    Some(marked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark_discontinuity() {
+        let mut payload = vec![0u8; PACKET_SIZE * 2];
+        payload[0] = SYNC_BYTE;
+        // adaptation field and payload
+        payload[3] = 0x30;
+        payload[4] = 7;
+        payload[PACKET_SIZE] = SYNC_BYTE;
+        payload[PACKET_SIZE + 3] = 0x10;
+
+        let marked = mark_discontinuity(&payload).unwrap();
+        assert_eq!(marked[5], 0x80);
+        assert_eq!(marked[PACKET_SIZE..], payload[PACKET_SIZE..]);
+
+        assert_eq!(mark_discontinuity(&payload[..100]), None);
+        assert_eq!(mark_discontinuity(&[0u8; PACKET_SIZE]), None);
+    }
+}
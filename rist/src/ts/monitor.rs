@@ -0,0 +1,204 @@
+use super::{PACKET_SIZE, SYNC_BYTE};
+use std::collections::BTreeMap;
+
+/// PID of null (stuffing) packets, which carry no continuity counter.
+const NULL_PID: u16 = 0x1fff;
+
+/// Counters for one PID, see [`TsStats::pids`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PidStats {
+    /// TS packets seen on this PID.
+    pub packets: u64,
+    /// Continuity counter jumps on this PID.
+    pub cc_errors: u64,
+}
+
+/// Counters collected by a [`TsMonitor`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TsStats {
+    /// TS packets inspected.
+    pub packets: u64,
+    /// Packets that didn't start with a sync byte, plus trailing bytes of
+    /// payloads that aren't a whole number of packets.
+    pub sync_errors: u64,
+    /// Packets with the transport error indicator set upstream.
+    pub transport_errors: u64,
+    /// Continuity counter jumps across all PIDs.
+    pub cc_errors: u64,
+    /// Per-PID counters, excluding null packets.
+    pub pids: BTreeMap<u16, PidStats>,
+}
+
+/// Checks received payloads for TS sync loss and continuity counter errors.
+///
+/// RIST recovers network loss, so continuity errors while the receiver's
+/// [`lost`](crate::ReceiverStats::lost) count stays flat point at the source
+/// rather than the network.
+///
+/// A receiver can run one per flow and report it with its stats, see
+/// [`Receiver::set_ts_monitor`](crate::Receiver::set_ts_monitor). Use a
+/// monitor directly for payloads from elsewhere, such as a recording.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::ts::TsMonitor;
+/// # fn example(receiver: rist::Receiver) -> rist::Result<()> {
+/// let mut monitor = TsMonitor::new();
+/// while let Some(block) = receiver.read(std::time::Duration::from_secs(1))? {
+///     monitor.inspect(block.payload());
+/// }
+/// if let Some(stats) = receiver.raw_stats() {
+///     println!("lost {} / cc errors {}", stats.lost, monitor.stats().cc_errors);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TsMonitor {
+    stats: TsStats,
+    // last continuity counter per PID
+    last_cc: BTreeMap<u16, u8>,
+}
+
+impl TsMonitor {
+    /// Create a monitor with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inspect one received payload of whole TS packets.
+    pub fn inspect(&mut self, payload: &[u8]) {
+        let mut packets = payload.chunks_exact(PACKET_SIZE);
+        for packet in &mut packets {
+            self.inspect_packet(packet);
+        }
+        if !packets.remainder().is_empty() {
+            self.stats.sync_errors += 1;
+        }
+    }
+
+    /// Counters collected so far.
+    pub fn stats(&self) -> &TsStats {
+        &self.stats
+    }
+
+    /// Reset all counters and forget the last continuity counters.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn inspect_packet(&mut self, packet: &[u8]) {
+        self.stats.packets += 1;
+        if packet[0] != SYNC_BYTE {
+            self.stats.sync_errors += 1;
+            return;
+        }
+        if packet[1] & 0x80 != 0 {
+            self.stats.transport_errors += 1;
+        }
+
+        let pid = u16::from(packet[1] & 0x1f) << 8 | u16::from(packet[2]);
+        if pid == NULL_PID {
+            return;
+        }
+
+        let adaptation = packet[3] & 0x20 != 0;
+        let has_payload = packet[3] & 0x10 != 0;
+        let cc = packet[3] & 0x0f;
+        let discontinuity = adaptation && packet[4] > 0 && packet[5] & 0x80 != 0;
+
+        let pid_stats = self.stats.pids.entry(pid).or_default();
+        pid_stats.packets += 1;
+
+        let last = self.last_cc.insert(pid, cc);
+        let Some(last) = last else {
+            return;
+        };
+        if discontinuity {
+            return;
+        }
+
+        // the counter only advances on packets with payload, and a single
+        // duplicate of the previous packet is allowed
+        let expected = if has_payload { (last + 1) & 0x0f } else { last };
+        if cc != expected && !(has_payload && cc == last) {
+            pid_stats.cc_errors += 1;
+            self.stats.cc_errors += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(pid: u16, cc: u8) -> [u8; PACKET_SIZE] {
+        let mut packet = [0xffu8; PACKET_SIZE];
+        packet[0] = SYNC_BYTE;
+        packet[1] = (pid >> 8) as u8;
+        packet[2] = pid as u8;
+        packet[3] = 0x10 | cc;
+        packet
+    }
+
+    #[test]
+    fn test_continuous_stream_has_no_errors() {
+        let mut monitor = TsMonitor::new();
+        let payload: Vec<u8> = (0..40).flat_map(|i| packet(0x100, i % 16)).collect();
+        monitor.inspect(&payload);
+
+        let stats = monitor.stats();
+        assert_eq!(stats.packets, 40);
+        assert_eq!(stats.cc_errors, 0);
+        assert_eq!(stats.sync_errors, 0);
+        assert_eq!(stats.pids[&0x100].packets, 40);
+    }
+
+    #[test]
+    fn test_counts_cc_jumps_per_pid() {
+        let mut monitor = TsMonitor::new();
+        for (pid, cc) in [(0x100, 0), (0x101, 5), (0x100, 1), (0x100, 3), (0x101, 6)] {
+            monitor.inspect(&packet(pid, cc));
+        }
+
+        let stats = monitor.stats();
+        assert_eq!(stats.cc_errors, 1);
+        assert_eq!(stats.pids[&0x100].cc_errors, 1);
+        assert_eq!(stats.pids[&0x101].cc_errors, 0);
+    }
+
+    #[test]
+    fn test_allows_duplicates_discontinuity_and_null_packets() {
+        let mut monitor = TsMonitor::new();
+        monitor.inspect(&packet(0x100, 4));
+        monitor.inspect(&packet(0x100, 4));
+        monitor.inspect(&packet(NULL_PID, 9));
+
+        let mut reset = packet(0x100, 12);
+        reset[3] |= 0x20;
+        reset[4] = 1;
+        reset[5] = 0x80;
+        monitor.inspect(&reset);
+
+        let stats = monitor.stats();
+        assert_eq!(stats.cc_errors, 0);
+        assert!(!stats.pids.contains_key(&NULL_PID));
+    }
+
+    #[test]
+    fn test_counts_sync_errors() {
+        let mut monitor = TsMonitor::new();
+        let mut payload = packet(0x100, 0).to_vec();
+        payload.extend(packet(0x100, 1));
+        payload[PACKET_SIZE] = 0x00;
+        payload.extend([SYNC_BYTE; 10]);
+        monitor.inspect(&payload);
+
+        assert_eq!(monitor.stats().sync_errors, 2);
+        assert_eq!(monitor.stats().packets, 2);
+
+        monitor.reset();
+        assert_eq!(monitor.stats(), &TsStats::default());
+    }
+}
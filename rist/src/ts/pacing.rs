@@ -0,0 +1,347 @@
+use super::{PACKET_SIZE, SYNC_BYTE};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// PCR ticks per second (27 MHz).
+const PCR_HZ: u64 = 27_000_000;
+
+/// PCR values wrap at 2^33 * 300.
+const PCR_WRAP: u64 = (1 << 33) * 300;
+
+/// Gaps between PCRs larger than this are treated as discontinuities.
+const MAX_PCR_GAP: Duration = Duration::from_secs(1);
+
+/// How [`TsPacer`] spaces packets out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacingMode {
+    /// Follow the program clock references in the stream.
+    Pcr {
+        /// PID carrying the PCR, or `None` to lock onto the first PID that
+        /// carries one.
+        pid: Option<u16>,
+    },
+    /// Release packets at a fixed rate, in bits per second.
+    ConstantBitrate(u64),
+}
+
+/// One TS packet.
+pub type TsPacket = [u8; PACKET_SIZE];
+
+/// Releases received TS packets at the pace they were produced at.
+///
+/// RIST delivers packets in bursts as retransmissions fill gaps, while many
+/// decoders expect a steady constant-bitrate input. Push received payloads
+/// in, then [`poll`](Self::poll) for packets whose release time has come
+/// and sleep until [`next_release`](Self::next_release) in between.
+///
+/// In [`PacingMode::Pcr`] the packets between two PCRs are spread evenly
+/// over the PCR interval, so output lags input by one PCR interval
+/// (at most 100 ms in a compliant stream). Packets before the first PCR are
+/// released straight away.
+///
+/// # Example
+///
+/// ```no_run
+/// use rist::ts::{PacingMode, TsPacer};
+/// use std::time::{Duration, Instant};
+///
+/// # fn example(receiver: rist::Receiver, mut decoder: impl std::io::Write) -> Result<(), Box<dyn std::error::Error>> {
+/// let mut pacer = TsPacer::new(PacingMode::Pcr { pid: None });
+/// loop {
+///     let wait = pacer
+///         .next_release()
+///         .map_or(Duration::from_millis(10), |at| at.saturating_duration_since(Instant::now()));
+///     if let Some(block) = receiver.read(wait)? {
+///         pacer.push(block.payload(), Instant::now());
+///     }
+///     while let Some(packet) = pacer.poll(Instant::now()) {
+///         decoder.write_all(&packet)?;
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TsPacer {
+    mode: PacingMode,
+    ready: VecDeque<(Instant, TsPacket)>,
+    // packets after the last PCR, waiting for the next one to be timed
+    pending: Vec<TsPacket>,
+    // last PCR and the time it was released at
+    anchor: Option<(u64, Instant)>,
+    pcr_pid: Option<u16>,
+    // release time of the last packet in constant bitrate mode
+    last_release: Option<Instant>,
+}
+
+impl TsPacer {
+    /// Create a pacer using `mode`.
+    pub fn new(mode: PacingMode) -> Self {
+        let pcr_pid = match mode {
+            PacingMode::Pcr { pid } => pid,
+            PacingMode::ConstantBitrate(_) => None,
+        };
+        Self {
+            mode,
+            ready: VecDeque::new(),
+            pending: Vec::new(),
+            anchor: None,
+            pcr_pid,
+            last_release: None,
+        }
+    }
+
+    /// Queue a received payload of whole TS packets, received at `now`.
+    ///
+    /// Bytes that don't form a whole packet starting with a sync byte are
+    /// dropped.
+    pub fn push(&mut self, payload: &[u8], now: Instant) {
+        for chunk in payload.chunks_exact(PACKET_SIZE) {
+            if chunk[0] != SYNC_BYTE {
+                continue;
+            }
+            let mut packet = [0u8; PACKET_SIZE];
+            packet.copy_from_slice(chunk);
+
+            match self.mode {
+                PacingMode::Pcr { .. } => self.push_pcr(packet, now),
+                PacingMode::ConstantBitrate(bitrate) => self.push_cbr(packet, bitrate, now),
+            }
+        }
+    }
+
+    /// Take the next packet whose release time is at or before `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<TsPacket> {
+        match self.ready.front() {
+            Some((at, _)) if *at <= now => self.ready.pop_front().map(|(_, packet)| packet),
+            _ => None,
+        }
+    }
+
+    /// When the next queued packet is due, or `None` if nothing is ready to
+    /// be timed yet.
+    pub fn next_release(&self) -> Option<Instant> {
+        self.ready.front().map(|(at, _)| *at)
+    }
+
+    /// Number of packets queued, including those waiting for the next PCR.
+    pub fn len(&self) -> usize {
+        self.ready.len() + self.pending.len()
+    }
+
+    /// Returns `true` if no packets are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Release the packets still waiting for a PCR, e.g. at the end of the
+    /// stream, right after the last timed packet or at `now` if that is
+    /// later.
+    pub fn flush(&mut self, now: Instant) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let at = self.ready.back().map_or(now, |(at, _)| (*at).max(now));
+        self.release_pending(at, at);
+    }
+
+    fn push_cbr(&mut self, packet: TsPacket, bitrate: u64, now: Instant) {
+        let interval = Duration::from_secs_f64((PACKET_SIZE * 8) as f64 / bitrate.max(1) as f64);
+        // after an idle period, start again from now rather than bursting to catch up
+        let at = match self.last_release {
+            Some(last) if last + interval > now => last + interval,
+            _ => now,
+        };
+        self.last_release = Some(at);
+        self.ready.push_back((at, packet));
+    }
+
+    fn push_pcr(&mut self, packet: TsPacket, now: Instant) {
+        let pcr = read_pcr(&packet).filter(|&(pid, _, _)| *self.pcr_pid.get_or_insert(pid) == pid);
+        let Some((_, pcr, discontinuity)) = pcr else {
+            // nothing to time packets against yet
+            if self.anchor.is_none() {
+                self.ready.push_back((now, packet));
+            } else {
+                self.pending.push(packet);
+            }
+            return;
+        };
+        self.pending.push(packet);
+
+        let at = match self.anchor {
+            Some((last_pcr, last_at)) if !discontinuity => {
+                let elapsed = pcr_duration(last_pcr, pcr);
+                let at = last_at + elapsed;
+                // re-anchor after a jump in the clock or if we drifted far behind
+                if elapsed > MAX_PCR_GAP || at + MAX_PCR_GAP < now {
+                    self.release_pending(now, now);
+                    self.anchor = Some((pcr, now));
+                    return;
+                }
+                self.release_pending(last_at, at);
+                at
+            }
+            _ => {
+                self.release_pending(now, now);
+                now
+            }
+        };
+        self.anchor = Some((pcr, at));
+    }
+
+    /// Spread the pending packets evenly after `from`, ending at `to`.
+    fn release_pending(&mut self, from: Instant, to: Instant) {
+        let count = self.pending.len() as u32;
+        let span = to.saturating_duration_since(from);
+        for (i, packet) in self.pending.drain(..).enumerate() {
+            let at = from + span * (i as u32 + 1) / count;
+            self.ready.push_back((at, packet));
+        }
+    }
+}
+
+/// Time between two PCR values, allowing for wraparound.
+fn pcr_duration(from: u64, to: u64) -> Duration {
+    let ticks = (to + PCR_WRAP - from) % PCR_WRAP;
+    Duration::from_nanos(ticks * 1_000 / (PCR_HZ / 1_000_000))
+}
+
+/// PID, PCR and discontinuity flag of a packet carrying a PCR.
+fn read_pcr(packet: &TsPacket) -> Option<(u16, u64, bool)> {
+    let has_adaptation = packet[3] & 0x20 != 0;
+    if !has_adaptation || packet[4] < 7 || packet[5] & 0x10 == 0 {
+        return None;
+    }
+
+    let pid = u16::from(packet[1] & 0x1f) << 8 | u16::from(packet[2]);
+    let base = u64::from(packet[6]) << 25
+        | u64::from(packet[7]) << 17
+        | u64::from(packet[8]) << 9
+        | u64::from(packet[9]) << 1
+        | u64::from(packet[10]) >> 7;
+    let ext = u64::from(packet[10] & 0x01) << 8 | u64::from(packet[11]);
+    let discontinuity = packet[5] & 0x80 != 0;
+
+    Some((pid, base * 300 + ext, discontinuity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(pid: u16) -> TsPacket {
+        let mut packet = [0xffu8; PACKET_SIZE];
+        packet[0] = SYNC_BYTE;
+        packet[1] = (pid >> 8) as u8;
+        packet[2] = pid as u8;
+        packet[3] = 0x10;
+        packet
+    }
+
+    fn pcr_packet(pid: u16, pcr: u64) -> TsPacket {
+        let mut packet = packet(pid);
+        let (base, ext) = (pcr / 300, pcr % 300);
+        packet[3] = 0x30;
+        packet[4] = 7;
+        packet[5] = 0x10;
+        packet[6] = (base >> 25) as u8;
+        packet[7] = (base >> 17) as u8;
+        packet[8] = (base >> 9) as u8;
+        packet[9] = (base >> 1) as u8;
+        packet[10] = ((base & 1) << 7) as u8 | 0x7e | (ext >> 8) as u8;
+        packet[11] = ext as u8;
+        packet
+    }
+
+    #[test]
+    fn test_read_pcr() {
+        let pcr = 123_456_789_012;
+        assert_eq!(read_pcr(&pcr_packet(0x100, pcr)), Some((0x100, pcr, false)));
+        assert_eq!(read_pcr(&packet(0x100)), None);
+    }
+
+    #[test]
+    fn test_pcr_duration_wraps() {
+        assert_eq!(pcr_duration(0, PCR_HZ / 10), Duration::from_millis(100));
+        assert_eq!(
+            pcr_duration(PCR_WRAP - PCR_HZ / 20, PCR_HZ / 20),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn test_spreads_packets_between_pcrs() {
+        let start = Instant::now();
+        let mut pacer = TsPacer::new(PacingMode::Pcr { pid: None });
+
+        pacer.push(&pcr_packet(0x100, 0), start);
+        let burst: Vec<u8> = (0..3)
+            .flat_map(|_| packet(0x101))
+            .chain(pcr_packet(0x100, PCR_HZ / 10))
+            .collect();
+        pacer.push(&burst, start);
+
+        let times: Vec<Duration> = std::iter::from_fn(|| {
+            let at = pacer.next_release()?;
+            pacer.poll(at)?;
+            Some(at - start)
+        })
+        .collect();
+        assert_eq!(
+            times,
+            vec![
+                Duration::ZERO,
+                Duration::from_millis(25),
+                Duration::from_millis(50),
+                Duration::from_millis(75),
+                Duration::from_millis(100),
+            ]
+        );
+        assert!(pacer.is_empty());
+    }
+
+    #[test]
+    fn test_holds_packets_until_due() {
+        let start = Instant::now();
+        let mut pacer = TsPacer::new(PacingMode::ConstantBitrate(PACKET_SIZE as u64 * 8 * 100));
+        let payload: Vec<u8> = (0..3).flat_map(|_| packet(0x100)).collect();
+        pacer.push(&payload, start);
+
+        assert!(pacer.poll(start).is_some());
+        assert!(pacer.poll(start).is_none());
+        assert_eq!(
+            pacer.next_release(),
+            Some(start + Duration::from_millis(10))
+        );
+        assert!(pacer.poll(start + Duration::from_millis(20)).is_some());
+        assert!(pacer.poll(start + Duration::from_millis(20)).is_some());
+        assert!(pacer.is_empty());
+    }
+
+    #[test]
+    fn test_flush_releases_pending() {
+        let start = Instant::now();
+        let mut pacer = TsPacer::new(PacingMode::Pcr { pid: None });
+        pacer.push(&pcr_packet(0x100, 0), start);
+        pacer.push(&packet(0x101), start);
+        assert!(pacer.poll(start).is_some());
+        assert_eq!(pacer.next_release(), None);
+
+        let later = start + Duration::from_millis(5);
+        pacer.flush(later);
+        assert_eq!(pacer.next_release(), Some(later));
+        assert_eq!(pacer.poll(later), Some(packet(0x101)));
+        assert!(pacer.is_empty());
+    }
+
+    #[test]
+    fn test_reanchors_on_pcr_jump() {
+        let start = Instant::now();
+        let mut pacer = TsPacer::new(PacingMode::Pcr { pid: Some(0x100) });
+        pacer.push(&pcr_packet(0x100, 0), start);
+        pacer.push(&pcr_packet(0x100, PCR_HZ * 10), start);
+
+        assert_eq!(pacer.poll(start), Some(pcr_packet(0x100, 0)));
+        assert_eq!(pacer.poll(start), Some(pcr_packet(0x100, PCR_HZ * 10)));
+    }
+}
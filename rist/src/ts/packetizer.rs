@@ -0,0 +1,208 @@
+use super::{PACKETS_PER_PAYLOAD, PACKET_SIZE, SYNC_BYTE};
+
+/// Cuts an MPEG-TS byte stream into RIST payloads of whole TS packets.
+///
+/// Input can arrive in chunks of any size; partial packets are kept until
+/// the rest arrives. If the stream loses sync, bytes are skipped up to the
+/// next pair of sync bytes one packet apart, and the payload in progress is
+/// cut short so every payload starts on a packet boundary.
+#[derive(Debug, Clone)]
+pub struct Packetizer {
+    buf: Vec<u8>,
+    // start of the data not yet returned
+    start: usize,
+    packets_per_payload: usize,
+    skipped: u64,
+}
+
+impl Default for Packetizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Packetizer {
+    /// Create a packetizer emitting seven TS packets (1316 bytes) per payload.
+    pub fn new() -> Self {
+        Self::with_packets_per_payload(PACKETS_PER_PAYLOAD)
+    }
+
+    /// Create a packetizer emitting `packets` TS packets per payload.
+    ///
+    /// `packets` is clamped to at least one.
+    pub fn with_packets_per_payload(packets: usize) -> Self {
+        let packets = packets.max(1);
+        Self {
+            buf: Vec::with_capacity(packets * PACKET_SIZE * 2),
+            start: 0,
+            packets_per_payload: packets,
+            skipped: 0,
+        }
+    }
+
+    /// Append a chunk of the TS stream.
+    pub fn push(&mut self, data: &[u8]) {
+        if self.start > 0 {
+            self.buf.drain(..self.start);
+            self.start = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Take the next full payload, or `None` until enough data has been pushed.
+    pub fn next_payload(&mut self) -> Option<&[u8]> {
+        self.sync();
+
+        let packets = self.aligned_packets();
+        if packets < self.packets_per_payload {
+            // a short run is only complete if it ended at a lost sync byte
+            let run_end = self.start + packets * PACKET_SIZE;
+            if packets == 0 || self.buf.len() < run_end + PACKET_SIZE {
+                return None;
+            }
+        }
+
+        Some(self.take(packets))
+    }
+
+    /// Take whatever whole packets are left at the end of the stream.
+    ///
+    /// A trailing partial packet is discarded and counted in
+    /// [`skipped_bytes`](Self::skipped_bytes).
+    pub fn flush(&mut self) -> Option<&[u8]> {
+        self.sync();
+
+        let packets = self.aligned_packets();
+        let len = packets * PACKET_SIZE;
+        let rest = self.buf.len() - self.start - len;
+        self.skipped += rest as u64;
+
+        let start = self.start;
+        self.start = self.buf.len();
+        (packets > 0).then(|| &self.buf[start..start + len])
+    }
+
+    /// Number of bytes buffered but not yet returned.
+    pub fn pending(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// Total bytes dropped while looking for sync or at [`flush`](Self::flush).
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Skip to the next sync byte if the stream isn't aligned on one.
+    fn sync(&mut self) {
+        let data = &self.buf[self.start..];
+        if matches!(data.first(), None | Some(&SYNC_BYTE)) {
+            return;
+        }
+
+        // a sync byte only counts if the next packet starts with one too
+        let found = (1..data.len()).find(|&i| {
+            data[i] == SYNC_BYTE && matches!(data.get(i + PACKET_SIZE), None | Some(&SYNC_BYTE))
+        });
+        let skip = found.unwrap_or(data.len());
+        self.skipped += skip as u64;
+        self.start += skip;
+    }
+
+    /// Complete packets from `start` that begin with a sync byte, up to a
+    /// full payload.
+    fn aligned_packets(&self) -> usize {
+        let data = &self.buf[self.start..];
+        (0..self.packets_per_payload)
+            .take_while(|i| {
+                let offset = i * PACKET_SIZE;
+                data.len() >= offset + PACKET_SIZE && data[offset] == SYNC_BYTE
+            })
+            .count()
+    }
+
+    fn take(&mut self, packets: usize) -> &[u8] {
+        let start = self.start;
+        self.start += packets * PACKET_SIZE;
+        &self.buf[start..self.start]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ts::PAYLOAD_SIZE;
+
+    fn packets(count: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|i| {
+                let mut packet = vec![i as u8; PACKET_SIZE];
+                packet[0] = SYNC_BYTE;
+                packet
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chunks_across_pushes() {
+        let stream = packets(15);
+        let mut packetizer = Packetizer::new();
+        let mut payloads = Vec::new();
+
+        for chunk in stream.chunks(100) {
+            packetizer.push(chunk);
+            while let Some(payload) = packetizer.next_payload() {
+                payloads.push(payload.to_vec());
+            }
+        }
+        if let Some(rest) = packetizer.flush() {
+            payloads.push(rest.to_vec());
+        }
+
+        let sizes: Vec<usize> = payloads.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![PAYLOAD_SIZE, PAYLOAD_SIZE, PACKET_SIZE]);
+        assert_eq!(payloads.concat(), stream);
+        assert_eq!(packetizer.skipped_bytes(), 0);
+    }
+
+    #[test]
+    fn test_resyncs_after_garbage() {
+        let mut stream = vec![0x00, 0x47, 0x12];
+        stream.extend(packets(8));
+        let mut packetizer = Packetizer::new();
+        packetizer.push(&stream);
+
+        let payload = packetizer.next_payload().unwrap();
+        assert_eq!(payload.len(), PAYLOAD_SIZE);
+        assert_eq!(payload[0], SYNC_BYTE);
+        assert_eq!(packetizer.skipped_bytes(), 3);
+        assert!(packetizer.next_payload().is_none());
+        assert_eq!(packetizer.pending(), PACKET_SIZE);
+    }
+
+    #[test]
+    fn test_cuts_payload_short_on_lost_sync() {
+        let mut stream = packets(2);
+        stream.extend([0xff; 10]);
+        stream.extend(packets(7));
+        let mut packetizer = Packetizer::new();
+        packetizer.push(&stream);
+
+        assert_eq!(packetizer.next_payload().unwrap().len(), 2 * PACKET_SIZE);
+        assert_eq!(packetizer.next_payload().unwrap().len(), PAYLOAD_SIZE);
+        assert_eq!(packetizer.skipped_bytes(), 10);
+    }
+
+    #[test]
+    fn test_flush_drops_partial_packet() {
+        let mut stream = packets(3);
+        stream.extend([SYNC_BYTE; 50]);
+        let mut packetizer = Packetizer::with_packets_per_payload(4);
+        packetizer.push(&stream);
+
+        assert!(packetizer.next_payload().is_none());
+        assert_eq!(packetizer.flush().unwrap().len(), 3 * PACKET_SIZE);
+        assert_eq!(packetizer.skipped_bytes(), 50);
+        assert_eq!(packetizer.pending(), 0);
+        assert!(packetizer.flush().is_none());
+    }
+}
@@ -0,0 +1,333 @@
+//! Building peer URLs without hand-formatting addresses.
+
+use crate::{Error, Result};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Host {
+    /// Every local IPv4 address.
+    Any,
+    Name(String),
+    Ip(IpAddr),
+}
+
+/// Builder for peer URLs.
+///
+/// IPv6 literals are bracketed, and link-local addresses can carry a zone
+/// ID naming the interface to use. The zone ID is written as `%zone`, the
+/// form the system resolver expects, not percent-encoded as `%25zone`.
+///
+/// # Example
+///
+/// ```
+/// use rist::RistUrl;
+///
+/// let url = RistUrl::connect("fe80::1", 5000).zone("eth0").build()?;
+/// assert_eq!(url, "rist://[fe80::1%eth0]:5000");
+///
+/// let url = RistUrl::listen_v6(5000).param("cname", "studio").build()?;
+/// assert_eq!(url, "rist://@[::]:5000?cname=studio");
+/// # Ok::<(), rist::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RistUrl {
+    listen: bool,
+    host: Host,
+    port: u16,
+    zone: Option<String>,
+    params: Vec<(String, String)>,
+}
+
+impl RistUrl {
+    /// A peer sending to or connecting to `host`, a host name or an IPv4 or
+    /// IPv6 address. An IPv6 address may include a zone ID, as in
+    /// `fe80::1%eth0`.
+    pub fn connect(host: &str, port: u16) -> Self {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Some((ip, zone)) = host.split_once('%') {
+            if let Ok(ip) = ip.parse::<Ipv6Addr>() {
+                return Self::new(false, Host::Ip(ip.into()), port).zone(zone);
+            }
+        }
+        let host = match host.parse() {
+            Ok(ip) => Host::Ip(ip),
+            Err(_) => Host::Name(host.to_string()),
+        };
+        Self::new(false, host, port)
+    }
+
+    /// A peer connecting to `addr`. A non-zero IPv6 scope ID becomes the
+    /// zone ID.
+    pub fn connect_addr(addr: SocketAddr) -> Self {
+        Self::from_addr(false, addr)
+    }
+
+    /// A peer listening on `port` on every IPv4 address.
+    pub fn listen(port: u16) -> Self {
+        Self::new(true, Host::Any, port)
+    }
+
+    /// A peer listening on `port` on every IPv6 address, and on IPv4 too
+    /// unless the socket is IPv6-only (see
+    /// [`ReceiverOptions::check_v6_only`](crate::ReceiverOptions::check_v6_only)).
+    pub fn listen_v6(port: u16) -> Self {
+        Self::new(true, Host::Ip(Ipv6Addr::UNSPECIFIED.into()), port)
+    }
+
+    /// A peer listening on `addr`. A non-zero IPv6 scope ID becomes the
+    /// zone ID.
+    pub fn listen_addr(addr: SocketAddr) -> Self {
+        Self::from_addr(true, addr)
+    }
+
+    /// Scope an IPv6 address to an interface, by name (`eth0`) or index.
+    pub fn zone(mut self, zone: impl Into<String>) -> Self {
+        self.zone = Some(zone.into());
+        self
+    }
+
+    /// Add a URL parameter such as `cname` or `buffer`.
+    pub fn param(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.params.push((key.into(), value.to_string()));
+        self
+    }
+
+    /// Format the URL.
+    ///
+    /// Fails if a zone ID is set on anything but an IPv6 address, or if a
+    /// zone ID or parameter contains characters that would break the URL,
+    /// see [`append_param`](Self::append_param).
+    pub fn build(&self) -> Result<String> {
+        let mut url = String::from("rist://");
+        if self.listen {
+            url.push('@');
+        }
+
+        match (&self.host, &self.zone) {
+            (Host::Ip(IpAddr::V6(ip)), zone) => {
+                url.push_str(&format!("[{ip}"));
+                if let Some(zone) = zone {
+                    check_component("zone ID", zone)?;
+                    url.push_str(&format!("%{zone}"));
+                }
+                url.push(']');
+            }
+            (_, Some(_)) => {
+                return Err(Error::Configuration(
+                    "a zone ID needs an IPv6 address".to_string(),
+                ))
+            }
+            (Host::Any, None) => {}
+            (Host::Ip(ip), None) => url.push_str(&ip.to_string()),
+            (Host::Name(name), None) => {
+                check_component("host", name)?;
+                url.push_str(name);
+            }
+        }
+        url.push_str(&format!(":{}", self.port));
+
+        for (key, value) in &self.params {
+            url = Self::append_param(&url, key, value)?;
+        }
+        Ok(url)
+    }
+
+    /// Add a parameter such as `secret` or `cname` to an already formatted
+    /// URL, e.g. one given on a command line.
+    ///
+    /// librist takes parameter values as they are, without percent-decoding,
+    /// so a value that contains whitespace or any of `& = # ? %`, or is
+    /// empty, is rejected rather than escaped. The error doesn't repeat the
+    /// value, which may be a passphrase.
+    pub fn append_param(url: &str, key: &str, value: impl ToString) -> Result<String> {
+        let value = value.to_string();
+        check_component("parameter", key)?;
+        let bad = |c: char| c.is_whitespace() || "&=#?%".contains(c);
+        if value.is_empty() || value.contains(bad) {
+            return Err(Error::Configuration(format!(
+                "invalid value for RIST URL parameter {key:?}: it must not be empty \
+                 or contain whitespace or any of & = # ? %"
+            )));
+        }
+        let separator = if url.contains('?') { '&' } else { '?' };
+        Ok(format!("{url}{separator}{key}={value}"))
+    }
+
+    fn new(listen: bool, host: Host, port: u16) -> Self {
+        Self {
+            listen,
+            host,
+            port,
+            zone: None,
+            params: Vec::new(),
+        }
+    }
+
+    fn from_addr(listen: bool, addr: SocketAddr) -> Self {
+        let url = Self::new(listen, Host::Ip(addr.ip()), addr.port());
+        match addr {
+            SocketAddr::V6(v6) if v6.scope_id() != 0 => url.zone(v6.scope_id().to_string()),
+            _ => url,
+        }
+    }
+}
+
+/// The `host:port` to resolve for a peer URL, if the peer connects to a
+/// host name rather than listening or using an IP address.
+#[cfg(feature = "tokio")]
+pub(crate) fn resolvable_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    if rest.starts_with('@') || rest.starts_with('[') {
+        return None;
+    }
+    let authority = rest.split(['/', '?']).next()?;
+    let (host, port) = authority.rsplit_once(':')?;
+    if host.is_empty() || host.parse::<IpAddr>().is_ok() || port.parse::<u16>().is_err() {
+        return None;
+    }
+    Some(authority.to_string())
+}
+
+/// Whether `url` already has the query parameter `key`.
+#[cfg(feature = "config")]
+pub(crate) fn has_param(url: &str, key: &str) -> bool {
+    url.split_once('?').is_some_and(|(_, query)| {
+        query
+            .split('&')
+            .any(|param| param.split_once('=').map_or(param, |(k, _)| k) == key)
+    })
+}
+
+/// Reject characters librist's URL parser treats as delimiters.
+fn check_component(what: &str, value: &str) -> Result<()> {
+    let bad = |c: char| c.is_whitespace() || "/?&=#@[]%:".contains(c);
+    if value.is_empty() || value.contains(bad) {
+        return Err(Error::Configuration(format!(
+            "invalid {what} in RIST URL: {value:?}"
+        )));
+    }
+    Ok(())
+}
+
+/// A peer URL fit for logs, with the values of its passphrase parameters
+/// replaced.
+#[cfg(feature = "tracing")]
+pub(crate) fn redact(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let params: Vec<_> = query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            Some((key, _)) if matches!(key, "secret" | "password") => format!("{key}=***"),
+            _ => param.to_string(),
+        })
+        .collect();
+    format!("{base}?{}", params.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddrV6;
+
+    #[test]
+    fn test_formats_addresses() {
+        assert_eq!(RistUrl::listen(5000).build().unwrap(), "rist://@:5000");
+        assert_eq!(
+            RistUrl::listen_v6(5000).build().unwrap(),
+            "rist://@[::]:5000"
+        );
+        assert_eq!(
+            RistUrl::connect("192.0.2.1", 5000).build().unwrap(),
+            "rist://192.0.2.1:5000"
+        );
+        assert_eq!(
+            RistUrl::connect("[2001:db8::1]", 5000).build().unwrap(),
+            "rist://[2001:db8::1]:5000"
+        );
+        assert_eq!(
+            RistUrl::connect("example.com", 5000)
+                .param("cname", "cam1")
+                .param("buffer", 500)
+                .build()
+                .unwrap(),
+            "rist://example.com:5000?cname=cam1&buffer=500"
+        );
+    }
+
+    #[test]
+    fn test_formats_zone_ids() {
+        assert_eq!(
+            RistUrl::connect("fe80::1", 5000)
+                .zone("eth0")
+                .build()
+                .unwrap(),
+            "rist://[fe80::1%eth0]:5000"
+        );
+        assert_eq!(
+            RistUrl::connect("[fe80::1%eth0]", 5000).build().unwrap(),
+            "rist://[fe80::1%eth0]:5000"
+        );
+
+        let addr = SocketAddrV6::new("fe80::1".parse().unwrap(), 6000, 0, 3);
+        assert_eq!(
+            RistUrl::listen_addr(addr.into()).build().unwrap(),
+            "rist://@[fe80::1%3]:6000"
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_resolvable_host() {
+        assert_eq!(
+            resolvable_host("rist://ingest.example.com:5000?cname=a").as_deref(),
+            Some("ingest.example.com:5000")
+        );
+        assert_eq!(resolvable_host("rist://192.0.2.1:5000"), None);
+        assert_eq!(resolvable_host("rist://[2001:db8::1]:5000"), None);
+        assert_eq!(resolvable_host("rist://@:5000"), None);
+        assert_eq!(resolvable_host("rist://@example.com:5000"), None);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_redacts_passphrases() {
+        assert_eq!(
+            redact("rist://192.0.2.1:5000?cname=a&secret=hunter22&aes-type=128"),
+            "rist://192.0.2.1:5000?cname=a&secret=***&aes-type=128"
+        );
+        assert_eq!(
+            redact("rist://@:5000?password=pw&username=u"),
+            "rist://@:5000?password=***&username=u"
+        );
+        assert_eq!(redact("rist://@:5000"), "rist://@:5000");
+    }
+
+    #[test]
+    fn test_rejects_malformed_components() {
+        assert!(RistUrl::connect("192.0.2.1", 5000)
+            .zone("eth0")
+            .build()
+            .is_err());
+        assert!(RistUrl::connect("fe80::1", 5000)
+            .zone("eth0]")
+            .build()
+            .is_err());
+        assert!(RistUrl::listen(5000).param("cname", "a&b").build().is_err());
+    }
+
+    #[test]
+    fn test_appends_params() {
+        let url = RistUrl::append_param("rist://@:5000", "secret", "p@ss:w/rd").unwrap();
+        assert_eq!(url, "rist://@:5000?secret=p@ss:w/rd");
+        let url = RistUrl::append_param(&url, "aes-type", 256).unwrap();
+        assert_eq!(url, "rist://@:5000?secret=p@ss:w/rd&aes-type=256");
+
+        for value in ["a&b", "a=b", "a#b", "a b", "50%", ""] {
+            let err = RistUrl::append_param("rist://@:5000", "secret", value).unwrap_err();
+            assert!(value.is_empty() || !err.to_string().contains(value));
+        }
+        assert!(RistUrl::append_param("rist://@:5000", "a=b", "c").is_err());
+    }
+}
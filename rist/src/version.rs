@@ -0,0 +1,178 @@
+//! Version and capability queries for the linked librist.
+
+use crate::{Error, Profile, Result, Sender};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::sync::OnceLock;
+
+/// Versions reported by the linked librist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// The librist release, e.g. `"0.2.11"`.
+    pub library: String,
+    /// The librist API version, e.g. `"4.4.0"`.
+    pub api: String,
+}
+
+impl Version {
+    /// Returns `true` if the librist release is at least `major.minor.patch`.
+    ///
+    /// Returns `false` if the release string can't be parsed.
+    pub fn library_at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        parse_version(&self.library).is_some_and(|v| v >= (major, minor, patch))
+    }
+
+    /// Returns `true` if the API version is at least `major.minor.patch`.
+    ///
+    /// Returns `false` if the version string can't be parsed.
+    pub fn api_at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        parse_version(&self.api).is_some_and(|v| v >= (major, minor, patch))
+    }
+}
+
+/// Query the versions of the linked librist.
+///
+/// librist doesn't report the version of its bundled cJSON, so only the
+/// library and API versions are available.
+///
+/// # Example
+///
+/// ```no_run
+/// let version = rist::version();
+/// if !version.library_at_least(0, 2, 7) {
+///     eprintln!("librist {} is too old", version.library);
+/// }
+/// ```
+pub fn version() -> Version {
+    unsafe {
+        Version {
+            library: c_str_to_string(rist_sys::librist_version()),
+            api: c_str_to_string(rist_sys::librist_api_version()),
+        }
+    }
+}
+
+/// An optional librist capability, see [`supports`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Feature {
+    /// AES encryption with a pre-shared secret, which needs librist built
+    /// with mbedtls or nettle.
+    Encryption,
+}
+
+/// Returns `true` if the linked librist supports `feature`.
+///
+/// Capabilities that depend on how librist was built are probed once by
+/// creating a throwaway peer, and the result is cached.
+pub fn supports(feature: Feature) -> bool {
+    match feature {
+        Feature::Encryption => {
+            static ENCRYPTION: OnceLock<bool> = OnceLock::new();
+            *ENCRYPTION.get_or_init(|| {
+                // the discard port; the sender is never started, so nothing is sent
+                Sender::new(Profile::Main)
+                    .and_then(|mut sender| {
+                        sender.add_peer("rist://127.0.0.1:9?secret=capability-probe&aes-type=128")
+                    })
+                    .is_ok()
+            })
+        }
+    }
+}
+
+/// A crypto library librist can be built against, see [`crypto_backend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CryptoBackend {
+    /// mbedtls, selected with the `crypto-mbedtls` feature.
+    Mbedtls,
+    /// nettle, selected with the `crypto-nettle` feature.
+    Nettle,
+}
+
+/// The crypto backend this build requires librist to use, if a
+/// `crypto-*` feature selected one.
+pub fn crypto_backend() -> Option<CryptoBackend> {
+    if cfg!(feature = "crypto-mbedtls") {
+        Some(CryptoBackend::Mbedtls)
+    } else if cfg!(feature = "crypto-nettle") {
+        Some(CryptoBackend::Nettle)
+    } else {
+        None
+    }
+}
+
+/// Check that the librist loaded at run time can encrypt, if this build
+/// requires a [`crypto_backend`].
+///
+/// The build checks the librist it links against, but a shared librist
+/// found at run time may be a different build. librist doesn't report its
+/// backend, so this checks [`Feature::Encryption`]; call it at startup to
+/// refuse to run unencrypted under a crypto policy.
+pub fn check_crypto_backend() -> Result<()> {
+    match crypto_backend() {
+        Some(backend) if !supports(Feature::Encryption) => Err(Error::Configuration(format!(
+            "built for {backend:?} encryption, but the linked librist {} can't encrypt",
+            version().library
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Parse the leading `major.minor.patch` of a version like `v0.2.11-3-gabcdef`.
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let version = version.trim().trim_start_matches('v');
+    let mut parts = version.split(|c: char| !c.is_ascii_digit()).take(3);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+unsafe fn c_str_to_string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("0.2.11"), Some((0, 2, 11)));
+        assert_eq!(parse_version("v0.2.7-12-g1a2b3c"), Some((0, 2, 7)));
+        assert_eq!(parse_version("4.4"), Some((4, 4, 0)));
+        assert_eq!(parse_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_linked_version() {
+        // the build requires librist 0.2 or newer
+        assert!(version().library_at_least(0, 2, 0));
+    }
+
+    #[test]
+    fn test_check_crypto_backend() {
+        let _guard = crate::TEST_MUTEX.lock().unwrap();
+        let required = crypto_backend().is_some();
+        assert_eq!(
+            check_crypto_backend().is_ok(),
+            !required || supports(Feature::Encryption)
+        );
+    }
+
+    #[test]
+    fn test_version_at_least() {
+        let version = Version {
+            library: "0.2.11".to_string(),
+            api: "4.4.0".to_string(),
+        };
+        assert!(version.library_at_least(0, 2, 7));
+        assert!(!version.library_at_least(0, 3, 0));
+        assert!(version.api_at_least(4, 4, 0));
+        assert!(!version.api_at_least(5, 0, 0));
+    }
+}